@@ -0,0 +1,49 @@
+//! Benchmarks the rendering throughput of tray_rust on the Cornell box scene, reporting
+//! the number of camera samples rendered per second. Run it with optimizations from the
+//! root of the repository:
+//!
+//! ```text
+//! cargo run --release --example cornell_box_bench -- [spp] [threads] [runs]
+//! ```
+//!
+//! By default 4 samples per pixel are taken using all the cores available and the frame is
+//! rendered 3 times. The Cornell box uses matte materials and a path tracer, so the time is
+//! mostly spent intersecting the scene and constructing and sampling BSDFs.
+
+extern crate tray_rust;
+extern crate num_cpus;
+
+use std::env;
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+use tray_rust::scene::Scene;
+use tray_rust::exec::{Config, Exec, MultiThreaded};
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    let spp = args.get(1).map_or(4, |s| s.parse().expect("spp must be a number"));
+    let threads = args.get(2).map_or(num_cpus::get() as u32, |s| s.parse().expect("threads must be a number"));
+    let runs = args.get(3).map_or(3, |s| s.parse().expect("runs must be a number"));
+    let scene_file = "scenes/cornell_box.json".to_owned();
+
+    let mut times = Vec::with_capacity(runs);
+    let mut num_samples = 0;
+    for _ in 0..runs {
+        let (mut scene, mut rt, _, frame_info) = Scene::load_file(&scene_file);
+        let dim = rt.dimensions();
+        num_samples = dim.0 * dim.1 * spp;
+        let config = Config::new(PathBuf::from("."), scene_file.clone(), spp, threads, frame_info, (0, 0));
+        let mut exec = MultiThreaded::new(threads);
+        let start = SystemTime::now();
+        exec.render(&mut scene, &mut rt, &config);
+        let time = start.elapsed().expect("Failed to get render time?");
+        times.push(time.as_secs() as f64 + time.subsec_nanos() as f64 * 1e-9);
+    }
+    let best = times.iter().cloned().fold(f64::INFINITY, f64::min);
+    let mean = times.iter().sum::<f64>() / runs as f64;
+    println!("--------------------");
+    println!("Cornell box: {} samples per run, {} threads, {} runs", num_samples, threads, runs);
+    println!("best {:.3}s, mean {:.3}s", best, mean);
+    println!("{:.3} Msamples/s (best run)", num_samples as f64 / best * 1e-6);
+}
@@ -9,6 +9,7 @@ use tray_rust::film::{Colorf, RenderTarget, Camera, ImageSample};
 use tray_rust::film::filter::MitchellNetravali;
 use tray_rust::geometry::{Sphere, Instance};
 use tray_rust::material::Matte;
+use tray_rust::texture::ConstantTexture;
 use tray_rust::sampler::{BlockQueue, LowDiscrepancy, Sampler};
 
 fn main() {
@@ -24,7 +25,8 @@ fn main() {
     let camera = Camera::new(transform, 40.0, rt.dimensions(), 0.5, 0);
     let sphere = Sphere::new(1.5);
     let geometry_lock = std::sync::Arc::new(sphere);
-    let white_wall = Matte::new(&Colorf::new(0.740063, 0.742313, 0.733934), 1.0);
+    let white_wall = Matte::new(std::sync::Arc::new(ConstantTexture::new(Colorf::new(0.740063, 0.742313, 0.733934))),
+                                std::sync::Arc::new(ConstantTexture::new(1.0)));
     let material_lock = std::sync::Arc::new(white_wall);
     let position_transform =
         AnimatedTransform::unanimated(&Transform::translate(&Vector::new(0.0, 2.0, 0.0)));
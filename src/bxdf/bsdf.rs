@@ -1,7 +1,6 @@
 //! Defines the BSDF which acts as a container for composing the various BRDFs
 //! and BTDFs that describe the surface's properties
 
-use std::cmp;
 use enum_set::EnumSet;
 
 use linalg::{self, Normal, Vector, Point};
@@ -9,13 +8,13 @@ use film::Colorf;
 use geometry::DifferentialGeometry;
 use bxdf::{BxDF, BxDFType};
 use sampler::Sample;
+use memory::Allocator;
 
 /// The BSDF contains the various BRDFs and BTDFs that describe the surface's properties
 /// at some point. It also transforms incident and outgoing light directions into
-/// shading space to make the BxDFs easier to implement.
-/// TODO: We really need the memory pool. Each time we get the bsdf from a
-/// material we need to allocate a decent amount of stuff since they each need
-/// their own tangent, bitangent and differential geometry reference.
+/// shading space to make the BxDFs easier to implement. The BSDF and its BxDFs are
+/// allocated in the memory arena passed to the material for each hit.
+#[derive(Clone, Copy)]
 pub struct BSDF<'a> {
     /// The hit point
     pub p: Point,
@@ -29,24 +28,50 @@ pub struct BSDF<'a> {
     pub bitan: Vector,
     /// Refractive index of the geometry
     pub eta: f32,
-    /// TODO: Currently a Vec is safe to use but once in the memory pool it
-    /// will leak since it won't be dropped. This would also migrate our BxDFs
-    /// from Box<BxDF> to &BxDF. When unboxed traits land we can move to unboxed
-    /// BxDFs here though.
-    bxdfs: &'a [Box<BxDF + Send + Sync>],
+    bxdfs: &'a [&'a BxDF],
+    /// The weight of each BxDF, which scales its contribution to the BSDF and
+    /// how often it's chosen when sampling
+    weights: &'a [f32],
 }
 
 impl<'a> BSDF<'a> {
     /// Create a new BSDF using the BxDFs passed to shade the differential geometry with
-    /// refractive index `eta`
-    pub fn new(bxdfs: &'a [Box<BxDF + Send + Sync>], eta: f32,
-               dg: &DifferentialGeometry<'a>)
-               -> BSDF<'a> {
+    /// refractive index `eta`. The list of BxDFs is copied into the arena of `alloc`
+    pub fn new(bxdfs: &[&'a BxDF], eta: f32, dg: &DifferentialGeometry, alloc: &'a Allocator) -> BSDF<'a> {
         let n = dg.n.normalized();
         let mut bitan = dg.dp_du.normalized();
-        let tan = linalg::cross(&n, &bitan);
+        // dp_du may not be perpendicular to the shading normal, e.g. after bump mapping
+        let tan = linalg::cross(&n, &bitan).normalized();
         bitan = linalg::cross(&tan, &n);
-        BSDF { p: dg.p, n: n, ng: dg.ng, tan: tan, bitan: bitan, bxdfs: bxdfs, eta: eta }
+        BSDF { p: dg.p, n: n, ng: dg.ng, tan: tan, bitan: bitan, eta: eta,
+               bxdfs: alloc.alloc_slice_with(bxdfs.len(), |i| bxdfs[i]),
+               weights: alloc.alloc_slice(bxdfs.len(), 1.0) }
+    }
+    /// Blend two BSDFs, the BxDFs of `b` are weighted by `weight` and those of `a` by `1 - weight`.
+    /// The blended BSDF uses the shading frame of `a`
+    pub fn mix(a: BSDF<'a>, b: BSDF<'a>, weight: f32, alloc: &'a Allocator) -> BSDF<'a> {
+        let n = a.bxdfs.len();
+        let len = n + b.bxdfs.len();
+        let bxdfs = alloc.alloc_slice_with(len, |i| if i < n { a.bxdfs[i] } else { b.bxdfs[i - n] });
+        let weights = alloc.alloc_slice_with(len, |i| {
+            if i < n { a.weights[i] * (1.0 - weight) } else { b.weights[i - n] * weight }
+        });
+        let eta = if weight >= 0.5 { b.eta } else { a.eta };
+        BSDF { eta: eta, bxdfs: bxdfs, weights: weights, .. a }
+    }
+    /// Replace each BxDF in the BSDF with the one returned by `f`, e.g. to layer them
+    /// underneath a coating
+    pub fn map_bxdfs<F>(self, alloc: &'a Allocator, mut f: F) -> BSDF<'a>
+        where F: FnMut(&'a BxDF) -> &'a BxDF {
+        let bxdfs = self.bxdfs;
+        BSDF { bxdfs: alloc.alloc_slice_with(bxdfs.len(), |i| f(bxdfs[i])), .. self }
+    }
+    /// Add a BxDF to the BSDF
+    pub fn add(&mut self, bxdf: &'a BxDF, alloc: &'a Allocator) {
+        let n = self.bxdfs.len();
+        let (bxdfs, weights) = (self.bxdfs, self.weights);
+        self.bxdfs = alloc.alloc_slice_with(n + 1, |i| if i < n { bxdfs[i] } else { bxdf });
+        self.weights = alloc.alloc_slice_with(n + 1, |i| if i < n { weights[i] } else { 1.0 });
     }
     /// Return the total number of BxDFs
     pub fn num_bxdfs(&self) -> usize { self.bxdfs.len() }
@@ -79,8 +104,9 @@ impl<'a> BSDF<'a> {
         } else {
             flags.remove(&BxDFType::Reflection);
         }
-        // Find all matching BxDFs and add their contribution to the material's color
-        self.bxdfs.iter().filter_map(|x| if x.matches(flags) { Some(x.eval(&w_o, &w_i)) } else { None })
+        // Find all matching BxDFs and add their weighted contribution to the material's color
+        self.bxdfs.iter().zip(self.weights.iter())
+            .filter_map(|(x, w)| if x.matches(flags) { Some(x.eval(&w_o, &w_i) * *w) } else { None })
             .fold(Colorf::broadcast(0.0), |x, y| x + y)
     }
     /// Sample a component of the BSDF to get an incident light direction for light
@@ -91,11 +117,11 @@ impl<'a> BSDF<'a> {
     pub fn sample(&self, wo_world: &Vector, flags: EnumSet<BxDFType>, samples: &Sample)
         -> (Colorf, Vector, f32, EnumSet<BxDFType>) {
         let n_matching = self.num_matching(flags);
-        if n_matching == 0 {
+        let total_weight = self.matching_weight(flags);
+        if n_matching == 0 || total_weight <= 0.0 {
             return (Colorf::broadcast(0.0), Vector::broadcast(0.0), 0.0, EnumSet::new());
         }
-        let comp = cmp::min((samples.one_d * n_matching as f32) as usize, n_matching - 1);
-        let bxdf = self.matching_at(comp, flags);
+        let (bxdf, weight) = self.choose_matching(samples.one_d * total_weight, flags);
         let w_o = self.to_shading(wo_world).normalized();
         let (mut f, w_i, mut pdf) = bxdf.sample(&w_o, &samples.two_d);
         if w_i.length_sqr() == 0.0 {
@@ -105,15 +131,17 @@ impl<'a> BSDF<'a> {
 
         // TODO: We re-use our functions but actually do a lot of redundant computation. I'm not
         // sure that the compiler will eliminate it. Should just copy in the code from pdf and eval
+        // The pdf computed for all matching components is already averaged over them
         if !bxdf.bxdf_type().contains(&BxDFType::Specular) && n_matching > 1 {
             pdf = self.pdf(wo_world, &wi_world, flags);
-        }
-        if n_matching > 1 {
-            pdf /= n_matching as f32;
+        } else if n_matching > 1 {
+            pdf *= weight / total_weight;
         }
 
         if !bxdf.bxdf_type().contains(&BxDFType::Specular) {
             f = self.eval(wo_world, &wi_world, flags);
+        } else {
+            f = f * weight;
         }
         (f, wi_world, pdf, bxdf.bxdf_type())
     }
@@ -123,23 +151,134 @@ impl<'a> BSDF<'a> {
         // should also normalize?
         let w_o = self.to_shading(wo_world).normalized();
         let w_i = self.to_shading(wi_world).normalized();
-        let (pdf_val, n_comps) = self.bxdfs.iter()
-            .filter_map(|x| if x.matches(flags) { Some(x.pdf(&w_o, &w_i)) } else { None })
-            .fold((0.0, 0), |(p, n), y| (p + y, n + 1));
-        if n_comps > 0 {
-            pdf_val / n_comps as f32
+        // The pdf of each component is weighted by how often it's chosen, specular components
+        // can still be chosen but have no chance of sampling this pair of directions
+        let (pdf_val, total_weight) = self.bxdfs.iter().zip(self.weights.iter())
+            .filter(|&(x, _)| x.matches(flags))
+            .map(|(x, w)| {
+                if x.bxdf_type().contains(&BxDFType::Specular) { (0.0, *w) } else { (x.pdf(&w_o, &w_i) * *w, *w) }
+            })
+            .fold((0.0, 0.0), |(p, tw), (y, w)| (p + y, tw + w));
+        if total_weight > 0.0 {
+            pdf_val / total_weight
         } else {
             0.0
         }
     }
-    /// Get the `i`th BxDF that matches the flags passed. There should not be fewer than i
-    /// BxDFs that match the flags
-    fn matching_at(&self, i: usize, flags: EnumSet<BxDFType>) -> &Box<BxDF + Send + Sync> {
-        let mut it = self.bxdfs.iter().filter(|x| x.matches(flags)).skip(i);
-        match it.next() {
-            Some(b) => b,
-            None => panic!("Out of bounds index for BxDF type {:?}", flags)
+    /// Get the sum of the weights of the BxDFs that match the flags passed
+    fn matching_weight(&self, flags: EnumSet<BxDFType>) -> f32 {
+        self.bxdfs.iter().zip(self.weights.iter()).filter(|&(x, _)| x.matches(flags)).fold(0.0, |s, (_, w)| s + w)
+    }
+    /// Choose the BxDF matching the flags passed at which the running sum of their weights passes
+    /// `x`, returns the BxDF and its weight. `x` should be less than the total weight of the BxDFs
+    /// that match the flags
+    fn choose_matching(&self, x: f32, flags: EnumSet<BxDFType>) -> (&'a BxDF, f32) {
+        let mut sum = 0.0;
+        let mut chosen = None;
+        for (b, w) in self.bxdfs.iter().zip(self.weights.iter()).filter(|&(b, _)| b.matches(flags)) {
+            // Skip BxDFs with no weight so they're never chosen, even by rounding
+            if *w > 0.0 {
+                chosen = Some((*b, *w));
+                sum += *w;
+                if x < sum {
+                    break;
+                }
+            }
+        }
+        match chosen {
+            Some(c) => c,
+            None => panic!("No BxDF with non-zero weight for BxDF type {:?}", flags)
         }
     }
 }
 
+#[test]
+fn test_bsdf_mix() {
+    use rand::{Rng, SeedableRng, StdRng};
+    use geometry::Sphere;
+    use bxdf::{Lambertian, SpecularReflection, TorranceSparrow};
+    use bxdf::fresnel::{Conductor, Dielectric};
+    use bxdf::microfacet::GGX;
+    use memory::MemoryArena;
+
+    let sphere = Sphere::new(1.0);
+    let dg = DifferentialGeometry::new(&Point::new(0.0, 0.0, 1.0), &Normal::new(0.0, 0.0, 1.0), 0.0, 0.0,
+                                       &Vector::new(1.0, 0.0, 0.0), &Vector::new(0.0, 1.0, 0.0), &sphere);
+    let mut arena = MemoryArena::new(4096);
+    let alloc = arena.allocator();
+    let diffuse = || {
+        let lambertian = alloc.alloc(Lambertian::new(&Colorf::broadcast(0.8)));
+        BSDF::new(&[lambertian], 1.0, &dg, &alloc)
+    };
+    let glossy = || {
+        let fresnel = alloc.alloc(Conductor::new(&Colorf::broadcast(0.2), &Colorf::broadcast(3.0)));
+        let microfacet = alloc.alloc(GGX::new(0.3));
+        let specular = alloc.alloc(TorranceSparrow::new(&Colorf::broadcast(1.0), fresnel, microfacet));
+        BSDF::new(&[specular], 1.0, &dg, &alloc)
+    };
+    let mirror = || {
+        let fresnel = alloc.alloc(Dielectric::new(1.0, 1.5));
+        let specular = alloc.alloc(SpecularReflection::new(&Colorf::broadcast(0.9), fresnel));
+        BSDF::new(&[specular], 1.0, &dg, &alloc)
+    };
+    let same_color = |a: &Colorf, b: &Colorf, eps: f32| {
+        f32::abs(a.r - b.r) <= eps && f32::abs(a.g - b.g) <= eps && f32::abs(a.b - b.b) <= eps
+    };
+    let mut rng: StdRng = SeedableRng::from_seed(&[1][..]);
+    // Estimate the fraction of light arriving at the surface that's reflected along `w_o`
+    let albedo = |bsdf: &BSDF, w_o: &Vector, rng: &mut StdRng| {
+        let n = 50000;
+        let mut albedo = 0.0;
+        for _ in 0..n {
+            let s = Sample::new(&(rng.next_f32(), rng.next_f32()), rng.next_f32());
+            let (f, w_i, pdf, _) = bsdf.sample(w_o, BxDFType::all(), &s);
+            if pdf > 0.0 {
+                albedo += f.luminance() * f32::abs(w_i.z) / pdf;
+            }
+        }
+        albedo / n as f32
+    };
+
+    // Sampling a non-specular mix should return the same value and pdf as evaluating it and
+    // the mix should reflect the weighted sum of the light reflected by each material
+    let mix = BSDF::mix(diffuse(), glossy(), 0.3, &alloc);
+    for &cos_o in &[1.0, 0.6, 0.2] {
+        let w_o = Vector::new(f32::sqrt(1.0 - cos_o * cos_o), 0.0, cos_o);
+        for _ in 0..100 {
+            let s = Sample::new(&(rng.next_f32(), rng.next_f32()), rng.next_f32());
+            let (f, w_i, pdf, _) = mix.sample(&w_o, BxDFType::all(), &s);
+            if pdf > 0.0 {
+                assert!(f32::abs(pdf - mix.pdf(&w_o, &w_i, BxDFType::all())) <= 1e-4 * pdf);
+                assert!(same_color(&f, &mix.eval(&w_o, &w_i, BxDFType::all()), 1e-4));
+            }
+        }
+        let expected = 0.7 * albedo(&diffuse(), &w_o, &mut rng) + 0.3 * albedo(&glossy(), &w_o, &mut rng);
+        let mixed = albedo(&mix, &w_o, &mut rng);
+        assert!(f32::abs(mixed - expected) < 0.02, "mix reflects {} but expected {}", mixed, expected);
+    }
+
+    // When the specular component of a mix is chosen it's scaled by its weight and the pdf
+    // by the chance of choosing it. The diffuse component has weight 0.6 and is chosen first
+    let mix = BSDF::mix(diffuse(), mirror(), 0.4, &alloc);
+    let w_o = Vector::new(0.6, 0.0, 0.8);
+    let s = Sample::new(&(0.3, 0.7), 0.8);
+    let (f, w_i, pdf, ty) = mix.sample(&w_o, BxDFType::all(), &s);
+    let (f_mirror, w_mirror, _, _) = mirror().sample(&w_o, BxDFType::all(), &s);
+    assert!(ty.contains(&BxDFType::Specular));
+    assert!(same_color(&f, &(f_mirror * 0.4), 1e-5));
+    assert!((w_i - w_mirror).length() < 1e-5);
+    assert!(f32::abs(pdf - 0.4) < 1e-5);
+    let s = Sample::new(&(0.3, 0.7), 0.2);
+    let (_, w_i, pdf, ty) = mix.sample(&w_o, BxDFType::all(), &s);
+    assert!(!ty.contains(&BxDFType::Specular));
+    assert!(f32::abs(pdf - 0.6 * f32::abs(w_i.z) * ::std::f32::consts::FRAC_1_PI) < 1e-4);
+
+    // A component with no weight is never chosen, even at the ends of the sample range
+    let no_mirror = BSDF::mix(diffuse(), mirror(), 0.0, &alloc);
+    let only_mirror = BSDF::mix(diffuse(), mirror(), 1.0, &alloc);
+    for &u in &[0.0, 0.25, 0.5, 0.75, 0.999999] {
+        let s = Sample::new(&(0.3, 0.7), u);
+        assert!(!no_mirror.sample(&w_o, BxDFType::all(), &s).3.contains(&BxDFType::Specular));
+        assert!(only_mirror.sample(&w_o, BxDFType::all(), &s).3.contains(&BxDFType::Specular));
+    }
+}
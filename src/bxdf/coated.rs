@@ -0,0 +1,51 @@
+//! Defines a BxDF which describes the light reaching another BxDF underneath a smooth
+//! coating, e.g. the diffuse base of a plastic beneath its specular surface. The light
+//! reflected by the coating can't reach the BxDF below so it's attenuated by the light
+//! transmitted through the coating when entering and again when leaving the surface,
+//! which keeps the sum of the layers from reflecting more light than it receives.
+
+use std::f32;
+use enum_set::EnumSet;
+
+use linalg::Vector;
+use film::Colorf;
+use bxdf::{self, BxDF, BxDFType};
+use bxdf::fresnel::Fresnel;
+
+/// A BxDF underneath a coating described by its Fresnel term
+#[derive(Clone, Copy)]
+pub struct Coated<'a> {
+    bxdf: &'a BxDF,
+    fresnel: &'a Fresnel,
+    /// Strength of the coating, scales the light it reflects
+    weight: f32,
+}
+
+impl<'a> Coated<'a> {
+    /// Create the BxDF `bxdf` under a coating with the Fresnel term `fresnel`. The light
+    /// reflected by the coating is scaled by `weight`, e.g. to fade the coating out
+    pub fn new(bxdf: &'a BxDF, fresnel: &'a Fresnel, weight: f32) -> Coated<'a> {
+        Coated { bxdf: bxdf, fresnel: fresnel, weight: weight }
+    }
+    /// Compute the fraction of light transmitted through the coating along `w`
+    fn transmitted(&self, w: &Vector) -> Colorf {
+        Colorf::broadcast(1.0) - self.fresnel.fresnel(f32::abs(bxdf::cos_theta(w))) * self.weight
+    }
+}
+
+impl<'a> BxDF for Coated<'a> {
+    fn bxdf_type(&self) -> EnumSet<BxDFType> {
+        self.bxdf.bxdf_type()
+    }
+    fn eval(&self, w_o: &Vector, w_i: &Vector) -> Colorf {
+        self.bxdf.eval(w_o, w_i) * self.transmitted(w_o) * self.transmitted(w_i)
+    }
+    fn sample(&self, w_o: &Vector, samples: &(f32, f32)) -> (Colorf, Vector, f32) {
+        let (f, w_i, pdf) = self.bxdf.sample(w_o, samples);
+        (f * self.transmitted(w_o) * self.transmitted(&w_i), w_i, pdf)
+    }
+    fn pdf(&self, w_o: &Vector, w_i: &Vector) -> f32 {
+        self.bxdf.pdf(w_o, w_i)
+    }
+}
+
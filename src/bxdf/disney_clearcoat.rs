@@ -0,0 +1,91 @@
+//! Defines the clearcoat BRDF of the Disney principled BRDF, a second specular lobe for
+//! a thin clear layer on top of the material using the GTR1 (Berry) microfacet distribution.
+//! See Burley, "Physically Based Shading at Disney", 2012.
+
+use std::f32;
+use enum_set::EnumSet;
+
+use linalg::{self, Vector};
+use film::Colorf;
+use bxdf::{self, BxDF, BxDFType};
+use bxdf::fresnel::schlick_weight;
+
+/// The Disney clearcoat BRDF, a fixed white specular lobe with reflectance 0.04 at normal
+/// incidence. Following Disney the lobe is scaled by 0.25 and uses a fixed roughness of
+/// 0.25 for its shadowing-masking term
+#[derive(Clone, Copy, Debug)]
+pub struct DisneyClearcoat {
+    /// Strength of the clearcoat, between 0 and 1
+    weight: f32,
+    /// Width of the GTR1 distribution
+    alpha: f32,
+}
+
+impl DisneyClearcoat {
+    /// Create a new clearcoat BRDF with the strength `weight` and glossiness `gloss`, both
+    /// between 0 and 1
+    pub fn new(weight: f32, gloss: f32) -> DisneyClearcoat {
+        DisneyClearcoat { weight: weight, alpha: linalg::lerp(gloss, &0.1, &0.001) }
+    }
+    /// Evaluate the GTR1 distribution for a microfacet normal with `cos_theta`
+    fn gtr1(&self, cos_theta: f32) -> f32 {
+        let alpha_sqr = self.alpha * self.alpha;
+        (alpha_sqr - 1.0) / (f32::consts::PI * f32::ln(alpha_sqr) * (1.0 + (alpha_sqr - 1.0) * cos_theta * cos_theta))
+    }
+}
+
+/// Smith's shadowing term for GGX divided by 2 cos_theta
+fn smith_ggx(cos_theta: f32, alpha: f32) -> f32 {
+    let alpha_sqr = alpha * alpha;
+    let cos_sqr = cos_theta * cos_theta;
+    1.0 / (cos_theta + f32::sqrt(alpha_sqr + cos_sqr - alpha_sqr * cos_sqr))
+}
+
+impl BxDF for DisneyClearcoat {
+    fn bxdf_type(&self) -> EnumSet<BxDFType> {
+        let mut e = EnumSet::new();
+        e.insert(BxDFType::Glossy);
+        e.insert(BxDFType::Reflection);
+        e
+    }
+    fn eval(&self, w_o: &Vector, w_i: &Vector) -> Colorf {
+        let w_h = *w_i + *w_o;
+        if w_h.x == 0.0 && w_h.y == 0.0 && w_h.z == 0.0 {
+            return Colorf::black();
+        }
+        let w_h = w_h.normalized();
+        let d = self.gtr1(f32::abs(bxdf::cos_theta(&w_h)));
+        let f = linalg::lerp(schlick_weight(linalg::dot(w_o, &w_h)), &0.04, &1.0);
+        let g = smith_ggx(f32::abs(bxdf::cos_theta(w_o)), 0.25) * smith_ggx(f32::abs(bxdf::cos_theta(w_i)), 0.25);
+        Colorf::broadcast(self.weight * g * f * d / 4.0)
+    }
+    fn sample(&self, w_o: &Vector, samples: &(f32, f32)) -> (Colorf, Vector, f32) {
+        let alpha_sqr = self.alpha * self.alpha;
+        let cos_theta = f32::sqrt(f32::max(0.0, (1.0 - f32::powf(alpha_sqr, 1.0 - samples.0)) / (1.0 - alpha_sqr)));
+        let sin_theta = f32::sqrt(f32::max(0.0, 1.0 - cos_theta * cos_theta));
+        let mut w_h = linalg::spherical_dir(sin_theta, cos_theta, 2.0 * f32::consts::PI * samples.1);
+        if !bxdf::same_hemisphere(w_o, &w_h) {
+            w_h = -w_h;
+        }
+        let w_i = linalg::reflect(w_o, &w_h);
+        if !bxdf::same_hemisphere(w_o, &w_i) {
+            (Colorf::black(), Vector::broadcast(0.0), 0.0)
+        } else {
+            (self.eval(w_o, &w_i), w_i, self.pdf(w_o, &w_i))
+        }
+    }
+    fn pdf(&self, w_o: &Vector, w_i: &Vector) -> f32 {
+        if !bxdf::same_hemisphere(w_o, w_i) {
+            return 0.0;
+        }
+        let w_h = *w_o + *w_i;
+        if w_h.x == 0.0 && w_h.y == 0.0 && w_h.z == 0.0 {
+            return 0.0;
+        }
+        let w_h = w_h.normalized();
+        let cos_theta_h = f32::abs(bxdf::cos_theta(&w_h));
+        // Convert from the pdf of the half vector using the Jacobian for reflection
+        self.gtr1(cos_theta_h) * cos_theta_h / (4.0 * f32::abs(linalg::dot(w_o, &w_h)))
+    }
+}
+
@@ -0,0 +1,52 @@
+//! Defines the diffuse BRDF of the Disney principled BRDF, which darkens the diffuse
+//! response at grazing angles for smooth surfaces and adds some retro-reflection for
+//! rough ones. See Burley, "Physically Based Shading at Disney", 2012.
+
+use std::f32;
+use enum_set::EnumSet;
+
+use linalg::{self, Vector};
+use film::Colorf;
+use bxdf::{self, BxDF, BxDFType};
+use bxdf::fresnel::schlick_weight;
+
+/// The Disney diffuse BRDF, a Lambertian model with a Fresnel-like factor for the
+/// light entering and leaving the surface that depends on the surface roughness
+#[derive(Clone, Copy, Debug)]
+pub struct DisneyDiffuse {
+    /// Color of the diffuse material
+    reflectance: Colorf,
+    /// Roughness of the surface, between 0 and 1
+    roughness: f32,
+}
+
+impl DisneyDiffuse {
+    /// Create a new Disney diffuse BRDF with the desired color and roughness
+    pub fn new(c: &Colorf, roughness: f32) -> DisneyDiffuse {
+        DisneyDiffuse { reflectance: *c, roughness: roughness }
+    }
+}
+
+impl BxDF for DisneyDiffuse {
+    fn bxdf_type(&self) -> EnumSet<BxDFType> {
+        let mut e = EnumSet::new();
+        e.insert(BxDFType::Diffuse);
+        e.insert(BxDFType::Reflection);
+        e
+    }
+    fn eval(&self, w_o: &Vector, w_i: &Vector) -> Colorf {
+        let f_o = schlick_weight(bxdf::cos_theta(w_o));
+        let f_i = schlick_weight(bxdf::cos_theta(w_i));
+        let lambert = (1.0 - 0.5 * f_o) * (1.0 - 0.5 * f_i);
+        let w_h = *w_i + *w_o;
+        if w_h.x == 0.0 && w_h.y == 0.0 && w_h.z == 0.0 {
+            return self.reflectance * lambert * f32::consts::FRAC_1_PI;
+        }
+        // The retro-reflection increases the reflectance at grazing angles for rough surfaces
+        let cos_theta_d = linalg::dot(w_i, &w_h.normalized());
+        let r_r = 2.0 * self.roughness * cos_theta_d * cos_theta_d;
+        let retro = r_r * (f_o + f_i + f_o * f_i * (r_r - 1.0));
+        self.reflectance * (lambert + retro) * f32::consts::FRAC_1_PI
+    }
+}
+
@@ -0,0 +1,42 @@
+//! Defines the sheen BRDF of the Disney principled BRDF, which adds a soft reflection
+//! at grazing angles like that seen on cloth. See Burley, "Physically Based Shading
+//! at Disney", 2012.
+
+use enum_set::EnumSet;
+
+use linalg::{self, Vector};
+use film::Colorf;
+use bxdf::{BxDF, BxDFType};
+use bxdf::fresnel::schlick_weight;
+
+/// The Disney sheen BRDF, reflecting more light as the angle between the incident
+/// direction and the half vector increases
+#[derive(Clone, Copy, Debug)]
+pub struct DisneySheen {
+    /// Color of the sheen
+    reflectance: Colorf,
+}
+
+impl DisneySheen {
+    /// Create a new sheen BRDF with the desired color
+    pub fn new(c: &Colorf) -> DisneySheen {
+        DisneySheen { reflectance: *c }
+    }
+}
+
+impl BxDF for DisneySheen {
+    fn bxdf_type(&self) -> EnumSet<BxDFType> {
+        let mut e = EnumSet::new();
+        e.insert(BxDFType::Diffuse);
+        e.insert(BxDFType::Reflection);
+        e
+    }
+    fn eval(&self, w_o: &Vector, w_i: &Vector) -> Colorf {
+        let w_h = *w_i + *w_o;
+        if w_h.x == 0.0 && w_h.y == 0.0 && w_h.z == 0.0 {
+            return Colorf::black();
+        }
+        self.reflectance * schlick_weight(linalg::dot(w_i, &w_h.normalized()))
+    }
+}
+
@@ -88,3 +88,54 @@ impl Fresnel for Conductor {
     fn fresnel(&self, cos_i: f32) -> Colorf { conductor(f32::abs(cos_i), &self.eta, &self.k) }
 }
 
+
+/// Compute Schlick's weight for his approximation to the Fresnel term, (1 - cos)^5
+pub fn schlick_weight(cos_i: f32) -> f32 {
+    f32::powf(1.0 - linalg::clamp(f32::abs(cos_i), 0.0, 1.0), 5.0)
+}
+
+/// Computes Schlick's approximation to the Fresnel term, interpolating from the reflectance
+/// at normal incidence to 1 at grazing angles
+#[derive(Clone, Copy, Debug)]
+pub struct Schlick {
+    /// Reflectance of the material at normal incidence
+    pub r0: Colorf,
+}
+
+impl Schlick {
+    /// Create a Schlick Fresnel term with the reflectance `r0` at normal incidence
+    pub fn new(r0: &Colorf) -> Schlick { Schlick { r0: *r0 } }
+}
+
+impl Fresnel for Schlick {
+    fn fresnel(&self, cos_i: f32) -> Colorf {
+        linalg::lerp(schlick_weight(cos_i), &self.r0, &Colorf::broadcast(1.0))
+    }
+}
+
+/// The Fresnel term used by the principled material, blending between a dielectric
+/// and Schlick's approximation for metals based on how metallic the material is
+#[derive(Clone, Copy, Debug)]
+pub struct DisneyFresnel {
+    /// Reflectance of the metal at normal incidence
+    pub r0: Colorf,
+    /// How metallic the material is, between 0 and 1
+    pub metallic: f32,
+    /// Refractive index of the dielectric
+    pub eta: f32,
+}
+
+impl DisneyFresnel {
+    /// Create the Fresnel term for a material which is `metallic` metal with reflectance `r0`
+    /// at normal incidence, and the rest a dielectric with refractive index `eta`
+    pub fn new(r0: &Colorf, metallic: f32, eta: f32) -> DisneyFresnel {
+        DisneyFresnel { r0: *r0, metallic: metallic, eta: eta }
+    }
+}
+
+impl Fresnel for DisneyFresnel {
+    fn fresnel(&self, cos_i: f32) -> Colorf {
+        linalg::lerp(self.metallic, &Dielectric::new(1.0, self.eta).fresnel(cos_i),
+                     &Schlick::new(&self.r0).fresnel(cos_i))
+    }
+}
@@ -0,0 +1,193 @@
+//! Provides tables of the directional albedo of the microfacet distributions, used to add
+//! back the energy lost by microfacet BxDFs which only model a single scattering event on
+//! the microfacets. Light scattered more than once between the microfacets is lost, making
+//! rough surfaces darker than they should be. Following Kulla and Conty, "Revisiting Physically
+//! Based Shading at Imageworks", 2017, the lost energy can be added back with a diffuse-like lobe
+//! computed from the albedo of the BxDF, which is precomputed for a white surface over the cosine
+//! of the outgoing direction and the roughness.
+//!
+//! Dielectrics also lose energy transmitted through the surface, so for them the albedo of
+//! the reflection and transmission together is tabulated for a range of refractive indices.
+//! The tables are computed the first time they're used, e.g. when the first material with
+//! energy compensation is created, and shared by all materials after that.
+
+use std::{f32, ptr};
+use std::sync::Once;
+use std::sync::atomic::{AtomicPtr, Ordering};
+
+use bxdf;
+use linalg::{self, Vector};
+use film::Colorf;
+use bxdf::fresnel::{Dielectric, Fresnel};
+use bxdf::microfacet::{Beckmann, GGX, MicrofacetDistribution};
+
+/// The largest refractive index in the albedo tables for dielectrics, the smallest is 1
+const MAX_ETA: f32 = 3.0;
+/// Number of stratified samples taken along each axis to estimate an entry in a table
+const TABLE_SAMPLES: usize = 32;
+
+/// Table of the directional albedo of a white microfacet BxDF, indexed by the cosine of the
+/// outgoing direction and the width of the microfacet distribution, which are both between 0
+/// and 1, and for dielectrics the refractive index. Also stores the albedo averaged over the
+/// hemisphere for each width and refractive index
+pub struct AlbedoTable {
+    /// Number of entries along the cosine and width axes
+    size: usize,
+    /// Number of refractive indices in the table, 0 for a conductor
+    etas: usize,
+    albedo: Vec<f32>,
+    average: Vec<f32>,
+}
+
+impl AlbedoTable {
+    /// Compute the albedo table with `size` entries along the cosine and width axes for the
+    /// microfacet distribution created by `distribution` for some width. If `etas` is 0 the
+    /// table is for a conductor which only reflects light, otherwise it's for a dielectric with
+    /// `etas` refractive indices evenly spaced between 1 and `MAX_ETA`
+    fn new<M, F>(distribution: F, size: usize, etas: usize) -> AlbedoTable
+        where M: MicrofacetDistribution, F: Fn(f32) -> M
+    {
+        let step = 1.0 / (size - 1) as f32;
+        let layers = if etas == 0 { 1 } else { etas };
+        let mut albedo = Vec::with_capacity(layers * size * size);
+        let mut average = Vec::with_capacity(layers * size);
+        for k in 0..layers {
+            let eta = if etas == 0 { None } else { Some(linalg::lerp(k as f32 / (etas - 1) as f32, &1.0, &MAX_ETA)) };
+            for j in 0..size {
+                let microfacet = distribution(f32::max(j as f32 * step, 0.001));
+                for i in 0..size {
+                    let cos_theta = f32::max(i as f32 * step, 0.001);
+                    let w_o = linalg::spherical_dir(f32::sqrt(1.0 - cos_theta * cos_theta), cos_theta, 0.0);
+                    albedo.push(directional_albedo(&microfacet, &w_o, eta));
+                }
+                // Integrate the albedo weighted by cos theta over the hemisphere with the trapezoid rule
+                let row = &albedo[(k * size + j) * size..];
+                let sum = (1..size).fold(0.0, |s, i| {
+                    s + 0.5 * (row[i - 1] * (i - 1) as f32 + row[i] * i as f32) * step * step
+                });
+                average.push(2.0 * sum);
+            }
+        }
+        AlbedoTable { size: size, etas: etas, albedo: albedo, average: average }
+    }
+    /// Look up the albedo for an outgoing direction with `cos_theta` for a distribution with
+    /// width `width` and a refractive index of `eta`, which is ignored for conductors.
+    /// The albedo is interpolated between the entries in the table
+    pub fn albedo(&self, cos_theta: f32, width: f32, eta: f32) -> f32 {
+        let (i, s) = table_coord(cos_theta, self.size);
+        let (j, t) = table_coord(width, self.size);
+        let (k, u) = self.eta_coord(eta);
+        let entry = |k: usize, j: usize| {
+            let row = &self.albedo[(k * self.size + j) * self.size..];
+            linalg::lerp(s, &row[i], &row[i + 1])
+        };
+        let layer = |k: usize| linalg::lerp(t, &entry(k, j), &entry(k, j + 1));
+        if self.etas == 0 { layer(0) } else { linalg::lerp(u, &layer(k), &layer(k + 1)) }
+    }
+    /// Look up the albedo averaged over the hemisphere for a distribution with width `width`
+    /// and a refractive index of `eta`, which is ignored for conductors
+    pub fn average(&self, width: f32, eta: f32) -> f32 {
+        let (j, t) = table_coord(width, self.size);
+        let (k, u) = self.eta_coord(eta);
+        let layer = |k: usize| linalg::lerp(t, &self.average[k * self.size + j], &self.average[k * self.size + j + 1]);
+        if self.etas == 0 { layer(0) } else { linalg::lerp(u, &layer(k), &layer(k + 1)) }
+    }
+    /// Find the entry for the refractive index `eta` and the offset between it and the next entry
+    fn eta_coord(&self, eta: f32) -> (usize, f32) {
+        if self.etas == 0 {
+            (0, 0.0)
+        } else {
+            table_coord((eta - 1.0) / (MAX_ETA - 1.0), self.etas)
+        }
+    }
+}
+
+/// Find the entry for `x` in `[0, 1]` along an axis with `n` entries and the offset of `x`
+/// between it and the next entry
+fn table_coord(x: f32, n: usize) -> (usize, f32) {
+    let x = linalg::clamp(x, 0.0, 1.0) * (n - 1) as f32;
+    let i = f32::min(x, (n - 2) as f32) as usize;
+    (i, x - i as f32)
+}
+
+/// Estimate the albedo of a white microfacet BxDF with the distribution `microfacet` for light
+/// leaving along `w_o` with stratified samples. If `eta` is None the BxDF is a conductor's BRDF,
+/// otherwise it's the BRDF and BTDF of a dielectric with refractive index `eta` on the side of
+/// the surface the normal points into. Sampling the visible normals the BxDF divided by the pdf
+/// is just the Fresnel term times the shadowing term for the incident direction, which we average
+/// directly to avoid the precision issues of evaluating the distribution when it's very narrow
+fn directional_albedo<M: MicrofacetDistribution>(microfacet: &M, w_o: &Vector, eta: Option<f32>) -> f32 {
+    let mut albedo = 0.0;
+    for i in 0..TABLE_SAMPLES {
+        for j in 0..TABLE_SAMPLES {
+            let samples = ((i as f32 + 0.5) / TABLE_SAMPLES as f32, (j as f32 + 0.5) / TABLE_SAMPLES as f32);
+            let w_h = microfacet.sample(w_o, &samples);
+            let f = match eta {
+                Some(eta) => Dielectric::new(1.0, eta).fresnel(linalg::dot(w_o, &w_h)).r,
+                None => 1.0,
+            };
+            let w_r = linalg::reflect(w_o, &w_h);
+            if bxdf::same_hemisphere(w_o, &w_r) {
+                albedo += f * microfacet.monodir_shadowing(&w_r, &w_h);
+            }
+            if let Some(eta) = eta {
+                match linalg::refract(w_o, &w_h, 1.0, eta) {
+                    Some(w_t) if !bxdf::same_hemisphere(w_o, &w_t) => {
+                        albedo += (1.0 - f) * microfacet.monodir_shadowing(&w_t, &w_h);
+                    },
+                    _ => {},
+                }
+            }
+        }
+    }
+    albedo / (TABLE_SAMPLES * TABLE_SAMPLES) as f32
+}
+
+/// Compute the Fresnel term averaged over the hemisphere weighted by cos theta
+pub fn average_fresnel(fresnel: &Fresnel) -> Colorf {
+    // Integrate over cos^2 theta so we can take evenly spaced samples
+    let n = 16;
+    (0..n).fold(Colorf::black(), |f, i| f + fresnel.fresnel(f32::sqrt((i as f32 + 0.5) / n as f32))) / n as f32
+}
+
+/// Define a function returning a shared albedo table which is computed by `$table` the
+/// first time the function is called and kept for the rest of the program
+macro_rules! lazy_albedo_table {
+    ($(#[$attr:meta])* fn $name:ident() => $table:expr) => {
+        $(#[$attr])*
+        pub fn $name() -> &'static AlbedoTable {
+            static INIT: Once = Once::new();
+            static TABLE: AtomicPtr<AlbedoTable> = AtomicPtr::new(ptr::null_mut());
+            INIT.call_once(|| {
+                TABLE.store(Box::into_raw(Box::new($table)), Ordering::Release);
+            });
+            // The table is set once by `call_once` and never freed, so it lives for the rest
+            // of the program once `call_once` has returned
+            unsafe { &*TABLE.load(Ordering::Acquire) }
+        }
+    }
+}
+
+lazy_albedo_table! {
+    /// Get the albedo table for a conductor with the Beckmann distribution, computing it if
+    /// this is the first use
+    fn beckmann() => AlbedoTable::new(Beckmann::new, 32, 0)
+}
+
+lazy_albedo_table! {
+    /// Get the albedo table for a dielectric with the Beckmann distribution, computing it if
+    /// this is the first use
+    fn beckmann_dielectric() => AlbedoTable::new(Beckmann::new, 16, 8)
+}
+
+lazy_albedo_table! {
+    /// Get the albedo table for a conductor with the GGX distribution, computing it if this is
+    /// the first use
+    fn ggx() => AlbedoTable::new(GGX::new, 32, 0)
+}
+
+lazy_albedo_table! {
+    /// Get the albedo table for a dielectric with the GGX distribution, computing it if this is
+    /// the first use
+    fn ggx_dielectric() => AlbedoTable::new(GGX::new, 16, 8)
+}
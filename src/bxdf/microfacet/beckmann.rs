@@ -5,44 +5,90 @@ use std::f32;
 
 use bxdf;
 use linalg::{self, Vector};
-use bxdf::microfacet::MicrofacetDistribution;
+use bxdf::microfacet::{self, albedo, MicrofacetDistribution};
 
 /// Beckmann microfacet distribution with Smith shadowing-masking. This is the
 /// microfacet model described by [Walter et al.](https://www.cs.cornell.edu/~srm/publications/EGSR07-btdf.pdf)
+/// The distribution can be anisotropic, with different widths along the x and y axes
+/// of the shading space to stretch highlights along one direction, e.g. for brushed metal.
+#[derive(Clone, Copy, Debug)]
 pub struct Beckmann {
-    width: f32,
+    width_x: f32,
+    width_y: f32,
+    /// Whether to sample only the microfacet normals visible from the outgoing direction
+    sample_visible: bool,
 }
 
 impl Beckmann {
     /// Create a new Beckmann distribution with the desired width
     pub fn new(w: f32) -> Beckmann {
-        let roughness = f32::max(w, 0.000001);
-        Beckmann { width: roughness }
+        Beckmann::anisotropic(w, w)
+    }
+    /// Create a new anisotropic Beckmann distribution with the width `w_x` along the x axis
+    /// of the shading space, i.e. along dp_du, and `w_y` along the y axis
+    pub fn anisotropic(w_x: f32, w_y: f32) -> Beckmann {
+        Beckmann { width_x: f32::max(w_x, 0.000001), width_y: f32::max(w_y, 0.000001), sample_visible: true }
+    }
+    /// Choose whether to sample only the microfacet normals visible from the outgoing direction,
+    /// which is the default, or the full distribution of normals
+    pub fn sample_visible(self, sample_visible: bool) -> Beckmann {
+        Beckmann { sample_visible: sample_visible, .. self }
+    }
+    /// Compute the width used to look up the albedo of the distribution, anisotropic distributions
+    /// are treated as an isotropic one with the geometric mean of their widths
+    fn albedo_width(&self) -> f32 {
+        f32::sqrt(self.width_x * self.width_y)
+    }
+    /// Compute the squared width of the distribution along the azimuthal direction of `v`
+    fn width_sqr(&self, v: &Vector) -> f32 {
+        f32::powf(bxdf::cos_phi(v) * self.width_x, 2.0) + f32::powf(bxdf::sin_phi(v) * self.width_y, 2.0)
+    }
+    /// Sample a microfacet normal visible from `w_o`, which must be in the upper hemisphere. The
+    /// distribution is stretched to a width of 1 and rotated so `w_o` has an azimuth of 0, the
+    /// slopes are sampled in this space then rotated and stretched back, see Heitz and d'Eon 2014
+    fn sample_visible_normal(&self, w_o: &Vector, samples: &(f32, f32)) -> Vector {
+        let v = Vector::new(self.width_x * w_o.x, self.width_y * w_o.y, w_o.z).normalized();
+        let (slope_x, slope_y) = sample_visible_slopes(bxdf::cos_theta(&v), samples);
+        let (cos_phi, sin_phi) = (bxdf::cos_phi(&v), bxdf::sin_phi(&v));
+        Vector::new(-self.width_x * (cos_phi * slope_x - sin_phi * slope_y),
+                    -self.width_y * (sin_phi * slope_x + cos_phi * slope_y), 1.0).normalized()
     }
 }
 
 impl MicrofacetDistribution for Beckmann {
     fn normal_distribution(&self, w_h: &Vector) -> f32 {
         if bxdf::cos_theta(w_h) > 0.0 {
-            let e = f32::exp(-f32::powf(bxdf::tan_theta(w_h) / self.width, 2.0));
-            e / (f32::consts::PI * f32::powf(self.width, 2.0) * f32::powf(bxdf::cos_theta(w_h), 4.0))
+            let e = f32::exp(-f32::powf(bxdf::tan_theta(w_h), 2.0)
+                             * (f32::powf(bxdf::cos_phi(w_h) / self.width_x, 2.0)
+                                + f32::powf(bxdf::sin_phi(w_h) / self.width_y, 2.0)));
+            e / (f32::consts::PI * self.width_x * self.width_y * f32::powf(bxdf::cos_theta(w_h), 4.0))
         } else {
             0.0
         }
     }
-    fn sample(&self, _: &Vector, samples: &(f32, f32)) -> Vector {
+    fn sample(&self, w_o: &Vector, samples: &(f32, f32)) -> Vector {
+        if self.sample_visible {
+            // The visible normals are sampled for a direction in the upper hemisphere, the
+            // BxDFs flip the normal to the side of `w_o` as they do for the full distribution
+            let w_o = if bxdf::cos_theta(w_o) < 0.0 { -*w_o } else { *w_o };
+            return self.sample_visible_normal(&w_o, samples);
+        }
         let log_sample = match f32::ln(1.0 - samples.0) {
             x if f32::is_infinite(x) => 0.0,
             x => x,
         };
-        let tan_theta_sqr = -f32::powf(self.width, 2.0) * log_sample;
-        let phi = 2.0 * f32::consts::PI * samples.1;
+        let (phi, width_sqr) = microfacet::sample_azimuth(self.width_x, self.width_y, samples.1);
+        let tan_theta_sqr = -width_sqr * log_sample;
         let cos_theta = 1.0 / f32::sqrt(1.0 + tan_theta_sqr);
         let sin_theta = f32::sqrt(f32::max(0.0, 1.0 - cos_theta * cos_theta));
         linalg::spherical_dir(sin_theta, cos_theta, phi)
     }
-    fn pdf(&self, w_h: &Vector) -> f32 {
-        f32::abs(bxdf::cos_theta(w_h)) * self.normal_distribution(w_h)
+    fn pdf(&self, w_o: &Vector, w_h: &Vector) -> f32 {
+        if self.sample_visible {
+            microfacet::visible_pdf(self, w_o, w_h)
+        } else {
+            f32::abs(bxdf::cos_theta(w_h)) * self.normal_distribution(w_h)
+        }
     }
     fn shadowing_masking(&self, w_i: &Vector, w_o: &Vector, w_h: &Vector) -> f32 {
         self.monodir_shadowing(w_i, w_h) * self.monodir_shadowing(w_o, w_h)
@@ -52,7 +98,7 @@ impl MicrofacetDistribution for Beckmann {
     /// `w` is the incident/outgoing light direction and `w_h` is the microfacet normal
     fn monodir_shadowing(&self, v: &Vector, w_h: &Vector) -> f32 {
         if linalg::dot(v, w_h) / bxdf::cos_theta(v) > 0.0 {
-            let a = 1.0 / (self.width * bxdf::tan_theta(v));
+            let a = 1.0 / (f32::sqrt(self.width_sqr(v)) * f32::abs(bxdf::tan_theta(v)));
             if a < 1.6 {
                 let a_sqr = f32::powf(a, 2.0);
                 (3.535 * a + 2.181 * a_sqr) / (1.0 + 2.276 * a + 2.577 * a_sqr)
@@ -63,5 +109,61 @@ impl MicrofacetDistribution for Beckmann {
             0.0
         }
     }
+    fn albedo(&self, cos_theta: f32, eta: Option<f32>) -> f32 {
+        match eta {
+            Some(eta) => albedo::beckmann_dielectric().albedo(f32::abs(cos_theta), self.albedo_width(), eta),
+            None => albedo::beckmann().albedo(f32::abs(cos_theta), self.albedo_width(), 1.0),
+        }
+    }
+    fn average_albedo(&self, eta: Option<f32>) -> f32 {
+        match eta {
+            Some(eta) => albedo::beckmann_dielectric().average(self.albedo_width(), eta),
+            None => albedo::beckmann().average(self.albedo_width(), 1.0),
+        }
+    }
 }
 
+/// Sample the slopes of the microfacets visible from a direction with `cos_theta_o` and an azimuth
+/// of 0 in an isotropic Beckmann distribution with width 1. The slope along x is found by inverting
+/// its CDF with Newton's method, the slope along y is independent of the direction and follows the
+/// distribution's slopes. See Jakob, "An Improved Visible Normal Sampling Routine for the Beckmann
+/// Distribution", 2014
+fn sample_visible_slopes(cos_theta_o: f32, samples: &(f32, f32)) -> (f32, f32) {
+    // At normal incidence all microfacets are visible so we sample the distribution's slopes
+    if cos_theta_o > 0.9999 {
+        let r = f32::sqrt(-f32::ln(1.0 - samples.0));
+        let phi = 2.0 * f32::consts::PI * samples.1;
+        return (r * f32::cos(phi), r * f32::sin(phi));
+    }
+    let sin_theta_o = f32::sqrt(f32::max(0.0, 1.0 - cos_theta_o * cos_theta_o));
+    let tan_theta_o = sin_theta_o / cos_theta_o;
+    let cot_theta_o = 1.0 / tan_theta_o;
+    let sqrt_pi_inv = 1.0 / f32::sqrt(f32::consts::PI);
+    let u = f32::max(samples.0, 1e-6);
+    // The root is searched for in erf space, bisecting the interval [a, c] containing it
+    // whenever a Newton step leaves the interval
+    let mut a = -1.0;
+    let mut c = linalg::erf(cot_theta_o);
+    // Start from a fitted approximation of the inverse CDF
+    let theta_o = f32::acos(cos_theta_o);
+    let fit = 1.0 + theta_o * (-0.876 + theta_o * (0.4265 - 0.0594 * theta_o));
+    let mut b = c - (1.0 + c) * f32::powf(1.0 - u, fit);
+    let normalization = 1.0 / (1.0 + c + sqrt_pi_inv * tan_theta_o * f32::exp(-cot_theta_o * cot_theta_o));
+    for _ in 0..9 {
+        if !(b >= a && b <= c) {
+            b = 0.5 * (a + c);
+        }
+        let inv_erf = linalg::erf_inv(b);
+        let value = normalization * (1.0 + b + sqrt_pi_inv * tan_theta_o * f32::exp(-inv_erf * inv_erf)) - u;
+        if f32::abs(value) < 1e-5 {
+            break;
+        }
+        if value > 0.0 {
+            c = b;
+        } else {
+            a = b;
+        }
+        b -= value / (normalization * (1.0 - inv_erf * tan_theta_o));
+    }
+    (linalg::erf_inv(b), linalg::erf_inv(2.0 * f32::max(samples.1, 1e-6) - 1.0))
+}
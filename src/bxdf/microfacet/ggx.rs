@@ -6,42 +6,104 @@ use std::f32;
 
 use bxdf;
 use linalg::{self, Vector};
-use bxdf::microfacet::MicrofacetDistribution;
+use bxdf::microfacet::{self, albedo, MicrofacetDistribution};
 
 /// GGX microfacet distribution with Smith shadowing-masking. This is the
 /// microfacet model described by [Walter et al.](https://www.cs.cornell.edu/~srm/publications/EGSR07-btdf.pdf)
+/// The distribution can be anisotropic, with different widths along the x and y
+/// axes of the shading space, see Burley, "Physically Based Shading at Disney", 2012.
+#[derive(Clone, Copy, Debug)]
 pub struct GGX {
-    width: f32,
+    width_x: f32,
+    width_y: f32,
+    /// Whether to sample only the microfacet normals visible from the outgoing direction
+    sample_visible: bool,
 }
 
 impl GGX {
     /// Create a new GGX distribution with the desired width
     pub fn new(w: f32) -> GGX {
-        let roughness = f32::max(w, 0.000001);
-        GGX { width: roughness }
+        GGX::anisotropic(w, w)
+    }
+    /// Create a new anisotropic GGX distribution with the width `w_x` along the x axis
+    /// of the shading space, i.e. along dp_du, and `w_y` along the y axis
+    pub fn anisotropic(w_x: f32, w_y: f32) -> GGX {
+        GGX { width_x: f32::max(w_x, 0.000001), width_y: f32::max(w_y, 0.000001), sample_visible: true }
+    }
+    /// Choose whether to sample only the microfacet normals visible from the outgoing direction,
+    /// which is the default, or the full distribution of normals
+    pub fn sample_visible(self, sample_visible: bool) -> GGX {
+        GGX { sample_visible: sample_visible, .. self }
+    }
+    /// Compute the width used to look up the albedo of the distribution, anisotropic distributions
+    /// are treated as an isotropic one with the geometric mean of their widths
+    fn albedo_width(&self) -> f32 {
+        f32::sqrt(self.width_x * self.width_y)
+    }
+    /// Compute the squared width of the distribution along the azimuthal direction of `v`
+    fn width_sqr(&self, v: &Vector) -> f32 {
+        f32::powf(bxdf::cos_phi(v) * self.width_x, 2.0) + f32::powf(bxdf::sin_phi(v) * self.width_y, 2.0)
+    }
+    /// Sample a microfacet normal visible from `w_o`, which must be in the upper hemisphere, using
+    /// the method from Heitz, "Sampling the GGX Distribution of Visible Normals", 2018. Stretching
+    /// the distribution to a width of 1 makes it the distribution of normals on a hemisphere, so
+    /// we sample the hemisphere's projected area seen from the stretched direction instead
+    fn sample_visible_normal(&self, w_o: &Vector, samples: &(f32, f32)) -> Vector {
+        let v = Vector::new(self.width_x * w_o.x, self.width_y * w_o.y, w_o.z).normalized();
+        // Build an orthonormal basis around the stretched direction
+        let len_sqr = v.x * v.x + v.y * v.y;
+        let t1 =
+            if len_sqr > 0.0 {
+                Vector::new(-v.y, v.x, 0.0) / f32::sqrt(len_sqr)
+            } else {
+                Vector::new(1.0, 0.0, 0.0)
+            };
+        let t2 = linalg::cross(&v, &t1);
+        // Sample the projected area, a disk with the half of it behind the hemisphere squashed
+        // to the ellipse it projects to
+        let r = f32::sqrt(samples.0);
+        let phi = 2.0 * f32::consts::PI * samples.1;
+        let p1 = r * f32::cos(phi);
+        let s = 0.5 * (1.0 + v.z);
+        let p2 = (1.0 - s) * f32::sqrt(1.0 - p1 * p1) + s * r * f32::sin(phi);
+        let n = p1 * t1 + p2 * t2 + f32::sqrt(f32::max(0.0, 1.0 - p1 * p1 - p2 * p2)) * v;
+        // Unstretch the normal back to the distribution
+        Vector::new(self.width_x * n.x, self.width_y * n.y, f32::max(0.0, n.z)).normalized()
     }
 }
 
 impl MicrofacetDistribution for GGX {
     fn normal_distribution(&self, w_h: &Vector) -> f32 {
         if bxdf::cos_theta(w_h) > 0.0 {
-            let width_sqr = f32::powf(self.width, 2.0);
-            let denom = f32::consts::PI * f32::powf(bxdf::cos_theta(w_h), 4.0)
-                * f32::powf(width_sqr + f32::powf(bxdf::tan_theta(w_h), 2.0), 2.0);
-            width_sqr / denom
+            let e = f32::powf(bxdf::tan_theta(w_h), 2.0)
+                * (f32::powf(bxdf::cos_phi(w_h) / self.width_x, 2.0)
+                   + f32::powf(bxdf::sin_phi(w_h) / self.width_y, 2.0));
+            let denom = f32::consts::PI * self.width_x * self.width_y * f32::powf(bxdf::cos_theta(w_h), 4.0)
+                * f32::powf(1.0 + e, 2.0);
+            1.0 / denom
         } else {
             0.0
         }
     }
-    fn sample(&self, _: &Vector, samples: &(f32, f32)) -> Vector {
-        let tan_theta_sqr = f32::powf(self.width * f32::sqrt(samples.0) / f32::sqrt(1.0 - samples.0), 2.0);
+    fn sample(&self, w_o: &Vector, samples: &(f32, f32)) -> Vector {
+        if self.sample_visible {
+            // The visible normals are sampled for a direction in the upper hemisphere, the
+            // BxDFs flip the normal to the side of `w_o` as they do for the full distribution
+            let w_o = if bxdf::cos_theta(w_o) < 0.0 { -*w_o } else { *w_o };
+            return self.sample_visible_normal(&w_o, samples);
+        }
+        let (phi, width_sqr) = microfacet::sample_azimuth(self.width_x, self.width_y, samples.1);
+        let tan_theta_sqr = width_sqr * samples.0 / (1.0 - samples.0);
         let cos_theta = 1.0 / f32::sqrt(1.0 + tan_theta_sqr);
         let sin_theta = f32::sqrt(f32::max(0.0, 1.0 - cos_theta * cos_theta));
-        let phi = 2.0 * f32::consts::PI * samples.1;
         linalg::spherical_dir(sin_theta, cos_theta, phi)
     }
-    fn pdf(&self, w_h: &Vector) -> f32 {
-        f32::abs(bxdf::cos_theta(w_h)) * self.normal_distribution(w_h)
+    fn pdf(&self, w_o: &Vector, w_h: &Vector) -> f32 {
+        if self.sample_visible {
+            microfacet::visible_pdf(self, w_o, w_h)
+        } else {
+            f32::abs(bxdf::cos_theta(w_h)) * self.normal_distribution(w_h)
+        }
     }
     fn shadowing_masking(&self, w_i: &Vector, w_o: &Vector, w_h: &Vector) -> f32 {
         self.monodir_shadowing(w_i, w_h) * self.monodir_shadowing(w_o, w_h)
@@ -51,11 +113,22 @@ impl MicrofacetDistribution for GGX {
     /// `w` is the incident/outgoing light direction and `w_h` is the microfacet normal
     fn monodir_shadowing(&self, v: &Vector, w_h: &Vector) -> f32 {
         if linalg::dot(v, w_h) / bxdf::cos_theta(v) > 0.0 {
-            2.0 / (1.0 + f32::sqrt(1.0 + f32::powf(self.width * bxdf::tan_theta(v), 2.0)))
+            2.0 / (1.0 + f32::sqrt(1.0 + self.width_sqr(v) * f32::powf(bxdf::tan_theta(v), 2.0)))
         } else {
             0.0
         }
     }
+    fn albedo(&self, cos_theta: f32, eta: Option<f32>) -> f32 {
+        match eta {
+            Some(eta) => albedo::ggx_dielectric().albedo(f32::abs(cos_theta), self.albedo_width(), eta),
+            None => albedo::ggx().albedo(f32::abs(cos_theta), self.albedo_width(), 1.0),
+        }
+    }
+    fn average_albedo(&self, eta: Option<f32>) -> f32 {
+        match eta {
+            Some(eta) => albedo::ggx_dielectric().average(self.albedo_width(), eta),
+            None => albedo::ggx().average(self.albedo_width(), 1.0),
+        }
+    }
 }
 
-
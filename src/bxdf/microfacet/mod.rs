@@ -1,11 +1,23 @@
 //! Module providing various microfacet distribution functions and trait that's
 //! implemented by all provided distributions
+//!
+//! By default the distributions sample only the microfacet normals visible from the
+//! outgoing direction, see Heitz and d'Eon, "Importance Sampling Microfacet-Based BSDFs
+//! using the Distribution of Visible Normals", 2014. This avoids sampling normals facing
+//! away from the viewer, which give directions below the surface or are weighted down by
+//! the shadowing term, so the sampled directions have much lower variance at grazing
+//! angles and high roughness. Sampling the full distribution of normals can still be
+//! selected with `sample_visible(false)` on the distribution for comparison.
 
-use linalg::Vector;
+use std::f32;
+
+use bxdf;
+use linalg::{self, Vector};
 
 pub use self::beckmann::Beckmann;
 pub use self::ggx::GGX;
 
+pub mod albedo;
 pub mod beckmann;
 pub mod ggx;
 
@@ -17,9 +29,9 @@ pub trait MicrofacetDistribution {
     /// Sample the distribution for some outgoing light direction `w_o`.
     /// returns the sampled microfacet normal
     fn sample(&self, w_o: &Vector, samples: &(f32, f32)) -> Vector;
-    /// Compute the probability of sampling a certain microfacet normal
-    /// from the distribution, `w_h`
-    fn pdf(&self, w_h: &Vector) -> f32;
+    /// Compute the probability of sampling the microfacet normal `w_h` from the
+    /// distribution for the outgoing light direction `w_o`
+    fn pdf(&self, w_o: &Vector, w_h: &Vector) -> f32;
     /// Compute the shadowing masking function for the incident and outgoing
     /// directions `w_i` and `w_o` for microfacets with normal `w_h`.
     /// Returns what fraction of the microfacets with the normal are visible
@@ -28,5 +40,130 @@ pub trait MicrofacetDistribution {
     /// Return the monodirectional shadowing function, G_1
     /// `v` is the reflected/incident direction, `w_h` is the microfacet normal
     fn monodir_shadowing(&self, v: &Vector, w_h: &Vector) -> f32;
+    /// Look up the albedo of a white microfacet BxDF with this distribution for light leaving
+    /// along a direction with `cos_theta`, see the `albedo` module. `eta` is None for the BRDF
+    /// of a conductor or the refractive index of a dielectric for its BRDF and BTDF together
+    fn albedo(&self, cos_theta: f32, eta: Option<f32>) -> f32;
+    /// Look up the albedo of a white microfacet BxDF with this distribution averaged over
+    /// the hemisphere, `eta` is used as in `albedo`
+    fn average_albedo(&self, eta: Option<f32>) -> f32;
+}
+
+/// Compute the pdf of sampling the microfacet normal `w_h` from the distribution of normals
+/// visible from `w_o`, i.e. the distribution weighted by the projected area of the
+/// microfacets in the direction `w_o` which aren't masked by other microfacets
+fn visible_pdf<M: MicrofacetDistribution>(microfacet: &M, w_o: &Vector, w_h: &Vector) -> f32 {
+    let cos_theta_o = f32::abs(bxdf::cos_theta(w_o));
+    if cos_theta_o == 0.0 {
+        0.0
+    } else {
+        microfacet.monodir_shadowing(w_o, w_h) * f32::abs(linalg::dot(w_o, w_h))
+            * microfacet.normal_distribution(w_h) / cos_theta_o
+    }
+}
+
+/// Sample the azimuthal angle of a microfacet normal for a distribution with widths
+/// `width_x` and `width_y` along the x and y axes using the sample `u`. Returns the
+/// angle and the squared width of the distribution along it
+fn sample_azimuth(width_x: f32, width_y: f32, u: f32) -> (f32, f32) {
+    if width_x == width_y {
+        (2.0 * f32::consts::PI * u, width_x * width_x)
+    } else {
+        // Sample the azimuth from the elliptical cross section of the distribution,
+        // tan gives us phi in [-pi/2, pi/2] so shift it to the right half of the circle
+        let mut phi = f32::atan(width_y / width_x * f32::tan(2.0 * f32::consts::PI * u + 0.5 * f32::consts::PI));
+        if u > 0.5 {
+            phi += f32::consts::PI;
+        }
+        let width_sqr = 1.0 / (f32::powf(f32::cos(phi) / width_x, 2.0) + f32::powf(f32::sin(phi) / width_y, 2.0));
+        (phi, width_sqr)
+    }
+}
+
+#[test]
+fn test_anisotropic_normalized() {
+    use linalg;
+    // The projected area of the microfacets must equal the area of the surface,
+    // i.e. the distribution times cos theta integrates to 1 over the hemisphere
+    let distributions = [Box::new(Beckmann::anisotropic(0.1, 0.4)) as Box<MicrofacetDistribution>,
+                         Box::new(GGX::anisotropic(0.3, 0.05)) as Box<MicrofacetDistribution>];
+    for d in &distributions {
+        let (n_theta, n_phi) = (2000, 400);
+        let mut area = 0.0;
+        for i in 0..n_theta {
+            let theta = (i as f32 + 0.5) / n_theta as f32 * 0.5 * f32::consts::PI;
+            for j in 0..n_phi {
+                let phi = (j as f32 + 0.5) / n_phi as f32 * 2.0 * f32::consts::PI;
+                let w_h = linalg::spherical_dir(f32::sin(theta), f32::cos(theta), phi);
+                area += d.normal_distribution(&w_h) * f32::cos(theta) * f32::sin(theta);
+            }
+        }
+        area *= 0.5 * f32::consts::PI / n_theta as f32 * 2.0 * f32::consts::PI / n_phi as f32;
+        assert!(f32::abs(area - 1.0) < 0.01, "projected microfacet area is {}", area);
+    }
 }
 
+#[test]
+fn test_sampling_chi2() {
+    use rand::{Rng, SeedableRng, StdRng};
+    // Check that the sampled microfacet normals follow the pdf with a chi-square goodness-of-fit
+    // test, binning the normals by theta and phi and integrating the pdf over each bin to find
+    // the number of samples expected in it
+    let distributions = [Box::new(Beckmann::new(0.3)) as Box<MicrofacetDistribution>,
+                         Box::new(Beckmann::anisotropic(0.15, 0.6)) as Box<MicrofacetDistribution>,
+                         Box::new(Beckmann::new(0.3).sample_visible(false)) as Box<MicrofacetDistribution>,
+                         Box::new(GGX::new(0.3)) as Box<MicrofacetDistribution>,
+                         Box::new(GGX::anisotropic(0.6, 0.15)) as Box<MicrofacetDistribution>,
+                         Box::new(GGX::new(0.3).sample_visible(false)) as Box<MicrofacetDistribution>];
+    let (n_theta, n_phi, n_samples) = (20, 40, 100000);
+    let theta_step = 0.5 * f32::consts::PI / n_theta as f32;
+    let phi_step = 2.0 * f32::consts::PI / n_phi as f32;
+    let mut rng: StdRng = SeedableRng::from_seed(&[1][..]);
+    for (k, d) in distributions.iter().enumerate() {
+        for &cos_o in &[1.0, 0.8, 0.4, 0.1] {
+            let w_o = linalg::spherical_dir(f32::sqrt(1.0 - cos_o * cos_o), cos_o, 1.0);
+            let mut observed = vec![0.0; n_theta * n_phi];
+            for _ in 0..n_samples {
+                let w_h = d.sample(&w_o, &(rng.next_f32(), rng.next_f32()));
+                let i = f32::min(linalg::spherical_theta(&w_h) / theta_step, n_theta as f32 - 1.0) as usize;
+                let j = f32::min(linalg::spherical_phi(&w_h) / phi_step, n_phi as f32 - 1.0) as usize;
+                observed[i * n_phi + j] += 1.0;
+            }
+            let mut expected = vec![0.0; n_theta * n_phi];
+            let res = 8;
+            for i in 0..n_theta * res {
+                let theta = (i as f32 + 0.5) * theta_step / res as f32;
+                for j in 0..n_phi * res {
+                    let phi = (j as f32 + 0.5) * phi_step / res as f32;
+                    let w_h = linalg::spherical_dir(f32::sin(theta), f32::cos(theta), phi);
+                    expected[(i / res) * n_phi + j / res] += d.pdf(&w_o, &w_h) * f32::sin(theta)
+                        * theta_step * phi_step / (res * res) as f32 * n_samples as f32;
+                }
+            }
+            // Bins expected to get only a few samples are pooled together, the chi-square
+            // distribution is a poor approximation of the statistic for them
+            let (mut chi2, mut dof) = (0.0, 0);
+            let (mut pool_observed, mut pool_expected) = (0.0, 0.0);
+            for (o, e) in observed.iter().zip(expected.iter()) {
+                if *e < 5.0 {
+                    pool_observed += *o;
+                    pool_expected += *e;
+                } else {
+                    chi2 += (o - e) * (o - e) / e;
+                    dof += 1;
+                }
+            }
+            if pool_expected > 0.0 {
+                let diff = pool_observed - pool_expected;
+                chi2 += diff * diff / f32::max(pool_expected, 5.0);
+                dof += 1;
+            }
+            // Find the critical value for a significance level of 0.01% with the Wilson-Hilferty
+            // approximation of the chi-square distribution with dof - 1 degrees of freedom
+            let dof = (dof - 1) as f32;
+            let critical = dof * f32::powf(1.0 - 2.0 / (9.0 * dof) + 3.719 * f32::sqrt(2.0 / (9.0 * dof)), 3.0);
+            assert!(chi2 < critical, "distribution {} at cos_o {} has chi-square {} above the critical value {}",
+                    k, cos_o, chi2, critical);
+        }
+    }
+}
@@ -9,23 +9,51 @@ use linalg::{self, Vector};
 use film::Colorf;
 use bxdf::{self, BxDF, BxDFType};
 use bxdf::fresnel::{Dielectric, Fresnel};
-use bxdf::microfacet::{MicrofacetDistribution};
+use bxdf::microfacet::{albedo, MicrofacetDistribution};
+use mc;
 
 /// Struct providing the microfacet BTDF, implemented as described in
 /// [Walter et al. 07](https://www.cs.cornell.edu/~srm/publications/EGSR07-btdf.pdf)
-pub struct MicrofacetTransmission {
+#[derive(Clone, Copy)]
+pub struct MicrofacetTransmission<'a> {
     reflectance: Colorf,
     fresnel: Dielectric,
     /// Microfacet distribution describing the structure of the microfacets of
     /// the material
-    microfacet: Box<MicrofacetDistribution + Send + Sync>,
+    microfacet: &'a MicrofacetDistribution,
+    /// Scale of the lobe transmitting the energy lost to light scattering more than
+    /// once between the microfacets, if energy compensation is enabled
+    multiple_scattering: Option<Colorf>,
 }
 
-impl MicrofacetTransmission {
+impl<'a> MicrofacetTransmission<'a> {
     /// Create a new transmissive microfacet BRDF
-    pub fn new(c: &Colorf, fresnel: Dielectric,
-               microfacet: Box<MicrofacetDistribution + Send + Sync>) -> MicrofacetTransmission {
-        MicrofacetTransmission { reflectance: *c, fresnel: fresnel, microfacet: microfacet }
+    pub fn new(c: &Colorf, fresnel: Dielectric, microfacet: &'a MicrofacetDistribution)
+               -> MicrofacetTransmission<'a> {
+        MicrofacetTransmission { reflectance: *c, fresnel: fresnel, microfacet: microfacet,
+                                 multiple_scattering: None }
+    }
+    /// Choose whether to add back the energy lost to multiple scattering between the microfacets,
+    /// see `bxdf::microfacet::albedo`. The energy lost by the reflection and transmission together
+    /// is split between them by the average Fresnel term, this BTDF transmits its share and the
+    /// reflected share is added back by the surface's `TorranceSparrow` BRDF, see
+    /// `TorranceSparrow::dielectric_energy_compensation`
+    pub fn energy_compensation(self, compensate: bool) -> MicrofacetTransmission<'a> {
+        let multiple_scattering =
+            if compensate {
+                let e_avg = self.microfacet.average_albedo(Some(self.relative_eta()));
+                let f_avg = albedo::average_fresnel(&self.fresnel);
+                Some(self.reflectance * (Colorf::broadcast(1.0) - f_avg)
+                     / (f32::consts::PI * f32::max(1.0 - e_avg, 0.0001)))
+            } else {
+                None
+            };
+        MicrofacetTransmission { multiple_scattering: multiple_scattering, .. self }
+    }
+    /// Get the refractive index of the material relative to the one outside it, used to look
+    /// up its albedo for energy compensation
+    fn relative_eta(&self) -> f32 {
+        self.fresnel.eta_t / self.fresnel.eta_i
     }
     /// Convenience method for getting `eta_i` and `eta_t` in the right order for if
     /// we're entering or exiting this material based on the direction of the outgoing
@@ -37,36 +65,21 @@ impl MicrofacetTransmission {
             (self.fresnel.eta_t, self.fresnel.eta_i)
         }
     }
-    /// Compute the Jacobian for the change of variables (see [Walter et al 07] section 4.2),
-    /// here we compute equation 17 in that section.
+    /// Compute the Jacobian for the change of variables from the microfacet normal to the
+    /// transmitted direction `w_i` (see [Walter et al 07] section 4.2), here we compute
+    /// equation 17 in that section.
     fn jacobian(w_o: &Vector, w_i: &Vector, w_h: &Vector, eta: (f32, f32)) -> f32 {
         let wi_dot_h = linalg::dot(w_i, w_h);
         let wo_dot_h = linalg::dot(w_o, w_h);
-        let denom = f32::powf(eta.0 * wi_dot_h + eta.1 * wo_dot_h, 2.0);
+        let denom = f32::powf(eta.0 * wo_dot_h + eta.1 * wi_dot_h, 2.0);
         if denom == 0.0 {
             0.0
         } else {
-            f32::powf(eta.1, 2.0) * f32::abs(wo_dot_h) / denom
+            f32::powf(eta.1, 2.0) * f32::abs(wi_dot_h) / denom
         }
     }
-    fn half_vector(w_o: &Vector, w_i: &Vector, eta: (f32, f32)) -> Option<Vector> {
-        let w_h = -eta.0 * *w_o - eta.1 * *w_i;
-        if w_h.x == 0.0 && w_h.y == 0.0 && w_h.z == 0.0 {
-            None
-        } else {
-            Some(w_h.normalized())
-        }
-    }
-}
-
-impl BxDF for MicrofacetTransmission {
-    fn bxdf_type(&self) -> EnumSet<BxDFType> {
-        let mut e = EnumSet::new();
-        e.insert(BxDFType::Glossy);
-        e.insert(BxDFType::Transmission);
-        e
-    }
-    fn eval(&self, w_o: &Vector, w_i: &Vector) -> Colorf {
+    /// Evaluate the single scattering BTDF for light arriving along `w_i` leaving along `w_o`
+    fn single_scattering(&self, w_o: &Vector, w_i: &Vector) -> Colorf {
         if bxdf::same_hemisphere(w_o, w_i) {
             return Colorf::black();
         }
@@ -80,44 +93,113 @@ impl BxDF for MicrofacetTransmission {
             let d = self.microfacet.normal_distribution(&w_h);
             let f = Colorf::broadcast(1.0) - self.fresnel.fresnel(linalg::dot(w_o, &w_h));
             let g = self.microfacet.shadowing_masking(w_i, w_o, &w_h);
-            let wi_dot_h = linalg::dot(w_i, &w_h);
+            let wo_dot_h = linalg::dot(w_o, &w_h);
             let jacobian = MicrofacetTransmission::jacobian(w_o, w_i, &w_h, eta);
-            self.reflectance * (f32::abs(wi_dot_h) / (f32::abs(w_i.z) * f32::abs(w_o.z)))
+            self.reflectance * (f32::abs(wo_dot_h) / (f32::abs(w_i.z) * f32::abs(w_o.z)))
                 * (f * g * d) * jacobian
         } else {
             Colorf::black()
         }
     }
-    fn sample(&self, w_o: &Vector, samples: &(f32, f32)) -> (Colorf, Vector, f32) {
+    /// Evaluate the lobe transmitting the energy lost to multiple scattering, which transmits
+    /// light like a diffuse surface weighted by the energy missing along `w_o` and `w_i`
+    fn multiple_scattering(&self, w_o: &Vector, w_i: &Vector) -> Colorf {
+        match self.multiple_scattering {
+            Some(c) if bxdf::cos_theta(w_o) > 0.0 && bxdf::cos_theta(w_i) < 0.0 => {
+                c * f32::max(1.0 - self.microfacet.albedo(bxdf::cos_theta(w_o), Some(self.relative_eta())), 0.0)
+                    * f32::max(1.0 - self.microfacet.albedo(bxdf::cos_theta(w_i), Some(self.relative_eta())), 0.0)
+            },
+            _ => Colorf::black(),
+        }
+    }
+    /// Get the probability of sampling the multiple scattering lobe for light leaving along `w_o`
+    fn multiple_scattering_prob(&self, w_o: &Vector) -> f32 {
+        if self.multiple_scattering.is_some() && bxdf::cos_theta(w_o) > 0.0 {
+            f32::max(1.0 - self.microfacet.albedo(bxdf::cos_theta(w_o), Some(self.relative_eta())), 0.0)
+        } else {
+            0.0
+        }
+    }
+    /// Sample an incident direction from the single scattering BTDF, returns the direction
+    /// and its pdf or None if no light is transmitted
+    fn sample_single_scattering(&self, w_o: &Vector, samples: &(f32, f32)) -> Option<(Vector, f32)> {
         let mut w_h = self.microfacet.sample(w_o, samples);
         if !bxdf::same_hemisphere(w_o, &w_h) {
             w_h = -w_h;
         }
         let eta = self.eta_for_interaction(w_o);
-        if let Some(w_i) = linalg::refract(w_o, &w_h, eta.0, eta.1) {
-            if bxdf::same_hemisphere(w_o, &w_i) {
-                (Colorf::black(), Vector::broadcast(0.0), 0.0)
-            } else {
-                let pdf = self.microfacet.pdf(&w_h)
-                    * MicrofacetTransmission::jacobian(w_o, &w_i, &w_h, eta);
-                (self.eval(w_o, &w_i), w_i, pdf)
-            }
-        } else {
-            (Colorf::black(), Vector::broadcast(0.0), 0.0)
+        match linalg::refract(w_o, &w_h, eta.0, eta.1) {
+            Some(w_i) if !bxdf::same_hemisphere(w_o, &w_i) => {
+                Some((w_i, self.microfacet.pdf(w_o, &w_h) * MicrofacetTransmission::jacobian(w_o, &w_i, &w_h, eta)))
+            },
+            _ => None,
         }
     }
-    fn pdf(&self, w_o: &Vector, w_i: &Vector) -> f32 {
+    /// Compute the pdf of sampling `w_i` from the single scattering BTDF
+    fn single_scattering_pdf(&self, w_o: &Vector, w_i: &Vector) -> f32 {
         if bxdf::same_hemisphere(w_o, w_i) {
             0.0
         } else {
             let eta = self.eta_for_interaction(w_o);
             if let Some(w_h) = MicrofacetTransmission::half_vector(w_o, w_i, eta) {
-                self.microfacet.pdf(&w_h) * MicrofacetTransmission::jacobian(w_o, w_i, &w_h, eta)
+                self.microfacet.pdf(w_o, &w_h) * MicrofacetTransmission::jacobian(w_o, w_i, &w_h, eta)
             } else {
                 0.0
             }
         }
     }
+    fn half_vector(w_o: &Vector, w_i: &Vector, eta: (f32, f32)) -> Option<Vector> {
+        let w_h = -eta.0 * *w_o - eta.1 * *w_i;
+        if w_h.x == 0.0 && w_h.y == 0.0 && w_h.z == 0.0 {
+            None
+        } else {
+            Some(w_h.normalized())
+        }
+    }
 }
 
-
+impl<'a> BxDF for MicrofacetTransmission<'a> {
+    fn bxdf_type(&self) -> EnumSet<BxDFType> {
+        let mut e = EnumSet::new();
+        e.insert(BxDFType::Glossy);
+        e.insert(BxDFType::Transmission);
+        e
+    }
+    fn eval(&self, w_o: &Vector, w_i: &Vector) -> Colorf {
+        self.single_scattering(w_o, w_i) + self.multiple_scattering(w_o, w_i)
+    }
+    fn sample(&self, w_o: &Vector, samples: &(f32, f32)) -> (Colorf, Vector, f32) {
+        let ms_prob = self.multiple_scattering_prob(w_o);
+        if ms_prob == 0.0 {
+            match self.sample_single_scattering(w_o, samples) {
+                Some((w_i, pdf)) => (self.eval(w_o, &w_i), w_i, pdf),
+                None => (Colorf::black(), Vector::broadcast(0.0), 0.0),
+            }
+        } else {
+            // Pick which lobe to sample with the first sample and re-scale it to use for the lobe
+            let w_i =
+                if samples.0 < ms_prob {
+                    let w = mc::cos_sample_hemisphere(&(samples.0 / ms_prob, samples.1));
+                    Some(Vector::new(w.x, w.y, -w.z))
+                } else {
+                    let s = (f32::min((samples.0 - ms_prob) / (1.0 - ms_prob), 0.99999), samples.1);
+                    self.sample_single_scattering(w_o, &s).map(|(w_i, _)| w_i)
+                };
+            match w_i {
+                Some(w_i) => (self.eval(w_o, &w_i), w_i, self.pdf(w_o, &w_i)),
+                None => (Colorf::black(), Vector::broadcast(0.0), 0.0),
+            }
+        }
+    }
+    fn pdf(&self, w_o: &Vector, w_i: &Vector) -> f32 {
+        let ms_prob = self.multiple_scattering_prob(w_o);
+        let pdf = self.single_scattering_pdf(w_o, w_i);
+        if ms_prob == 0.0 {
+            pdf
+        } else if bxdf::cos_theta(w_i) < 0.0 {
+            (1.0 - ms_prob) * pdf + ms_prob * mc::cos_hemisphere_pdf(-bxdf::cos_theta(w_i))
+        } else {
+            (1.0 - ms_prob) * pdf
+        }
+    }
+}
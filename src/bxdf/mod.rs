@@ -18,6 +18,10 @@ pub use self::specular_transmission::SpecularTransmission;
 pub use self::merl::Merl;
 pub use self::torrance_sparrow::TorranceSparrow;
 pub use self::microfacet_transmission::MicrofacetTransmission;
+pub use self::disney_diffuse::DisneyDiffuse;
+pub use self::disney_sheen::DisneySheen;
+pub use self::disney_clearcoat::DisneyClearcoat;
+pub use self::coated::Coated;
 
 pub mod bsdf;
 pub mod lambertian;
@@ -29,6 +33,10 @@ pub mod merl;
 pub mod microfacet;
 pub mod torrance_sparrow;
 pub mod microfacet_transmission;
+pub mod disney_diffuse;
+pub mod disney_sheen;
+pub mod disney_clearcoat;
+pub mod coated;
 
 /// Various types of BxDFs that can be selected to specify which
 /// types of surface functions should be evaluated
@@ -9,34 +9,61 @@ use linalg::{self, Vector};
 use film::Colorf;
 use bxdf::{self, BxDF, BxDFType};
 use bxdf::fresnel::Fresnel;
-use bxdf::microfacet::{MicrofacetDistribution};
+use bxdf::microfacet::{albedo, MicrofacetDistribution};
+use mc;
 
 /// Struct providing the Torrance Sparrow BRDF, implemented as described in
 /// [Walter et al. 07](https://www.cs.cornell.edu/~srm/publications/EGSR07-btdf.pdf)
-pub struct TorranceSparrow {
+#[derive(Clone, Copy)]
+pub struct TorranceSparrow<'a> {
     reflectance: Colorf,
-    fresnel: Box<Fresnel + Send + Sync>,
+    fresnel: &'a Fresnel,
     /// Microfacet distribution describing the structure of the microfacets of
     /// the material
-    microfacet: Box<MicrofacetDistribution + Send + Sync>,
+    microfacet: &'a MicrofacetDistribution,
+    /// Scale of the lobe adding back the energy lost to light scattering more than
+    /// once between the microfacets, if energy compensation is enabled
+    multiple_scattering: Option<Colorf>,
+    /// Refractive index of the dielectric the BRDF reflects off, if it's compensating
+    /// for the energy lost by a dielectric instead of a conductor
+    albedo_eta: Option<f32>,
 }
 
-impl TorranceSparrow {
+impl<'a> TorranceSparrow<'a> {
     /// Create a new Torrance Sparrow microfacet BRDF
-    pub fn new(c: &Colorf, fresnel: Box<Fresnel + Send + Sync>,
-               microfacet: Box<MicrofacetDistribution + Send + Sync>) -> TorranceSparrow {
-        TorranceSparrow { reflectance: *c, fresnel: fresnel, microfacet: microfacet }
+    pub fn new(c: &Colorf, fresnel: &'a Fresnel, microfacet: &'a MicrofacetDistribution) -> TorranceSparrow<'a> {
+        TorranceSparrow { reflectance: *c, fresnel: fresnel, microfacet: microfacet, multiple_scattering: None,
+                          albedo_eta: None }
     }
-}
-
-impl BxDF for TorranceSparrow {
-    fn bxdf_type(&self) -> EnumSet<BxDFType> {
-        let mut e = EnumSet::new();
-        e.insert(BxDFType::Glossy);
-        e.insert(BxDFType::Reflection);
-        e
+    /// Choose whether to add back the energy lost to multiple scattering between the microfacets,
+    /// which is otherwise lost making rough surfaces too dark. See `bxdf::microfacet::albedo`.
+    /// For a dielectric which also transmits light use `dielectric_energy_compensation` instead
+    pub fn energy_compensation(self, compensate: bool) -> TorranceSparrow<'a> {
+        let multiple_scattering =
+            if compensate {
+                // Light leaving after scattering multiple times has been attenuated by the Fresnel
+                // term at each bounce, eq. 15 in Kulla and Conty's 2017 course notes finds the
+                // average Fresnel term of this light from the average over the hemisphere
+                let e_avg = self.microfacet.average_albedo(None);
+                let f_avg = albedo::average_fresnel(self.fresnel);
+                let f_ms = f_avg * f_avg * e_avg / (Colorf::broadcast(1.0) - f_avg * (1.0 - e_avg));
+                Some(self.reflectance * f_ms / (f32::consts::PI * f32::max(1.0 - e_avg, 0.0001)))
+            } else {
+                None
+            };
+        TorranceSparrow { multiple_scattering: multiple_scattering, albedo_eta: None, .. self }
     }
-    fn eval(&self, w_o: &Vector, w_i: &Vector) -> Colorf {
+    /// Add back the share of the energy lost to multiple scattering which is reflected by a
+    /// dielectric with refractive index `eta`, for use with a `MicrofacetTransmission` BTDF
+    /// with energy compensation transmitting the rest
+    pub fn dielectric_energy_compensation(self, eta: f32) -> TorranceSparrow<'a> {
+        let e_avg = self.microfacet.average_albedo(Some(eta));
+        let f_avg = albedo::average_fresnel(self.fresnel);
+        let multiple_scattering = self.reflectance * f_avg / (f32::consts::PI * f32::max(1.0 - e_avg, 0.0001));
+        TorranceSparrow { multiple_scattering: Some(multiple_scattering), albedo_eta: Some(eta), .. self }
+    }
+    /// Evaluate the single scattering BRDF for light arriving along `w_i` leaving along `w_o`
+    fn single_scattering(&self, w_o: &Vector, w_i: &Vector) -> Colorf {
         let cos_to = f32::abs(bxdf::cos_theta(w_o));
         let cos_ti = f32::abs(bxdf::cos_theta(w_i));
         if cos_to == 0.0 || cos_ti == 0.0 {
@@ -50,25 +77,27 @@ impl BxDF for TorranceSparrow {
         let d = self.microfacet.normal_distribution(&w_h);
         let f = self.fresnel.fresnel(linalg::dot(w_i, &w_h));
         let g = self.microfacet.shadowing_masking(w_i, w_o, &w_h);
-        (self.reflectance * f * d * g / (4.0 * cos_ti * cos_to))
+        self.reflectance * f * d * g / (4.0 * cos_ti * cos_to)
     }
-    fn sample(&self, w_o: &Vector, samples: &(f32, f32)) -> (Colorf, Vector, f32) {
+    /// Sample an incident direction from the single scattering BRDF, returns the direction
+    /// and its pdf or None if the sampled direction is below the surface
+    fn sample_single_scattering(&self, w_o: &Vector, samples: &(f32, f32)) -> Option<(Vector, f32)> {
         let mut w_h = self.microfacet.sample(w_o, samples);
         if !bxdf::same_hemisphere(w_o, &w_h) {
             w_h = -w_h;
         }
         let w_i = linalg::reflect(w_o, &w_h);
         if !bxdf::same_hemisphere(w_o, &w_i) {
-            (Colorf::black(), Vector::broadcast(0.0), 0.0)
+            None
         } else {
             // This term is p_o(o) in eq. 38 of Walter et al's 07 paper and is for reflection so
             // we use the Jacobian for reflection, eq. 14
             let jacobian = 1.0 / (4.0 * f32::abs(linalg::dot(w_o, &w_h)));
-            let pdf = self.microfacet.pdf(&w_h) * jacobian;
-            (self.eval(w_o, &w_i), w_i, pdf)
+            Some((w_i, self.microfacet.pdf(w_o, &w_h) * jacobian))
         }
     }
-    fn pdf(&self, w_o: &Vector, w_i: &Vector) -> f32 {
+    /// Compute the pdf of sampling `w_i` from the single scattering BRDF
+    fn single_scattering_pdf(&self, w_o: &Vector, w_i: &Vector) -> f32 {
         if !bxdf::same_hemisphere(w_o, w_i) {
             0.0
         } else {
@@ -80,9 +109,115 @@ impl BxDF for TorranceSparrow {
                 // This term is p_o(o) in eq. 38 of Walter et al's 07 paper and is for reflection so
                 // we use the Jacobian for reflection, eq. 14
                 let jacobian = 1.0 / (4.0 * f32::abs(linalg::dot(w_o, &w_h)));
-                self.microfacet.pdf(&w_h.normalized()) * jacobian
+                self.microfacet.pdf(w_o, &w_h) * jacobian
             }
         }
     }
+    /// Evaluate the lobe adding back the energy lost to multiple scattering, which reflects
+    /// like a diffuse surface weighted by the energy missing along `w_o` and `w_i`
+    fn multiple_scattering(&self, w_o: &Vector, w_i: &Vector) -> Colorf {
+        match self.multiple_scattering {
+            Some(c) if bxdf::cos_theta(w_o) > 0.0 && bxdf::cos_theta(w_i) > 0.0 => {
+                c * f32::max(1.0 - self.microfacet.albedo(bxdf::cos_theta(w_o), self.albedo_eta), 0.0)
+                    * f32::max(1.0 - self.microfacet.albedo(bxdf::cos_theta(w_i), self.albedo_eta), 0.0)
+            },
+            _ => Colorf::black(),
+        }
+    }
+    /// Get the probability of sampling the multiple scattering lobe for light leaving along `w_o`,
+    /// which is the fraction of energy lost by the single scattering BRDF
+    fn multiple_scattering_prob(&self, w_o: &Vector) -> f32 {
+        if self.multiple_scattering.is_some() && bxdf::cos_theta(w_o) > 0.0 {
+            f32::max(1.0 - self.microfacet.albedo(bxdf::cos_theta(w_o), self.albedo_eta), 0.0)
+        } else {
+            0.0
+        }
+    }
 }
 
+impl<'a> BxDF for TorranceSparrow<'a> {
+    fn bxdf_type(&self) -> EnumSet<BxDFType> {
+        let mut e = EnumSet::new();
+        e.insert(BxDFType::Glossy);
+        e.insert(BxDFType::Reflection);
+        e
+    }
+    fn eval(&self, w_o: &Vector, w_i: &Vector) -> Colorf {
+        self.single_scattering(w_o, w_i) + self.multiple_scattering(w_o, w_i)
+    }
+    fn sample(&self, w_o: &Vector, samples: &(f32, f32)) -> (Colorf, Vector, f32) {
+        let ms_prob = self.multiple_scattering_prob(w_o);
+        if ms_prob == 0.0 {
+            match self.sample_single_scattering(w_o, samples) {
+                Some((w_i, pdf)) => (self.eval(w_o, &w_i), w_i, pdf),
+                None => (Colorf::black(), Vector::broadcast(0.0), 0.0),
+            }
+        } else {
+            // Pick which lobe to sample with the first sample and re-scale it to use for the lobe
+            let w_i =
+                if samples.0 < ms_prob {
+                    Some(mc::cos_sample_hemisphere(&(samples.0 / ms_prob, samples.1)))
+                } else {
+                    let s = (f32::min((samples.0 - ms_prob) / (1.0 - ms_prob), 0.99999), samples.1);
+                    self.sample_single_scattering(w_o, &s).map(|(w_i, _)| w_i)
+                };
+            match w_i {
+                Some(w_i) => (self.eval(w_o, &w_i), w_i, self.pdf(w_o, &w_i)),
+                None => (Colorf::black(), Vector::broadcast(0.0), 0.0),
+            }
+        }
+    }
+    fn pdf(&self, w_o: &Vector, w_i: &Vector) -> f32 {
+        let ms_prob = self.multiple_scattering_prob(w_o);
+        let pdf = self.single_scattering_pdf(w_o, w_i);
+        if ms_prob == 0.0 {
+            pdf
+        } else if bxdf::cos_theta(w_i) > 0.0 {
+            (1.0 - ms_prob) * pdf + ms_prob * mc::cos_hemisphere_pdf(bxdf::cos_theta(w_i))
+        } else {
+            (1.0 - ms_prob) * pdf
+        }
+    }
+}
+
+#[test]
+fn test_energy_compensation() {
+    use rand::{Rng, SeedableRng, StdRng};
+    use bxdf::fresnel::Schlick;
+    use bxdf::microfacet::{Beckmann, GGX};
+
+    // A white rough conductor loses a lot of energy to light scattering multiple times
+    // between the microfacets, with energy compensation it should reflect nearly all of
+    // the light it receives in a white furnace
+    let white = Schlick::new(&Colorf::broadcast(1.0));
+    let distributions = [Box::new(Beckmann::new(0.4)) as Box<MicrofacetDistribution>,
+                         Box::new(Beckmann::new(1.0)) as Box<MicrofacetDistribution>,
+                         Box::new(GGX::new(0.3)) as Box<MicrofacetDistribution>,
+                         Box::new(GGX::new(1.0)) as Box<MicrofacetDistribution>];
+    let mut rng: StdRng = SeedableRng::from_seed(&[1][..]);
+    let albedo = |brdf: &TorranceSparrow, w_o: &Vector, rng: &mut StdRng| {
+        let n = 20000;
+        let mut albedo = 0.0;
+        for _ in 0..n {
+            let (f, w_i, pdf) = brdf.sample(w_o, &(rng.next_f32(), rng.next_f32()));
+            if pdf > 0.0 {
+                albedo += f.r * f32::abs(bxdf::cos_theta(&w_i)) / pdf;
+            }
+        }
+        albedo / n as f32
+    };
+    for (i, d) in distributions.iter().enumerate() {
+        let single = TorranceSparrow::new(&Colorf::broadcast(1.0), &white, &**d);
+        let compensated = single.energy_compensation(true);
+        for &cos_o in &[1.0, 0.6, 0.2] {
+            let w_o = Vector::new(f32::sqrt(1.0 - cos_o * cos_o), 0.0, cos_o);
+            let a = albedo(&compensated, &w_o, &mut rng);
+            assert!(f32::abs(a - 1.0) < 0.02, "distribution {} at cos_o {} reflects {}", i, cos_o, a);
+            // Check the roughest surfaces do lose a noticeable amount of energy without compensation
+            if i % 2 == 1 {
+                let a = albedo(&single, &w_o, &mut rng);
+                assert!(a < 0.9, "distribution {} at cos_o {} reflects {} without compensation", i, cos_o, a);
+            }
+        }
+    }
+}
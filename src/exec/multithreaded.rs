@@ -1,18 +1,20 @@
 //! The multithreaded module provides a multithreaded execution for rendering
 //! the image.
 
-use std::iter;
+use std::{iter, cmp};
 use std::time::SystemTime;
 
 use scoped_threadpool::Pool;
 use rand::StdRng;
 
 use sampler::BlockQueue;
-use film::{RenderTarget, ImageSample, Colorf};
-use geometry::{Instance, Emitter};
+use film::{RenderTarget, ImageSample};
+use geometry::Instance;
+use light::LightList;
 use sampler::{self, Sampler};
 use scene::Scene;
 use exec::{Config, Exec};
+use memory::MemoryArena;
 
 /// The `MultiThreaded` execution uses a configurable number of threads in
 /// a threadpool to render each frame
@@ -28,25 +30,43 @@ impl MultiThreaded {
     /// Launch a rendering job in parallel across the threads and wait for it to finish
     fn render_parallel(&mut self, scene: &Scene, rt: &RenderTarget, config: &Config) {
         let dim = rt.dimensions();
-        let block_queue = BlockQueue::new((dim.0 as u32, dim.1 as u32), (8, 8), config.select_blocks);
-        let light_list: Vec<_> = scene.bvh.iter().filter_map(|x| {
+        let lights: Vec<_> = scene.bvh.iter().filter_map(|x| {
             match *x {
                 Instance::Emitter(ref e) => Some(e),
                 _ => None,
             }
-        }).collect();
-        assert!(!light_list.is_empty(), "At least one light is required");
+        }).chain(scene.infinite_lights.iter()).collect();
+        // Build the distribution (and light BVH if requested) to choose lights
+        // from over the frame's shutter time
+        let shutter = scene.active_camera().shutter_time();
+        let light_list = LightList::new(lights, scene.light_sampling, shutter.0, shutter.1);
         let n = self.pool.thread_count();
-        self.pool.scoped(|scope| {
-            for _ in 0..n {
-                let b = &block_queue;
-                let r = &rt;
-                let l = &light_list;
-                scope.execute(move || {
-                    thread_work(config.spp, b, scene, r, l);
-                });
+        // The samples for each pixel are split between the passes the integrator renders in,
+        // the first passes take one more sample each if they can't be split evenly. Each pass
+        // takes at least one sample so we render fewer passes if there aren't enough samples
+        let num_passes = cmp::min(scene.integrator.num_passes(), cmp::max(config.spp, 1));
+        if num_passes < scene.integrator.num_passes() {
+            println!("Warning: {} samples per pixel can't be split between {} passes, rendering {} passes",
+                     config.spp, scene.integrator.num_passes(), num_passes);
+        }
+        for pass in 0..num_passes {
+            let spp = cmp::max(config.spp / num_passes + if pass < config.spp % num_passes { 1 } else { 0 }, 1);
+            if num_passes > 1 {
+                println!("Rendering pass {} of {}", pass + 1, num_passes);
             }
-        });
+            scene.integrator.begin_pass(scene, &light_list, pass, &mut self.pool);
+            let block_queue = BlockQueue::new((dim.0 as u32, dim.1 as u32), (8, 8), config.select_blocks);
+            self.pool.scoped(|scope| {
+                for _ in 0..n {
+                    let b = &block_queue;
+                    let r = &rt;
+                    let l = &light_list;
+                    scope.execute(move || {
+                        thread_work(spp, b, scene, r, l);
+                    });
+                }
+            });
+        }
     }
 }
 
@@ -69,16 +89,21 @@ impl Exec for MultiThreaded {
 }
 
 fn thread_work(spp: usize, queue: &BlockQueue, scene: &Scene,
-               target: &RenderTarget, light_list: &[&Emitter]) {
+               target: &RenderTarget, light_list: &LightList) {
     let mut sampler = sampler::LowDiscrepancy::new(queue.block_dim(), spp);
     let mut sample_pos = Vec::with_capacity(sampler.max_spp());
     let mut time_samples: Vec<_> = iter::repeat(0.0).take(sampler.max_spp()).collect();
     let block_dim = queue.block_dim();
     let mut block_samples = Vec::with_capacity(sampler.max_spp() * (block_dim.0 * block_dim.1) as usize);
+    let mut block_splats = Vec::new();
+    let mut block_splat_paths = 0;
     let mut rng = match StdRng::new() {
         Ok(r) => r,
         Err(e) => { println!("Failed to get StdRng, {}", e); return }
     };
+    // The BSDFs and sample buffers for each camera sample are allocated in the arena,
+    // which is reset after each sample by dropping its allocator
+    let mut arena = MemoryArena::new(64 * 1024);
     let camera = scene.active_camera();
     // Grab a block from the queue and start working on it, submitting samples
     // to the render target thread after each pixel
@@ -90,13 +115,15 @@ fn thread_work(spp: usize, queue: &BlockQueue, scene: &Scene,
             sampler.get_samples(&mut sample_pos, &mut rng);
             sampler.get_samples_1d(&mut time_samples[..], &mut rng);
             for (s, t) in sample_pos.iter().zip(time_samples.iter()) {
+                let alloc = arena.allocator();
                 let mut ray = camera.generate_ray(s, *t);
                 if let Some(hit) = scene.intersect(&mut ray) {
-                    let c = scene.integrator.illumination(scene, light_list, &ray,
-                                                          &hit, &mut sampler, &mut rng).clamp();
+                    let c = scene.integrator.illumination_splat(scene, light_list, &ray, &hit, &mut sampler,
+                                                                &mut rng, &alloc, &mut block_splats).clamp();
+                    block_splat_paths += 1;
                     block_samples.push(ImageSample::new(s.0, s.1, c));
                 } else {
-                    block_samples.push(ImageSample::new(s.0, s.1, Colorf::black()));
+                    block_samples.push(ImageSample::new(s.0, s.1, scene.escaped_radiance(&ray).clamp()));
                 }
             }
             // If the samples are ok the samples for the next pixel start at the end of the current
@@ -106,7 +133,10 @@ fn thread_work(spp: usize, queue: &BlockQueue, scene: &Scene,
             }
         }
         target.write(&block_samples, sampler.get_region());
+        target.splat(&block_splats, block_splat_paths);
         block_samples.clear();
+        block_splats.clear();
+        block_splat_paths = 0;
     }
 }
 
@@ -36,6 +36,12 @@ pub struct Camera {
     cam_world: AnimatedTransform,
     /// Transformation from raster space to screen space
     raster_screen: Transform,
+    /// Transformation from screen space to raster space
+    screen_raster: Transform,
+    /// Dimensions of the image in pixels
+    dims: (usize, usize),
+    /// Area of the image on the plane at z = 1 in camera space before scaling by the fov
+    screen_area: f32,
     /// The projective division matrix, the perspective matrix is changing in the
     /// case of animated FOV so we deconstruct it some to reduce creating a new
     /// transform each time
@@ -83,7 +89,8 @@ impl Camera {
              0.0, 0.0, 1.0, 0.0]);
         let tan_fov = f32::tan(linalg::to_radians(fov) / 2.0);
         let scaling = Vector::new(tan_fov, tan_fov, 1.0);
-        Camera { cam_world: cam_world, raster_screen: raster_screen,
+        Camera { cam_world: cam_world, raster_screen: raster_screen, screen_raster: screen_raster, dims: dims,
+                 screen_area: (screen[1] - screen[0]) * (screen[3] - screen[2]),
                  proj_div_inv: Transform::from_mat(&proj_div).inverse(),
                  shutter_open: 0.0, shutter_close: 0.0, shutter_size: shutter_size,
                  fov: CameraFov::Unanimated(fov), scaling: scaling, active_at: active_at
@@ -116,7 +123,8 @@ impl Camera {
              0.0, 0.0, 1.0, 0.0]);
         let tan_fov = f32::tan(linalg::to_radians(fovs[0]) / 2.0);
         let scaling = Vector::new(tan_fov, tan_fov, 1.0);
-        Camera { cam_world: cam_world, raster_screen: raster_screen,
+        Camera { cam_world: cam_world, raster_screen: raster_screen, screen_raster: screen_raster, dims: dims,
+                 screen_area: (screen[1] - screen[0]) * (screen[3] - screen[2]),
                  proj_div_inv: Transform::from_mat(&proj_div).inverse(),
                  shutter_open: 0.0, shutter_close: 0.0, shutter_size: shutter_size,
                  fov: CameraFov::Animated(BSpline::new(fov_spline_degree, fovs, fov_knots)),
@@ -155,5 +163,59 @@ impl Camera {
         let frame_time = (self.shutter_close - self.shutter_open) * time + self.shutter_open;
         self.cam_world.transform(frame_time) * Ray::new(&Point::broadcast(0.0), &d, frame_time)
     }
+    /// Find where light arriving at the camera from `p` at `time` lands on the image. Returns
+    /// the raster position it lands at, the position of the camera, the importance the camera
+    /// emits towards `p` and the solid angle pdf at `p` of choosing the camera's position.
+    /// Returns None if `p` is outside the camera's view
+    pub fn sample_incident(&self, p: &Point, time: f32) -> Option<((f32, f32), Point, f32, f32)> {
+        let world_cam = self.cam_world.transform(time).inverse();
+        let p_cam = world_cam * *p;
+        if p_cam.z <= 0.0 {
+            return None;
+        }
+        let d = Vector::new(p_cam.x, p_cam.y, p_cam.z);
+        let raster = match self.raster_pos(&d) {
+            Some(r) => r,
+            None => return None,
+        };
+        let cos_theta = d.normalized().z;
+        // The pinhole is a delta position so we can only choose it, the pdf converts this
+        // choice from area measure at the camera to solid angle at `p`
+        let pdf = d.length_sqr() / cos_theta;
+        let position = self.cam_world.transform(time) * Point::broadcast(0.0);
+        Some((raster, position, self.importance(cos_theta), pdf))
+    }
+    /// Compute the solid angle pdf of the camera generating a ray along the world space
+    /// direction `w` at `time` when sampling a position on the image uniformly
+    pub fn pdf_dir(&self, w: &Vector, time: f32) -> f32 {
+        let d = (self.cam_world.transform(time).inverse() * *w).normalized();
+        if d.z <= 0.0 || self.raster_pos(&d).is_none() {
+            return 0.0;
+        }
+        // Area on the plane at z = 1 maps to solid angle by cos^3 theta
+        1.0 / (self.image_area() * d.z * d.z * d.z)
+    }
+    /// Find the raster position the camera space direction `d` passes through, returns None
+    /// if it's outside the image. `d` must point in front of the camera
+    fn raster_pos(&self, d: &Vector) -> Option<(f32, f32)> {
+        let screen = Point::new(d.x / (d.z * self.scaling.x), d.y / (d.z * self.scaling.y), 0.0);
+        let raster = self.screen_raster * screen;
+        if raster.x < 0.0 || raster.x >= self.dims.0 as f32 || raster.y < 0.0 || raster.y >= self.dims.1 as f32 {
+            None
+        } else {
+            Some((raster.x, raster.y))
+        }
+    }
+    /// Get the importance emitted by the pinhole along a direction making an angle with
+    /// cosine `cos_theta` to the view direction, it's normalized so the importance over
+    /// the whole image integrates to 1
+    fn importance(&self, cos_theta: f32) -> f32 {
+        let cos2 = cos_theta * cos_theta;
+        1.0 / (self.image_area() * cos2 * cos2)
+    }
+    /// Get the area of the image on the plane at z = 1 in camera space
+    fn image_area(&self) -> f32 {
+        self.screen_area * self.scaling.x * self.scaling.y
+    }
 }
 
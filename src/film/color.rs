@@ -65,6 +65,19 @@ impl Colorf {
         }
         srgb
     }
+    /// Convert the sRGB color to linear RGB
+    pub fn to_linear(&self) -> Colorf {
+        let a = 0.055f32;
+        let mut linear = Colorf::broadcast(0.0);
+        for i in 0..3 {
+            if self[i] <= 0.04045 {
+                linear[i] = self[i] / 12.92;
+            } else {
+                linear[i] = f32::powf((self[i] + a) / (1.0 + a), 2.4);
+            }
+        }
+        linear
+    }
     /// Return the color with values { e^r, e^g, e^b }
     pub fn exp(&self) -> Colorf {
         Colorf { r: f32::exp(self.r), g: f32::exp(self.g),
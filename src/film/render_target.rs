@@ -4,6 +4,7 @@
 use std::vec::Vec;
 use std::{iter, cmp, f32};
 use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
 
 use film::Colorf;
 use film::filter::Filter;
@@ -25,11 +26,18 @@ impl ImageSample {
     }
 }
 
-/// `RenderTarget` is a RGBF render target to write our image too while rendering
+/// `RenderTarget` is a RGBF render target to write our image too while rendering.
+/// Along with the filtered image samples it stores light splatted onto the image, e.g. by
+/// connecting light paths to the camera, which is added to the pixels when reading the image
 pub struct RenderTarget {
     width: usize,
     height: usize,
     pixels_locked: Vec<Mutex<Vec<Colorf>>>,
+    /// Unfiltered sum of the light splatted onto each pixel, in the same blocks as the pixels
+    splats_locked: Vec<Mutex<Vec<Colorf>>>,
+    /// Number of paths that could have splatted light onto the image, the splats are
+    /// an estimate of the light reaching the image from this many paths
+    num_splat_paths: AtomicUsize,
     lock_size: (i32, i32),
     filter: Box<Filter + Send + Sync>,
     filter_table: Vec<f32>,
@@ -60,13 +68,18 @@ impl RenderTarget {
         let x_blocks = width / lock_size.0;
         let y_blocks = height / lock_size.1;
         let mut pixels_locked = Vec::with_capacity(x_blocks * y_blocks);
+        let mut splats_locked = Vec::with_capacity(x_blocks * y_blocks);
         for _ in 0..x_blocks * y_blocks {
             pixels_locked.push(Mutex::new(iter::repeat(Colorf::broadcast(0.0))
                                           .take(lock_size.0 * lock_size.1).collect()));
+            splats_locked.push(Mutex::new(iter::repeat(Colorf::broadcast(0.0))
+                                          .take(lock_size.0 * lock_size.1).collect()));
         }
 
         RenderTarget { width: width, height: height,
             pixels_locked: pixels_locked,
+            splats_locked: splats_locked,
+            num_splat_paths: AtomicUsize::new(0),
             lock_size: (lock_size.0 as i32, lock_size.1 as i32),
             filter: filter,
             filter_table: filter_table,
@@ -163,6 +176,22 @@ impl RenderTarget {
             }
         }
     }
+    /// Add the light splatted onto the image at each sample's position by `num_paths` paths,
+    /// including those which didn't splat anything. Unlike the samples passed to `write` splats
+    /// aren't filtered or averaged, they're summed in the pixel they land in and scaled by the
+    /// number of pixels per path traced
+    pub fn splat(&self, samples: &[ImageSample], num_paths: usize) {
+        self.num_splat_paths.fetch_add(num_paths, Ordering::SeqCst);
+        let blocks_per_row = self.width / self.lock_size.0 as usize;
+        for s in samples {
+            let x = cmp::min(s.x as usize, self.width - 1);
+            let y = cmp::min(s.y as usize, self.height - 1);
+            let block_idx = (y / self.lock_size.1 as usize) * blocks_per_row + x / self.lock_size.0 as usize;
+            let px = (y % self.lock_size.1 as usize) * self.lock_size.0 as usize + x % self.lock_size.0 as usize;
+            let mut splats = self.splats_locked[block_idx].lock().unwrap();
+            splats[px] = splats[px] + s.color;
+        }
+    }
     /// Clear the render target to black
     pub fn clear(&mut self) {
         let x_blocks = self.width / self.lock_size.0 as usize;
@@ -174,8 +203,25 @@ impl RenderTarget {
                 for p in pixels.iter_mut() {
                     *p = Colorf::broadcast(0.0);
                 }
+                let mut splats = self.splats_locked[block_idx].lock().unwrap();
+                for p in splats.iter_mut() {
+                    *p = Colorf::broadcast(0.0);
+                }
             }
         }
+        self.num_splat_paths.store(0, Ordering::SeqCst);
+    }
+    /// Get the pixels of the block, with the light splatted onto them added in. The splats
+    /// are weighted by the pixel's filter weight so they're unchanged when it's normalized
+    fn block_pixels(&self, block_idx: usize) -> Vec<Colorf> {
+        let num_paths = self.num_splat_paths.load(Ordering::SeqCst);
+        let splat_scale = if num_paths == 0 { 0.0 } else { (self.width * self.height) as f32 / num_paths as f32 };
+        let pixels = self.pixels_locked[block_idx].lock().unwrap();
+        let splats = self.splats_locked[block_idx].lock().unwrap();
+        pixels.iter().zip(splats.iter()).map(|(p, s)| {
+            Colorf { r: p.r + s.r * splat_scale * p.a, g: p.g + s.g * splat_scale * p.a,
+                     b: p.b + s.b * splat_scale * p.a, a: p.a }
+        }).collect()
     }
     /// Get the dimensions of the render target
     pub fn dimensions(&self) -> (usize, usize) {
@@ -191,7 +237,7 @@ impl RenderTarget {
                 let block_x_start = bx * self.lock_size.0 as usize;
                 let block_y_start = by * self.lock_size.1 as usize;
                 let block_idx = (by * x_blocks + bx) as usize;
-                let pixels = self.pixels_locked[block_idx].lock().unwrap();
+                let pixels = self.block_pixels(block_idx);
                 for y in 0..self.lock_size.1 as usize {
                     for x in 0..self.lock_size.0 as usize {
                         let c = &pixels[y * self.lock_size.0 as usize + x];
@@ -223,7 +269,7 @@ impl RenderTarget {
                 let block_x_start = bx * block_size.0;
                 let block_y_start = by * block_size.1;
                 let block_idx = by * x_blocks + bx;
-                let pixels = self.pixels_locked[block_idx].lock().unwrap();
+                let pixels = self.block_pixels(block_idx);
                 if pixels.iter().fold(true, |acc, px| acc && px.a != 0.0) {
                     blocks.push((block_x_start, block_y_start));
                     for y in 0..block_size.1 {
@@ -249,7 +295,7 @@ impl RenderTarget {
                 let block_x_start = bx * self.lock_size.0 as usize;
                 let block_y_start = by * self.lock_size.1 as usize;
                 let block_idx = (by * x_blocks + bx) as usize;
-                let pixels = self.pixels_locked[block_idx].lock().unwrap();
+                let pixels = self.block_pixels(block_idx);
                 for y in 0..self.lock_size.1 as usize {
                     for x in 0..self.lock_size.0 as usize {
                         let c = &pixels[y * self.lock_size.0 as usize + x];
@@ -131,6 +131,10 @@ impl<T: Boundable> BVH<T> {
     pub fn iter(&self) -> Iter<T> {
         self.geometry.iter()
     }
+    /// Get the object at index `i` in the geometry passed when creating the BVH
+    pub fn get(&self, i: usize) -> &T {
+        &self.geometry[i]
+    }
     /// Construct the BVH tree using SAH splitting heuristic to determine split locations
     /// returns the root node of the subtree constructed over the slice of geom info passed
     /// and will increment `total_nodes` by the number of nodes in this subtree
@@ -9,14 +9,27 @@ use geometry::Geometry;
 pub struct DifferentialGeometry<'a> {
     /// The hit point
     pub p: Point,
+    /// The hit point in the local space of the object that was hit, this isn't
+    /// changed when the rest of the geometry is transformed into world space
+    pub p_local: Point,
     /// The shading normal
     pub n: Normal,
     /// The geometry normal
     pub ng: Normal,
+    /// The u parameterization coord of the surface at the hit point, for triangle meshes
+    /// this is the texture coordinate interpolated from the triangle's vertices
+    pub u: f32,
+    /// The v parameterization coord of the surface at the hit point, for triangle meshes
+    /// this is the texture coordinate interpolated from the triangle's vertices
+    pub v: f32,
     /// Derivative of the point with respect to the u parameterization coord of the surface
     pub dp_du: Vector,
     /// Derivative of the point with respect to the v parameterization coord of the surface
     pub dp_dv: Vector,
+    /// Derivative of the local space point with respect to the u parameterization coord
+    pub dp_du_local: Vector,
+    /// Derivative of the local space point with respect to the v parameterization coord
+    pub dp_dv_local: Vector,
     /// The geometry that was hit
     pub geom: &'a (Geometry + 'a),
 }
@@ -24,20 +37,22 @@ pub struct DifferentialGeometry<'a> {
 impl<'a> DifferentialGeometry<'a> {
     /// Setup the differential geometry. Note that the normal will be computed
     /// using cross(dp_du, dp_dv)
-    pub fn new(p: &Point, ng: &Normal, dp_du: &Vector, dp_dv: &Vector,
+    pub fn new(p: &Point, ng: &Normal, u: f32, v: f32, dp_du: &Vector, dp_dv: &Vector,
                geom: &'a (Geometry + 'a))
                -> DifferentialGeometry<'a> {
         let n = linalg::cross(dp_du, dp_dv).normalized();
-        DifferentialGeometry { p: *p, n: Normal::new(n.x, n.y, n.z), ng: ng.normalized(),
-                               dp_du: *dp_du, dp_dv: *dp_dv, geom: geom }
+        DifferentialGeometry { p: *p, p_local: *p, n: Normal::new(n.x, n.y, n.z), ng: ng.normalized(), u: u, v: v,
+                               dp_du: *dp_du, dp_dv: *dp_dv, dp_du_local: *dp_du, dp_dv_local: *dp_dv,
+                               geom: geom }
     }
     /// Setup the differential geometry using the normal passed for the surface normal
-    pub fn with_normal(p: &Point, n: &Normal, dp_du: &Vector, dp_dv: &Vector,
+    pub fn with_normal(p: &Point, n: &Normal, u: f32, v: f32, dp_du: &Vector, dp_dv: &Vector,
                geom: &'a (Geometry + 'a))
                -> DifferentialGeometry<'a> {
         let nn = n.normalized();
-        DifferentialGeometry { p: *p, n: nn, ng: nn,
-                               dp_du: *dp_du, dp_dv: *dp_dv, geom: geom }
+        DifferentialGeometry { p: *p, p_local: *p, n: nn, ng: nn, u: u, v: v,
+                               dp_du: *dp_du, dp_dv: *dp_dv, dp_du_local: *dp_du, dp_dv_local: *dp_dv,
+                               geom: geom }
     }
 }
 
@@ -67,8 +67,15 @@ impl Geometry for Disk {
         ray.max_t = t;
         let hit_radius = f32::sqrt(dist_sqr);
         let dp_du = Vector::new(-f32::consts::PI * 2.0 * p.y, f32::consts::PI * 2.0 * p.x, 0.0);
-        let dp_dv = ((self.inner_radius - self.radius) / hit_radius) * Vector::new(p.x, p.y, 0.0);
-        Some(DifferentialGeometry::new(&p, &Normal::new(0.0, 0.0, 1.0), &dp_du, &dp_dv, self))
+        // At the center of the disk the radial direction is undefined so pick the one at phi = 0
+        let dp_dv = if hit_radius > 0.0 {
+            ((self.inner_radius - self.radius) / hit_radius) * Vector::new(p.x, p.y, 0.0)
+        } else {
+            Vector::new(self.inner_radius - self.radius, 0.0, 0.0)
+        };
+        let u = phi / (f32::consts::PI * 2.0);
+        let v = (self.radius - hit_radius) / (self.radius - self.inner_radius);
+        Some(DifferentialGeometry::new(&p, &Normal::new(0.0, 0.0, 1.0), u, v, &dp_du, &dp_dv, self))
     }
 }
 
@@ -1,14 +1,19 @@
 //! An emitter is an instance of geometry that both receives and emits light
 //!
 //! # Scene Usage Example
-//! An emitter is an object in the scene that emits light, it can be a point light
-//! or an area light. The emitter takes an extra 'emitter' parameter to specify
-//! whether the instance is an area or point emitter and an 'emission' parameter
-//! to set the color and strength of emitted light.
+//! An emitter is an object in the scene that emits light, it can be a point light,
+//! a spot light, a directional light, an area light, an environment light or a sky light.
+//! The emitter takes an extra 'emitter' parameter to specify whether the instance is a point,
+//! spot, directional, area, environment or sky emitter and an 'emission' parameter to set
+//! the color and strength of emitted light.
 //!
 //! ## Point Light Example
 //! The point light has no geometry, material or transformation since it's not a
 //! physical object. Instead it simply takes a position to place the light at in the scene.
+//! Point and spot lights can optionally take an 'ies' file containing an IES LM-63 photometric
+//! profile to shape their emission, relative paths are resolved relative to the scene file.
+//! The profile's nadir points along the light's +z axis, with the brightest direction of the
+//! profile emitting the light's 'emission'.
 //!
 //! ```json
 //! "objects": [
@@ -17,6 +22,7 @@
 //!         "type": "emitter",
 //!         "emitter": "point",
 //!         "emission": [1, 1, 1, 100],
+//!         "ies": "downlight.ies",
 //!         "transform": [
 //!             {
 //!                 "type": "translate",
@@ -28,11 +34,65 @@
 //! ]
 //! ```
 //!
+//! ## Spot Light Example
+//! The spot light is a point light which only emits light in a cone about its +z axis, the
+//! transform (or keyframes) places and orients the light in the scene the same way it does
+//! for cameras. 'outer_angle' is the angle in degrees from the axis to the edge of the cone
+//! and 'inner_angle' the angle within which the light is at full strength, between the
+//! two the light falls off smoothly.
+//!
+//! ```json
+//! "objects": [
+//!     {
+//!         "name": "my_spot",
+//!         "type": "emitter",
+//!         "emitter": "spot",
+//!         "emission": [1, 1, 1, 100],
+//!         "inner_angle": 20,
+//!         "outer_angle": 30,
+//!         "transform": [
+//!             {
+//!                 "type": "rotate_x",
+//!                 "rotation": 90
+//!             },
+//!             {
+//!                 "type": "translate",
+//!                 "translation": [0, 10, 0]
+//!             }
+//!         ]
+//!     },
+//!     ...
+//! ]
+//! ```
+//!
+//! ## Directional Light Example
+//! The directional light illuminates the whole scene from a single direction, like a far away
+//! sun. The light travels along the +z axis rotated by the transform (or keyframes), the same
+//! as the spot light, and the emission is the irradiance arriving at surfaces facing the light.
+//!
+//! ```json
+//! "objects": [
+//!     {
+//!         "name": "my_sun",
+//!         "type": "emitter",
+//!         "emitter": "directional",
+//!         "emission": [1, 0.95, 0.9, 3],
+//!         "transform": [
+//!             {
+//!                 "type": "rotate_x",
+//!                 "rotation": 60
+//!             }
+//!         ]
+//!     },
+//!     ...
+//! ]
+//! ```
+//!
 //! ## Area Light Example
 //! The area light looks similar to a regular receiver except it has an additional emission
 //! parameter. Area lights are also restricted somewhat in which geometry they can use as
 //! it needs to be possible to sample the geometry. Area lights can only accept geometry
-//! that implements `geometry::Sampleable`.
+//! that implements `geometry::Sampleable`: spheres, disks, rectangles and triangle meshes.
 //!
 //! ```json
 //! "objects": [
@@ -56,23 +116,92 @@
 //!     ...
 //! ]
 //! ```
+//!
+//! ## Environment Light Example
+//! The environment light surrounds the scene and provides the radiance arriving along rays
+//! that leave the scene. It takes a 'file' parameter with the path to an equirectangular
+//! image to load, relative to the scene file. Radiance HDR (.hdr) images are loaded as
+//! linear radiance, other formats are assumed to be sRGB. The image is scaled by the emission
+//! color and strength. The top of the image is towards +z in the map's local space, so the
+//! transform can be used to orient the map, e.g. rotating -90 degrees about x for scenes that
+//! use +y as up.
+//!
+//! ```json
+//! "objects": [
+//!     {
+//!         "name": "my_environment",
+//!         "type": "emitter",
+//!         "emitter": "environment",
+//!         "emission": [1, 1, 1, 1],
+//!         "file": "studio.hdr",
+//!         "transform": [
+//!             {
+//!                 "type": "rotate_x",
+//!                 "rotation": -90
+//!             }
+//!         ]
+//!     },
+//!     ...
+//! ]
+//! ```
+//!
+//! ## Sky Light Example
+//! The sky light surrounds the scene with an analytic model of the sky and sun, see
+//! `light::sun_sky` for details on the model. It takes the direction towards the sun in
+//! 'sun_direction', which is rotated by the emitter's transform so the sun can be animated
+//! with keyframes, e.g. to render a time-lapse. The optional 'up' parameter sets the direction
+//! towards the zenith (default [0, 1, 0]), 'turbidity' sets the haziness of the atmosphere
+//! in [2, 10] (default 3) and 'ground_albedo' the color of the ground seen below the
+//! horizon (default [0.3, 0.3, 0.3]). The sky's radiance is in kcd/m^2, which the emission
+//! can scale down to the scene's units.
+//!
+//! ```json
+//! "objects": [
+//!     {
+//!         "name": "my_sky",
+//!         "type": "emitter",
+//!         "emitter": "sky",
+//!         "emission": [1, 1, 1, 0.05],
+//!         "sun_direction": [0.5, 1, 0.3],
+//!         "turbidity": 3,
+//!         "ground_albedo": [0.3, 0.3, 0.3],
+//!         "transform": []
+//!     },
+//!     ...
+//! ]
+//! ```
 
+use std::f32;
 use std::sync::Arc;
 
 use geometry::{Boundable, BBox, SampleableGeom, DifferentialGeometry};
 use material::Material;
-use linalg::{self, AnimatedTransform, Point, Ray, Vector, Normal};
+use linalg::{self, AnimatedTransform, Transform, Point, Ray, Vector, Normal};
 use film::{AnimatedColor, Colorf};
-use light::{Light, OcclusionTester};
+use light::{Light, LightBounds, OcclusionTester, EnvironmentMap, SunSky, IesProfile};
+use mc;
 
-/// The type of emitter, either a point light or an area light
-/// in which case the emitter has associated geometry and a material
+/// The type of emitter, either a point, spot or directional light, an area light in which
+/// case the emitter has associated geometry and a material, an environment light or a sky light
 /// TODO: Am I happy with this design?
 enum EmitterType {
-    Point,
+    /// The point light optionally holds an IES profile scaling its intensity in each direction
+    Point { ies: Option<Arc<IesProfile>> },
+    /// The spot light holds the cosines of the angles to the edges of the
+    /// inner and outer cones of its emission and optionally an IES profile
+    Spot { cos_inner: f32, cos_outer: f32, ies: Option<Arc<IesProfile>> },
+    /// The directional light holds a sphere bounding the scene, used when emitting
+    /// light into the scene
+    Directional { world_center: Point, world_radius: f32 },
     /// The area light holds the geometry that is emitting the light
     /// and the material for the geometry
     Area(Arc<SampleableGeom + Send + Sync>, Arc<Material + Send + Sync>),
+    /// The environment light holds the map of incident radiance along with
+    /// a sphere bounding the scene, used when emitting light into the scene
+    Environment { map: Arc<EnvironmentMap>, world_center: Point, world_radius: f32 },
+    /// The sky light holds the sky model and the direction towards the sun before
+    /// being rotated by the transform, along with a sphere bounding the scene
+    Sky { sky: SunSky, sun: Vector, world_center: Point, world_radius: f32 },
 }
 
 /// An instance of geometry in the scene that receives and emits light.
@@ -105,19 +234,95 @@ impl Emitter {
                   tag: tag }
     }
     /// Create a point light at the origin that is transformed by `transform` to its location
-    /// in the world
-    pub fn point(transform: AnimatedTransform, emission: AnimatedColor, tag: String) -> Emitter {
-        Emitter { emitter: EmitterType::Point,
+    /// in the world. If an IES profile is passed it scales the intensity of the light in each
+    /// direction, with the profile's nadir along the light's +z axis
+    pub fn point(transform: AnimatedTransform, emission: AnimatedColor, ies: Option<Arc<IesProfile>>,
+                 tag: String) -> Emitter {
+        Emitter { emitter: EmitterType::Point { ies: ies },
+                  emission: emission,
+                  transform: transform,
+                  tag: tag }
+    }
+    /// Create a spot light at the origin shining down the +z axis that is transformed by
+    /// `transform` to its location and orientation in the world. The light is at full strength
+    /// within `inner_angle` degrees of the axis and falls off smoothly to zero at `outer_angle`.
+    /// An optional IES profile further scales the intensity within the cone
+    pub fn spot(transform: AnimatedTransform, emission: AnimatedColor, inner_angle: f32, outer_angle: f32,
+                ies: Option<Arc<IesProfile>>, tag: String) -> Emitter {
+        assert!(inner_angle <= outer_angle, "The inner angle of a spot light must be within the outer angle");
+        Emitter { emitter: EmitterType::Spot { cos_inner: f32::cos(linalg::to_radians(inner_angle)),
+                                               cos_outer: f32::cos(linalg::to_radians(outer_angle)),
+                                               ies: ies },
                   emission: emission,
                   transform: transform,
                   tag: tag }
     }
+    /// Create a directional light shining along the +z axis rotated by `transform`, `emission`
+    /// is the irradiance arriving at surfaces facing the light. The bounds of the scene must be
+    /// set with `set_world_bounds` before rendering
+    pub fn directional(transform: AnimatedTransform, emission: AnimatedColor, tag: String) -> Emitter {
+        Emitter { emitter: EmitterType::Directional { world_center: Point::broadcast(0.0), world_radius: 0.0 },
+                  emission: emission,
+                  transform: transform,
+                  tag: tag }
+    }
+    /// Create an environment light surrounding the scene which emits the radiance in
+    /// `map` scaled by `emission`, the map is oriented in the world by `transform`.
+    /// The bounds of the scene must be set with `set_world_bounds` before rendering
+    pub fn environment(map: Arc<EnvironmentMap>, transform: AnimatedTransform, emission: AnimatedColor,
+                       tag: String) -> Emitter {
+        Emitter { emitter: EmitterType::Environment { map: map, world_center: Point::broadcast(0.0),
+                                                      world_radius: 0.0 },
+                  emission: emission,
+                  transform: transform,
+                  tag: tag }
+    }
+    /// Create a sky light surrounding the scene with the sun in the direction `sun`, which
+    /// is rotated by `transform` to animate the sun. The sky is scaled by `emission`.
+    /// The bounds of the scene must be set with `set_world_bounds` before rendering
+    pub fn sky(sky: SunSky, sun: &Vector, transform: AnimatedTransform, emission: AnimatedColor,
+               tag: String) -> Emitter {
+        Emitter { emitter: EmitterType::Sky { sky: sky, sun: *sun, world_center: Point::broadcast(0.0),
+                                              world_radius: 0.0 },
+                  emission: emission,
+                  transform: transform,
+                  tag: tag }
+    }
+    /// Check if the emitter is an infinitely far away light surrounding the scene,
+    /// these lights aren't placed in the scene BVH
+    pub fn is_infinite(&self) -> bool {
+        match self.emitter {
+            EmitterType::Directional { .. } | EmitterType::Environment { .. } | EmitterType::Sky { .. } => true,
+            _ => false,
+        }
+    }
+    /// Set the bounds of the scene, which infinite lights need to emit light into it
+    pub fn set_world_bounds(&mut self, bounds: &BBox) {
+        match self.emitter {
+            EmitterType::Directional { ref mut world_center, ref mut world_radius }
+            | EmitterType::Environment { ref mut world_center, ref mut world_radius, .. }
+            | EmitterType::Sky { ref mut world_center, ref mut world_radius, .. } => {
+                *world_center = bounds.lerp(0.5, 0.5, 0.5);
+                *world_radius = world_center.distance(&bounds.max);
+            },
+            _ => {},
+        }
+    }
+    /// Prepare the emitter for rendering a frame over the time period [start, end],
+    /// the sky light tabulates the sky for the sun's position in the middle of the frame
+    pub fn update_frame(&mut self, start: f32, end: f32) {
+        let transform = self.transform.transform((start + end) / 2.0);
+        if let EmitterType::Sky { ref mut sky, ref sun, .. } = self.emitter {
+            sky.set_sun(&(transform * *sun));
+        }
+    }
     /// Test the ray for intersection against this insance of geometry.
     /// returns Some(Intersection) if an intersection was found and None if not.
     /// If an intersection is found `ray.max_t` will be set accordingly
     pub fn intersect(&self, ray: &mut Ray) -> Option<(DifferentialGeometry, &Material)> {
         match self.emitter {
-            EmitterType::Point => None,
+            EmitterType::Point { .. } | EmitterType::Spot { .. } | EmitterType::Directional { .. }
+                | EmitterType::Environment { .. } | EmitterType::Sky { .. } => None,
             EmitterType::Area(ref geom, ref mat) => {
                 let transform = self.transform.transform(ray.time);
                 let mut local = transform.inv_mul_ray(ray);
@@ -148,15 +353,108 @@ impl Emitter {
     pub fn set_transform(&mut self, transform: AnimatedTransform) {
         self.transform = transform;
     }
+    /// Compute the bounds on the position, power and emission directions of the light
+    /// over the time period [start, end], with the power computed at `time`. These
+    /// are used to place the light in the `LightBVH`, infinite lights have no bounds
+    pub fn light_bounds(&self, start: f32, end: f32, time: f32) -> Option<LightBounds> {
+        let bounds = self.bounds(start, end);
+        let power = f32::max(self.power(time).luminance(), 0.0);
+        let axis = Vector::new(0.0, 0.0, 1.0);
+        match self.emitter {
+            EmitterType::Point { .. } => Some(LightBounds::new(bounds, power, &axis, f32::consts::PI,
+                                                        f32::consts::FRAC_PI_2)),
+            EmitterType::Spot { cos_inner, cos_outer, .. } => {
+                // If the light is moving it could be pointing anywhere during the frame
+                if self.transform.is_animated() {
+                    Some(LightBounds::new(bounds, power, &axis, f32::consts::PI, f32::consts::FRAC_PI_2))
+                } else {
+                    // Treat the falloff region as the emission past the normal cone, keeping it
+                    // within (0, pi/2] so the importance is positive everywhere in the outer cone
+                    let dir = self.transform.transform(start) * axis;
+                    let outer = f32::acos(cos_outer);
+                    let theta_e = linalg::clamp(outer - f32::acos(cos_inner), 1e-3, f32::consts::FRAC_PI_2);
+                    Some(LightBounds::new(bounds, power, &dir, f32::max(outer - theta_e, 0.0), theta_e))
+                }
+            },
+            EmitterType::Directional { .. } | EmitterType::Environment { .. } | EmitterType::Sky { .. } => None,
+            EmitterType::Area(ref g, _) => {
+                // Check if the surface is flat by looking at normals over its surface, if it is
+                // (and isn't moving) we can bound its emission to the hemisphere about its normal
+                let transform = self.transform.transform(start);
+                let mut normals = (0..16).map(|i| {
+                    let (_, n) = g.sample_uniform(&(((i % 4) as f32 + 0.5) / 4.0, ((i / 4) as f32 + 0.5) / 4.0));
+                    let n = (transform * n).normalized();
+                    Vector::new(n.x, n.y, n.z)
+                });
+                let first = normals.next().unwrap();
+                let flat = !self.transform.is_animated() && normals.all(|n| linalg::dot(&n, &first) > 0.9999);
+                if flat {
+                    Some(LightBounds::new(bounds, power, &first, 0.0, f32::consts::FRAC_PI_2))
+                } else {
+                    Some(LightBounds::new(bounds, power, &axis, f32::consts::PI, f32::consts::FRAC_PI_2))
+                }
+            },
+        }
+    }
+}
+
+/// Compute the falloff of a spot light's emission in a direction at acos(`cos_theta`) to its axis,
+/// the light is at full strength within the inner cone and falls off smoothly to zero at the
+/// edge of the outer cone
+fn spot_falloff(cos_theta: f32, cos_inner: f32, cos_outer: f32) -> f32 {
+    if cos_theta >= cos_inner {
+        1.0
+    } else if cos_theta <= cos_outer {
+        0.0
+    } else {
+        let t = (cos_theta - cos_outer) / (cos_inner - cos_outer);
+        t * t * (3.0 - 2.0 * t)
+    }
+}
+
+/// Compute the scaling of a light's intensity along the direction `w_l` in the light's
+/// local space from its IES profile, lights without a profile emit equally in all directions
+fn ies_scale(ies: &Option<Arc<IesProfile>>, w_l: &Vector) -> f32 {
+    match *ies {
+        Some(ref p) => p.intensity(w_l),
+        None => 1.0,
+    }
+}
+
+/// Sample a point to emit light arriving along `w_i` from an infinite light into the scene
+/// bounded by the sphere at `center` with `radius`. The light is emitted from a disk facing
+/// the scene just outside the bounding sphere, returns the ray emitted, the normal of the
+/// disk and the area pdf of sampling the point on the disk
+fn infinite_emitted_ray(w_i: &Vector, samples: &(f32, f32), center: &Point, radius: f32, time: f32)
+                        -> (Ray, Normal, f32) {
+    let (v1, v2) = linalg::coordinate_system(w_i);
+    let d = mc::concentric_sample_disk(samples);
+    let p = *center + radius * (*w_i + d.0 * v1 + d.1 * v2);
+    let ray = Ray::segment(&p, &-*w_i, 0.001, f32::INFINITY, time);
+    (ray, Normal::new(-w_i.x, -w_i.y, -w_i.z), 1.0 / (f32::consts::PI * radius * radius))
+}
+
+/// Compute how much `transform` scales a differential area on a surface with
+/// normal `n` in the geometry's local space. This is used to convert area
+/// measure pdfs on the local geometry into world space area measure pdfs
+fn area_scale(transform: &Transform, n: &Normal) -> f32 {
+    let (tan, bitan) = linalg::coordinate_system(&Vector::new(n.x, n.y, n.z).normalized());
+    linalg::cross(&(*transform * tan), &(*transform * bitan)).length()
 }
 
 impl Boundable for Emitter {
     fn bounds(&self, start: f32, end: f32) -> BBox {
         match self.emitter {
-            EmitterType::Point => self.transform.animation_bounds(&BBox::singular(Point::broadcast(0.0)), start, end),
+            EmitterType::Point { .. } | EmitterType::Spot { .. } => {
+                self.transform.animation_bounds(&BBox::singular(Point::broadcast(0.0)), start, end)
+            },
             EmitterType::Area(ref g, _) => {
                 self.transform.animation_bounds(&g.bounds(start, end), start, end)
             },
+            // Infinite lights surround the scene and aren't placed in the BVH
+            EmitterType::Directional { .. } | EmitterType::Environment { .. } | EmitterType::Sky { .. } => {
+                BBox::new()
+            },
         }
     }
 }
@@ -166,12 +464,27 @@ impl Light for Emitter {
         -> (Colorf, Vector, f32, OcclusionTester)
     {
         match self.emitter {
-            EmitterType::Point => {
+            EmitterType::Point { ref ies } => {
+                let transform = self.transform.transform(time);
+                let pos = transform * Point::broadcast(0.0);
+                let w_i = (pos - *p).normalized();
+                let scale = ies_scale(ies, &transform.inv_mul_vector(&-w_i));
+                (self.emission.color(time) * scale / pos.distance_sqr(p), w_i, 1.0,
+                 OcclusionTester::test_points(p, &pos, time))
+            },
+            EmitterType::Spot { cos_inner, cos_outer, ref ies } => {
                 let transform = self.transform.transform(time);
                 let pos = transform * Point::broadcast(0.0);
                 let w_i = (pos - *p).normalized();
-                (self.emission.color(time) / pos.distance_sqr(p), w_i, 1.0, OcclusionTester::test_points(p, &pos, time))
-            }
+                let w_l = transform.inv_mul_vector(&-w_i).normalized();
+                let scale = spot_falloff(w_l.z, cos_inner, cos_outer) * ies_scale(ies, &w_l);
+                (self.emission.color(time) * scale / pos.distance_sqr(p),
+                 w_i, 1.0, OcclusionTester::test_points(p, &pos, time))
+            },
+            EmitterType::Directional { .. } => {
+                let w_i = -(self.transform.transform(time) * Vector::new(0.0, 0.0, 1.0)).normalized();
+                (self.emission.color(time), w_i, 1.0, OcclusionTester::test_ray(p, &w_i, time))
+            },
             EmitterType::Area(ref g, _) => {
                 let transform = self.transform.transform(time);
                 let p_l = transform.inv_mul_point(p);
@@ -182,24 +495,307 @@ impl Light for Emitter {
                 let p_w = transform * p_sampled;
                 (radiance, transform * w_il, pdf, OcclusionTester::test_points(p, &p_w, time))
             },
+            EmitterType::Environment { ref map, .. } => {
+                let transform = self.transform.transform(time);
+                let (w_l, pdf) = map.sample(samples);
+                let w_i = (transform * w_l).normalized();
+                (map.lookup(&w_l) * self.emission.color(time), w_i, pdf, OcclusionTester::test_ray(p, &w_i, time))
+            },
+            EmitterType::Sky { ref sky, ref sun, .. } => {
+                let sun = self.transform.transform(time) * *sun;
+                let (w_i, pdf) = sky.sample(samples, &sun);
+                (sky.radiance(&w_i, &sun) * self.emission.color(time), w_i, pdf,
+                 OcclusionTester::test_ray(p, &w_i, time))
+            },
         }
     }
     fn delta_light(&self) -> bool {
         match self.emitter {
-            EmitterType::Point => true,
+            EmitterType::Point { .. } | EmitterType::Spot { .. } | EmitterType::Directional { .. } => true,
             _ => false,
         }
     }
     fn pdf(&self, p: &Point, w_i: &Vector, time: f32) -> f32 {
         match self.emitter {
-            EmitterType::Point => 0.0,
+            EmitterType::Point { .. } | EmitterType::Spot { .. } | EmitterType::Directional { .. } => 0.0,
             EmitterType::Area(ref g, _ ) => {
                 let transform = self.transform.transform(time);
                 let p_l = transform.inv_mul_point(p);
                 let w = (transform.inv_mul_vector(w_i)).normalized();
                 g.pdf(&p_l, &w)
-            }
+            },
+            EmitterType::Environment { ref map, .. } => {
+                let transform = self.transform.transform(time);
+                map.pdf(&transform.inv_mul_vector(w_i))
+            },
+            EmitterType::Sky { ref sky, ref sun, .. } => {
+                sky.pdf(w_i, &(self.transform.transform(time) * *sun))
+            },
+        }
+    }
+    fn sample_emitted(&self, pos_samples: &(f32, f32), dir_samples: &(f32, f32), time: f32)
+        -> (Colorf, Ray, Normal, f32, f32)
+    {
+        match self.emitter {
+            EmitterType::Point { ref ies } => {
+                let transform = self.transform.transform(time);
+                let pos = transform * Point::broadcast(0.0);
+                let w = mc::uniform_sample_sphere(dir_samples);
+                let ray = Ray::segment(&pos, &w, 0.001, f32::INFINITY, time);
+                let scale = ies_scale(ies, &transform.inv_mul_vector(&w));
+                (self.emission.color(time) * scale, ray, Normal::new(w.x, w.y, w.z), 1.0, mc::uniform_sphere_pdf())
+            },
+            EmitterType::Spot { cos_inner, cos_outer, ref ies } => {
+                let transform = self.transform.transform(time);
+                let pos = transform * Point::broadcast(0.0);
+                let w_l = mc::uniform_sample_cone(dir_samples, cos_outer);
+                let w = (transform * w_l).normalized();
+                let ray = Ray::segment(&pos, &w, 0.001, f32::INFINITY, time);
+                let scale = spot_falloff(w_l.z, cos_inner, cos_outer) * ies_scale(ies, &w_l);
+                (self.emission.color(time) * scale, ray,
+                 Normal::new(w.x, w.y, w.z), 1.0, mc::uniform_cone_pdf(cos_outer))
+            },
+            EmitterType::Directional { ref world_center, world_radius } => {
+                let w_i = -(self.transform.transform(time) * Vector::new(0.0, 0.0, 1.0)).normalized();
+                let (ray, n, pdf_pos) = infinite_emitted_ray(&w_i, pos_samples, world_center, world_radius, time);
+                (self.emission.color(time), ray, n, pdf_pos, 1.0)
+            },
+            EmitterType::Area(ref g, _) => {
+                let transform = self.transform.transform(time);
+                let (p_l, n_l) = g.sample_uniform(pos_samples);
+                let pdf_pos = 1.0 / (g.surface_area() * area_scale(&transform, &n_l));
+                let p = transform * p_l;
+                let n = (transform * n_l).normalized();
+                // Cosine weighted sample a direction on the hemisphere about the normal
+                let w_z = Vector::new(n.x, n.y, n.z);
+                let (w_x, w_y) = linalg::coordinate_system(&w_z);
+                let w_l = mc::cos_sample_hemisphere(dir_samples);
+                let w = w_l.x * w_x + w_l.y * w_y + w_l.z * w_z;
+                let ray = Ray::segment(&p, &w, 0.001, f32::INFINITY, time);
+                (self.radiance(&w, &p, &n, time), ray, n, pdf_pos, mc::cos_hemisphere_pdf(w_l.z))
+            },
+            EmitterType::Environment { ref map, ref world_center, world_radius } => {
+                // Sample a direction light arrives from and then a point on the disk facing
+                // it just outside the scene's bounding sphere to emit the light from
+                let transform = self.transform.transform(time);
+                let (w_l, pdf_dir) = map.sample(dir_samples);
+                let w_i = (transform * w_l).normalized();
+                let (ray, n, pdf_pos) = infinite_emitted_ray(&w_i, pos_samples, world_center, world_radius, time);
+                (map.lookup(&w_l) * self.emission.color(time), ray, n, pdf_pos, pdf_dir)
+            },
+            EmitterType::Sky { ref sky, ref sun, ref world_center, world_radius } => {
+                let sun = self.transform.transform(time) * *sun;
+                let (w_i, pdf_dir) = sky.sample(dir_samples, &sun);
+                let (ray, n, pdf_pos) = infinite_emitted_ray(&w_i, pos_samples, world_center, world_radius, time);
+                (sky.radiance(&w_i, &sun) * self.emission.color(time), ray, n, pdf_pos, pdf_dir)
+            },
         }
     }
+    fn pdf_emitted(&self, _: &Point, n: &Normal, w: &Vector, time: f32) -> (f32, f32) {
+        match self.emitter {
+            EmitterType::Point { .. } => (0.0, mc::uniform_sphere_pdf()),
+            EmitterType::Spot { cos_outer, .. } => {
+                let cos_theta = self.transform.transform(time).inv_mul_vector(w).normalized().z;
+                (0.0, if cos_theta >= cos_outer { mc::uniform_cone_pdf(cos_outer) } else { 0.0 })
+            },
+            EmitterType::Directional { world_radius, .. } => {
+                (1.0 / (f32::consts::PI * world_radius * world_radius), 0.0)
+            },
+            EmitterType::Area(ref g, _) => {
+                let transform = self.transform.transform(time);
+                let n_l = transform.inv_mul_normal(n);
+                let pdf_pos = 1.0 / (g.surface_area() * area_scale(&transform, &n_l));
+                let cos_theta = linalg::dot(n, w);
+                (pdf_pos, if cos_theta > 0.0 { mc::cos_hemisphere_pdf(cos_theta) } else { 0.0 })
+            },
+            EmitterType::Environment { ref map, world_radius, .. } => {
+                let transform = self.transform.transform(time);
+                let pdf_pos = 1.0 / (f32::consts::PI * world_radius * world_radius);
+                (pdf_pos, map.pdf(&transform.inv_mul_vector(&-*w)))
+            },
+            EmitterType::Sky { ref sky, ref sun, world_radius, .. } => {
+                let pdf_pos = 1.0 / (f32::consts::PI * world_radius * world_radius);
+                (pdf_pos, sky.pdf(&-*w, &(self.transform.transform(time) * *sun)))
+            },
+        }
+    }
+    fn power(&self, time: f32) -> Colorf {
+        match self.emitter {
+            EmitterType::Point { ref ies } => {
+                let average = ies.as_ref().map_or(1.0, |p| p.average());
+                self.emission.color(time) * 4.0 * f32::consts::PI * average
+            },
+            // All the light passes through a disk the size of the scene
+            EmitterType::Directional { world_radius, .. } => {
+                self.emission.color(time) * f32::consts::PI * world_radius * world_radius
+            },
+            // Approximate the falloff as being linear in the cosine, with a profile
+            // the power can't exceed what the profile emits over the sphere
+            EmitterType::Spot { cos_inner, cos_outer, ref ies } => {
+                let cone = 2.0 * f32::consts::PI * (1.0 - 0.5 * (cos_inner + cos_outer));
+                let sphere = ies.as_ref().map_or(cone, |p| 4.0 * f32::consts::PI * p.average());
+                self.emission.color(time) * f32::min(cone, sphere)
+            },
+            EmitterType::Area(ref g, _) => {
+                // The area scale is only the same over the whole surface for uniform
+                // scaling, otherwise this is an approximation of the world space area
+                let transform = self.transform.transform(time);
+                let (_, n) = g.sample_uniform(&(0.5, 0.5));
+                let area = g.surface_area() * area_scale(&transform, &n);
+                self.emission.color(time) * f32::consts::PI * area
+            },
+            EmitterType::Environment { ref map, world_radius, .. } => {
+                // The light arriving from all directions passes through a disk
+                // the size of the scene
+                map.average() * self.emission.color(time) * 4.0 * f32::consts::PI
+                    * f32::consts::PI * world_radius * world_radius
+            },
+            EmitterType::Sky { ref sky, world_radius, .. } => {
+                sky.average() * self.emission.color(time) * 4.0 * f32::consts::PI
+                    * f32::consts::PI * world_radius * world_radius
+            },
+        }
+    }
+    fn escaped_radiance(&self, ray: &Ray) -> Colorf {
+        match self.emitter {
+            EmitterType::Environment { ref map, .. } => {
+                let transform = self.transform.transform(ray.time);
+                map.lookup(&transform.inv_mul_vector(&ray.d)) * self.emission.color(ray.time)
+            },
+            EmitterType::Sky { ref sky, ref sun, .. } => {
+                let sun = self.transform.transform(ray.time) * *sun;
+                sky.radiance(&ray.d, &sun) * self.emission.color(ray.time)
+            },
+            _ => Colorf::black(),
+        }
+    }
+}
+
+#[test]
+fn test_point_emitted() {
+    use film::ColorKeyframe;
+    let emission = AnimatedColor::with_keyframes(vec![ColorKeyframe::new(&Colorf::broadcast(2.0), 0.0)]);
+    let transform = AnimatedTransform::unanimated(&Transform::translate(&Vector::new(1.0, 2.0, 3.0)));
+    let light = Emitter::point(transform, emission, None, "light".to_owned());
+    let (li, ray, n, pdf_pos, pdf_dir) = light.sample_emitted(&(0.3, 0.6), &(0.2, 0.7), 0.0);
+    assert_eq!(ray.o, Point::new(1.0, 2.0, 3.0));
+    assert_eq!(li, Colorf::broadcast(2.0));
+    assert!(pdf_pos > 0.0);
+    assert_eq!(light.pdf_emitted(&ray.o, &n, &ray.d, 0.0).1, pdf_dir);
+    assert_eq!(light.power(0.0).r, 8.0 * f32::consts::PI);
 }
 
+#[test]
+fn test_area_emitted() {
+    use film::ColorKeyframe;
+    use geometry::Rectangle;
+    use material::Matte;
+    use texture::ConstantTexture;
+    let emission = AnimatedColor::with_keyframes(vec![ColorKeyframe::new(&Colorf::broadcast(1.0), 0.0)]);
+    let transform = AnimatedTransform::unanimated(&(Transform::translate(&Vector::new(0.0, 0.0, 5.0))
+                                                    * Transform::scale(&Vector::broadcast(2.0))));
+    let matte = Matte::new(Arc::new(ConstantTexture::new(Colorf::broadcast(0.5))), Arc::new(ConstantTexture::new(0.0)));
+    let light = Emitter::area(Arc::new(Rectangle::new(2.0, 4.0)), Arc::new(matte),
+                              emission, transform, "light".to_owned());
+    let world_area = 2.0 * 4.0 * 4.0;
+    for &(u, v) in &[(0.1, 0.9), (0.5, 0.5), (0.8, 0.25)] {
+        let (li, ray, n, pdf_pos, pdf_dir) = light.sample_emitted(&(u, v), &(v, u), 0.0);
+        assert_eq!(ray.o.z, 5.0);
+        assert!(linalg::dot(&n, &ray.d) > 0.0);
+        assert!(!li.is_black());
+        assert!(f32::abs(pdf_pos - 1.0 / world_area) < 1e-6);
+        let (pdf_pos_eval, pdf_dir_eval) = light.pdf_emitted(&ray.o, &n, &ray.d, 0.0);
+        assert!(f32::abs(pdf_pos - pdf_pos_eval) < 1e-6);
+        assert!(f32::abs(pdf_dir - pdf_dir_eval) < 1e-4);
+    }
+    assert!(f32::abs(light.power(0.0).r - world_area * f32::consts::PI) < 1e-3);
+}
+
+#[test]
+fn test_spot_light() {
+    use film::ColorKeyframe;
+    use linalg::Keyframe;
+    // A spot light moving along +x while pointing down, its +z axis is rotated to -y
+    let emission = AnimatedColor::with_keyframes(vec![ColorKeyframe::new(&Colorf::broadcast(2.0), 0.0)]);
+    let keyframes = vec![Keyframe::new(&(Transform::translate(&Vector::new(0.0, 5.0, 0.0))
+                                         * Transform::rotate_x(90.0))),
+                         Keyframe::new(&(Transform::translate(&Vector::new(2.0, 5.0, 0.0))
+                                         * Transform::rotate_x(90.0)))];
+    let transform = AnimatedTransform::with_keyframes(keyframes, vec![0.0, 0.0, 1.0, 1.0], 1);
+    let light = Emitter::spot(transform, emission, 20.0, 40.0, None, "light".to_owned());
+    let pos = Point::new(1.0, 5.0, 0.0);
+    // Check the light arriving at points 4 units from the light at some angle to its axis
+    let incident = |deg: f32| {
+        let theta = linalg::to_radians(deg);
+        let p = pos + 4.0 * Vector::new(f32::sin(theta), -f32::cos(theta), 0.0);
+        let (li, w_i, pdf, _) = light.sample_incident(&p, &(0.5, 0.5), 0.5);
+        assert!(linalg::dot(&w_i, &(pos - p).normalized()) > 0.9999);
+        assert_eq!(pdf, 1.0);
+        li.r * 16.0 / 2.0
+    };
+    assert!(f32::abs(incident(0.0) - 1.0) < 1e-4);
+    assert!(f32::abs(incident(15.0) - 1.0) < 1e-4);
+    assert_eq!(incident(45.0), 0.0);
+    assert_eq!(incident(180.0), 0.0);
+    let (cos_inner, cos_outer) = (f32::cos(linalg::to_radians(20.0)), f32::cos(linalg::to_radians(40.0)));
+    let t = (f32::cos(linalg::to_radians(30.0)) - cos_outer) / (cos_inner - cos_outer);
+    assert!(f32::abs(incident(30.0) - t * t * (3.0 - 2.0 * t)) < 1e-4);
+
+    // Emitted rays stay within the outer cone and the direction pdf integrates to 1 over the sphere
+    let axis = Vector::new(0.0, -1.0, 0.0);
+    let n = 200;
+    let mut integral = 0.0;
+    for i in 0..n {
+        for j in 0..n {
+            let u = ((i as f32 + 0.5) / n as f32, (j as f32 + 0.5) / n as f32);
+            let (_, ray, normal, _, pdf_dir) = light.sample_emitted(&(0.5, 0.5), &u, 0.5);
+            assert!(linalg::dot(&ray.d, &axis) >= cos_outer - 1e-4);
+            assert!(f32::abs(pdf_dir - mc::uniform_cone_pdf(cos_outer)) < 1e-4);
+            assert!(f32::abs(light.pdf_emitted(&ray.o, &normal, &ray.d, 0.5).1 - pdf_dir) < 1e-4);
+            let w = mc::uniform_sample_sphere(&u);
+            integral += light.pdf_emitted(&pos, &normal, &w, 0.5).1 / (mc::uniform_sphere_pdf() * (n * n) as f32);
+        }
+    }
+    assert!(f32::abs(integral - 1.0) < 0.01, "spot light direction pdf integrates to {}", integral);
+}
+
+#[test]
+fn test_directional_light() {
+    use film::ColorKeyframe;
+    // A directional light shining down, its +z axis is rotated to -y
+    let emission = AnimatedColor::with_keyframes(vec![ColorKeyframe::new(&Colorf::broadcast(3.0), 0.0)]);
+    let mut light = Emitter::directional(AnimatedTransform::unanimated(&Transform::rotate_x(90.0)), emission,
+                                         "light".to_owned());
+    let p = Point::new(0.5, -1.0, 0.25);
+    let (li, w_i, pdf, occlusion) = light.sample_incident(&p, &(0.3, 0.7), 0.0);
+    assert!(linalg::dot(&w_i, &Vector::new(0.0, 1.0, 0.0)) > 0.9999);
+    assert_eq!(li, Colorf::broadcast(3.0));
+    assert_eq!(pdf, 1.0);
+    assert_eq!(occlusion.ray.o, p);
+    assert_eq!(occlusion.ray.max_t, f32::INFINITY);
+
+    // The scene is bounded by a sphere at (1, 0, -1) with radius sqrt(12)
+    light.set_world_bounds(&BBox::span(Point::new(-1.0, -2.0, -3.0), Point::new(3.0, 2.0, 1.0)));
+    let (center, radius) = (Point::new(1.0, 0.0, -1.0), f32::sqrt(12.0));
+    let disk_area = f32::consts::PI * radius * radius;
+    assert!(f32::abs(light.power(0.0).r - 3.0 * disk_area) < 1e-3);
+    // Emitted rays leave a disk above the scene covering it, sampled uniformly by area
+    let n = 32;
+    let mut max_dist: f32 = 0.0;
+    for i in 0..n {
+        for j in 0..n {
+            let u = ((i as f32 + 0.5) / n as f32, (j as f32 + 0.5) / n as f32);
+            let (le, ray, normal, pdf_pos, pdf_dir) = light.sample_emitted(&u, &(0.5, 0.5), 0.0);
+            assert_eq!(le, Colorf::broadcast(3.0));
+            assert!(linalg::dot(&ray.d, &Vector::new(0.0, -1.0, 0.0)) > 0.9999);
+            assert!(f32::abs(ray.o.y - radius) < 1e-4);
+            let dist = Vector::new(ray.o.x - center.x, 0.0, ray.o.z - center.z).length();
+            assert!(dist <= radius + 1e-4);
+            max_dist = f32::max(max_dist, dist);
+            assert!(f32::abs(pdf_pos - 1.0 / disk_area) < 1e-6);
+            assert_eq!(pdf_dir, 1.0);
+            assert!(f32::abs(light.pdf_emitted(&ray.o, &normal, &ray.d, 0.0).0 - pdf_pos) < 1e-6);
+        }
+    }
+    assert!(max_dist > 0.95 * radius);
+}
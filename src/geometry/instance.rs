@@ -65,8 +65,9 @@ use std::sync::Arc;
 use geometry::{Intersection, Boundable, BBox, BoundableGeom, Receiver, Emitter,
                SampleableGeom};
 use material::Material;
-use linalg::{Ray, AnimatedTransform};
+use linalg::{Ray, Vector, AnimatedTransform};
 use film::AnimatedColor;
+use light::{EnvironmentMap, SunSky, IesProfile};
 
 /// Defines an instance of some geometry with its own transform and material
 pub enum Instance {
@@ -86,9 +87,32 @@ impl Instance {
         Instance::Emitter(Emitter::area(geom, material, emission, transform, tag))
     }
     /// Create a point light at the origin that is transformed by `transform` to its location
-    /// in the world
-    pub fn point_light(transform: AnimatedTransform, emission: AnimatedColor, tag: String) ->  Instance {
-        Instance::Emitter(Emitter::point(transform, emission, tag))
+    /// in the world, optionally shaped by an IES profile
+    pub fn point_light(transform: AnimatedTransform, emission: AnimatedColor, ies: Option<Arc<IesProfile>>,
+                       tag: String) ->  Instance {
+        Instance::Emitter(Emitter::point(transform, emission, ies, tag))
+    }
+    /// Create a spot light at the origin shining down +z that is transformed by `transform`
+    /// to its location and orientation in the world, with inner and outer cone angles in degrees
+    /// and optionally shaped by an IES profile
+    pub fn spot_light(transform: AnimatedTransform, emission: AnimatedColor, inner_angle: f32, outer_angle: f32,
+                      ies: Option<Arc<IesProfile>>, tag: String) -> Instance {
+        Instance::Emitter(Emitter::spot(transform, emission, inner_angle, outer_angle, ies, tag))
+    }
+    /// Create a directional light shining along +z rotated by `transform`
+    pub fn directional_light(transform: AnimatedTransform, emission: AnimatedColor, tag: String) -> Instance {
+        Instance::Emitter(Emitter::directional(transform, emission, tag))
+    }
+    /// Create an environment light surrounding the scene, oriented by `transform`
+    pub fn environment_light(map: Arc<EnvironmentMap>, transform: AnimatedTransform, emission: AnimatedColor,
+                             tag: String) -> Instance {
+        Instance::Emitter(Emitter::environment(map, transform, emission, tag))
+    }
+    /// Create a sky light surrounding the scene with the sun in the direction `sun`,
+    /// which is rotated by `transform` to animate the sun
+    pub fn sky_light(sky: SunSky, sun: &Vector, transform: AnimatedTransform, emission: AnimatedColor,
+                     tag: String) -> Instance {
+        Instance::Emitter(Emitter::sky(sky, sun, transform, emission, tag))
     }
     /// Test the ray for intersection against this insance of geometry.
     /// returns Some(Intersection) if an intersection was found and None if not.
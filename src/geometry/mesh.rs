@@ -8,27 +8,85 @@
 //! assigned to the model in the file it will be given the name "`unnamed_model`",
 //! however it's recommended to name your models.
 //!
+//! Models without normals are given smooth normals computed from their faces and models without
+//! texture coordinates have their positions projected onto a plane to make some. The optional
+//! 'crease_angle' parameter re-computes the normals, keeping edges where faces meet at more than
+//! 'crease_angle' degrees sharp. Setting the optional 'smooth' parameter to false shades each
+//! triangle with its geometric normal, rendering the model faceted.
+//!
+//! Meshes can also be loaded from PLY files, which are recognized by their '.ply' extension.
+//! A PLY file holds a single model named after the file, e.g. "bunny" for "bunny.ply", so
+//! the 'model' parameter can be left out. PLY files don't have materials so 'use_mtl' is ignored.
+//!
+//! Setting the optional 'use_mtl' parameter to true will shade the model with the material
+//! assigned to it in the OBJ's MTL file, the object's 'material' is then only required for
+//! models without an MTL material. MTL materials are converted as follows: materials with a
+//! dissolve ('d') below 1 become glass transmitting the diffuse color ('Kd') with the refractive
+//! index 'Ni' (default 1.5), materials with a specular color ('Ks') become plastic with a roughness
+//! computed from the specular exponent ('Ns') and all others become matte. A diffuse texture
+//! ('map_Kd') replaces the diffuse color. If a material in the scene's materials list has the same
+//! name as the MTL material it will be used instead, allowing the MTL materials to be overridden.
+//!
 //! ```json
 //! "geometry": {
 //!     "type": "mesh",
 //!     "file": "./suzanne.obj",
-//!     "model": "Suzanne"
+//!     "model": "Suzanne",
+//!     "use_mtl": true
+//! }
+//! ```
+//!
+//! ```json
+//! "geometry": {
+//!     "type": "mesh",
+//!     "file": "./bunny.ply",
+//!     "crease_angle": 30
 //! }
 //! ```
+//!
+//! Meshes can also be used as the geometry of area lights, e.g. for emissive signs or lamp
+//! shades modeled in another program. Points are sampled on the mesh uniformly by area and
+//! the light is emitted from the side of each triangle its vertex normals face.
 
 extern crate tobj;
 
+use std::f32;
 use std::sync::Arc;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::collections::HashMap;
 
-use geometry::{Geometry, DifferentialGeometry, Boundable, BBox, BVH};
+use geometry::{Geometry, DifferentialGeometry, Boundable, BBox, BVH, Sampleable};
+use geometry::ply;
 use linalg::{self, Normal, Vector, Ray, Point};
+use film::Colorf;
+use mc::Distribution1D;
+
+/// The properties of a material loaded from an OBJ's MTL file
+#[derive(Clone, Debug)]
+pub struct MtlMaterial {
+    /// The name of the material in the MTL file
+    pub name: String,
+    /// The diffuse color, 'Kd'
+    pub diffuse: Colorf,
+    /// The specular color, 'Ks'
+    pub specular: Colorf,
+    /// The specular exponent, 'Ns'
+    pub shininess: f32,
+    /// The refractive index, 'Ni'. Defaults to 1.5 if not set
+    pub ior: f32,
+    /// The opacity of the material, 'd'
+    pub dissolve: f32,
+    /// The diffuse texture file, 'map_Kd', resolved relative to the OBJ file
+    pub diffuse_texture: Option<PathBuf>,
+}
 
 /// A mesh composed of triangles, specified by directly passing the position,
 /// normal and index buffers for the triangles making up the mesh
 pub struct Mesh {
     bvh: BVH<Triangle>,
+    /// Distribution for picking triangles in proportion to their area
+    area_distrib: Distribution1D,
+    surface_area: f32,
 }
 
 impl Mesh {
@@ -40,43 +98,198 @@ impl Mesh {
         let triangles = indices.chunks(3).map(|i| {
             Triangle::new(i[0] as usize, i[1] as usize, i[2] as usize, positions.clone(),
                           normals.clone(), texcoords.clone())
-            }).collect();
-        Mesh { bvh: BVH::unanimated(16, triangles) }
+            }).collect::<Vec<_>>();
+        let areas: Vec<_> = triangles.iter().map(|t| t.area()).collect();
+        let surface_area = areas.iter().fold(0.0, |s, a| s + a);
+        Mesh { bvh: BVH::unanimated(16, triangles), area_distrib: Distribution1D::new(&areas[..]),
+               surface_area: surface_area }
+    }
+    /// Create a new Mesh from the buffers loaded for the model `name`, computing smooth normals
+    /// if the model doesn't have any and projecting the positions to get texture coordinates if
+    /// it doesn't have those
+    pub fn with_missing_attributes(name: &str, positions: Vec<Point>, normals: Vec<Normal>, texcoords: Vec<Point>,
+                               indices: Vec<u32>) -> Mesh {
+        let texcoords = if texcoords.is_empty() {
+            println!("Mesh warning! {} has no texture coordinates, projecting them onto a plane", name);
+            planar_texcoords(&positions)
+        } else {
+            texcoords
+        };
+        let (positions, normals, texcoords, indices) = if normals.is_empty() {
+            println!("Mesh warning! {} has no normals, computing smooth normals", name);
+            compute_normals(&positions, &texcoords, &indices, 180.0)
+        } else {
+            (positions, normals, texcoords, indices)
+        };
+        Mesh::new(Arc::new(positions), Arc::new(normals), Arc::new(texcoords), indices)
+    }
+    /// Create a copy of the mesh with its normals re-computed from its faces, ignoring the
+    /// normals it was loaded with. Faces meeting at an angle larger than `crease_angle` degrees
+    /// are shaded separately, giving a sharp edge between them, so a crease angle of 0 gives
+    /// each triangle its geometric normal to render the mesh faceted
+    pub fn with_normals(&self, crease_angle: f32) -> Mesh {
+        let first = self.bvh.get(0);
+        let indices: Vec<_> = self.bvh.iter().flat_map(|t| vec![t.a as u32, t.b as u32, t.c as u32]).collect();
+        let (positions, normals, texcoords, indices) = compute_normals(&first.positions, &first.texcoords, &indices,
+                                                                       crease_angle);
+        Mesh::new(Arc::new(positions), Arc::new(normals), Arc::new(texcoords), indices)
     }
     /// Load all the meshes defined in an OBJ file and return them in a hashmap that maps the
     /// model's name in the file to its loaded mesh
-    /// TODO: Currently materials are ignored
     pub fn load_obj(file_name: &Path) -> HashMap<String, Arc<Mesh>> {
+        Mesh::load_obj_with_materials(file_name).0
+    }
+    /// Load the mesh in a PLY file and return it in a hashmap that maps the file's name without
+    /// its extension to the mesh, as used by `load_obj`
+    pub fn load_ply(file_name: &Path) -> HashMap<String, Arc<Mesh>> {
+        let mut meshes = HashMap::new();
+        let name = match file_name.file_stem() {
+            Some(s) => s.to_string_lossy().into_owned(),
+            None => "unnamed_model".to_owned(),
+        };
+        match ply::load_file(file_name) {
+            Ok(mesh) => {
+                println!("Loading model {}", name);
+                println!("{} has {} triangles", name, mesh.indices.len() / 3);
+                let mesh = Mesh::with_missing_attributes(&name, mesh.positions, mesh.normals, mesh.texcoords,
+                                                         mesh.indices);
+                meshes.insert(name, Arc::new(mesh));
+            },
+            Err(e) => println!("Failed to load {:?} due to {}", file_name, e),
+        }
+        meshes
+    }
+    /// Load all the meshes defined in an OBJ file along with the materials assigned to them in
+    /// its MTL file. Returns the hashmap mapping the model's name to its loaded mesh and a hashmap
+    /// mapping the model's name to its material, models without a material aren't included
+    pub fn load_obj_with_materials(file_name: &Path) -> (HashMap<String, Arc<Mesh>>, HashMap<String, MtlMaterial>) {
         match tobj::load_obj(file_name) {
-            Ok((models, _)) => {
+            Ok((models, materials)) => {
+                let base_path = file_name.parent().unwrap_or(Path::new(""));
+                let materials: Vec<_> = materials.iter().map(|m| MtlMaterial::from_tobj(m, base_path)).collect();
                 let mut meshes = HashMap::new();
+                let mut model_materials = HashMap::new();
                 for m in models {
                     println!("Loading model {}", m.name);
                     let mesh = m.mesh;
-                    if mesh.normals.is_empty() || mesh.texcoords.is_empty() {
-                        print!("Mesh::load_obj error! Normals and texture coordinates are required!");
-                        println!("Skipping {}", m.name);
-                        continue;
-                    }
                     println!("{} has {} triangles", m.name, mesh.indices.len() / 3);
-                    let positions = Arc::new(mesh.positions.chunks(3).map(|i| Point::new(i[0], i[1], i[2]))
-                                             .collect());
-                    let normals = Arc::new(mesh.normals.chunks(3).map(|i| Normal::new(i[0], i[1], i[2]))
-                                           .collect());
-                    let texcoords = Arc::new(mesh.texcoords.chunks(2).map(|i| Point::new(i[0], i[1], 0.0))
-                                             .collect());
-                    meshes.insert(m.name, Arc::new(Mesh::new(positions, normals, texcoords, mesh.indices)));
+                    let positions = mesh.positions.chunks(3).map(|i| Point::new(i[0], i[1], i[2])).collect();
+                    let normals = mesh.normals.chunks(3).map(|i| Normal::new(i[0], i[1], i[2])).collect();
+                    let texcoords = mesh.texcoords.chunks(2).map(|i| Point::new(i[0], i[1], 0.0)).collect();
+                    if let Some(id) = mesh.material_id {
+                        model_materials.insert(m.name.clone(), materials[id].clone());
+                    }
+                    let loaded = Mesh::with_missing_attributes(&m.name, positions, normals, texcoords, mesh.indices);
+                    meshes.insert(m.name, Arc::new(loaded));
                 }
-                meshes
+                (meshes, model_materials)
             },
             Err(e) => {
                 println!("Failed to load {:?} due to {:?}", file_name, e);
-                HashMap::new()
+                (HashMap::new(), HashMap::new())
             },
         }
     }
 }
 
+/// Compute texture coordinates for the vertices by projecting them onto the plane spanned by
+/// the two longest axes of their bounds, scaled to the range [0, 1] along the longest axis
+fn planar_texcoords(positions: &[Point]) -> Vec<Point> {
+    let bounds = positions.iter().fold(BBox::new(), |b, p| b.point_union(p));
+    let extent = bounds.max - bounds.min;
+    let mut axes = [0, 1, 2];
+    axes.sort_by(|a, b| extent[*b].partial_cmp(&extent[*a]).unwrap());
+    let scale = if extent[axes[0]] > 0.0 { 1.0 / extent[axes[0]] } else { 1.0 };
+    positions.iter().map(|p| {
+        let d = *p - bounds.min;
+        Point::new(d[axes[0]] * scale, d[axes[1]] * scale, 0.0)
+    }).collect()
+}
+
+/// Compute smooth normals for the triangles in `indices`, the normal at a vertex is the average
+/// of the normals of the faces sharing it weighted by the angle of each face at the vertex. Faces
+/// only share their normals with neighbors they meet at less than `crease_angle` degrees, vertices
+/// on a crease are split so each side of it gets its own normal. Returns the positions, normals,
+/// texture coordinates and indices of the mesh with the split vertices
+fn compute_normals(positions: &[Point], texcoords: &[Point], indices: &[u32], crease_angle: f32)
+                   -> (Vec<Point>, Vec<Normal>, Vec<Point>, Vec<u32>) {
+    let face_normals: Vec<_> = indices.chunks(3).map(|t| {
+        let n = linalg::cross(&(positions[t[1] as usize] - positions[t[0] as usize]),
+                              &(positions[t[2] as usize] - positions[t[0] as usize]));
+        if n.length_sqr() > 0.0 { n.normalized() } else { n }
+    }).collect();
+    // The angle of each triangle at each of its vertices
+    let corner_angles: Vec<_> = (0..indices.len()).map(|c| {
+        let tri = c - c % 3;
+        let p = positions[indices[c] as usize];
+        let e0 = positions[indices[tri + (c + 1) % 3] as usize] - p;
+        let e1 = positions[indices[tri + (c + 2) % 3] as usize] - p;
+        let cos = linalg::dot(&e0, &e1) / f32::sqrt(e0.length_sqr() * e1.length_sqr());
+        if cos.is_finite() { f32::acos(linalg::clamp(cos, -1.0, 1.0)) } else { 0.0 }
+    }).collect();
+    // The corners of the triangles sharing each vertex
+    let mut vertex_corners = vec![Vec::new(); positions.len()];
+    for (c, i) in indices.iter().enumerate() {
+        vertex_corners[*i as usize].push(c);
+    }
+
+    let cos_crease = f32::cos(linalg::to_radians(f32::min(crease_angle, 180.0)));
+    let mut out_positions = Vec::with_capacity(positions.len());
+    let mut out_normals = Vec::with_capacity(positions.len());
+    let mut out_texcoords = Vec::with_capacity(positions.len());
+    let mut out_indices = Vec::with_capacity(indices.len());
+    // The normals created for each vertex so far and the index of the vertex created for each
+    let mut vertex_normals: Vec<Vec<(Vector, u32)>> = vec![Vec::new(); positions.len()];
+    for (c, i) in indices.iter().enumerate() {
+        let i = *i as usize;
+        let face_n = face_normals[c / 3];
+        let n = vertex_corners[i].iter().fold(Vector::broadcast(0.0), |n, k| {
+            let other_n = face_normals[k / 3];
+            if k / 3 == c / 3 || linalg::dot(&face_n, &other_n) >= cos_crease {
+                n + other_n * corner_angles[*k]
+            } else {
+                n
+            }
+        });
+        let n = if n.length_sqr() > 0.0 { n.normalized() } else { face_n };
+        // Re-use the vertex if another corner already created it with the same normal
+        let existing = vertex_normals[i].iter().find(|v| v.0 == n).map(|v| v.1);
+        let index = match existing {
+            Some(index) => index,
+            None => {
+                let index = out_positions.len() as u32;
+                out_positions.push(positions[i]);
+                out_normals.push(Normal::new(n.x, n.y, n.z));
+                out_texcoords.push(texcoords[i]);
+                vertex_normals[i].push((n, index));
+                index
+            },
+        };
+        out_indices.push(index);
+    }
+    (out_positions, out_normals, out_texcoords, out_indices)
+}
+
+impl MtlMaterial {
+    /// Get the properties of the material loaded by tobj, texture files are resolved
+    /// relative to `base_path`
+    fn from_tobj(m: &tobj::Material, base_path: &Path) -> MtlMaterial {
+        let ior = match m.unknown_param.get("Ni") {
+            Some(ni) => ni.parse().unwrap_or(1.5),
+            None => 1.5,
+        };
+        let diffuse_texture = if m.diffuse_texture.is_empty() {
+            None
+        } else {
+            Some(base_path.join(&m.diffuse_texture))
+        };
+        MtlMaterial { name: m.name.clone(), diffuse: Colorf::new(m.diffuse[0], m.diffuse[1], m.diffuse[2]),
+                      specular: Colorf::new(m.specular[0], m.specular[1], m.specular[2]),
+                      shininess: m.shininess, ior: ior, dissolve: m.dissolve,
+                      diffuse_texture: diffuse_texture }
+    }
+}
+
 impl Geometry for Mesh {
     fn intersect(&self, ray: &mut linalg::Ray) -> Option<DifferentialGeometry> {
         self.bvh.intersect(ray, |r, i| i.intersect(r))
@@ -89,6 +302,38 @@ impl Boundable for Mesh {
     }
 }
 
+impl Sampleable for Mesh {
+    /// Pick a triangle with probability proportional to its area and uniformly sample
+    /// a point on it, the first sample is re-used to sample within the triangle after
+    /// picking it
+    fn sample_uniform(&self, samples: &(f32, f32)) -> (Point, Normal) {
+        let (x, _, i) = self.area_distrib.sample_continuous(samples.0);
+        let u = f32::min(x * self.area_distrib.count() as f32 - i as f32, 1.0 - f32::EPSILON);
+        self.bvh.get(i).sample_uniform(&(u, samples.1))
+    }
+    fn sample(&self, _: &Point, samples: &(f32, f32)) -> (Point, Normal) {
+        self.sample_uniform(samples)
+    }
+    fn surface_area(&self) -> f32 {
+        self.surface_area
+    }
+    /// Compute the PDF that the ray from `p` with direction `w_i` intersects the mesh.
+    /// Since points are sampled uniformly by area this is the area pdf converted to solid
+    /// angle at the first triangle hit by the ray
+    fn pdf(&self, p: &Point, w_i: &Vector) -> f32 {
+        let mut ray = Ray::segment(p, w_i, 0.001, f32::INFINITY, 0.0);
+        match self.intersect(&mut ray) {
+            Some(d) => {
+                let w = -*w_i;
+                let pdf = p.distance_sqr(&ray.at(ray.max_t))
+                    / (f32::abs(linalg::dot(&d.ng, &w)) * self.surface_area);
+                if f32::is_finite(pdf) { pdf } else { 0.0 }
+            },
+            None => 0.0
+        }
+    }
+}
+
 /// A triangle in some mesh. Just stores a reference to the mesh
 /// and the indices of each vertex
 pub struct Triangle {
@@ -107,6 +352,30 @@ impl Triangle {
         Triangle { a: a, b: b, c: c, positions: positions, normals: normals,
                    texcoords: texcoords }
     }
+    /// Compute the area of the triangle
+    fn area(&self) -> f32 {
+        let pa = &self.positions[self.a];
+        0.5 * linalg::cross(&(self.positions[self.b] - *pa), &(self.positions[self.c] - *pa)).length()
+    }
+    /// Compute the triangle's geometric normal, facing the same side as the normal
+    /// `n` interpolated from its vertices
+    fn face_normal(&self, n: &Normal) -> Normal {
+        let pa = &self.positions[self.a];
+        let ng = linalg::cross(&(self.positions[self.b] - *pa), &(self.positions[self.c] - *pa)).normalized();
+        let ng = Normal::new(ng.x, ng.y, ng.z);
+        if linalg::dot(&ng, n) < 0.0 { -ng } else { ng }
+    }
+    /// Uniformly sample a point on the triangle, returning the point and the
+    /// triangle's geometric normal
+    fn sample_uniform(&self, samples: &(f32, f32)) -> (Point, Normal) {
+        // Map the samples to barycentric coordinates distributed uniformly over the triangle
+        let s = f32::sqrt(samples.0);
+        let bary = [1.0 - s, samples.1 * s, (1.0 - samples.1) * s];
+        let pa = &self.positions[self.a];
+        let p = *pa + bary[1] * (self.positions[self.b] - *pa) + bary[2] * (self.positions[self.c] - *pa);
+        let n = bary[0] * self.normals[self.a] + bary[1] * self.normals[self.b] + bary[2] * self.normals[self.c];
+        (p, self.face_normal(&n))
+    }
 }
 
 impl Geometry for Triangle {
@@ -176,7 +445,11 @@ impl Geometry for Triangle {
                 let dp_dv = (-du[1] * dp[0] + du[0] * dp[1]) * det;
                 (dp_du, dp_dv)
             };
-        Some(DifferentialGeometry::with_normal(&p, &n, &dp_du, &dp_dv, self))
+        let u = bary[0] * ta.x + bary[1] * tb.x + bary[2] * tc.x;
+        let v = bary[0] * ta.y + bary[1] * tb.y + bary[2] * tc.y;
+        let mut dg = DifferentialGeometry::with_normal(&p, &n, u, v, &dp_du, &dp_dv, self);
+        dg.ng = self.face_normal(&n);
+        Some(dg)
     }
 }
 
@@ -188,3 +461,126 @@ impl Boundable for Triangle {
     }
 }
 
+#[test]
+fn test_triangle_texcoords() {
+    let positions = Arc::new(vec![Point::new(0.0, 0.0, 0.0), Point::new(1.0, 0.0, 0.0), Point::new(0.0, 1.0, 0.0)]);
+    let normals = Arc::new(vec![Normal::new(0.0, 0.0, 1.0); 3]);
+    let texcoords = Arc::new(vec![Point::new(0.5, 0.5, 0.0), Point::new(1.0, 0.5, 0.0), Point::new(0.5, 0.0, 0.0)]);
+    let tri = Triangle::new(0, 1, 2, positions, normals, texcoords);
+    let mut ray = Ray::new(&Point::new(0.25, 0.5, 1.0), &Vector::new(0.0, 0.0, -1.0), 0.0);
+    let dg = tri.intersect(&mut ray).expect("The ray should hit the triangle");
+    // The hit has barycentric coordinates (0.25, 0.25, 0.5) so the texcoords are interpolated
+    assert!(f32::abs(dg.u - 0.625) < 1e-6 && f32::abs(dg.v - 0.25) < 1e-6);
+    // Moving along the triangle's edges moves through the texture coordinates
+    assert!((dg.dp_du - Vector::new(2.0, 0.0, 0.0)).length() < 1e-5);
+    assert!((dg.dp_dv - Vector::new(0.0, -2.0, 0.0)).length() < 1e-5);
+}
+
+#[test]
+fn test_load_mtl() {
+    use std::{env, fs, process};
+    use std::fs::File;
+    use std::io::Write;
+
+    let dir = env::temp_dir().join(format!("tray_rust_test_load_mtl_{}", process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let obj_file = dir.join("tray_rust_test_load_mtl.obj");
+    let mut obj = File::create(&obj_file).unwrap();
+    obj.write_all(b"mtllib tray_rust_test_load_mtl.mtl\no Tri\nv 0 0 0\nv 1 0 0\nv 0 1 0\nvt 0 0\nvt 1 0\nvt 0 1\n\
+                    vn 0 0 1\nusemtl shiny\nf 1/1/1 2/2/1 3/3/1\no Plain\nv 0 0 0\nv 1 0 0\nv 0 1 0\nvt 0 0\n\
+                    vt 1 0\nvt 0 1\nvn 0 0 1\nusemtl plain\nf 4/4/2 5/5/2 6/6/2\n").unwrap();
+    let mut mtl = File::create(dir.join("tray_rust_test_load_mtl.mtl")).unwrap();
+    mtl.write_all(b"newmtl shiny\nKd 0.5 0.25 0\nKs 1 1 1\nNs 50\nNi 1.33\nd 1\nmap_Kd wood.png\n\
+                    newmtl plain\nKd 1 1 1\n").unwrap();
+
+    let (meshes, materials) = Mesh::load_obj_with_materials(&obj_file);
+    assert_eq!(meshes.len(), 2);
+    assert_eq!(materials.len(), 2);
+    assert_eq!(materials["Plain"].name, "plain");
+    assert_eq!(materials["Plain"].ior, 1.5);
+    assert_eq!(materials["Plain"].diffuse_texture, None);
+    let m = &materials["Tri"];
+    assert_eq!(m.name, "shiny");
+    assert_eq!(m.diffuse, Colorf::new(0.5, 0.25, 0.0));
+    assert_eq!(m.specular, Colorf::broadcast(1.0));
+    assert_eq!(m.shininess, 50.0);
+    assert_eq!(m.ior, 1.33);
+    assert_eq!(m.diffuse_texture, Some(dir.join("wood.png")));
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_mesh_sampling() {
+    use rand::{Rng, SeedableRng, StdRng};
+    use mc;
+
+    // A flat quad made of a small and a large triangle, so picking the triangles by
+    // area is different from picking them uniformly
+    let positions = Arc::new(vec![Point::new(0.0, 0.0, 0.0), Point::new(1.0, 0.0, 0.0), Point::new(0.0, 1.0, 0.0),
+                                  Point::new(3.0, 3.0, 0.0)]);
+    let normals = Arc::new(vec![Normal::new(0.0, 0.0, 1.0); 4]);
+    let texcoords = Arc::new(vec![Point::broadcast(0.0); 4]);
+    let mesh = Mesh::new(positions, normals, texcoords, vec![0, 1, 2, 1, 3, 2]);
+    assert!(f32::abs(mesh.surface_area() - 3.0) < 1e-5);
+
+    let mut rng: StdRng = SeedableRng::from_seed(&[1][..]);
+    let n = 100000;
+    let mut small = 0;
+    for _ in 0..n {
+        let (p, normal) = mesh.sample_uniform(&(rng.next_f32(), rng.next_f32()));
+        assert_eq!(normal, Normal::new(0.0, 0.0, 1.0));
+        if p.x + p.y < 1.0 {
+            small += 1;
+        }
+    }
+    // The small triangle has a sixth of the mesh's area
+    let frac = small as f32 / n as f32;
+    assert!(f32::abs(frac - 1.0 / 6.0) < 0.01, "{} of the samples were in the small triangle", frac);
+
+    // Integrating the solid angle pdf seen from a point above the mesh should give 1
+    let p = Point::new(0.5, 0.5, 1.0);
+    let mut integral = 0.0;
+    for _ in 0..n {
+        let w = mc::uniform_sample_sphere(&(rng.next_f32(), rng.next_f32()));
+        integral += mesh.pdf(&p, &w) / mc::uniform_sphere_pdf();
+    }
+    integral /= n as f32;
+    assert!(f32::abs(integral - 1.0) < 0.02, "The pdf integrates to {}", integral);
+}
+
+#[test]
+fn test_compute_normals() {
+    use std::{env, fs, process};
+    use std::fs::File;
+    use std::io::Write;
+
+    // A cube without normals or texture coordinates, which used to be skipped
+    let dir = env::temp_dir().join(format!("tray_rust_test_compute_normals_{}", process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let obj_file = dir.join("cube.obj");
+    let mut obj = File::create(&obj_file).unwrap();
+    obj.write_all(b"o Cube\nv 0 0 0\nv 1 0 0\nv 0 1 0\nv 1 1 0\nv 0 0 1\nv 1 0 1\nv 0 1 1\nv 1 1 1\n\
+                    f 1 3 4 2\nf 5 6 8 7\nf 1 2 6 5\nf 3 7 8 4\nf 1 5 7 3\nf 2 4 8 6\n").unwrap();
+    let meshes = Mesh::load_obj(&obj_file);
+    let cube = &meshes["Cube"];
+    let tri = cube.bvh.get(0);
+    // The smooth normals at the corners point diagonally out of the cube
+    assert_eq!(tri.normals.len(), 8);
+    for (p, n) in tri.positions.iter().zip(tri.normals.iter()) {
+        let expected = (*p - Point::broadcast(0.5)).normalized();
+        assert!((Vector::new(n.x, n.y, n.z) - expected).length() < 1e-5);
+    }
+    assert!(tri.texcoords.iter().all(|t| t.x >= 0.0 && t.x <= 1.0 && t.y >= 0.0 && t.y <= 1.0));
+
+    // With a crease angle the cube's edges are sharp so each side has its own vertices
+    // with the side's normal
+    let sharp = cube.with_normals(30.0);
+    assert_eq!(sharp.bvh.get(0).normals.len(), 24);
+    for t in sharp.bvh.iter() {
+        let face_n = linalg::cross(&(t.positions[t.b] - t.positions[t.a]), &(t.positions[t.c] - t.positions[t.a]));
+        for i in &[t.a, t.b, t.c] {
+            assert!(linalg::dot(&t.normals[*i], &face_n.normalized()) > 0.9999);
+        }
+    }
+    fs::remove_dir_all(&dir).unwrap();
+}
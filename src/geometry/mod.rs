@@ -34,7 +34,7 @@ pub use self::disk::Disk;
 pub use self::rectangle::Rectangle;
 pub use self::bbox::BBox;
 pub use self::bvh::BVH;
-pub use self::mesh::Mesh;
+pub use self::mesh::{Mesh, MtlMaterial};
 pub use self::receiver::Receiver;
 pub use self::emitter::Emitter;
 
@@ -48,6 +48,7 @@ pub mod rectangle;
 pub mod bbox;
 pub mod bvh;
 pub mod mesh;
+pub mod ply;
 pub mod receiver;
 pub mod emitter;
 
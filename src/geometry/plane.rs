@@ -37,7 +37,7 @@ impl Geometry for Plane {
             let n = Normal::new(0.0, 0.0, 1.0);
             let dp_du = Vector::new(2.0, 0.0, 0.0);
             let dp_dv = Vector::new(0.0, 2.0, 0.0);
-            Some(DifferentialGeometry::new(&p, &n, &dp_du, &dp_dv, self))
+            Some(DifferentialGeometry::new(&p, &n, (p.x + 1.0) / 2.0, (p.y + 1.0) / 2.0, &dp_du, &dp_dv, self))
         } else {
             None
         }
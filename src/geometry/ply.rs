@@ -0,0 +1,351 @@
+//! Provides a loader for triangle meshes stored in the Stanford PLY format, which is
+//! commonly used for scanned models. ASCII and little and big endian binary files are
+//! supported. The vertex positions are read along with the normals and texture coordinates
+//! if the file has them, texture coordinates can be named 'u' and 'v', 's' and 't' or
+//! 'texture_u' and 'texture_v'. Faces with more than three vertices, e.g. quads, are split
+//! into triangles. Any other elements or properties in the file are skipped.
+
+use std::fs::File;
+use std::io::{Cursor, Read};
+use std::path::Path;
+use std::str::{self, SplitWhitespace};
+use byteorder::{ByteOrder, BigEndian, LittleEndian, ReadBytesExt};
+
+use linalg::{Point, Normal};
+
+/// The triangle mesh loaded from a PLY file, the normals and texture coordinates are
+/// empty if the file doesn't have them
+pub struct PlyMesh {
+    pub positions: Vec<Point>,
+    pub normals: Vec<Normal>,
+    pub texcoords: Vec<Point>,
+    /// Indices of the vertices of each triangle
+    pub indices: Vec<u32>,
+}
+
+/// The format the body of the file is stored in
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Format {
+    Ascii,
+    BinaryLittleEndian,
+    BinaryBigEndian,
+}
+
+/// The types a property's values can be stored as
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Scalar {
+    I8, U8, I16, U16, I32, U32, F32, F64,
+}
+
+impl Scalar {
+    /// Get the type named by `name` in the header
+    fn parse(name: &str) -> Result<Scalar, String> {
+        match name {
+            "char" | "int8" => Ok(Scalar::I8),
+            "uchar" | "uint8" => Ok(Scalar::U8),
+            "short" | "int16" => Ok(Scalar::I16),
+            "ushort" | "uint16" => Ok(Scalar::U16),
+            "int" | "int32" => Ok(Scalar::I32),
+            "uint" | "uint32" => Ok(Scalar::U32),
+            "float" | "float32" => Ok(Scalar::F32),
+            "double" | "float64" => Ok(Scalar::F64),
+            _ => Err(format!("unknown property type '{}'", name)),
+        }
+    }
+}
+
+/// A property of an element, lists store the type of their length followed by the type of their items
+#[derive(Debug)]
+enum Property {
+    Scalar(String, Scalar),
+    List(String, Scalar, Scalar),
+}
+
+impl Property {
+    fn name(&self) -> &str {
+        match *self {
+            Property::Scalar(ref n, _) | Property::List(ref n, _, _) => &n[..],
+        }
+    }
+}
+
+/// An element declared in the header, e.g. the vertices or faces
+#[derive(Debug)]
+struct Element {
+    name: String,
+    count: usize,
+    properties: Vec<Property>,
+}
+
+/// Reads the values stored in the body of the file
+enum Body<'a> {
+    Ascii(SplitWhitespace<'a>),
+    Binary(Cursor<&'a [u8]>, Format),
+}
+
+impl<'a> Body<'a> {
+    /// Read the next value, which is stored as the type `ty`
+    fn read(&mut self, ty: Scalar) -> Result<f64, String> {
+        match *self {
+            Body::Ascii(ref mut tokens) => {
+                match tokens.next() {
+                    Some(t) => t.parse::<f64>().map_err(|_| format!("invalid number '{}'", t)),
+                    None => Err("unexpected end of file".to_owned()),
+                }
+            },
+            Body::Binary(ref mut c, Format::BinaryBigEndian) => read_binary::<BigEndian>(c, ty),
+            Body::Binary(ref mut c, _) => read_binary::<LittleEndian>(c, ty),
+        }
+    }
+    /// Read the values of a property, scalars give a single value
+    fn read_property(&mut self, property: &Property, values: &mut Vec<f64>) -> Result<(), String> {
+        values.clear();
+        match *property {
+            Property::Scalar(_, ty) => values.push(try!(self.read(ty))),
+            Property::List(_, len_ty, ty) => {
+                let len = try!(self.read(len_ty)) as usize;
+                for _ in 0..len {
+                    values.push(try!(self.read(ty)));
+                }
+            },
+        }
+        Ok(())
+    }
+}
+
+/// Read a binary value of type `ty` stored with the byte order `B`
+fn read_binary<B: ByteOrder>(c: &mut Cursor<&[u8]>, ty: Scalar) -> Result<f64, String> {
+    let v = match ty {
+        Scalar::I8 => c.read_i8().map(|x| x as f64),
+        Scalar::U8 => c.read_u8().map(|x| x as f64),
+        Scalar::I16 => c.read_i16::<B>().map(|x| x as f64),
+        Scalar::U16 => c.read_u16::<B>().map(|x| x as f64),
+        Scalar::I32 => c.read_i32::<B>().map(|x| x as f64),
+        Scalar::U32 => c.read_u32::<B>().map(|x| x as f64),
+        Scalar::F32 => c.read_f32::<B>().map(|x| x as f64),
+        Scalar::F64 => c.read_f64::<B>(),
+    };
+    v.map_err(|_| "unexpected end of file".to_owned())
+}
+
+/// Load the mesh from a PLY file, returns an error if the file can't be read or is invalid
+pub fn load_file(path: &Path) -> Result<PlyMesh, String> {
+    let mut file = match File::open(path) {
+        Ok(f) => f,
+        Err(e) => return Err(format!("failed to open {:?} due to {}", path, e)),
+    };
+    let mut data = Vec::new();
+    if let Err(e) = file.read_to_end(&mut data) {
+        return Err(format!("failed to read {:?} due to {}", path, e));
+    }
+    parse(&data)
+}
+
+/// Parse the mesh from the contents of a PLY file
+pub fn parse(data: &[u8]) -> Result<PlyMesh, String> {
+    let (format, elements, body_start) = try!(parse_header(data));
+    let mut body = if format == Format::Ascii {
+        match str::from_utf8(&data[body_start..]) {
+            Ok(s) => Body::Ascii(s.split_whitespace()),
+            Err(_) => return Err("the body of an ASCII file must be text".to_owned()),
+        }
+    } else {
+        Body::Binary(Cursor::new(&data[body_start..]), format)
+    };
+
+    let mut mesh = PlyMesh { positions: Vec::new(), normals: Vec::new(), texcoords: Vec::new(),
+                             indices: Vec::new() };
+    let mut values = Vec::new();
+    for e in &elements {
+        if e.name == "vertex" {
+            try!(read_vertices(&mut body, e, &mut mesh));
+        } else if e.name == "face" {
+            let list = e.properties.iter().position(|p| p.name() == "vertex_indices" || p.name() == "vertex_index");
+            let list = match list {
+                Some(l) => l,
+                None => return Err("faces must have a vertex_indices property".to_owned()),
+            };
+            for _ in 0..e.count {
+                for (i, p) in e.properties.iter().enumerate() {
+                    try!(body.read_property(p, &mut values));
+                    if i != list {
+                        continue;
+                    }
+                    if values.iter().any(|v| *v < 0.0 || *v as usize >= mesh.positions.len()) {
+                        return Err("face refers to a vertex that doesn't exist".to_owned());
+                    }
+                    // Split the polygon into a fan of triangles about its first vertex
+                    for j in 2..values.len() {
+                        mesh.indices.extend([values[0] as u32, values[j - 1] as u32, values[j] as u32].iter());
+                    }
+                }
+            }
+        } else {
+            for _ in 0..e.count {
+                for p in &e.properties {
+                    try!(body.read_property(p, &mut values));
+                }
+            }
+        }
+    }
+    if mesh.positions.is_empty() || mesh.indices.is_empty() {
+        return Err("the file doesn't contain any triangles".to_owned());
+    }
+    Ok(mesh)
+}
+
+/// Parse the header of the file, returning the format of the body, the elements declared
+/// and the offset of the body in the file
+fn parse_header(data: &[u8]) -> Result<(Format, Vec<Element>, usize), String> {
+    if !data.starts_with(b"ply") {
+        return Err("not a PLY file".to_owned());
+    }
+    let end = match data.windows(10).position(|w| w == b"end_header") {
+        Some(e) => e,
+        None => return Err("no end_header found".to_owned()),
+    };
+    let body_start = match data[end..].iter().position(|c| *c == b'\n') {
+        Some(n) => end + n + 1,
+        None => data.len(),
+    };
+    let header = match str::from_utf8(&data[..end]) {
+        Ok(h) => h,
+        Err(_) => return Err("the header must be text".to_owned()),
+    };
+    let mut format = None;
+    let mut elements: Vec<Element> = Vec::new();
+    for line in header.lines().skip(1) {
+        let tokens: Vec<_> = line.split_whitespace().collect();
+        if tokens.is_empty() || tokens[0] == "comment" || tokens[0] == "obj_info" {
+            continue;
+        }
+        if tokens[0] == "format" && tokens.len() >= 2 {
+            format = match tokens[1] {
+                "ascii" => Some(Format::Ascii),
+                "binary_little_endian" => Some(Format::BinaryLittleEndian),
+                "binary_big_endian" => Some(Format::BinaryBigEndian),
+                f => return Err(format!("unknown format '{}'", f)),
+            };
+        } else if tokens[0] == "element" && tokens.len() == 3 {
+            let count = match tokens[2].parse() {
+                Ok(c) => c,
+                Err(_) => return Err(format!("invalid count for element '{}'", tokens[1])),
+            };
+            elements.push(Element { name: tokens[1].to_owned(), count: count, properties: Vec::new() });
+        } else if tokens[0] == "property" {
+            let property = if tokens.len() == 5 && tokens[1] == "list" {
+                Property::List(tokens[4].to_owned(), try!(Scalar::parse(tokens[2])), try!(Scalar::parse(tokens[3])))
+            } else if tokens.len() == 3 {
+                Property::Scalar(tokens[2].to_owned(), try!(Scalar::parse(tokens[1])))
+            } else {
+                return Err(format!("invalid property '{}'", line));
+            };
+            match elements.last_mut() {
+                Some(e) => e.properties.push(property),
+                None => return Err("property declared before any element".to_owned()),
+            }
+        } else {
+            return Err(format!("invalid header line '{}'", line));
+        }
+    }
+    match format {
+        Some(f) => Ok((f, elements, body_start)),
+        None => Err("no format specified".to_owned()),
+    }
+}
+
+/// Read the vertex element's positions and the normals and texture coordinates if it has them
+fn read_vertices(body: &mut Body, element: &Element, mesh: &mut PlyMesh) -> Result<(), String> {
+    let find = |names: &[&str]| element.properties.iter().position(|p| names.contains(&p.name()));
+    let position = [find(&["x"]), find(&["y"]), find(&["z"])];
+    let normal = [find(&["nx"]), find(&["ny"]), find(&["nz"])];
+    let texcoord = [find(&["u", "s", "texture_u", "texture_s"]), find(&["v", "t", "texture_v", "texture_t"])];
+    if position.iter().any(|p| p.is_none()) {
+        return Err("vertices must have x, y and z properties".to_owned());
+    }
+    let has_normals = normal.iter().all(|n| n.is_some());
+    let has_texcoords = texcoord.iter().all(|t| t.is_some());
+    let mut vertex = vec![0.0; element.properties.len()];
+    let mut values = Vec::new();
+    for _ in 0..element.count {
+        for (i, p) in element.properties.iter().enumerate() {
+            try!(body.read_property(p, &mut values));
+            vertex[i] = values.first().cloned().unwrap_or(0.0);
+        }
+        let get = |i: Option<usize>| vertex[i.unwrap()] as f32;
+        mesh.positions.push(Point::new(get(position[0]), get(position[1]), get(position[2])));
+        if has_normals {
+            mesh.normals.push(Normal::new(get(normal[0]), get(normal[1]), get(normal[2])));
+        }
+        if has_texcoords {
+            mesh.texcoords.push(Point::new(get(texcoord[0]), get(texcoord[1]), 0.0));
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn test_round_trip() {
+    use std::{env, fs, process};
+    use std::io::Write;
+    use byteorder::WriteBytesExt;
+
+    let positions = [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [1.0, 1.0, 0.0], [0.0, 1.0, 0.0], [0.5, 0.5, 1.0]];
+    let texcoords = [[0.0, 0.0], [1.0, 0.0], [1.0, 1.0], [0.0, 1.0], [0.5, 0.5]];
+    // A quad and a triangle
+    let faces: [&[u32]; 2] = [&[0, 1, 2, 3], &[2, 4, 3]];
+    let header = |format: &str| {
+        format!("ply\nformat {} 1.0\ncomment generated by tray_rust\nelement vertex 5\nproperty float x\n\
+                 property float y\nproperty float z\nproperty float nx\nproperty float ny\nproperty float nz\n\
+                 property float s\nproperty float t\nelement face 2\nproperty uchar flags\n\
+                 property list uchar int vertex_indices\nend_header\n", format)
+    };
+
+    let mut ascii = header("ascii").into_bytes();
+    for (p, t) in positions.iter().zip(texcoords.iter()) {
+        write!(ascii, "{} {} {} 0 0 1 {} {}\n", p[0], p[1], p[2], t[0], t[1]).unwrap();
+    }
+    for f in &faces {
+        write!(ascii, "7 {}", f.len()).unwrap();
+        for i in f.iter() {
+            write!(ascii, " {}", i).unwrap();
+        }
+        write!(ascii, "\n").unwrap();
+    }
+    fn binary<B: ByteOrder>(mut data: Vec<u8>, positions: &[[f32; 3]], texcoords: &[[f32; 2]],
+                            faces: &[&[u32]]) -> Vec<u8> {
+        for (p, t) in positions.iter().zip(texcoords.iter()) {
+            for x in p.iter().chain([0.0, 0.0, 1.0].iter()).chain(t.iter()) {
+                data.write_f32::<B>(*x).unwrap();
+            }
+        }
+        for f in faces {
+            data.write_u8(7).unwrap();
+            data.write_u8(f.len() as u8).unwrap();
+            for i in f.iter() {
+                data.write_i32::<B>(*i as i32).unwrap();
+            }
+        }
+        data
+    }
+    let little = binary::<LittleEndian>(header("binary_little_endian").into_bytes(), &positions, &texcoords,
+                                        &faces);
+    let big = binary::<BigEndian>(header("binary_big_endian").into_bytes(), &positions, &texcoords, &faces);
+
+    let dir = env::temp_dir().join(format!("tray_rust_test_ply_{}", process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    for (name, data) in vec![("ascii", ascii), ("little", little), ("big", big)] {
+        let file = dir.join(format!("{}.ply", name));
+        File::create(&file).unwrap().write_all(&data).unwrap();
+        let mesh = load_file(&file).unwrap();
+        assert_eq!(mesh.indices, vec![0, 1, 2, 0, 2, 3, 2, 4, 3]);
+        assert_eq!(mesh.positions.len(), 5);
+        for i in 0..positions.len() {
+            let p = &positions[i];
+            assert_eq!(mesh.positions[i], Point::new(p[0], p[1], p[2]));
+            assert_eq!(mesh.normals[i], Normal::new(0.0, 0.0, 1.0));
+            assert_eq!(mesh.texcoords[i], Point::new(texcoords[i][0], texcoords[i][1], 0.0));
+        }
+    }
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -57,3 +57,32 @@ impl Boundable for Receiver {
     }
 }
 
+#[test]
+fn test_surface_uv() {
+    use linalg::{Transform, Point, Vector};
+    use film::Colorf;
+    use geometry::{Sphere, Disk, Rectangle, Plane};
+    use material::Matte;
+    use texture::ConstantTexture;
+
+    let matte = Arc::new(Matte::new(Arc::new(ConstantTexture::new(Colorf::broadcast(0.5))),
+                                    Arc::new(ConstantTexture::new(0.0))));
+    let transform = AnimatedTransform::unanimated(&(Transform::translate(&Vector::new(0.0, 0.0, 5.0))
+                                                    * Transform::scale(&Vector::broadcast(2.0))));
+    // The shape, the ray hitting it in world space and the expected u, v at the hit
+    let tests: Vec<(Arc<BoundableGeom + Send + Sync>, Ray, f32, f32)> = vec![
+        (Arc::new(Sphere::new(1.0)), Ray::new(&Point::new(0.0, 10.0, 5.0), &Vector::new(0.0, -1.0, 0.0), 0.0),
+         0.25, 0.5),
+        (Arc::new(Disk::new(1.0, 0.5)), Ray::new(&Point::new(0.0, 1.5, 10.0), &Vector::new(0.0, 0.0, -1.0), 0.0),
+         0.25, 0.5),
+        (Arc::new(Rectangle::new(2.0, 4.0)),
+         Ray::new(&Point::new(1.0, -2.0, 10.0), &Vector::new(0.0, 0.0, -1.0), 0.0), 0.75, 0.25),
+        (Arc::new(Plane), Ray::new(&Point::new(1.0, -1.0, 10.0), &Vector::new(0.0, 0.0, -1.0), 0.0), 0.75, 0.25),
+    ];
+    for (geom, mut ray, u, v) in tests {
+        let receiver = Receiver::new(geom, matte.clone(), transform.clone(), "test".to_owned());
+        let (dg, _) = receiver.intersect(&mut ray).expect("The ray should hit the shape");
+        assert!(f32::abs(dg.u - u) < 1e-5 && f32::abs(dg.v - v) < 1e-5,
+                "Expected uv ({}, {}) but got ({}, {})", u, v, dg.u, dg.v);
+    }
+}
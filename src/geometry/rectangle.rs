@@ -52,9 +52,11 @@ impl Geometry for Rectangle {
         if p.x >= -half_width && p.x <= half_width && p.y >= -half_height && p.y <= half_height {
             ray.max_t = t;
             let n = Normal::new(0.0, 0.0, 1.0);
-            let dp_du = Vector::new(1.0, 0.0, 0.0);
-            let dp_dv = Vector::new(0.0, 1.0, 0.0);
-            Some(DifferentialGeometry::new(&p, &n, &dp_du, &dp_dv, self))
+            let u = p.x / self.width + 0.5;
+            let v = p.y / self.height + 0.5;
+            let dp_du = Vector::new(self.width, 0.0, 0.0);
+            let dp_dv = Vector::new(0.0, self.height, 0.0);
+            Some(DifferentialGeometry::new(&p, &n, u, v, &dp_du, &dp_dv, self))
         } else {
             None
         }
@@ -59,20 +59,23 @@ impl Geometry for Sphere {
         let p = ray.at(t_hit);
         let n = Normal::new(p.x, p.y, p.z);
         let theta = f32::acos(linalg::clamp(p.z / self.radius, -1.0, 1.0));
+        let mut phi = f32::atan2(p.y, p.x);
+        if phi < 0.0 {
+            phi += f32::consts::PI * 2.0;
+        }
 
         // Compute derivatives for point vs. parameterization
         let inv_z = 1.0 / f32::sqrt(p.x * p.x + p.y * p.y);
         let cos_phi = p.x * inv_z;
         let sin_phi = p.y * inv_z;
-        // TODO: It doesn't make sense that dp_du x dp_dv and n point it such different
-        // directions, they should at least point in a similar direction
-        // Doing dp_dv x dp_du gives the same as normal, kind of as we'd expect since they're
-        // facing opposite directions, but it doesn't explain why this would be wrong
+        // v runs from the bottom of the sphere at theta = pi up to the top so that
+        // dp_du x dp_dv points out of the sphere along the normal
         let dp_du = Vector::new(-f32::consts::PI * 2.0 * p.y, f32::consts::PI * 2.0 * p.x, 0.0);
         let dp_dv = Vector::new(p.z * cos_phi, p.z * sin_phi,
-                                -self.radius * f32::sin(theta)) * f32::consts::PI;
+                                -self.radius * f32::sin(theta)) * -f32::consts::PI;
 
-        Some(DifferentialGeometry::with_normal(&p, &n, &dp_du, &dp_dv, self))
+        Some(DifferentialGeometry::with_normal(&p, &n, phi / (f32::consts::PI * 2.0), 1.0 - theta / f32::consts::PI,
+                                               &dp_du, &dp_dv, self))
     }
 }
 
@@ -119,7 +122,7 @@ impl Sampleable for Sphere {
     }
     /// Compute the sphere's surface area
     fn surface_area(&self) -> f32 {
-        4.0 * f32::consts::PI * self.radius
+        4.0 * f32::consts::PI * self.radius * self.radius
     }
     /// Compute the PDF that the ray from `p` with direction `w_i` intersects
     /// the shape
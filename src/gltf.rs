@@ -0,0 +1,712 @@
+//! Provides an importer for glTF 2.0 files, allowing scenes exported from other programs
+//! to be rendered along with their materials, lights and cameras. Both the JSON `.gltf`
+//! format, with its buffers in separate files or embedded as base64 data URIs, and the
+//! binary `.glb` format are supported.
+//!
+//! The nodes of the glTF scene are imported as follows:
+//!
+//! - Meshes: each triangle primitive becomes a `Mesh`, normals and texture coordinates are
+//!   computed for primitives without them. Points, lines and sparse accessors aren't supported.
+//! - Materials: the PBR metallic-roughness materials become `Principled` materials using the
+//!   base color, metallic and roughness factors and textures. The `KHR_materials_transmission`
+//!   and `KHR_materials_clearcoat` extensions set the principled material's specular transmission
+//!   and clearcoat. Primitives with an emissive material become area lights emitting the emissive
+//!   factor scaled by `KHR_materials_emissive_strength`. Normal, occlusion and emissive textures
+//!   are ignored.
+//! - Cameras: perspective cameras are imported with their vertical field of view, orthographic
+//!   cameras aren't supported.
+//! - Lights: point, spot and directional lights from `KHR_lights_punctual` become emitters with
+//!   their color scaled by their intensity.
+//!
+//! # Scene Usage Example
+//! A glTF file is added to the scene as an object with the 'file' to load, relative paths are
+//! resolved relative to the scene file. The object's transform is applied on top of the node
+//! transforms in the file, like a group. If a material in the scene's materials list has the same
+//! name as a material in the file it will be used instead, allowing the file's materials to be
+//! overridden. The cameras in the file are used if the scene doesn't specify a camera.
+//!
+//! ```json
+//! "objects": [
+//!     {
+//!         "name": "sponza",
+//!         "type": "gltf",
+//!         "file": "./sponza.glb",
+//!         "transform": [
+//!             {
+//!                 "type": "scale",
+//!                 "scaling": 0.1
+//!             }
+//!         ]
+//!     },
+//!     ...
+//! ]
+//! ```
+
+use std::f32;
+use std::str;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use std::sync::Arc;
+use std::collections::HashMap;
+
+use byteorder::{ByteOrder, LittleEndian};
+use image;
+use rustc_serialize::base64::FromBase64;
+use serde_json::{self, Value};
+
+use linalg::{self, Transform, Matrix4, Quaternion, Point, Normal, Vector, AnimatedTransform};
+use film::{Camera, Colorf, AnimatedColor, ColorKeyframe};
+use geometry::{Instance, Mesh};
+use material::{Material, Principled};
+use texture::{Texture, ConstantTexture, ImageTexture, WrapMode};
+
+/// A perspective camera imported from a glTF file
+pub struct GltfCamera {
+    /// The camera's transform to world space, the camera looks down its -z axis
+    pub transform: AnimatedTransform,
+    /// The vertical field of view in degrees
+    pub yfov: f32,
+}
+
+impl GltfCamera {
+    /// Create the camera rendering to an image with dimensions `dims`. The field of view of
+    /// tray_rust's cameras is along the shorter side of the image so it's converted to match
+    pub fn to_camera(&self, dims: (usize, usize)) -> Camera {
+        // Our cameras look down +z with +x to the right of the image, so the z axis is flipped
+        let flip = AnimatedTransform::unanimated(&Transform::scale(&Vector::new(1.0, 1.0, -1.0)));
+        let aspect_ratio = dims.0 as f32 / dims.1 as f32;
+        let fov = if aspect_ratio >= 1.0 {
+            self.yfov
+        } else {
+            2.0 * f32::atan(f32::tan(linalg::to_radians(self.yfov) / 2.0) * aspect_ratio).to_degrees()
+        };
+        Camera::new(self.transform.clone() * flip, fov, dims, 0.5, 0)
+    }
+}
+
+/// The instances and cameras imported from a glTF file, placed by the file's node transforms
+pub struct GltfScene {
+    pub instances: Vec<Instance>,
+    pub cameras: Vec<GltfCamera>,
+}
+
+/// Load the default scene in a glTF file, materials in `materials` with the same name as a
+/// material in the file are used in place of the file's material. Panics if the file can't
+/// be read or is invalid
+pub fn load_file(path: &Path, materials: &HashMap<String, Arc<Material + Send + Sync>>) -> GltfScene {
+    let mut file = match File::open(path) {
+        Ok(f) => f,
+        Err(e) => panic!("gltf::load_file - failed to open {:?} due to {}", path, e),
+    };
+    let mut data = Vec::new();
+    if let Err(e) = file.read_to_end(&mut data) {
+        panic!("gltf::load_file - failed to read {:?} due to {}", path, e);
+    }
+    let base_path = path.parent().unwrap_or(Path::new(""));
+    match Importer::new(&data, base_path, materials).and_then(|mut i| i.import()) {
+        Ok(s) => s,
+        Err(e) => panic!("gltf::load_file - invalid glTF file {:?}: {}", path, e),
+    }
+}
+
+/// Imports the nodes of the glTF file, caching the meshes and materials created so they're
+/// shared by all the nodes using them
+struct Importer<'a> {
+    json: Value,
+    buffers: Vec<Vec<u8>>,
+    base_path: &'a Path,
+    overrides: &'a HashMap<String, Arc<Material + Send + Sync>>,
+    /// The meshes created for each primitive of each mesh, None if the primitive isn't triangles
+    meshes: HashMap<usize, Vec<Option<Arc<Mesh>>>>,
+    materials: HashMap<usize, (Arc<Material + Send + Sync>, Colorf)>,
+    default_material: Option<Arc<Material + Send + Sync>>,
+}
+
+/// Get the array `key` in the JSON object, or an empty slice if it doesn't have one
+fn array<'b>(v: &'b Value, key: &str) -> &'b [Value] {
+    match v.find(key).and_then(|a| a.as_array()) {
+        Some(a) => &a[..],
+        None => &[],
+    }
+}
+
+/// Get the number `key` in the JSON object, or `default` if it doesn't have it
+fn number(v: &Value, key: &str, default: f32) -> f32 {
+    v.find(key).and_then(|x| x.as_f64()).map_or(default, |x| x as f32)
+}
+
+/// Get the index `key` in the JSON object
+fn index(v: &Value, key: &str) -> Option<usize> {
+    v.find(key).and_then(|x| x.as_u64()).map(|x| x as usize)
+}
+
+/// Get the array of numbers `key` in the JSON object, which must have `n` elements
+fn numbers(v: &Value, key: &str, n: usize) -> Result<Option<Vec<f32>>, String> {
+    match v.find(key) {
+        Some(a) => {
+            let a: Vec<_> = a.as_array().map_or(Vec::new(), |a| a.iter().filter_map(|x| x.as_f64()).collect());
+            if a.len() != n {
+                return Err(format!("'{}' must be an array of {} numbers", key, n));
+            }
+            Ok(Some(a.iter().map(|x| *x as f32).collect()))
+        },
+        None => Ok(None),
+    }
+}
+
+/// Get the element `i` of the array `key` in the JSON object
+fn element<'b>(v: &'b Value, key: &str, i: usize) -> Result<&'b Value, String> {
+    array(v, key).get(i).ok_or_else(|| format!("{} {} doesn't exist", key, i))
+}
+
+impl<'a> Importer<'a> {
+    /// Parse the JSON and load the buffers of the file, which may be a binary glTF file
+    fn new(data: &[u8], base_path: &'a Path, overrides: &'a HashMap<String, Arc<Material + Send + Sync>>)
+           -> Result<Importer<'a>, String> {
+        let (json, bin) = try!(split_glb(data));
+        let json: Value = match str::from_utf8(json).map_err(|e| e.to_string())
+                                .and_then(|s| serde_json::from_str(s).map_err(|e| e.to_string())) {
+            Ok(j) => j,
+            Err(e) => return Err(format!("JSON parsing error: {}", e)),
+        };
+        let version = json.find_path(&["asset", "version"]).and_then(|v| v.as_str()).unwrap_or("");
+        if !version.starts_with('2') {
+            return Err(format!("only glTF 2.0 is supported, found version '{}'", version));
+        }
+        let mut buffers = Vec::new();
+        for b in array(&json, "buffers") {
+            let buffer = match b.find("uri").and_then(|u| u.as_str()) {
+                Some(uri) => try!(load_uri(uri, base_path)),
+                None => match bin {
+                    Some(bin) => bin.to_vec(),
+                    None => return Err("buffer without a uri in a non-binary file".to_owned()),
+                },
+            };
+            if buffer.len() < index(b, "byteLength").unwrap_or(0) {
+                return Err("buffer is shorter than its byteLength".to_owned());
+            }
+            buffers.push(buffer);
+        }
+        Ok(Importer { json: json, buffers: buffers, base_path: base_path, overrides: overrides,
+                      meshes: HashMap::new(), materials: HashMap::new(), default_material: None })
+    }
+    /// Import the nodes of the file's default scene, or all the root nodes if it has no scenes
+    fn import(&mut self) -> Result<GltfScene, String> {
+        let roots: Vec<usize> = match self.json.find("scenes") {
+            Some(_) => {
+                let scene = try!(element(&self.json, "scenes", index(&self.json, "scene").unwrap_or(0)));
+                array(scene, "nodes").iter().filter_map(|n| n.as_u64()).map(|n| n as usize).collect()
+            },
+            None => {
+                let nodes = array(&self.json, "nodes");
+                let children: Vec<_> = nodes.iter().flat_map(|n| array(n, "children").iter())
+                    .filter_map(|c| c.as_u64()).collect();
+                (0..nodes.len()).filter(|i| !children.contains(&(*i as u64))).collect()
+            },
+        };
+        let mut scene = GltfScene { instances: Vec::new(), cameras: Vec::new() };
+        for r in roots {
+            try!(self.import_node(r, &Transform::identity(), &mut scene, 0));
+        }
+        Ok(scene)
+    }
+    /// Import the node and its children, `parent` is the transform of its parent to world space
+    fn import_node(&mut self, node_index: usize, parent: &Transform, scene: &mut GltfScene, depth: usize)
+                   -> Result<(), String> {
+        // Nodes form a tree so a deeper hierarchy than this must have a cycle
+        if depth > 256 {
+            return Err("node hierarchy has a cycle".to_owned());
+        }
+        let node = try!(element(&self.json, "nodes", node_index)).clone();
+        let transform = *parent * try!(node_transform(&node));
+        let name = match node.find("name").and_then(|n| n.as_str()) {
+            Some(n) => n.to_owned(),
+            None => format!("node_{}", node_index),
+        };
+        if let Some(m) = index(&node, "mesh") {
+            let primitives = try!(self.load_mesh(m));
+            for (p, mesh) in primitives.into_iter().enumerate() {
+                let mesh = match mesh {
+                    Some(m) => m,
+                    None => continue,
+                };
+                let prim = try!(element(try!(element(&self.json, "meshes", m)), "primitives", p)).clone();
+                let (material, emission) = try!(self.load_material(index(&prim, "material")));
+                let tag = if p == 0 { name.clone() } else { format!("{}_{}", name, p) };
+                let t = AnimatedTransform::unanimated(&transform);
+                if emission.is_black() {
+                    scene.instances.push(Instance::receiver(mesh, material, t, tag));
+                } else {
+                    let emission = AnimatedColor::with_keyframes(vec![ColorKeyframe::new(&emission, 0.0)]);
+                    scene.instances.push(Instance::area_light(mesh, material, emission, t, tag));
+                }
+            }
+        }
+        if let Some(c) = index(&node, "camera") {
+            let camera = try!(element(&self.json, "cameras", c));
+            match camera.find("perspective") {
+                Some(p) => {
+                    let yfov = number(p, "yfov", 0.0).to_degrees();
+                    if yfov <= 0.0 {
+                        return Err(format!("camera {} must have a positive yfov", c));
+                    }
+                    scene.cameras.push(GltfCamera { transform: AnimatedTransform::unanimated(&transform),
+                                                    yfov: yfov });
+                },
+                None => println!("gltf::load_file warning: skipping camera {}, only perspective cameras \
+                                  are supported", c),
+            }
+        }
+        if let Some(l) = node.find_path(&["extensions", "KHR_lights_punctual", "light"]).and_then(|l| l.as_u64()) {
+            try!(self.import_light(l as usize, &transform, &name, scene));
+        }
+        for c in array(&node, "children").iter().filter_map(|c| c.as_u64()) {
+            try!(self.import_node(c as usize, &transform, scene, depth + 1));
+        }
+        Ok(())
+    }
+    /// Import the punctual light `light_index` placed by `transform`
+    fn import_light(&self, light_index: usize, transform: &Transform, name: &str, scene: &mut GltfScene)
+                    -> Result<(), String> {
+        let lights = match self.json.find_path(&["extensions", "KHR_lights_punctual"]) {
+            Some(l) => l,
+            None => return Err("node refers to a light but the file has no lights".to_owned()),
+        };
+        let light = try!(element(lights, "lights", light_index));
+        let color = try!(numbers(light, "color", 3)).unwrap_or(vec![1.0; 3]);
+        let intensity = number(light, "intensity", 1.0);
+        let emission = AnimatedColor::with_keyframes(vec![
+            ColorKeyframe::new(&(Colorf::new(color[0], color[1], color[2]) * intensity), 0.0)]);
+        // glTF lights shine down -z while ours shine down +z
+        let t = AnimatedTransform::unanimated(&(*transform * Transform::rotate_x(180.0)));
+        let tag = name.to_owned();
+        match light.find("type").and_then(|t| t.as_str()) {
+            Some("point") => scene.instances.push(Instance::point_light(t, emission, None, tag)),
+            Some("directional") => scene.instances.push(Instance::directional_light(t, emission, tag)),
+            Some("spot") => {
+                let spot = light.find("spot");
+                let inner = spot.map_or(0.0, |s| number(s, "innerConeAngle", 0.0)).to_degrees();
+                let outer = spot.map_or(f32::consts::FRAC_PI_4, |s| number(s, "outerConeAngle", f32::consts::FRAC_PI_4))
+                    .to_degrees();
+                scene.instances.push(Instance::spot_light(t, emission, f32::min(inner, outer), outer, None, tag));
+            },
+            ty => return Err(format!("unknown light type {:?}", ty)),
+        }
+        Ok(())
+    }
+    /// Load the meshes for the primitives of the mesh `mesh_index`
+    fn load_mesh(&mut self, mesh_index: usize) -> Result<Vec<Option<Arc<Mesh>>>, String> {
+        if let Some(m) = self.meshes.get(&mesh_index) {
+            return Ok(m.clone());
+        }
+        let mesh = try!(element(&self.json, "meshes", mesh_index)).clone();
+        let mesh_name = mesh.find("name").and_then(|n| n.as_str()).map_or(format!("mesh_{}", mesh_index),
+                                                                          |n| n.to_owned());
+        let mut primitives = Vec::new();
+        for (p, prim) in array(&mesh, "primitives").iter().enumerate() {
+            // Only triangles, mode 4, can be rendered
+            if index(prim, "mode").unwrap_or(4) != 4 {
+                println!("gltf::load_file warning: skipping primitive {} of {}, only triangles are supported",
+                         p, mesh_name);
+                primitives.push(None);
+                continue;
+            }
+            let attributes = match prim.find("attributes") {
+                Some(a) => a,
+                None => return Err(format!("primitive {} of {} has no attributes", p, mesh_name)),
+            };
+            let position = match index(attributes, "POSITION") {
+                Some(a) => a,
+                None => return Err(format!("primitive {} of {} has no positions", p, mesh_name)),
+            };
+            let positions: Vec<_> = try!(self.read_accessor(position, "VEC3")).chunks(3)
+                .map(|p| Point::new(p[0] as f32, p[1] as f32, p[2] as f32)).collect();
+            let normals = match index(attributes, "NORMAL") {
+                Some(a) => try!(self.read_accessor(a, "VEC3")).chunks(3)
+                    .map(|n| Normal::new(n[0] as f32, n[1] as f32, n[2] as f32)).collect(),
+                None => Vec::new(),
+            };
+            // glTF's texture coordinates start at the top left of the image while ours start
+            // at the bottom left
+            let texcoords = match index(attributes, "TEXCOORD_0") {
+                Some(a) => try!(self.read_accessor(a, "VEC2")).chunks(2)
+                    .map(|t| Point::new(t[0] as f32, 1.0 - t[1] as f32, 0.0)).collect(),
+                None => Vec::new(),
+            };
+            let indices: Vec<u32> = match index(prim, "indices") {
+                Some(a) => try!(self.read_accessor(a, "SCALAR")).iter().map(|i| *i as u32).collect(),
+                None => (0..positions.len() as u32).collect(),
+            };
+            if indices.len() < 3 || indices.iter().any(|i| *i as usize >= positions.len())
+                || (!normals.is_empty() && normals.len() != positions.len())
+                || (!texcoords.is_empty() && texcoords.len() != positions.len()) {
+                return Err(format!("primitive {} of {} has invalid attributes or indices", p, mesh_name));
+            }
+            let indices = indices[..indices.len() - indices.len() % 3].to_vec();
+            let name = format!("{}_{}", mesh_name, p);
+            println!("{} has {} triangles", name, indices.len() / 3);
+            primitives.push(Some(Arc::new(Mesh::with_missing_attributes(&name, positions, normals, texcoords,
+                                                                         indices))));
+        }
+        self.meshes.insert(mesh_index, primitives.clone());
+        Ok(primitives)
+    }
+    /// Read the values of the accessor `accessor_index`, which must store elements of `ty`.
+    /// Normalized integers are converted to floats
+    fn read_accessor(&self, accessor_index: usize, ty: &str) -> Result<Vec<f64>, String> {
+        let accessor = try!(element(&self.json, "accessors", accessor_index));
+        if accessor.find("type").and_then(|t| t.as_str()) != Some(ty) {
+            return Err(format!("accessor {} must be a {}", accessor_index, ty));
+        }
+        if accessor.find("sparse").is_some() {
+            return Err(format!("accessor {} is sparse, which isn't supported", accessor_index));
+        }
+        let components = match ty {
+            "SCALAR" => 1,
+            "VEC2" => 2,
+            "VEC3" => 3,
+            _ => 4,
+        };
+        let count = index(accessor, "count").unwrap_or(0);
+        let component_type = index(accessor, "componentType").unwrap_or(0);
+        let normalized = accessor.find("normalized").and_then(|n| n.as_bool()).unwrap_or(false);
+        let size = match component_type {
+            5120 | 5121 => 1,
+            5122 | 5123 => 2,
+            5125 | 5126 => 4,
+            _ => return Err(format!("accessor {} has unknown component type {}", accessor_index, component_type)),
+        };
+        let view = match index(accessor, "bufferView") {
+            Some(v) => try!(element(&self.json, "bufferViews", v)),
+            // Accessors without a buffer view are all zeros
+            None => return Ok(vec![0.0; count * components]),
+        };
+        let buffer = match index(view, "buffer").and_then(|b| self.buffers.get(b)) {
+            Some(b) => b,
+            None => return Err(format!("the buffer view of accessor {} has no buffer", accessor_index)),
+        };
+        let start = index(view, "byteOffset").unwrap_or(0) + index(accessor, "byteOffset").unwrap_or(0);
+        let stride = index(view, "byteStride").unwrap_or(size * components);
+        if count > 0 && start + (count - 1) * stride + size * components > buffer.len() {
+            return Err(format!("accessor {} reads past the end of its buffer", accessor_index));
+        }
+        let mut values = Vec::with_capacity(count * components);
+        for i in 0..count {
+            for c in 0..components {
+                let b = &buffer[start + i * stride + c * size..];
+                let x = match component_type {
+                    5120 => b[0] as i8 as f64,
+                    5121 => b[0] as f64,
+                    5122 => LittleEndian::read_i16(b) as f64,
+                    5123 => LittleEndian::read_u16(b) as f64,
+                    5125 => LittleEndian::read_u32(b) as f64,
+                    _ => LittleEndian::read_f32(b) as f64,
+                };
+                values.push(if normalized { normalize(x, component_type) } else { x });
+            }
+        }
+        Ok(values)
+    }
+    /// Load the material `material_index`, returning the material and the light it emits.
+    /// Primitives without a material use the default glTF material
+    fn load_material(&mut self, material_index: Option<usize>) -> Result<(Arc<Material + Send + Sync>, Colorf),
+                                                                            String> {
+        let i = match material_index {
+            Some(i) => i,
+            None => {
+                if self.default_material.is_none() {
+                    let white = Arc::new(ConstantTexture::new(Colorf::broadcast(1.0)));
+                    self.default_material = Some(principled(white, constant(1.0), constant(1.0), constant(0.0),
+                                                            constant(0.0), constant(0.0)));
+                }
+                return Ok((self.default_material.clone().unwrap(), Colorf::black()));
+            },
+        };
+        if let Some(m) = self.materials.get(&i) {
+            return Ok(m.clone());
+        }
+        let m = try!(element(&self.json, "materials", i)).clone();
+        let emissive = try!(numbers(&m, "emissiveFactor", 3)).unwrap_or(vec![0.0; 3]);
+        let strength = m.find_path(&["extensions", "KHR_materials_emissive_strength"])
+            .map_or(1.0, |e| number(e, "emissiveStrength", 1.0));
+        let emission = Colorf::new(emissive[0], emissive[1], emissive[2]) * strength;
+        let overridden = m.find("name").and_then(|n| n.as_str()).and_then(|n| self.overrides.get(n));
+        let material = match overridden {
+            Some(o) => o.clone(),
+            None => try!(self.principled_material(&m)),
+        };
+        self.materials.insert(i, (material.clone(), emission));
+        Ok((material, emission))
+    }
+    /// Create the principled material matching the glTF material's PBR parameters
+    fn principled_material(&self, m: &Value) -> Result<Arc<Material + Send + Sync>, String> {
+        let no_pbr = Value::Null;
+        let pbr = m.find("pbrMetallicRoughness").unwrap_or(&no_pbr);
+        let base_factor = try!(numbers(pbr, "baseColorFactor", 4)).unwrap_or(vec![1.0; 4]);
+        let base_factor = Colorf::new(base_factor[0], base_factor[1], base_factor[2]);
+        let base_color: Arc<Texture<Colorf> + Send + Sync> = match pbr.find("baseColorTexture") {
+            Some(t) => Arc::new(try!(self.load_texture(t, true, |c| c * base_factor))),
+            None => Arc::new(ConstantTexture::new(base_factor)),
+        };
+        let metallic = number(pbr, "metallicFactor", 1.0);
+        let roughness = number(pbr, "roughnessFactor", 1.0);
+        // The metallic-roughness texture stores roughness in green and metalness in blue
+        let (metallic, roughness): (Arc<Texture<f32> + Send + Sync>, Arc<Texture<f32> + Send + Sync>) =
+            match pbr.find("metallicRoughnessTexture") {
+                Some(t) => (Arc::new(try!(self.load_texture(t, false, |c| Colorf::broadcast(c.b * metallic)))),
+                            Arc::new(try!(self.load_texture(t, false, |c| Colorf::broadcast(c.g * roughness))))),
+                None => (constant(metallic), constant(roughness)),
+            };
+        let extension = |name: &str, param: &str| {
+            m.find_path(&["extensions", name]).map_or(0.0, |e| number(e, param, 0.0))
+        };
+        let transmission = extension("KHR_materials_transmission", "transmissionFactor");
+        let clearcoat = extension("KHR_materials_clearcoat", "clearcoatFactor");
+        let clearcoat_gloss = 1.0 - extension("KHR_materials_clearcoat", "clearcoatRoughnessFactor");
+        Ok(principled(base_color, metallic, roughness, constant(clearcoat), constant(clearcoat_gloss),
+                      constant(transmission)))
+    }
+    /// Load the texture referenced by the texture info `info`, mapping each of its pixels
+    /// through `f`. If `srgb` is set the image is converted from sRGB to linear first
+    fn load_texture<F: Fn(Colorf) -> Colorf>(&self, info: &Value, srgb: bool, f: F) -> Result<ImageTexture, String> {
+        if index(info, "texCoord").unwrap_or(0) != 0 {
+            println!("gltf::load_file warning: only the first set of texture coordinates is supported");
+        }
+        let texture = try!(element(&self.json, "textures", index(info, "index").unwrap_or(0)));
+        let img = try!(element(&self.json, "images", index(texture, "source").unwrap_or(0)));
+        let data = match (img.find("uri").and_then(|u| u.as_str()), index(img, "bufferView")) {
+            (Some(uri), _) => try!(load_uri(uri, self.base_path)),
+            (None, Some(v)) => {
+                let view = try!(element(&self.json, "bufferViews", v));
+                let start = index(view, "byteOffset").unwrap_or(0);
+                let end = start + index(view, "byteLength").unwrap_or(0);
+                match index(view, "buffer").and_then(|b| self.buffers.get(b)) {
+                    Some(b) if end <= b.len() => b[start..end].to_vec(),
+                    _ => return Err("image buffer view is out of bounds".to_owned()),
+                }
+            },
+            _ => return Err("image has no uri or buffer view".to_owned()),
+        };
+        let img = match image::load_from_memory(&data) {
+            Ok(img) => img.to_rgb(),
+            Err(e) => return Err(format!("failed to decode image: {}", e)),
+        };
+        let pixels = img.pixels().map(|c| {
+            let c = Colorf::new(c.data[0] as f32 / 255.0, c.data[1] as f32 / 255.0, c.data[2] as f32 / 255.0);
+            f(if srgb { c.to_linear() } else { c })
+        }).collect();
+        let sampler = index(texture, "sampler").and_then(|s| array(&self.json, "samplers").get(s));
+        let wrap = match sampler.and_then(|s| index(s, "wrapS")) {
+            Some(33071) => WrapMode::Clamp,
+            Some(33648) => WrapMode::Mirror,
+            _ => WrapMode::Repeat,
+        };
+        Ok(ImageTexture::new(img.width() as usize, img.height() as usize, pixels, wrap))
+    }
+}
+
+/// Create a constant scalar texture
+fn constant(x: f32) -> Arc<Texture<f32> + Send + Sync> {
+    Arc::new(ConstantTexture::new(x))
+}
+
+/// Create a principled material with the parameters glTF sets, the rest are left at their defaults
+fn principled(base_color: Arc<Texture<Colorf> + Send + Sync>, metallic: Arc<Texture<f32> + Send + Sync>,
+              roughness: Arc<Texture<f32> + Send + Sync>, clearcoat: Arc<Texture<f32> + Send + Sync>,
+              clearcoat_gloss: Arc<Texture<f32> + Send + Sync>, transmission: Arc<Texture<f32> + Send + Sync>)
+              -> Arc<Material + Send + Sync> {
+    Arc::new(Principled::new(base_color, metallic, roughness, constant(0.5), constant(0.0), constant(0.0),
+                             constant(0.0), constant(0.5), clearcoat, clearcoat_gloss, transmission))
+}
+
+/// Convert a normalized integer of the glTF component type to a float
+fn normalize(x: f64, component_type: usize) -> f64 {
+    match component_type {
+        5120 => f64::max(x / 127.0, -1.0),
+        5121 => x / 255.0,
+        5122 => f64::max(x / 32767.0, -1.0),
+        5123 => x / 65535.0,
+        _ => x,
+    }
+}
+
+/// Compute the transform of the node relative to its parent, from its matrix or its
+/// translation, rotation and scale
+fn node_transform(node: &Value) -> Result<Transform, String> {
+    if let Some(m) = try!(numbers(node, "matrix", 16)) {
+        // glTF matrices are stored in column major order
+        let mut mat = [0.0; 16];
+        mat.copy_from_slice(&m);
+        return Ok(Transform::from_mat(&Matrix4::new(mat).transpose()));
+    }
+    let t = try!(numbers(node, "translation", 3)).unwrap_or(vec![0.0; 3]);
+    let r = try!(numbers(node, "rotation", 4)).unwrap_or(vec![0.0, 0.0, 0.0, 1.0]);
+    let s = try!(numbers(node, "scale", 3)).unwrap_or(vec![1.0; 3]);
+    let rotation = Quaternion { v: Vector::new(r[0], r[1], r[2]), w: r[3] }.normalized();
+    Ok(Transform::translate(&Vector::new(t[0], t[1], t[2])) * rotation.to_transform()
+       * Transform::scale(&Vector::new(s[0], s[1], s[2])))
+}
+
+/// Split a binary glTF file into its JSON and binary chunks, a JSON glTF file is returned
+/// as is without a binary chunk
+fn split_glb(data: &[u8]) -> Result<(&[u8], Option<&[u8]>), String> {
+    if !data.starts_with(b"glTF") {
+        return Ok((data, None));
+    }
+    if data.len() < 20 || LittleEndian::read_u32(&data[4..]) != 2 {
+        return Err("only version 2 binary glTF files are supported".to_owned());
+    }
+    let length = usize::min(LittleEndian::read_u32(&data[8..]) as usize, data.len());
+    let mut json = None;
+    let mut bin = None;
+    let mut offset = 12;
+    while offset + 8 <= length {
+        let chunk_length = LittleEndian::read_u32(&data[offset..]) as usize;
+        let chunk_type = LittleEndian::read_u32(&data[offset + 4..]);
+        let start = offset + 8;
+        if start + chunk_length > length {
+            return Err("binary chunk extends past the end of the file".to_owned());
+        }
+        let chunk = &data[start..start + chunk_length];
+        // The chunk types are "JSON" and "BIN\0" as little endian integers
+        if chunk_type == 0x4E4F534A && json.is_none() {
+            json = Some(chunk);
+        } else if chunk_type == 0x004E4942 && bin.is_none() {
+            bin = Some(chunk);
+        }
+        offset = start + chunk_length;
+    }
+    match json {
+        Some(j) => Ok((j, bin)),
+        None => Err("binary file has no JSON chunk".to_owned()),
+    }
+}
+
+/// Load the data referenced by a uri, either a base64 data uri or a file relative to `base_path`
+fn load_uri(uri: &str, base_path: &Path) -> Result<Vec<u8>, String> {
+    if uri.starts_with("data:") {
+        match uri.find(";base64,") {
+            Some(i) => uri[i + 8..].from_base64().map_err(|e| format!("invalid base64 data: {}", e)),
+            None => Err("only base64 data uris are supported".to_owned()),
+        }
+    } else {
+        // Spaces and other characters may be percent encoded in the file name
+        let file = percent_decode(uri);
+        let mut data = Vec::new();
+        match File::open(base_path.join(&file)).and_then(|mut f| f.read_to_end(&mut data)) {
+            Ok(_) => Ok(data),
+            Err(e) => Err(format!("failed to read {} due to {}", file, e)),
+        }
+    }
+}
+
+/// Decode the percent encoded characters in a uri
+fn percent_decode(uri: &str) -> String {
+    let bytes = uri.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = if bytes[i] == b'%' && i + 2 < bytes.len() {
+            str::from_utf8(&bytes[i + 1..i + 3]).ok().and_then(|h| u8::from_str_radix(h, 16).ok())
+        } else {
+            None
+        };
+        match hex {
+            Some(c) => {
+                decoded.push(c);
+                i += 3;
+            },
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            },
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+#[test]
+fn test_import() {
+    use rustc_serialize::base64::{ToBase64, STANDARD};
+    use byteorder::WriteBytesExt;
+    use linalg::Ray;
+
+    // A triangle's positions followed by its indices
+    let mut buffer = Vec::new();
+    for x in &[-1.0, -1.0, 0.0, 1.0, -1.0, 0.0, 0.0, 1.0, 0.0] {
+        buffer.write_f32::<LittleEndian>(*x).unwrap();
+    }
+    for i in &[0, 1, 2, 0] {
+        buffer.write_u16::<LittleEndian>(*i).unwrap();
+    }
+    // The triangle is placed at x = 1 under a parent at z = 5, the emissive copy is at the parent's
+    // origin and the camera and light are at the parent's origin looking down -z
+    let json = r#"{
+        "asset": { "version": "2.0" },
+        "scene": 0,
+        "scenes": [{ "nodes": [0] }],
+        "nodes": [
+            { "translation": [0, 0, 5], "children": [1, 2, 3, 4] },
+            { "name": "tri", "mesh": 0, "translation": [1, 0, 0] },
+            { "name": "glow", "mesh": 1 },
+            { "camera": 0 },
+            { "name": "lamp", "extensions": { "KHR_lights_punctual": { "light": 0 } } }
+        ],
+        "meshes": [
+            { "primitives": [{ "attributes": { "POSITION": 0 }, "indices": 1, "material": 0 }] },
+            { "primitives": [{ "attributes": { "POSITION": 0 }, "indices": 1, "material": 1 }] }
+        ],
+        "materials": [
+            { "name": "red", "pbrMetallicRoughness": { "baseColorFactor": [1, 0, 0, 1], "metallicFactor": 0 } },
+            { "name": "light", "emissiveFactor": [1, 1, 1],
+              "extensions": { "KHR_materials_emissive_strength": { "emissiveStrength": 4 } } }
+        ],
+        "cameras": [{ "type": "perspective", "perspective": { "yfov": 0.7853982, "znear": 0.1 } }],
+        "extensions": { "KHR_lights_punctual": { "lights": [{ "type": "point", "intensity": 10 }] } },
+        "accessors": [
+            { "bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3" },
+            { "bufferView": 0, "byteOffset": 36, "componentType": 5123, "count": 3, "type": "SCALAR" }
+        ],
+        "bufferViews": [{ "buffer": 0, "byteLength": 44 }],
+        "buffers": [{ "byteLength": 44URI }]
+    }"#;
+    let gltf = json.replace("URI", &format!(", \"uri\": \"data:application/octet-stream;base64,{}\"",
+                                            buffer.to_base64(STANDARD)));
+    // The binary file stores the buffer in its own chunk, chunks are padded to 4 bytes
+    let mut json_chunk = json.replace("URI", "").into_bytes();
+    while json_chunk.len() % 4 != 0 {
+        json_chunk.push(b' ');
+    }
+    let mut glb = Vec::new();
+    glb.extend_from_slice(b"glTF");
+    glb.write_u32::<LittleEndian>(2).unwrap();
+    glb.write_u32::<LittleEndian>((12 + 8 + json_chunk.len() + 8 + buffer.len()) as u32).unwrap();
+    glb.write_u32::<LittleEndian>(json_chunk.len() as u32).unwrap();
+    glb.write_u32::<LittleEndian>(0x4E4F534A).unwrap();
+    glb.extend_from_slice(&json_chunk);
+    glb.write_u32::<LittleEndian>(buffer.len() as u32).unwrap();
+    glb.write_u32::<LittleEndian>(0x004E4942).unwrap();
+    glb.extend_from_slice(&buffer);
+
+    let materials = HashMap::new();
+    for data in &[gltf.into_bytes(), glb] {
+        let scene = Importer::new(data, Path::new(""), &materials).and_then(|mut i| i.import())
+            .expect("The test file should import");
+        assert_eq!(scene.instances.len(), 3);
+        let tags: Vec<_> = scene.instances.iter().map(|i| i.tag()).collect();
+        assert_eq!(tags, vec!["tri", "glow", "lamp"]);
+        match (&scene.instances[0], &scene.instances[1], &scene.instances[2]) {
+            (&Instance::Receiver(_), &Instance::Emitter(_), &Instance::Emitter(_)) => {},
+            _ => panic!("Expected a receiver, an area light and a point light"),
+        }
+        // The ray only hits the triangle if it's been placed by both nodes' transforms
+        let mut ray = Ray::new(&Point::new(1.0, 0.0, -10.0), &Vector::new(0.0, 0.0, 1.0), 0.0);
+        let hit = scene.instances[0].intersect(&mut ray).expect("The ray should hit the triangle");
+        assert!(f32::abs(hit.dg.p.z - 5.0) < 1e-4, "Expected the triangle at z = 5 but hit {:?}", hit.dg.p);
+        assert_eq!(scene.cameras.len(), 1);
+        assert!(f32::abs(scene.cameras[0].yfov - 45.0) < 1e-3);
+        let camera_pos = scene.cameras[0].transform.transform(0.0) * Point::broadcast(0.0);
+        assert!(f32::abs(camera_pos.z - 5.0) < 1e-4);
+    }
+}
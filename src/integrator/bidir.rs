@@ -0,0 +1,630 @@
+//! Defines the Bidir integrator which implements bidirectional path tracing.
+//! A subpath is traced from the camera and another from a light in the scene,
+//! and paths are formed by connecting each prefix of the camera subpath with
+//! each prefix of the light subpath. The contributions of the different connection
+//! strategies are combined with multiple importance sampling using the power heuristic.
+//!
+//! Vertices on the light subpath are also connected directly to the camera. The light
+//! found this way arrives at some other pixel of the image, so it's splatted onto the
+//! render target instead of being returned for the pixel being rendered. These strategies
+//! find caustics from small or point lights, e.g. seen on a diffuse surface through glass,
+//! which no other strategy can. They're only used when the integrator can splat, i.e.
+//! through `illumination_splat`, otherwise they're left out of the MIS weights.
+//!
+//! See [Veach, Robust Monte Carlo Methods for Light Transport Simulation](https://graphics.stanford.edu/papers/veach_thesis/)
+//!
+//! # Scene Usage Example
+//! The bidir integrator takes the same parameters as the pathtracer, a maximum
+//! path depth to terminate paths at and a minimum depth to start applying Russian
+//! Roulette to terminate the subpaths early.
+//!
+//! ```json
+//! "integrator": {
+//!     "type": "bidir",
+//!     "min_depth": 3,
+//!     "max_depth": 8
+//! }
+//! ```
+
+use std::{f32, cmp};
+use rand::{StdRng, Rng};
+
+use scene::Scene;
+use linalg::{self, Ray, Point, Vector, Normal};
+use geometry::{Intersection, Emitter, Instance};
+use film::{Colorf, Camera, ImageSample};
+use integrator::Integrator;
+use bxdf::{BSDF, BxDFType};
+use light::{Light, LightList, OcclusionTester};
+use sampler::{Sampler, Sample};
+use memory::Allocator;
+
+/// The bidir integrator implementing bidirectional path tracing
+#[derive(Clone, Copy, Debug)]
+pub struct Bidir {
+    min_depth: usize,
+    max_depth: usize,
+}
+
+/// The types of vertices that can appear on a subpath
+enum VertexType<'a> {
+    /// The position of the camera, this is the first vertex of a camera subpath
+    Camera(&'a Camera),
+    /// A vertex on a light, this is the first vertex of a light subpath
+    Light(&'a Emitter),
+    /// A vertex on some surface in the scene along with the BSDF at the surface
+    Surface(BSDF<'a>, &'a Instance),
+    /// A vertex at infinity for a camera subpath which left the scene, light arrives
+    /// at it from all of the infinite lights. One of the infinite lights is stored to
+    /// compute the pdf of emitting light from it, which is the same for each of them
+    Infinite(&'a Emitter),
+}
+
+/// A vertex on a camera or light subpath. The pdfs are stored in area measure
+/// so they can be compared when computing the MIS weights for the path
+struct Vertex<'a> {
+    ty: VertexType<'a>,
+    p: Point,
+    /// Shading normal at the vertex
+    n: Normal,
+    /// Geometric normal at the vertex
+    ng: Normal,
+    /// Direction towards the previous vertex on the subpath
+    w_o: Vector,
+    /// Throughput of the subpath up to this vertex
+    beta: Colorf,
+    /// Pdf of sampling this vertex from the previous vertex on the subpath
+    pdf_fwd: f32,
+    /// Pdf of sampling this vertex from the next vertex on the subpath, ie. the
+    /// pdf of the subpath being traced in the opposite direction
+    pdf_rev: f32,
+    /// Set if the subpath was continued from this vertex by sampling a specular BxDF
+    delta: bool,
+}
+
+impl<'a> Vertex<'a> {
+    /// Create a vertex for the camera at `p`, the camera doesn't have a surface so the
+    /// normal is set to the direction the camera is looking towards `w`
+    fn camera(camera: &'a Camera, p: &Point, w: &Vector, beta: &Colorf) -> Vertex<'a> {
+        let n = Normal::new(w.x, w.y, w.z);
+        Vertex { ty: VertexType::Camera(camera), p: *p, n: n, ng: n, w_o: *w, beta: *beta,
+                 pdf_fwd: 0.0, pdf_rev: 0.0, delta: false }
+    }
+    /// Create a vertex on the light at `p` with normal `n`
+    fn light(light: &'a Emitter, p: &Point, n: &Normal, beta: &Colorf, pdf_fwd: f32) -> Vertex<'a> {
+        Vertex { ty: VertexType::Light(light), p: *p, n: *n, ng: *n,
+                 w_o: Vector::new(n.x, n.y, n.z), beta: *beta, pdf_fwd: pdf_fwd,
+                 pdf_rev: 0.0, delta: false }
+    }
+    /// Create a vertex at infinity for the ray which left the scene, `pdf_fwd` is the
+    /// solid angle pdf of sampling the ray's direction
+    fn infinite(light: &'a Emitter, ray: &Ray, beta: &Colorf, pdf_fwd: f32) -> Vertex<'a> {
+        let n = Normal::new(-ray.d.x, -ray.d.y, -ray.d.z);
+        Vertex { ty: VertexType::Infinite(light), p: ray.o + ray.d, n: n, ng: n, w_o: -ray.d,
+                 beta: *beta, pdf_fwd: pdf_fwd, pdf_rev: 0.0, delta: false }
+    }
+    /// Create a vertex on a surface in the scene with the BSDF for the surface
+    fn surface(bsdf: BSDF<'a>, instance: &'a Instance, w_o: &Vector, beta: &Colorf) -> Vertex<'a> {
+        let p = bsdf.p;
+        let n = bsdf.n;
+        let ng = bsdf.ng;
+        Vertex { ty: VertexType::Surface(bsdf, instance), p: p, n: n, ng: ng,
+                 w_o: *w_o, beta: *beta, pdf_fwd: 0.0, pdf_rev: 0.0, delta: false }
+    }
+    /// Check if the vertex is on a surface, point lights and the camera are not
+    fn on_surface(&self) -> bool {
+        match self.ty {
+            VertexType::Camera(_) => false,
+            VertexType::Light(l) => !l.delta_light(),
+            VertexType::Surface(..) => true,
+            VertexType::Infinite(_) => false,
+        }
+    }
+    /// Check if the vertex is on an infinite light
+    fn is_infinite_light(&self) -> bool {
+        match self.ty {
+            VertexType::Light(l) => l.is_infinite(),
+            VertexType::Camera(_) | VertexType::Surface(..) => false,
+            VertexType::Infinite(_) => true,
+        }
+    }
+    /// Check if we can connect a path to this vertex, which we can't do
+    /// if the surface is purely specular
+    fn connectible(&self) -> bool {
+        match self.ty {
+            VertexType::Camera(_) | VertexType::Light(_) => true,
+            VertexType::Surface(ref bsdf, _) => bsdf.num_matching(BxDFType::non_specular()) > 0,
+            VertexType::Infinite(_) => false,
+        }
+    }
+    /// Get the light at this vertex, if the vertex is on a light
+    fn emitter(&self) -> Option<&'a Emitter> {
+        match self.ty {
+            VertexType::Light(l) | VertexType::Infinite(l) => Some(l),
+            VertexType::Surface(_, &Instance::Emitter(ref e)) => Some(e),
+            VertexType::Camera(_) | VertexType::Surface(..) => None,
+        }
+    }
+    /// Evaluate the BSDF at the vertex for light scattered between the previous
+    /// vertex on the subpath and the direction `w_i`
+    fn f(&self, w_i: &Vector) -> Colorf {
+        match self.ty {
+            VertexType::Camera(_) | VertexType::Light(_) | VertexType::Infinite(_) => Colorf::black(),
+            VertexType::Surface(ref bsdf, _) => bsdf.eval(&self.w_o, w_i, BxDFType::all()),
+        }
+    }
+    /// Compute the pdf of sampling `next` from this vertex, where `w_prev` is the direction
+    /// towards the vertex this one was reached from. `w_prev` is ignored for camera and light vertices
+    fn pdf(&self, w_prev: &Vector, next: &Vertex, time: f32) -> f32 {
+        match self.ty {
+            VertexType::Camera(c) => convert_density(c.pdf_dir(&(next.p - self.p).normalized(), time), &self.p, next),
+            VertexType::Light(l) | VertexType::Infinite(l) => pdf_light(l, &self.p, &self.ng, next, time),
+            VertexType::Surface(ref bsdf, _) => {
+                let w = (next.p - self.p).normalized();
+                convert_density(bsdf.pdf(w_prev, &w, BxDFType::non_specular()), &self.p, next)
+            },
+        }
+    }
+    /// Compute the pdf of a light subpath starting at this vertex, which must
+    /// be on a light
+    fn pdf_light_origin(&self, light_list: &LightList, time: f32) -> f32 {
+        if self.is_infinite_light() {
+            return infinite_light_density(light_list, &-self.w_o, time);
+        }
+        match self.emitter() {
+            Some(e) => e.pdf_emitted(&self.p, &self.ng, &self.w_o, time).0 * light_list.pdf(e),
+            None => 0.0,
+        }
+    }
+}
+
+/// Convert the solid angle pdf of sampling the direction from `p` towards
+/// `next` to the area measure pdf of sampling `next`
+fn convert_density(pdf: f32, p: &Point, next: &Vertex) -> f32 {
+    // Vertices on infinite lights are sampled by direction
+    if next.is_infinite_light() {
+        return pdf;
+    }
+    let w = next.p - *p;
+    let dist_sqr = w.length_sqr();
+    if dist_sqr == 0.0 {
+        return 0.0;
+    }
+    if next.on_surface() {
+        pdf * f32::abs(linalg::dot(&next.ng, &w.normalized())) / dist_sqr
+    } else {
+        pdf / dist_sqr
+    }
+}
+
+/// Compute the area measure pdf of `light` emitting a ray from `p`, with normal `n`,
+/// that arrives at `next`
+fn pdf_light(light: &Emitter, p: &Point, n: &Normal, next: &Vertex, time: f32) -> f32 {
+    let w = (next.p - *p).normalized();
+    let (pdf_pos, pdf_dir) = light.pdf_emitted(p, n, &w, time);
+    // Infinite lights emit along a fixed direction from a point on a disk facing
+    // the scene, so only the position is sampled on the way to `next`
+    if light.is_infinite() {
+        if next.on_surface() {
+            pdf_pos * f32::abs(linalg::dot(&next.ng, &w))
+        } else {
+            pdf_pos
+        }
+    } else {
+        convert_density(pdf_dir, p, next)
+    }
+}
+
+/// Compute the solid angle pdf of choosing and sampling any of the infinite lights
+/// in the list to emit light arriving along `w`, the direction towards the lights
+fn infinite_light_density(light_list: &LightList, w: &Vector, time: f32) -> f32 {
+    let p = Point::broadcast(0.0);
+    light_list.lights().iter().filter(|l| l.is_infinite())
+        .fold(0.0, |pdf, l| pdf + l.pdf(&p, w, time) * light_list.pdf(l))
+}
+
+/// Remap a pdf of 0, which we use for delta distributions, to 1 so the ratios
+/// of pdfs used when computing MIS weights are well defined
+fn remap0(pdf: f32) -> f32 {
+    if pdf != 0.0 { pdf } else { 1.0 }
+}
+
+impl Bidir {
+    /// Create a new bidir integrator with the min and max length desired for paths
+    pub fn new(min_depth: u32, max_depth: u32) -> Bidir {
+        Bidir { min_depth: min_depth as usize, max_depth: max_depth as usize }
+    }
+    /// Continue tracing a subpath through the scene starting at `hit`, which was found by
+    /// tracing `ray` from the last vertex in `path` (or from the camera if `path` is empty).
+    /// `beta` is the throughput of the subpath up to `hit` and `pdf` the solid angle
+    /// pdf of sampling the direction of `ray`. The subpath is traced until it has
+    /// `max_vertices` vertices or is terminated. If `escape` is set a vertex at infinity
+    /// is added when the subpath leaves the scene and there are infinite lights
+    fn random_walk<'a>(&self, scene: &'a Scene, ray: &Ray, hit: Intersection<'a, 'a>, beta: Colorf,
+                       pdf: f32, max_vertices: usize, escape: bool, samples: &[(f32, f32)],
+                       samples_comp: &[f32], path: &mut Vec<Vertex<'a>>, rng: &mut StdRng, alloc: &'a Allocator) {
+        let mut ray = *ray;
+        let mut current_hit = hit;
+        let mut beta = beta;
+        let mut pdf_fwd = pdf;
+        let mut bounce = 0;
+        loop {
+            let bsdf = current_hit.material.bsdf(&current_hit, alloc);
+            let w_o = -ray.d;
+            let sample = Sample::new(&samples[bounce], samples_comp[bounce]);
+            let (f, w_i, pdf, sampled_type) = bsdf.sample(&w_o, BxDFType::all(), &sample);
+            let specular = sampled_type.contains(&BxDFType::Specular);
+            let pdf_rev = if specular { 0.0 } else { bsdf.pdf(&w_i, &w_o, BxDFType::non_specular()) };
+            let cos_theta = f32::abs(linalg::dot(&w_i, &bsdf.n));
+
+            let mut vertex = Vertex::surface(bsdf, current_hit.instance, &w_o, &beta);
+            if let Some(prev) = path.last() {
+                vertex.pdf_fwd = convert_density(pdf_fwd, &prev.p, &vertex);
+            }
+            path.push(vertex);
+            let n = path.len();
+            if n >= max_vertices || f.is_black() || pdf == 0.0 {
+                break;
+            }
+            path[n - 1].delta = specular;
+            if n > 1 {
+                path[n - 2].pdf_rev = convert_density(pdf_rev, &path[n - 1].p, &path[n - 2]);
+            }
+            beta = beta * f * cos_theta / pdf;
+            pdf_fwd = if specular { 0.0 } else { pdf };
+
+            if bounce > self.min_depth {
+                let cont_prob = f32::max(0.5, beta.luminance());
+                if rng.next_f32() > cont_prob {
+                    break;
+                }
+                beta = beta / cont_prob;
+            }
+
+            ray = ray.child(&path[n - 1].p, &w_i.normalized());
+            ray.min_t = 0.001;
+            match scene.intersect(&mut ray) {
+                Some(h) => current_hit = h,
+                None => {
+                    if escape {
+                        if let Some(l) = scene.infinite_lights.first() {
+                            path.push(Vertex::infinite(l, &ray, &beta, pdf_fwd));
+                        }
+                    }
+                    break;
+                },
+            }
+            bounce += 1;
+        }
+    }
+    /// Compute the contribution of the path formed by connecting the first `s` vertices
+    /// of the light subpath to the first `t` vertices of the camera subpath, `t` must be at
+    /// least 2. When `s == 1` a new point on a light is sampled with `samples` and `sample_comp`
+    /// for the connection. `light_tracing` is set if paths are also connected to the camera
+    fn connect<'a>(&self, scene: &Scene, light_list: &LightList<'a>, light_path: &[Vertex<'a>],
+                   camera_path: &[Vertex<'a>], s: usize, t: usize, samples: &(f32, f32),
+                   sample_comp: f32, time: f32, light_tracing: bool) -> Colorf {
+        let pt = &camera_path[t - 1];
+        let mut sampled = None;
+        let illum =
+            if s == 0 {
+                // The camera subpath is a complete path if it hit a light
+                if let VertexType::Infinite(_) = pt.ty {
+                    let ray = Ray::segment(&pt.p, &-pt.w_o, 0.0, f32::INFINITY, time);
+                    return pt.beta * scene.escaped_radiance(&ray)
+                        * self.mis_weight(light_path, camera_path, None, s, t, light_list, time, light_tracing);
+                }
+                match pt.emitter() {
+                    Some(e) => pt.beta * e.radiance(&pt.w_o, &pt.p, &pt.ng, time),
+                    None => return Colorf::black(),
+                }
+            } else if s == 1 {
+                // Sample a new point on a light to connect to instead of using the
+                // start of the light subpath
+                if !pt.connectible() {
+                    return Colorf::black();
+                }
+                let (light, light_pdf) = light_list.sample(sample_comp);
+                let (li, w_i, pdf, occlusion) = light.sample_incident(&pt.p, samples, time);
+                if pdf == 0.0 || li.is_black() {
+                    return Colorf::black();
+                }
+                let p_l = occlusion.ray.at(1.0);
+                // Find the normal at the point we sampled on area lights, point and infinite
+                // lights don't have a surface so any normal will do
+                let n_l =
+                    if light.delta_light() || light.is_infinite() {
+                        Normal::new(-w_i.x, -w_i.y, -w_i.z)
+                    } else {
+                        let mut ray = occlusion.ray;
+                        ray.max_t = 1.001;
+                        match light.intersect(&mut ray) {
+                            Some((dg, _)) => dg.ng.normalized(),
+                            None => return Colorf::black(),
+                        }
+                    };
+                let illum = pt.beta * pt.f(&w_i) * li * f32::abs(linalg::dot(&w_i, &pt.n)) / (pdf * light_pdf);
+                if illum.is_black() || occlusion.occluded(scene) {
+                    return Colorf::black();
+                }
+                let mut vertex = Vertex::light(light, &p_l, &n_l, &(li / (pdf * light_pdf)), 0.0);
+                vertex.pdf_fwd = vertex.pdf_light_origin(light_list, time);
+                sampled = Some(vertex);
+                illum
+            } else {
+                let qs = &light_path[s - 1];
+                if !qs.connectible() || !pt.connectible() {
+                    return Colorf::black();
+                }
+                let d = qs.p - pt.p;
+                let dist_sqr = d.length_sqr();
+                let w = d.normalized();
+                let g = f32::abs(linalg::dot(&w, &qs.n)) * f32::abs(linalg::dot(&w, &pt.n)) / dist_sqr;
+                let illum = qs.beta * qs.f(&-w) * pt.f(&w) * pt.beta * g;
+                if illum.is_black() || OcclusionTester::test_points(&pt.p, &qs.p, time).occluded(scene) {
+                    return Colorf::black();
+                }
+                illum
+            };
+        if illum.is_black() {
+            return illum;
+        }
+        illum * self.mis_weight(light_path, camera_path, sampled.as_ref(), s, t, light_list, time, light_tracing)
+    }
+    /// Compute the light arriving at the camera along the path formed by connecting the first
+    /// `s` vertices of the light subpath to the camera, returns the light to splat onto the
+    /// image at the raster position it arrives at
+    fn connect_camera<'a>(&self, scene: &'a Scene, light_list: &LightList<'a>, light_path: &[Vertex<'a>],
+                          camera_path: &[Vertex<'a>], s: usize, time: f32) -> Option<ImageSample> {
+        let qs = &light_path[s - 1];
+        if !qs.connectible() {
+            return None;
+        }
+        let camera = scene.active_camera();
+        let (raster, p_cam, importance, pdf) = match camera.sample_incident(&qs.p, time) {
+            Some(c) => c,
+            None => return None,
+        };
+        let w = (p_cam - qs.p).normalized();
+        let mut illum = qs.beta * qs.f(&w) * importance / pdf;
+        if qs.on_surface() {
+            illum = illum * f32::abs(linalg::dot(&w, &qs.n));
+        }
+        if illum.is_black() || OcclusionTester::test_points(&qs.p, &p_cam, time).occluded(scene) {
+            return None;
+        }
+        let vertex = Vertex::camera(camera, &p_cam, &-w, &Colorf::broadcast(importance / pdf));
+        let weight = self.mis_weight(light_path, camera_path, Some(&vertex), s, 1, light_list, time, true);
+        Some(ImageSample::new(raster.0, raster.1, illum * weight))
+    }
+    /// Compute the MIS weight for the path formed by connecting the first `s` vertices of the
+    /// light subpath to the first `t` vertices of the camera subpath, using the power heuristic.
+    /// `sampled` is the light vertex sampled for the connection when `s == 1`, or the camera vertex
+    /// when `t == 1`. The strategies connecting to the camera are included if `light_tracing` is set
+    fn mis_weight(&self, light_path: &[Vertex], camera_path: &[Vertex], sampled: Option<&Vertex>,
+                  s: usize, t: usize, light_list: &LightList, time: f32, light_tracing: bool) -> f32 {
+        if s + t == 2 {
+            return 1.0;
+        }
+        let pt = match sampled {
+            Some(v) if t == 1 => v,
+            _ => &camera_path[t - 1],
+        };
+        let qs = match sampled {
+            Some(v) if s == 1 => Some(v),
+            _ if s > 0 => Some(&light_path[s - 1]),
+            _ => None,
+        };
+        // Copy out the (forward pdf, reverse pdf, delta) of the vertices on the path
+        // so we can update the ones that change due to the connection
+        let pdfs = |v: &Vertex| (v.pdf_fwd, v.pdf_rev, v.delta);
+        let mut light_pdfs: Vec<_> = match sampled {
+            Some(v) if s == 1 => vec![pdfs(v)],
+            _ => light_path[..s].iter().map(pdfs).collect(),
+        };
+        let mut camera_pdfs: Vec<_> = match sampled {
+            Some(v) if t == 1 => vec![pdfs(v)],
+            _ => camera_path[..t].iter().map(pdfs).collect(),
+        };
+
+        camera_pdfs[t - 1].1 = match qs {
+            Some(qs) => qs.pdf(&qs.w_o, pt, time),
+            None => pt.pdf_light_origin(light_list, time),
+        };
+        camera_pdfs[t - 1].2 = false;
+        if t > 1 {
+            let pt_minus = &camera_path[t - 2];
+            camera_pdfs[t - 2].1 = match qs {
+                Some(qs) => pt.pdf(&(qs.p - pt.p).normalized(), pt_minus, time),
+                None => match pt.emitter() {
+                    Some(e) => pdf_light(e, &pt.p, &pt.ng, pt_minus, time),
+                    None => 0.0,
+                },
+            };
+        }
+        if let Some(qs) = qs {
+            light_pdfs[s - 1].1 = pt.pdf(&pt.w_o, qs, time);
+            light_pdfs[s - 1].2 = false;
+            if s > 1 {
+                light_pdfs[s - 2].1 = qs.pdf(&(pt.p - qs.p).normalized(), &light_path[s - 2], time);
+            }
+        }
+
+        // Sum the ratios of the pdfs of the other strategies that could have
+        // produced this path to the pdf of this strategy. The camera vertex can't be hit
+        // by light paths so it's never the last vertex of the light subpath
+        let mut sum_ri = 0.0;
+        let mut ri = 1.0;
+        let min_t = if light_tracing { 1 } else { 2 };
+        for i in (min_t..t).rev() {
+            ri *= f32::powf(remap0(camera_pdfs[i].1) / remap0(camera_pdfs[i].0), 2.0);
+            if !camera_pdfs[i].2 && !camera_pdfs[i - 1].2 {
+                sum_ri += ri;
+            }
+        }
+        let delta_light = match sampled {
+            _ if s == 0 => false,
+            Some(v) if s == 1 => v.emitter().map_or(false, |e| e.delta_light()),
+            _ => light_path[0].emitter().map_or(false, |e| e.delta_light()),
+        };
+        ri = 1.0;
+        for i in (0..s).rev() {
+            ri *= f32::powf(remap0(light_pdfs[i].1) / remap0(light_pdfs[i].0), 2.0);
+            let delta_prev = if i > 0 { light_pdfs[i - 1].2 } else { delta_light };
+            if !light_pdfs[i].2 && !delta_prev {
+                sum_ri += ri;
+            }
+        }
+        1.0 / (1.0 + sum_ri)
+    }
+}
+
+impl Bidir {
+    /// Compute the illumination arriving along the camera ray `r` which hit `hit`, if `splats`
+    /// is passed light subpaths are also connected to the camera and the light they carry to
+    /// the image is pushed on to it
+    fn trace(&self, scene: &Scene, light_list: &LightList, r: &Ray, hit: &Intersection, sampler: &mut Sampler,
+             rng: &mut StdRng, alloc: &Allocator, mut splats: Option<&mut Vec<ImageSample>>) -> Colorf {
+        // Paths can bounce up to `max_depth + 1` times to match the path tracer, which
+        // computes direct lighting at the vertex it terminates at
+        let num_samples = self.max_depth as usize + 2;
+        let camera_samples = alloc.alloc_slice(num_samples, (0.0, 0.0));
+        let camera_samples_comp = alloc.alloc_slice(num_samples, 0.0);
+        let light_samples = alloc.alloc_slice(num_samples, (0.0, 0.0));
+        let light_samples_comp = alloc.alloc_slice(num_samples, 0.0);
+        let connect_samples = alloc.alloc_slice(num_samples, (0.0, 0.0));
+        let connect_samples_comp = alloc.alloc_slice(num_samples, 0.0);
+        // The position and direction samples for the light ray must come from separate
+        // calls, samples from within a single call aren't independent of each other
+        let mut emit_pos_samples = [(0.0, 0.0)];
+        let mut emit_dir_samples = [(0.0, 0.0)];
+        let mut emit_samples_comp = [0.0];
+        sampler.get_samples_2d(camera_samples, rng);
+        sampler.get_samples_2d(light_samples, rng);
+        sampler.get_samples_2d(connect_samples, rng);
+        sampler.get_samples_2d(&mut emit_pos_samples[..], rng);
+        sampler.get_samples_2d(&mut emit_dir_samples[..], rng);
+        sampler.get_samples_1d(camera_samples_comp, rng);
+        sampler.get_samples_1d(light_samples_comp, rng);
+        sampler.get_samples_1d(connect_samples_comp, rng);
+        sampler.get_samples_1d(&mut emit_samples_comp[..], rng);
+
+        // The camera subpath starts at the camera followed by up to `num_samples` vertices in the scene
+        let camera = scene.active_camera();
+        let mut camera_path = Vec::with_capacity(num_samples + 1);
+        camera_path.push(Vertex::camera(camera, &r.o, &r.d, &Colorf::broadcast(1.0)));
+        self.random_walk(scene, r, *hit, Colorf::broadcast(1.0), camera.pdf_dir(&r.d, r.time), num_samples + 1,
+                         true, camera_samples, camera_samples_comp, &mut camera_path, rng, alloc);
+
+        // Pick a light to start the light subpath from and sample a ray leaving it
+        let mut light_path = Vec::with_capacity(self.max_depth + 2);
+        if !light_list.is_empty() {
+            let (light, light_pdf) = light_list.sample(emit_samples_comp[0]);
+            let (le, mut ray, n, pdf_pos, pdf_dir) = light.sample_emitted(&emit_pos_samples[0], &emit_dir_samples[0],
+                                                                            r.time);
+            if pdf_pos != 0.0 && pdf_dir != 0.0 && !le.is_black() {
+                light_path.push(Vertex::light(light, &ray.o, &n, &(le / (pdf_pos * light_pdf)), pdf_pos * light_pdf));
+                let beta = le * f32::abs(linalg::dot(&n, &ray.d)) / (light_pdf * pdf_pos * pdf_dir);
+                if let Some(h) = scene.intersect(&mut ray) {
+                    self.random_walk(scene, &ray, h, beta, pdf_dir, self.max_depth + 2, false, light_samples,
+                                     light_samples_comp, &mut light_path, rng, alloc);
+                }
+                // Infinite lights sample the direction first, so the first vertex's pdf is by direction
+                // and the pdf of the next vertex comes from the position sampled on the disk
+                if light.is_infinite() {
+                    light_path[0].pdf_fwd = infinite_light_density(light_list, &-ray.d, r.time);
+                    if light_path.len() > 1 {
+                        light_path[1].pdf_fwd = pdf_pos * f32::abs(linalg::dot(&light_path[1].ng, &ray.d));
+                    }
+                }
+            }
+        }
+
+        // Connect each prefix of the camera subpath with each prefix of the light subpath,
+        // a new light vertex is always sampled for s = 1 so we can try it even if the light
+        // subpath couldn't be traced. Connecting a single light vertex to the camera would
+        // only find light sources seen directly, which the camera subpath already does well
+        let light_tracing = splats.is_some();
+        let mut illum = Colorf::black();
+        for t in 1..camera_path.len() + 1 {
+            for s in 0..cmp::max(light_path.len(), 1) + 1 {
+                if s + t > self.max_depth + 3 {
+                    continue;
+                }
+                if t == 1 {
+                    if s < 2 || s > light_path.len() {
+                        continue;
+                    }
+                    if let Some(ref mut splats) = splats {
+                        if let Some(splat) = self.connect_camera(scene, light_list, &light_path[..],
+                                                                 &camera_path[..], s, r.time) {
+                            splats.push(splat);
+                        }
+                    }
+                } else {
+                    illum = illum + self.connect(scene, light_list, &light_path[..], &camera_path[..], s, t,
+                                                 &connect_samples[t - 2], connect_samples_comp[t - 2], r.time,
+                                                 light_tracing);
+                }
+            }
+        }
+        illum
+    }
+}
+
+impl Integrator for Bidir {
+    fn illumination(&self, scene: &Scene, light_list: &LightList, r: &Ray,
+                    hit: &Intersection, sampler: &mut Sampler, rng: &mut StdRng, alloc: &Allocator) -> Colorf {
+        self.trace(scene, light_list, r, hit, sampler, rng, alloc, None)
+    }
+    fn illumination_splat(&self, scene: &Scene, light_list: &LightList, r: &Ray, hit: &Intersection,
+                          sampler: &mut Sampler, rng: &mut StdRng, alloc: &Allocator,
+                          splats: &mut Vec<ImageSample>) -> Colorf {
+        self.trace(scene, light_list, r, hit, sampler, rng, alloc, Some(splats))
+    }
+}
+
+#[test]
+fn test_bidir_matches_path() {
+    use std::{env, fs, process};
+    use std::io::Write;
+    use exec::{Config, Exec, MultiThreaded};
+    use integrator::Path;
+
+    // A diffuse floor and ball lit by a small area light above them. The light is only clamped
+    // where the camera sees it directly, which both integrators find the same way
+    let scene = r#"{
+        "film": { "width": 32, "height": 32, "samples": 16, "frames": 1, "start_frame": 0, "end_frame": 0,
+                  "scene_time": 0, "filter": { "type": "gaussian", "width": 1.0, "height": 1.0, "alpha": 2.0 } },
+        "camera": { "fov": 40, "transform": [{ "type": "translate", "translation": [0, 2, -12] }] },
+        "integrator": { "type": "pathtracer", "min_depth": 3, "max_depth": 3 },
+        "materials": [{ "name": "white", "type": "matte", "diffuse": [0.7, 0.7, 0.7], "roughness": 0.0 }],
+        "objects": [
+            { "name": "floor", "type": "receiver", "material": "white", "geometry": { "type": "plane" },
+              "transform": [{ "type": "scale", "scaling": 8.0 }, { "type": "rotate_x", "rotation": -90 }] },
+            { "name": "ball", "type": "receiver", "material": "white", "geometry": { "type": "sphere", "radius": 1.5 },
+              "transform": [{ "type": "translate", "translation": [-1, 1.5, 0] }] },
+            { "name": "light", "type": "emitter", "emitter": "area", "emission": [1, 1, 1, 4], "material": "white",
+              "geometry": { "type": "rectangle", "width": 2, "height": 2 },
+              "transform": [{ "type": "rotate_x", "rotation": 90 }, { "type": "translate", "translation": [1, 6, 0] }] }
+        ]
+    }"#;
+    let dir = env::temp_dir().join(format!("tray_rust_test_bidir_{}", process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let file = dir.join("scene.json");
+    fs::File::create(&file).and_then(|mut f| f.write_all(scene.as_bytes())).unwrap();
+    let file = file.to_str().unwrap().to_owned();
+    // Render the scene with the integrator and return the average luminance of the image
+    let render = |integrator: Box<Integrator + Send + Sync>| {
+        let (mut scene, mut rt, spp, frame_info) = Scene::load_file(&file);
+        scene.integrator = integrator;
+        let config = Config::new(dir.clone(), file.clone(), spp, 4, frame_info, (0, 0));
+        MultiThreaded::new(4).render(&mut scene, &mut rt, &config);
+        let img = rt.get_renderf32();
+        img.chunks(4).fold(0.0, |l, c| l + Colorf::new(c[0], c[1], c[2]).luminance()) / (img.len() / 4) as f32
+    };
+    let path = render(Box::new(Path::new(3, 3)));
+    let bidir = render(Box::new(Bidir::new(3, 3)));
+    fs::remove_dir_all(&dir).unwrap();
+    assert!(f32::abs(bidir - path) < 0.03 * path, "bidir renders {} but the path tracer renders {}", bidir, path);
+}
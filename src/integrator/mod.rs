@@ -13,39 +13,64 @@
 //!     ...
 //! }
 //! ```
+//!
+//! The integrator object can also set the optional `light_sampling` parameter to choose
+//! how lights are picked for sampling, see `light::light_list` for details.
 
 use std::f32;
-use std::cmp;
 use enum_set::EnumSet;
 use rand::StdRng;
+use scoped_threadpool::Pool;
 
 use scene::Scene;
 use linalg::{self, Ray, Vector, Point};
-use geometry::{Intersection, Emitter, Instance};
-use film::Colorf;
+use geometry::{Intersection, Instance};
+use film::{Colorf, ImageSample};
 use bxdf::{BSDF, BxDFType};
-use light::Light;
+use light::{Light, LightList};
 use sampler::{Sampler, Sample};
 use mc;
+use memory::Allocator;
 
 pub use self::whitted::Whitted;
 pub use self::path::Path;
+pub use self::bidir::Bidir;
+pub use self::sppm::SPPM;
 pub use self::normals_debug::NormalsDebug;
 
 pub mod whitted;
 pub mod path;
+pub mod bidir;
+pub mod sppm;
 pub mod normals_debug;
 
 /// Trait implemented by the various integration methods that can be used to render
 /// the scene. For scene usage information see whitted and path to get information
 /// on how to specify them.
 pub trait Integrator {
-    /// Compute the illumination at the intersection in the scene
-    fn illumination(&self, scene: &Scene, light_list: &[&Emitter], ray: &Ray,
-                    hit: &Intersection, sampler: &mut Sampler, rng: &mut StdRng) -> Colorf;
+    /// Compute the illumination at the intersection in the scene. The BSDFs and any
+    /// temporary buffers needed for the sample are allocated with `alloc`
+    fn illumination(&self, scene: &Scene, light_list: &LightList, ray: &Ray,
+                    hit: &Intersection, sampler: &mut Sampler, rng: &mut StdRng, alloc: &Allocator) -> Colorf;
+    /// Compute the illumination at the intersection in the scene along with any light found
+    /// arriving at other pixels of the image, e.g. by connecting light paths to the camera, which
+    /// is pushed on to `splats` at its raster position. By default nothing is splatted
+    fn illumination_splat(&self, scene: &Scene, light_list: &LightList, ray: &Ray, hit: &Intersection,
+                          sampler: &mut Sampler, rng: &mut StdRng, alloc: &Allocator,
+                          _: &mut Vec<ImageSample>) -> Colorf {
+        self.illumination(scene, light_list, ray, hit, sampler, rng, alloc)
+    }
+    /// Get the number of passes the integrator renders each frame in, the samples
+    /// taken for each pixel are split between the passes, so at most one pass is rendered
+    /// for each sample
+    fn num_passes(&self) -> usize { 1 }
+    /// Prepare the integrator to render pass `pass` of the frame, this is called before
+    /// any samples are taken for the pass. Expensive preparation can be split across the
+    /// render threads in `pool`
+    fn begin_pass(&self, _: &Scene, _: &LightList, _: usize, _: &mut Pool) {}
     /// Compute the color of specularly reflecting light off the intersection
-    fn specular_reflection(&self, scene: &Scene, light_list: &[&Emitter], ray: &Ray,
-                           bsdf: &BSDF, sampler: &mut Sampler, rng: &mut StdRng) -> Colorf {
+    fn specular_reflection(&self, scene: &Scene, light_list: &LightList, ray: &Ray,
+                           bsdf: &BSDF, sampler: &mut Sampler, rng: &mut StdRng, alloc: &Allocator) -> Colorf {
         let w_o = -ray.d;
         let mut spec_refl = EnumSet::new();
         spec_refl.insert(BxDFType::Specular);
@@ -60,16 +85,18 @@ pub trait Integrator {
         if pdf > 0.0 && !f.is_black() && f32::abs(linalg::dot(&w_i, &bsdf.n)) != 0.0 {
             let mut refl_ray = ray.child(&bsdf.p, &w_i);
             refl_ray.min_t = 0.001;
-            if let Some(hit) = scene.intersect(&mut refl_ray) {
-                let li = self.illumination(scene, light_list, &refl_ray, &hit, sampler, rng);
-                refl = f * li * f32::abs(linalg::dot(&w_i, &bsdf.n)) / pdf;
-            }
+            let li = match scene.intersect(&mut refl_ray) {
+                Some(hit) => self.illumination(scene, light_list, &refl_ray, &hit, sampler, rng, alloc),
+                None => scene.escaped_radiance(&refl_ray),
+            };
+            refl = f * li * f32::abs(linalg::dot(&w_i, &bsdf.n)) / pdf;
         }
         refl
     }
     /// Compute the color of specularly transmitted light through the intersection
-    fn specular_transmission(&self, scene: &Scene, light_list: &[&Emitter], ray: &Ray,
-                             bsdf: &BSDF, sampler: &mut Sampler, rng: &mut StdRng) -> Colorf {
+    fn specular_transmission(&self, scene: &Scene, light_list: &LightList, ray: &Ray,
+                             bsdf: &BSDF, sampler: &mut Sampler, rng: &mut StdRng, alloc: &Allocator)
+                             -> Colorf {
         let w_o = -ray.d;
         let mut spec_trans = EnumSet::new();
         spec_trans.insert(BxDFType::Specular);
@@ -84,14 +111,15 @@ pub trait Integrator {
         if pdf > 0.0 && !f.is_black() && f32::abs(linalg::dot(&w_i, &bsdf.n)) != 0.0 {
             let mut trans_ray = ray.child(&bsdf.p, &w_i);
             trans_ray.min_t = 0.001;
-            if let Some(hit) = scene.intersect(&mut trans_ray) {
-                let li = self.illumination(scene, light_list, &trans_ray, &hit, sampler, rng);
-                transmit = f * li * f32::abs(linalg::dot(&w_i, &bsdf.n)) / pdf;
-            }
+            let li = match scene.intersect(&mut trans_ray) {
+                Some(hit) => self.illumination(scene, light_list, &trans_ray, &hit, sampler, rng, alloc),
+                None => scene.escaped_radiance(&trans_ray),
+            };
+            transmit = f * li * f32::abs(linalg::dot(&w_i, &bsdf.n)) / pdf;
         }
         transmit
     }
-    /// Uniformly sample the contribution of a randomly chosen light in the scene
+    /// Sample the contribution of a randomly chosen light in the scene
     /// to the illumination of this BSDF at the point
     ///
     /// - `w_o` outgoing direction of the light that is incident from the light being
@@ -99,11 +127,15 @@ pub trait Integrator {
     /// - `bsdf` surface properties of the surface being illuminated
     /// - `light_sample` 3 random samples for the light
     /// - `bsdf_sample` 3 random samples for the bsdf
-    fn sample_one_light(&self, scene: &Scene, light_list: &[&Emitter], w_o: &Vector, p: &Point,
+    fn sample_one_light(&self, scene: &Scene, light_list: &LightList, w_o: &Vector, p: &Point,
                         bsdf: &BSDF, light_sample: &Sample, bsdf_sample: &Sample, time: f32) -> Colorf {
-        let l = cmp::min((light_sample.one_d * light_list.len() as f32) as usize, light_list.len() - 1);
-        self.estimate_direct(scene, w_o, p, bsdf, light_sample, bsdf_sample, light_list[l],
-                             BxDFType::non_specular(), time)
+        match light_list.sample_at(&bsdf.p, &bsdf.n, light_sample.one_d) {
+            Some((light, light_pdf)) => {
+                self.estimate_direct(scene, w_o, p, bsdf, light_sample, bsdf_sample, light,
+                                     BxDFType::non_specular(), time) / light_pdf
+            },
+            None => Colorf::black(),
+        }
     }
     /// Estimate the direct light contribution to the surface being shaded by the light
     /// using multiple importance sampling
@@ -149,12 +181,15 @@ pub trait Integrator {
                 // Find out if the ray along w_i actually hits the light source
                 let mut ray = Ray::segment(p, &w_i, 0.001, f32::INFINITY, time);
                 let mut li = Colorf::black();
-                if let Some(h) = scene.intersect(&mut ray) {
-                    if let Instance::Emitter(ref e) = *h.instance {
-                        if e as *const Light == light as *const Light {
-                            li = e.radiance(&-w_i, &h.dg.p, &h.dg.ng, time)
+                match scene.intersect(&mut ray) {
+                    Some(h) => {
+                        if let Instance::Emitter(ref e) = *h.instance {
+                            if e as *const Light == light as *const Light {
+                                li = e.radiance(&-w_i, &h.dg.p, &h.dg.ng, time)
+                            }
                         }
-                    }
+                    },
+                    None => li = light.escaped_radiance(&ray),
                 }
                 if !li.is_black() {
                     direct_light = direct_light + f * li * f32::abs(linalg::dot(&w_i, &bsdf.n)) * w / pdf_bsdf;
@@ -14,19 +14,21 @@ use rand::StdRng;
 
 use scene::Scene;
 use linalg::Ray;
-use geometry::{Intersection, Emitter};
+use geometry::Intersection;
 use film::Colorf;
 use integrator::Integrator;
+use light::LightList;
 use sampler::Sampler;
+use memory::Allocator;
 
 /// The `NormalsDebug` integrator implementing the `NormalsDebug` recursive ray tracing algorithm
 #[derive(Clone, Copy, Debug)]
 pub struct NormalsDebug;
 
 impl Integrator for NormalsDebug {
-    fn illumination(&self, _: &Scene, _: &[&Emitter], _: &Ray,
-                    hit: &Intersection, _: &mut Sampler, _: &mut StdRng) -> Colorf {
-        let bsdf = hit.material.bsdf(hit);
+    fn illumination(&self, _: &Scene, _: &LightList, _: &Ray,
+                    hit: &Intersection, _: &mut Sampler, _: &mut StdRng, alloc: &Allocator) -> Colorf {
+        let bsdf = hit.material.bsdf(hit, alloc);
         (Colorf::new(bsdf.n.x, bsdf.n.y, bsdf.n.z) + Colorf::broadcast(1.0)) / 2.0
     }
 }
@@ -15,16 +15,18 @@
 //! }
 //! ```
 
-use std::{f32, iter};
+use std::f32;
 use rand::{StdRng, Rng};
 
 use scene::Scene;
 use linalg::{self, Ray};
-use geometry::{Intersection, Emitter, Instance};
+use geometry::{Intersection, Instance};
 use film::Colorf;
 use integrator::Integrator;
+use light::LightList;
 use bxdf::BxDFType;
 use sampler::{Sampler, Sample};
+use memory::Allocator;
 
 /// The path integrator implementing Path tracing with explicit light sampling
 #[derive(Clone, Copy, Debug)]
@@ -41,22 +43,21 @@ impl Path {
 }
 
 impl Integrator for Path {
-    fn illumination(&self, scene: &Scene, light_list: &[&Emitter], r: &Ray,
-                    hit: &Intersection, sampler: &mut Sampler, rng: &mut StdRng) -> Colorf {
-        // TODO: We really need the memory pool now
+    fn illumination(&self, scene: &Scene, light_list: &LightList, r: &Ray,
+                    hit: &Intersection, sampler: &mut Sampler, rng: &mut StdRng, alloc: &Allocator) -> Colorf {
         let num_samples = self.max_depth as usize + 1;
-        let mut l_samples: Vec<_> = iter::repeat((0.0, 0.0)).take(num_samples).collect();
-        let mut l_samples_comp: Vec<_> = iter::repeat(0.0).take(num_samples).collect();
-        let mut bsdf_samples: Vec<_> = iter::repeat((0.0, 0.0)).take(num_samples).collect();
-        let mut bsdf_samples_comp: Vec<_> = iter::repeat(0.0).take(num_samples).collect();
-        let mut path_samples: Vec<_> = iter::repeat((0.0, 0.0)).take(num_samples).collect();
-        let mut path_samples_comp: Vec<_> = iter::repeat(0.0).take(num_samples).collect();
-        sampler.get_samples_2d(&mut l_samples[..], rng);
-        sampler.get_samples_2d(&mut bsdf_samples[..], rng);
-        sampler.get_samples_2d(&mut path_samples[..], rng);
-        sampler.get_samples_1d(&mut l_samples_comp[..], rng);
-        sampler.get_samples_1d(&mut bsdf_samples_comp[..], rng);
-        sampler.get_samples_1d(&mut path_samples_comp[..], rng);
+        let l_samples = alloc.alloc_slice(num_samples, (0.0, 0.0));
+        let l_samples_comp = alloc.alloc_slice(num_samples, 0.0);
+        let bsdf_samples = alloc.alloc_slice(num_samples, (0.0, 0.0));
+        let bsdf_samples_comp = alloc.alloc_slice(num_samples, 0.0);
+        let path_samples = alloc.alloc_slice(num_samples, (0.0, 0.0));
+        let path_samples_comp = alloc.alloc_slice(num_samples, 0.0);
+        sampler.get_samples_2d(l_samples, rng);
+        sampler.get_samples_2d(bsdf_samples, rng);
+        sampler.get_samples_2d(path_samples, rng);
+        sampler.get_samples_1d(l_samples_comp, rng);
+        sampler.get_samples_1d(bsdf_samples_comp, rng);
+        sampler.get_samples_1d(path_samples_comp, rng);
 
         let mut illum = Colorf::black();
         let mut path_throughput = Colorf::broadcast(1.0);
@@ -72,7 +73,7 @@ impl Integrator for Path {
                     illum = illum + path_throughput * e.radiance(&w, &hit.dg.p, &hit.dg.ng, ray.time);
                 }
             }
-            let bsdf = current_hit.material.bsdf(&current_hit);
+            let bsdf = current_hit.material.bsdf(&current_hit, alloc);
             let w_o = -ray.d;
             let light_sample = Sample::new(&l_samples[bounce], l_samples_comp[bounce]);
             let bsdf_sample = Sample::new(&bsdf_samples[bounce], bsdf_samples_comp[bounce]);
@@ -110,7 +111,14 @@ impl Integrator for Path {
             // Find the next vertex on the path
             match scene.intersect(&mut ray) {
                 Some(h) => current_hit = h,
-                None => break,
+                None => {
+                    // Light from infinite lights was already sampled at the last vertex
+                    // unless the bounce was specular
+                    if specular_bounce {
+                        illum = illum + path_throughput * scene.escaped_radiance(&ray);
+                    }
+                    break;
+                },
             }
             bounce += 1;
         }
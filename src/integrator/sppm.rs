@@ -0,0 +1,333 @@
+//! Defines the SPPM integrator which implements stochastic progressive photon mapping.
+//! The frame is rendered in a series of passes, at the start of each pass photons are
+//! traced from the lights in the scene and stored in a hash grid. Camera paths are followed
+//! through specular bounces until they hit a non-specular surface, where direct lighting
+//! is computed by sampling the lights and indirect lighting is estimated from the photons
+//! within the gather radius. The gather radius shrinks with each pass so the bias of the
+//! estimate goes to zero as more passes are rendered, this makes the integrator well
+//! suited for rendering caustics which path tracing struggles to find.
+//!
+//! The radius is reduced following the probabilistic formulation of progressive photon
+//! mapping, which lets each pass be rendered independently with a global radius
+//! and the results of the passes simply be averaged.
+//!
+//! See [Hachisuka and Jensen, Stochastic Progressive Photon Mapping](http://dl.acm.org/citation.cfm?id=1618487)
+//! and [Knaus and Zwicker, Progressive Photon Mapping: A Probabilistic Approach](http://dl.acm.org/citation.cfm?id=1966397)
+//!
+//! # Scene Usage Example
+//! The SPPM integrator needs the number of passes to render each frame in, the number of
+//! photons to trace in each pass and the gather radius to use for the first pass. The
+//! `alpha` parameter controls how quickly the radius shrinks between passes and should be
+//! in (0, 1), with smaller values reducing the radius faster. The maximum depth is used to
+//! terminate both photon and camera paths. The samples per pixel set for the film are split
+//! between the passes, with the first passes taking one more sample if they don't divide
+//! evenly. Each pass takes at least one sample, if there are fewer samples than passes only
+//! as many passes as samples are rendered.
+//!
+//! ```json
+//! "integrator": {
+//!     "type": "sppm",
+//!     "num_passes": 16,
+//!     "photons_per_pass": 100000,
+//!     "initial_radius": 0.5,
+//!     "alpha": 0.7,
+//!     "max_depth": 8
+//! }
+//! ```
+
+use std::f32;
+use std::collections::HashMap;
+use std::sync::RwLock;
+use rand::{StdRng, Rng};
+use scoped_threadpool::Pool;
+
+use scene::Scene;
+use linalg::{self, Ray, Point, Vector};
+use geometry::{Intersection, Instance};
+use film::Colorf;
+use integrator::Integrator;
+use bxdf::{BSDF, BxDFType};
+use light::{Light, LightList};
+use sampler::{Sampler, Sample};
+use memory::{Allocator, MemoryArena};
+
+/// A photon stored at a non-specular surface in the scene
+#[derive(Clone, Copy, Debug)]
+struct Photon {
+    p: Point,
+    /// Direction the photon arrived from
+    w_i: Vector,
+    power: Colorf,
+}
+
+/// A uniform hash grid storing the photons traced for a pass, the grid cells
+/// are sized so a gather can touch at most 8 cells
+struct PhotonMap {
+    radius: f32,
+    inv_cell_size: f32,
+    cells: HashMap<(i32, i32, i32), Vec<Photon>>,
+}
+
+impl PhotonMap {
+    /// Create an empty photon map for gathering photons within `radius`
+    fn new(radius: f32) -> PhotonMap {
+        PhotonMap { radius: radius, inv_cell_size: 1.0 / (2.0 * radius), cells: HashMap::new() }
+    }
+    /// Get the grid cell containing the point
+    fn cell(&self, p: &Point) -> (i32, i32, i32) {
+        (f32::floor(p.x * self.inv_cell_size) as i32,
+         f32::floor(p.y * self.inv_cell_size) as i32,
+         f32::floor(p.z * self.inv_cell_size) as i32)
+    }
+    fn insert(&mut self, photon: Photon) {
+        let cell = self.cell(&photon.p);
+        self.cells.entry(cell).or_insert_with(Vec::new).push(photon);
+    }
+    /// Move the photons stored in `other` into this map
+    fn merge(&mut self, other: PhotonMap) {
+        for (cell, mut photons) in other.cells {
+            self.cells.entry(cell).or_insert_with(Vec::new).append(&mut photons);
+        }
+    }
+    /// Call `f` with each photon within the gather radius of `p`
+    fn gather<F: FnMut(&Photon)>(&self, p: &Point, mut f: F) {
+        let r = Vector::broadcast(self.radius);
+        let lo = self.cell(&(*p - r));
+        let hi = self.cell(&(*p + r));
+        let radius_sqr = self.radius * self.radius;
+        for z in lo.2..hi.2 + 1 {
+            for y in lo.1..hi.1 + 1 {
+                for x in lo.0..hi.0 + 1 {
+                    if let Some(photons) = self.cells.get(&(x, y, z)) {
+                        for ph in photons.iter().filter(|ph| ph.p.distance_sqr(p) <= radius_sqr) {
+                            f(ph);
+                        }
+                    }
+                }
+            }
+        }
+    }
+    /// Estimate the radiance reflected along `w_o` at the surface described by `bsdf`
+    /// from the density of photons around it
+    fn estimate(&self, bsdf: &BSDF, w_o: &Vector) -> Colorf {
+        let mut flux = Colorf::black();
+        self.gather(&bsdf.p, |ph| flux = flux + bsdf.eval(w_o, &ph.w_i, BxDFType::all()) * ph.power);
+        flux / (f32::consts::PI * self.radius * self.radius)
+    }
+}
+
+/// The SPPM integrator implementing stochastic progressive photon mapping
+pub struct SPPM {
+    num_passes: usize,
+    photons_per_pass: usize,
+    initial_radius: f32,
+    alpha: f32,
+    max_depth: usize,
+    /// The photons traced for the pass currently being rendered
+    photon_map: RwLock<PhotonMap>,
+}
+
+impl SPPM {
+    /// Create a new SPPM integrator that will render in `num_passes` passes each tracing
+    /// `photons_per_pass` photons. The gather radius starts at `initial_radius` and is
+    /// reduced each pass at a rate controlled by `alpha`
+    pub fn new(num_passes: u32, photons_per_pass: u32, initial_radius: f32, alpha: f32,
+               max_depth: u32) -> SPPM {
+        if num_passes == 0 {
+            panic!("SPPM must render at least one pass");
+        }
+        SPPM { num_passes: num_passes as usize, photons_per_pass: photons_per_pass as usize,
+               initial_radius: initial_radius, alpha: alpha, max_depth: max_depth as usize,
+               photon_map: RwLock::new(PhotonMap::new(initial_radius)) }
+    }
+    /// Compute the gather radius to use for pass `pass`
+    fn pass_radius(&self, pass: usize) -> f32 {
+        let mut radius_sqr = self.initial_radius * self.initial_radius;
+        for i in 1..pass + 1 {
+            radius_sqr *= (i as f32 + self.alpha) / (i as f32 + 1.0);
+        }
+        f32::sqrt(radius_sqr)
+    }
+    /// Trace a photon from a randomly chosen light and store it at each
+    /// non-specular surface it hits after leaving the light
+    fn trace_photon(&self, scene: &Scene, light_list: &LightList, time: f32, photon_map: &mut PhotonMap,
+                    rng: &mut StdRng, alloc: &Allocator) {
+        let (light, light_pdf) = light_list.sample(rng.next_f32());
+        let pos_samples = (rng.next_f32(), rng.next_f32());
+        let dir_samples = (rng.next_f32(), rng.next_f32());
+        let (le, mut ray, n, pdf_pos, pdf_dir) = light.sample_emitted(&pos_samples, &dir_samples, time);
+        if pdf_pos == 0.0 || pdf_dir == 0.0 || le.is_black() {
+            return;
+        }
+        let mut power = le * f32::abs(linalg::dot(&n, &ray.d))
+            / (light_pdf * pdf_pos * pdf_dir * self.photons_per_pass as f32);
+        // Photons can be stored after up to `max_depth` bounces, matching the longest
+        // paths the path tracer computes direct lighting for
+        for depth in 0..self.max_depth + 1 {
+            let hit = match scene.intersect(&mut ray) {
+                Some(h) => h,
+                None => break,
+            };
+            let bsdf = hit.material.bsdf(&hit, alloc);
+            let w_o = -ray.d;
+            // Photons arriving directly from the light are accounted for by
+            // the direct lighting computed when rendering
+            if depth > 0 && bsdf.num_matching(BxDFType::non_specular()) > 0 {
+                photon_map.insert(Photon { p: bsdf.p, w_i: w_o, power: power });
+            }
+            let sample_2d = (rng.next_f32(), rng.next_f32());
+            let sample = Sample::new(&sample_2d, rng.next_f32());
+            let (f, w_i, pdf, _) = bsdf.sample(&w_o, BxDFType::all(), &sample);
+            if f.is_black() || pdf == 0.0 {
+                break;
+            }
+            let new_power = power * f * f32::abs(linalg::dot(&w_i, &bsdf.n)) / pdf;
+            // Terminate photons with Russian roulette based on how much
+            // power they lost at this bounce
+            let cont_prob = f32::min(1.0, new_power.luminance() / power.luminance());
+            if !(cont_prob > 0.0) || rng.next_f32() > cont_prob {
+                break;
+            }
+            power = new_power / cont_prob;
+            ray = ray.child(&bsdf.p, &w_i.normalized());
+            ray.min_t = 0.001;
+        }
+    }
+}
+
+impl Integrator for SPPM {
+    fn illumination(&self, scene: &Scene, light_list: &LightList, r: &Ray,
+                    hit: &Intersection, sampler: &mut Sampler, rng: &mut StdRng, alloc: &Allocator) -> Colorf {
+        let num_samples = self.max_depth as usize + 1;
+        let path_samples = alloc.alloc_slice(num_samples, (0.0, 0.0));
+        let path_samples_comp = alloc.alloc_slice(num_samples, 0.0);
+        let mut l_samples = [(0.0, 0.0)];
+        let mut l_samples_comp = [0.0];
+        let mut bsdf_samples = [(0.0, 0.0)];
+        let mut bsdf_samples_comp = [0.0];
+        sampler.get_samples_2d(path_samples, rng);
+        sampler.get_samples_2d(&mut l_samples[..], rng);
+        sampler.get_samples_2d(&mut bsdf_samples[..], rng);
+        sampler.get_samples_1d(path_samples_comp, rng);
+        sampler.get_samples_1d(&mut l_samples_comp[..], rng);
+        sampler.get_samples_1d(&mut bsdf_samples_comp[..], rng);
+
+        let photon_map = self.photon_map.read().unwrap();
+        let mut illum = Colorf::black();
+        let mut path_throughput = Colorf::broadcast(1.0);
+        let mut current_hit = *hit;
+        let mut ray = *r;
+        // Follow the camera path through specular bounces until we find a
+        // non-specular surface to gather photons at
+        for bounce in 0..num_samples {
+            let bsdf = current_hit.material.bsdf(&current_hit, alloc);
+            let w_o = -ray.d;
+            if let Instance::Emitter(ref e) = *current_hit.instance {
+                illum = illum + path_throughput * e.radiance(&w_o, &bsdf.p, &bsdf.ng, ray.time);
+            }
+            if bsdf.num_matching(BxDFType::non_specular()) > 0 {
+                let light_sample = Sample::new(&l_samples[0], l_samples_comp[0]);
+                let bsdf_sample = Sample::new(&bsdf_samples[0], bsdf_samples_comp[0]);
+                let direct = self.sample_one_light(scene, light_list, &w_o, &bsdf.p, &bsdf,
+                                                   &light_sample, &bsdf_sample, ray.time);
+                illum = illum + path_throughput * (direct + photon_map.estimate(&bsdf, &w_o));
+                break;
+            }
+
+            let path_sample = Sample::new(&path_samples[bounce], path_samples_comp[bounce]);
+            let (f, w_i, pdf, _) = bsdf.sample(&w_o, BxDFType::all(), &path_sample);
+            if f.is_black() || pdf == 0.0 {
+                break;
+            }
+            path_throughput = path_throughput * f * f32::abs(linalg::dot(&w_i, &bsdf.n)) / pdf;
+            ray = ray.child(&bsdf.p, &w_i.normalized());
+            ray.min_t = 0.001;
+            match scene.intersect(&mut ray) {
+                Some(h) => current_hit = h,
+                None => {
+                    illum = illum + path_throughput * scene.escaped_radiance(&ray);
+                    break;
+                },
+            }
+        }
+        illum
+    }
+    fn num_passes(&self) -> usize {
+        self.num_passes
+    }
+    fn begin_pass(&self, scene: &Scene, light_list: &LightList, pass: usize, pool: &mut Pool) {
+        let radius = self.pass_radius(pass);
+        let shutter = scene.active_camera().shutter_time();
+        // Each thread traces its share of the photons into its own map with its own
+        // independently seeded rng and memory arena, the maps are merged once all the
+        // photons are traced
+        let n = pool.thread_count() as usize;
+        let mut thread_maps: Vec<_> = (0..n).map(|_| PhotonMap::new(radius)).collect();
+        pool.scoped(|scope| {
+            for (i, photon_map) in thread_maps.iter_mut().enumerate() {
+                let num_photons = self.photons_per_pass / n + if i < self.photons_per_pass % n { 1 } else { 0 };
+                scope.execute(move || {
+                    let mut rng = StdRng::new().expect("Failed to get StdRng for tracing photons");
+                    let mut arena = MemoryArena::new(64 * 1024);
+                    for _ in 0..num_photons {
+                        let time = linalg::lerp(rng.next_f32(), &shutter.0, &shutter.1);
+                        self.trace_photon(scene, light_list, time, photon_map, &mut rng, &arena.allocator());
+                    }
+                });
+            }
+        });
+        let mut photon_map = PhotonMap::new(radius);
+        for m in thread_maps {
+            photon_map.merge(m);
+        }
+        *self.photon_map.write().unwrap() = photon_map;
+    }
+}
+
+#[test]
+fn test_photon_map_gather() {
+    use rand::SeedableRng;
+
+    let mut rng: StdRng = SeedableRng::from_seed(&[1][..]);
+    let photon = |p: Point| Photon { p: p, w_i: Vector::new(0.0, 0.0, 1.0), power: Colorf::broadcast(1.0) };
+    // With a radius of 0.5 the cells are 1 unit wide, so these photons are in the cells next to
+    // the ones containing the gather points below while still being within the radius of them
+    let mut photons = vec![photon(Point::new(0.8, 0.0, 0.0)), photon(Point::new(0.2, 0.1, 0.0)),
+                           photon(Point::new(-1.05, 0.95, 0.05))];
+    for _ in 0..2000 {
+        photons.push(photon(Point::new(rng.next_f32() * 4.0 - 2.0, rng.next_f32() * 4.0 - 2.0,
+                                       rng.next_f32() * 4.0 - 2.0)));
+    }
+    // Split the photons between two maps and merge them, like the photons traced by each thread
+    let mut photon_map = PhotonMap::new(0.5);
+    let mut other = PhotonMap::new(0.5);
+    for (i, ph) in photons.iter().enumerate() {
+        if i % 2 == 0 { photon_map.insert(*ph) } else { other.insert(*ph) }
+    }
+    photon_map.merge(other);
+    let points = [Point::new(1.2, 0.0, 0.0), Point::new(-0.1, -0.1, -0.1), Point::new(-0.9, 1.05, -0.05),
+                  Point::broadcast(0.0), Point::new(-1.9, -1.5, -1.7), Point::new(0.5, -0.5, 1.0)];
+    for p in &points {
+        let mut found = Vec::new();
+        photon_map.gather(p, |ph| found.push(ph.p));
+        let expected: Vec<_> = photons.iter().filter(|ph| ph.p.distance_sqr(p) <= 0.25).map(|ph| ph.p).collect();
+        assert!(!expected.is_empty());
+        assert_eq!(found.len(), expected.len());
+        for e in &expected {
+            assert!(found.contains(e), "Photon at {:?} within the radius of {:?} wasn't gathered", e, p);
+        }
+    }
+}
+#[test]
+fn test_pass_radius() {
+    let sppm = SPPM::new(4, 1000, 2.0, 0.5, 5);
+    assert_eq!(sppm.pass_radius(0), 2.0);
+    // The squared radius is scaled by (i + alpha) / (i + 1) for each pass i after the first
+    let expected_sqr = [4.0, 4.0 * 0.75, 4.0 * 0.75 * 2.5 / 3.0, 4.0 * 0.75 * 2.5 / 3.0 * 3.5 / 4.0];
+    for (i, r) in expected_sqr.iter().enumerate() {
+        assert!(f32::abs(sppm.pass_radius(i) - f32::sqrt(*r)) < 1e-5);
+    }
+    for i in 1..100 {
+        assert!(sppm.pass_radius(i) < sppm.pass_radius(i - 1));
+    }
+}
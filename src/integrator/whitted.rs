@@ -17,12 +17,13 @@ use rand::StdRng;
 
 use scene::Scene;
 use linalg::{self, Ray};
-use geometry::{Intersection, Emitter, Instance};
+use geometry::{Intersection, Instance};
 use film::Colorf;
 use integrator::Integrator;
 use bxdf::BxDFType;
-use light::Light;
+use light::{Light, LightList};
 use sampler::Sampler;
+use memory::Allocator;
 
 /// The Whitted integrator implementing the Whitted recursive ray tracing algorithm
 #[derive(Clone, Copy, Debug)]
@@ -37,9 +38,9 @@ impl Whitted {
 }
 
 impl Integrator for Whitted {
-    fn illumination(&self, scene: &Scene, light_list: &[&Emitter], ray: &Ray,
-                    hit: &Intersection, sampler: &mut Sampler, rng: &mut StdRng) -> Colorf {
-        let bsdf = hit.material.bsdf(hit);
+    fn illumination(&self, scene: &Scene, light_list: &LightList, ray: &Ray,
+                    hit: &Intersection, sampler: &mut Sampler, rng: &mut StdRng, alloc: &Allocator) -> Colorf {
+        let bsdf = hit.material.bsdf(hit, alloc);
         let w_o = -ray.d;
         let mut sample_2d = [(0.0, 0.0)];
         sampler.get_samples_2d(&mut sample_2d[..], rng);
@@ -51,7 +52,7 @@ impl Integrator for Whitted {
             }
         }
 
-        for light in light_list {
+        for light in light_list.lights() {
             let (li, w_i, pdf, occlusion) = light.sample_incident(&hit.dg.p, &sample_2d[0], ray.time);
             let f = bsdf.eval(&w_o, &w_i, BxDFType::all());
             if !li.is_black() && !f.is_black() && !occlusion.occluded(scene) {
@@ -59,8 +60,8 @@ impl Integrator for Whitted {
             }
         }
         if ray.depth < self.max_depth {
-            illum = illum + self.specular_reflection(scene, light_list, ray, &bsdf, sampler, rng);
-            illum = illum + self.specular_transmission(scene, light_list, ray, &bsdf, sampler, rng);
+            illum = illum + self.specular_reflection(scene, light_list, ray, &bsdf, sampler, rng, alloc);
+            illum = illum + self.specular_transmission(scene, light_list, ray, &bsdf, sampler, rng, alloc);
         }
         illum
     }
@@ -40,9 +40,6 @@
 //! ## TODO
 //!
 //! - More material models (eg. more microfacet models, rough glass, etc.)
-//! - Textures
-//! - Support for using an OBJ's associated MTL files
-//! - Bump mapping
 //! - [Subsurface scattering?](http://en.wikipedia.org/wiki/Subsurface_scattering)
 //! - [Vertex Connection and Merging?](http://iliyan.com/publications/VertexMerging)
 //! 
@@ -103,8 +100,11 @@ pub mod integrator;
 pub mod scene;
 pub mod bxdf;
 pub mod material;
+pub mod texture;
 pub mod light;
 pub mod mc;
+pub mod memory;
 pub mod partition;
 pub mod exec;
+pub mod gltf;
 
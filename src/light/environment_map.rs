@@ -0,0 +1,121 @@
+//! Provides the `EnvironmentMap` which stores an equirectangular image of the
+//! radiance arriving from the environment surrounding the scene, along with a
+//! distribution for importance sampling directions based on the image's brightness.
+//!
+//! In the map's local space the top row of the image is towards +z and the image's
+//! u coordinate wraps around the z axis starting at +x, the map can be oriented in the
+//! scene by the transform of the environment light using it. See `geometry::emitter`
+//! for how to add an environment light to the scene.
+
+use std::f32;
+use std::path::Path;
+
+use linalg::{self, Vector};
+use film::Colorf;
+use mc::Distribution2D;
+use texture;
+
+/// An equirectangular environment map which can be importance sampled
+pub struct EnvironmentMap {
+    width: usize,
+    height: usize,
+    pixels: Vec<Colorf>,
+    /// Distribution over the image used to sample directions
+    distribution: Distribution2D,
+    /// Average radiance arriving from the map over the sphere of directions
+    average: Colorf,
+}
+
+impl EnvironmentMap {
+    /// Create an environment map from the `width` by `height` image of radiance values
+    /// passed, the pixels are stored in rows starting at the top of the image
+    pub fn new(width: usize, height: usize, pixels: Vec<Colorf>) -> EnvironmentMap {
+        assert_eq!(pixels.len(), width * height);
+        // Weight the brightness of each row by sin(theta) to account for the
+        // rows near the poles being squished together on the sphere
+        let mut func = Vec::with_capacity(width * height);
+        let mut average = Colorf::black();
+        let mut weight = 0.0;
+        for (y, row) in pixels.chunks(width).enumerate() {
+            let sin_theta = f32::sin(f32::consts::PI * (y as f32 + 0.5) / height as f32);
+            for p in row {
+                func.push(f32::max(p.luminance(), 0.0) * sin_theta);
+                average = average + *p * sin_theta;
+                weight += sin_theta;
+            }
+        }
+        EnvironmentMap { width: width, height: height, pixels: pixels,
+                         distribution: Distribution2D::new(&func[..], width, height),
+                         average: average / weight }
+    }
+    /// Load an environment map from an image file, Radiance HDR files are loaded as linear
+    /// radiance while other image formats are assumed to be sRGB and converted to linear.
+    /// Panics if the file can't be loaded
+    pub fn load_file(path: &Path) -> EnvironmentMap {
+        match texture::load_image(path, true) {
+            Ok((width, height, pixels)) => EnvironmentMap::new(width, height, pixels),
+            Err(e) => panic!("Failed to load environment map: {}", e),
+        }
+    }
+    /// Get the radiance arriving from the direction `w` in the map's local space
+    pub fn lookup(&self, w: &Vector) -> Colorf {
+        let (u, v) = self.dir_to_uv(w);
+        let x = linalg::clamp((u * self.width as f32) as usize, 0, self.width - 1);
+        let y = linalg::clamp((v * self.height as f32) as usize, 0, self.height - 1);
+        self.pixels[y * self.width + x]
+    }
+    /// Sample a direction in the map's local space based on the brightness of the map,
+    /// returns the direction and its solid angle pdf
+    pub fn sample(&self, samples: &(f32, f32)) -> (Vector, f32) {
+        let ((u, v), pdf) = self.distribution.sample_continuous(samples);
+        let theta = v * f32::consts::PI;
+        let phi = u * 2.0 * f32::consts::PI;
+        let sin_theta = f32::sin(theta);
+        let w = linalg::spherical_dir(sin_theta, f32::cos(theta), phi);
+        if sin_theta == 0.0 {
+            (w, 0.0)
+        } else {
+            // Convert from the pdf over the image to solid angle
+            (w, pdf / (2.0 * f32::consts::PI * f32::consts::PI * sin_theta))
+        }
+    }
+    /// Compute the solid angle pdf of sampling the direction `w` in the map's local space
+    pub fn pdf(&self, w: &Vector) -> f32 {
+        let (u, v) = self.dir_to_uv(w);
+        let sin_theta = f32::sin(v * f32::consts::PI);
+        if sin_theta == 0.0 {
+            0.0
+        } else {
+            self.distribution.pdf(&(u, v)) / (2.0 * f32::consts::PI * f32::consts::PI * sin_theta)
+        }
+    }
+    /// Get the average radiance arriving from the map over the sphere of directions
+    pub fn average(&self) -> Colorf {
+        self.average
+    }
+    /// Find the image coordinates that the direction `w` maps to
+    fn dir_to_uv(&self, w: &Vector) -> (f32, f32) {
+        let w = w.normalized();
+        (linalg::spherical_phi(&w) / (2.0 * f32::consts::PI), linalg::spherical_theta(&w) / f32::consts::PI)
+    }
+}
+
+#[test]
+fn test_sample_pdf() {
+    // A dim map with a bright patch, the sampled pdfs should match
+    // the pdf computed for the direction
+    let mut pixels = vec![Colorf::broadcast(0.1); 16 * 8];
+    pixels[3 * 16 + 5] = Colorf::broadcast(50.0);
+    let map = EnvironmentMap::new(16, 8, pixels);
+    let mut in_patch = 0;
+    for i in 0..64 {
+        let samples = ((i % 8) as f32 / 8.0 + 0.01, (i / 8) as f32 / 8.0 + 0.02);
+        let (w, pdf) = map.sample(&samples);
+        assert!(f32::abs(w.length() - 1.0) < 1e-4);
+        assert!(f32::abs(pdf - map.pdf(&w)) / pdf < 1e-3);
+        if map.lookup(&w).r == 50.0 {
+            in_patch += 1;
+        }
+    }
+    assert!(in_patch > 32);
+}
@@ -0,0 +1,239 @@
+//! Provides the `IesProfile` which loads an IES LM-63 photometric profile describing
+//! how the intensity of a light fixture varies with direction. Profiles can be applied to
+//! point and spot lights, see `geometry::emitter` for how to add them to a light in the scene.
+//!
+//! Only type C photometry, the type used by nearly all architectural fixtures, is supported.
+//! The profile's vertical angles are measured from its nadir, which is placed along the light's
+//! local +z axis (the direction a spot light shines), and its horizontal angles run counter
+//! clockwise about the axis starting from +x. The candela values are normalized so the
+//! brightest direction of the profile has an intensity of 1, the light's emission then sets
+//! the intensity in that direction.
+
+use std::f32;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+use linalg::{self, Vector};
+
+/// A photometric profile describing the intensity of a light in each direction
+#[derive(Debug)]
+pub struct IesProfile {
+    /// Vertical angles the intensity was measured at in degrees from the nadir
+    vertical: Vec<f32>,
+    /// Horizontal angles the intensity was measured at in degrees about the nadir
+    horizontal: Vec<f32>,
+    /// The normalized intensity measured at each horizontal angle for each vertical angle,
+    /// stored by horizontal angle
+    intensity: Vec<f32>,
+    /// Average normalized intensity over the sphere of directions
+    average: f32,
+}
+
+impl IesProfile {
+    /// Load an IES profile from the file, panics if the file can't be read or is invalid
+    pub fn load_file(path: &Path) -> IesProfile {
+        let mut file = match File::open(path) {
+            Ok(f) => f,
+            Err(e) => panic!("light::IesProfile::load_file - failed to open {:?} due to {}", path, e),
+        };
+        let mut text = String::new();
+        if let Err(e) = file.read_to_string(&mut text) {
+            panic!("light::IesProfile::load_file - failed to read {:?} due to {}", path, e);
+        }
+        match IesProfile::parse(&text) {
+            Ok(p) => p,
+            Err(e) => panic!("light::IesProfile::load_file - invalid IES file {:?}: {}", path, e),
+        }
+    }
+    /// Parse an IES profile from the text of an LM-63 file
+    pub fn parse(text: &str) -> Result<IesProfile, String> {
+        // Skip the keyword header to find the tilt line, which precedes the photometric data
+        let mut lines = text.lines();
+        let tilt = loop {
+            match lines.next() {
+                Some(l) => {
+                    let l = l.trim();
+                    if l.starts_with("TILT=") {
+                        break l[5..].trim().to_owned();
+                    }
+                },
+                None => return Err("no TILT line found".to_owned()),
+            }
+        };
+        let rest: Vec<&str> = lines.collect();
+        let mut values = Vec::new();
+        for tok in rest.iter().flat_map(|l| l.split(|c: char| c.is_whitespace() || c == ',')) {
+            if tok.is_empty() {
+                continue;
+            }
+            match tok.parse::<f32>() {
+                Ok(v) => values.push(v),
+                Err(_) => return Err(format!("invalid number '{}'", tok)),
+            }
+        }
+        let mut values = values.into_iter();
+        let mut next = || values.next().ok_or_else(|| "unexpected end of file".to_owned());
+        // Tilt data changes the output of lamps mounted at an angle, we only use the profile
+        // of the light in its measured orientation so it's skipped
+        if tilt == "INCLUDE" {
+            try!(next());
+            let n = try!(next()) as usize;
+            for _ in 0..2 * n {
+                try!(next());
+            }
+        } else if tilt != "NONE" {
+            return Err(format!("tilt files are not supported, found TILT={}", tilt));
+        }
+        let _num_lamps = try!(next());
+        let _lumens = try!(next());
+        let multiplier = try!(next());
+        let num_vertical = try!(next()) as usize;
+        let num_horizontal = try!(next()) as usize;
+        let photometric_type = try!(next()) as i32;
+        // Skip the units and luminous opening dimensions, ballast factors and input watts
+        for _ in 0..7 {
+            try!(next());
+        }
+        if photometric_type != 1 {
+            return Err(format!("only type C photometry is supported, found type {}", photometric_type));
+        }
+        if num_vertical == 0 || num_horizontal == 0 {
+            return Err("the profile must have at least one vertical and horizontal angle".to_owned());
+        }
+        let mut vertical = Vec::with_capacity(num_vertical);
+        for _ in 0..num_vertical {
+            vertical.push(try!(next()));
+        }
+        let mut horizontal = Vec::with_capacity(num_horizontal);
+        for _ in 0..num_horizontal {
+            horizontal.push(try!(next()));
+        }
+        if vertical.windows(2).any(|w| w[0] > w[1]) || horizontal.windows(2).any(|w| w[0] > w[1]) {
+            return Err("the profile's angles must be in increasing order".to_owned());
+        }
+        let mut intensity = Vec::with_capacity(num_vertical * num_horizontal);
+        for _ in 0..num_vertical * num_horizontal {
+            intensity.push(f32::max(try!(next()) * multiplier, 0.0));
+        }
+        let max = intensity.iter().fold(0.0, |m, x| f32::max(m, *x));
+        if max > 0.0 {
+            for i in &mut intensity {
+                *i = *i / max;
+            }
+        }
+        let mut profile = IesProfile { vertical: vertical, horizontal: horizontal, intensity: intensity,
+                                       average: 0.0 };
+        profile.average = profile.compute_average();
+        Ok(profile)
+    }
+    /// Get the normalized intensity of the light in the direction `w` in the light's local space
+    pub fn intensity(&self, w: &Vector) -> f32 {
+        let w = w.normalized();
+        let theta = f32::acos(linalg::clamp(w.z, -1.0, 1.0)).to_degrees();
+        let mut phi = f32::atan2(w.y, w.x).to_degrees();
+        if phi < 0.0 {
+            phi += 360.0;
+        }
+        // The last horizontal angle tells us how the profile is symmetric about the nadir
+        let last = self.horizontal[self.horizontal.len() - 1];
+        if last == 90.0 {
+            phi = phi % 180.0;
+            if phi > 90.0 {
+                phi = 180.0 - phi;
+            }
+        } else if last == 180.0 && phi > 180.0 {
+            phi = 360.0 - phi;
+        }
+        let (v, tv) = match find_interval(&self.vertical, theta) {
+            Some(i) => i,
+            None => return 0.0,
+        };
+        let (h, th) = match find_interval(&self.horizontal, phi) {
+            Some(i) => i,
+            // Extend the profile past the horizontal angles it was measured at
+            None => if phi < self.horizontal[0] { (0, 0.0) } else { (self.horizontal.len() - 1, 0.0) },
+        };
+        let nv = self.vertical.len();
+        let at = |h: usize, v: usize| {
+            let h = if h < self.horizontal.len() { h } else { h - 1 };
+            let v = if v < nv { v } else { v - 1 };
+            self.intensity[h * nv + v]
+        };
+        let a = linalg::lerp(tv, &at(h, v), &at(h, v + 1));
+        let b = linalg::lerp(tv, &at(h + 1, v), &at(h + 1, v + 1));
+        linalg::lerp(th, &a, &b)
+    }
+    /// Get the average normalized intensity of the light over the sphere of directions
+    pub fn average(&self) -> f32 {
+        self.average
+    }
+    /// Numerically integrate the intensity over the sphere to find its average
+    fn compute_average(&self) -> f32 {
+        let (n_theta, n_phi) = (90, 180);
+        let d_theta = f32::consts::PI / n_theta as f32;
+        let d_phi = 2.0 * f32::consts::PI / n_phi as f32;
+        let mut sum = 0.0;
+        for i in 0..n_theta {
+            let theta = (i as f32 + 0.5) * d_theta;
+            let sin_theta = f32::sin(theta);
+            for j in 0..n_phi {
+                let phi = (j as f32 + 0.5) * d_phi;
+                sum += self.intensity(&linalg::spherical_dir(sin_theta, f32::cos(theta), phi))
+                    * sin_theta * d_theta * d_phi;
+            }
+        }
+        sum / (4.0 * f32::consts::PI)
+    }
+}
+
+/// Find the interval in the sorted `angles` containing `x`, returns the index of the start of
+/// the interval and how far along it `x` is, or None if `x` is outside the angles
+fn find_interval(angles: &[f32], x: f32) -> Option<(usize, f32)> {
+    if angles.len() == 1 {
+        return Some((0, 0.0));
+    }
+    if x < angles[0] || x > angles[angles.len() - 1] {
+        return None;
+    }
+    let i = match angles.iter().position(|a| *a > x) {
+        Some(i) => i - 1,
+        None => angles.len() - 2,
+    };
+    let width = angles[i + 1] - angles[i];
+    let t = if width > 0.0 { (x - angles[i]) / width } else { 0.0 };
+    Some((i, linalg::clamp(t, 0.0, 1.0)))
+}
+
+#[test]
+fn test_parse() {
+    // A rotationally symmetric downlight which only emits below the horizon
+    let downlight = "IESNA:LM-63-2002\n[TEST] downlight\n[MANUFAC] none\nTILT=NONE\n\
+                     1 1000 2.0 3 1 1 1 0 0 0\n1.0 1.0 50\n\
+                     0 45 90\n0\n500 250 0\n";
+    let p = IesProfile::parse(downlight).unwrap();
+    let nadir = Vector::new(0.0, 0.0, 1.0);
+    assert_eq!(p.intensity(&nadir), 1.0);
+    let half = p.intensity(&Vector::new(f32::sin(f32::consts::PI / 8.0), 0.0, f32::cos(f32::consts::PI / 8.0)));
+    assert!(f32::abs(half - 0.75) < 1e-3);
+    assert_eq!(p.intensity(&Vector::new(0.0, 0.0, -1.0)), 0.0);
+    assert_eq!(p.intensity(&Vector::new(1.0, 0.0, 0.1)), p.intensity(&Vector::new(0.0, -1.0, 0.1)));
+    assert!(p.average() > 0.0 && p.average() < 0.5);
+
+    // A bilaterally symmetric profile with tilt data, split over lines and
+    // separated by commas
+    let bilateral = "IESNA91\nTILT=INCLUDE\n1\n2\n0 90\n1.0 0.8\n\
+                     1, 1000, 1, 2, 3, 1, 2, 0.1, 0.2, 0.0\n1 1\n\
+                     100\n0 180\n0 90\n180\n10 10\n20 0\n40\n20\n";
+    let p = IesProfile::parse(bilateral).unwrap();
+    // Along the nadir at phi = 0
+    assert!(f32::abs(p.intensity(&Vector::new(0.001, 0.0, 1.0)) - 0.25) < 1e-3);
+    // theta = 90 at phi = 90 and the mirrored phi = 270
+    assert!(f32::abs(p.intensity(&Vector::new(0.0, 1.0, 0.0)) - 0.25) < 1e-3);
+    assert_eq!(p.intensity(&Vector::new(0.0, 1.0, 0.0)), p.intensity(&Vector::new(0.0, -1.0, 0.0)));
+    // theta = 180 at phi = 180
+    assert!(f32::abs(p.intensity(&Vector::new(-0.001, 0.0, -1.0)) - 0.5) < 1e-3);
+
+    assert!(IesProfile::parse("IESNA:LM-63-2002\n[TEST] no tilt\n1 1000 1 1 1 1 1 0 0 0\n").is_err());
+    assert!(IesProfile::parse("IESNA:LM-63-2002\nTILT=NONE\n1 1000 1 2 1 1 1 0 0 0\n1 1 50\n0 90\n0\n5\n").is_err());
+}
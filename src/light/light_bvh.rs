@@ -0,0 +1,327 @@
+//! Provides the `LightBVH`, a hierarchy built over the lights in the scene which is
+//! used to choose lights based on an estimate of how much they contribute to the point
+//! being shaded. Each node stores the bounds, total power and a cone bounding the emission
+//! directions of the lights beneath it. When sampling we walk down the tree choosing
+//! each child with probability proportional to its importance to the shading point and
+//! normal, so in scenes with many lights those that are near and facing the point
+//! receive most of the samples.
+//!
+//! See [Conty Estevez and Kulla, Importance Sampling of Many Lights with Adaptive Tree Splitting](http://dl.acm.org/citation.cfm?id=3233305)
+//!
+//! # Scene Usage Example
+//! The light BVH is used for direct lighting by setting the optional `light_sampling`
+//! parameter of the integrator to `bvh`. Integrators that need to choose lights without
+//! knowing the point being shaded, e.g. to start paths or photons at a light, will fall
+//! back to choosing lights proportional to their power.
+//!
+//! ```json
+//! "integrator": {
+//!     "type": "pathtracer",
+//!     "min_depth": 3,
+//!     "max_depth": 8,
+//!     "light_sampling": "bvh"
+//! }
+//! ```
+
+use std::f32;
+
+use geometry::BBox;
+use linalg::{self, Point, Vector, Normal};
+
+/// Bounds on the position, power and emission directions of one or more lights
+#[derive(Clone, Copy, Debug)]
+pub struct LightBounds {
+    pub bounds: BBox,
+    /// Luminance of the power emitted by the lights
+    pub power: f32,
+    /// Axis of the cone bounding the normals of the emitting surfaces
+    pub axis: Vector,
+    /// Spread of the normal cone about `axis`
+    pub theta_o: f32,
+    /// Angle past the normals that light is emitted into
+    pub theta_e: f32,
+}
+
+impl LightBounds {
+    /// Create bounds for lights within `bounds` emitting `power` whose surface normals lie
+    /// within `theta_o` of `axis` and emit light within `theta_e` of their normal
+    pub fn new(bounds: BBox, power: f32, axis: &Vector, theta_o: f32, theta_e: f32) -> LightBounds {
+        LightBounds { bounds: bounds, power: power, axis: axis.normalized(), theta_o: theta_o, theta_e: theta_e }
+    }
+    /// Get the bounds containing both this set of lights and `b`
+    pub fn union(&self, b: &LightBounds) -> LightBounds {
+        if self.power == 0.0 {
+            return *b;
+        } else if b.power == 0.0 {
+            return *self;
+        }
+        let (axis, theta_o) = cone_union(&self.axis, self.theta_o, &b.axis, b.theta_o);
+        LightBounds { bounds: self.bounds.box_union(&b.bounds), power: self.power + b.power,
+                      axis: axis, theta_o: theta_o, theta_e: f32::max(self.theta_e, b.theta_e) }
+    }
+    /// Estimate the contribution of the lights to the point `p` with surface normal `n`.
+    /// The estimate is conservative, it will only be zero if none of the lights can
+    /// illuminate the point
+    pub fn importance(&self, p: &Point, n: &Normal) -> f32 {
+        if self.power == 0.0 {
+            return 0.0;
+        }
+        let center = self.bounds.lerp(0.5, 0.5, 0.5);
+        let radius_sqr = center.distance_sqr(&self.bounds.max);
+        let to_p = *p - center;
+        let dist_sqr = to_p.length_sqr();
+        // Find the angle subtended by the bounds as seen from the point, if we're
+        // inside the bounds light could be arriving from any direction
+        let theta_b = if dist_sqr <= radius_sqr {
+            f32::consts::PI
+        } else {
+            f32::asin(f32::sqrt(radius_sqr / dist_sqr))
+        };
+        let w = if dist_sqr > 0.0 { to_p / f32::sqrt(dist_sqr) } else { self.axis };
+        // Find the smallest angle between an emission direction of the lights
+        // and the direction to the point
+        let theta_w = f32::acos(linalg::clamp(linalg::dot(&self.axis, &w), -1.0, 1.0));
+        let theta = f32::max(0.0, theta_w - self.theta_o - theta_b);
+        if theta >= self.theta_e {
+            return 0.0;
+        }
+        let theta_i = f32::acos(linalg::clamp(f32::abs(linalg::dot(n, &w)), 0.0, 1.0));
+        let cos_i = f32::cos(f32::max(0.0, theta_i - theta_b));
+        self.power * f32::cos(theta) * cos_i / f32::max(dist_sqr, f32::max(radius_sqr, 1e-8))
+    }
+    /// Get the center of the bounds
+    fn center(&self) -> Point {
+        self.bounds.lerp(0.5, 0.5, 0.5)
+    }
+}
+
+/// Find the cone bounding the cones about `a` and `b` with spreads `theta_a` and `theta_b`
+fn cone_union(a: &Vector, theta_a: f32, b: &Vector, theta_b: f32) -> (Vector, f32) {
+    if theta_b > theta_a {
+        return cone_union(b, theta_b, a, theta_a);
+    }
+    let theta_d = f32::acos(linalg::clamp(linalg::dot(a, b), -1.0, 1.0));
+    if f32::min(theta_d + theta_b, f32::consts::PI) <= theta_a {
+        return (*a, theta_a);
+    }
+    let theta_o = (theta_a + theta_d + theta_b) / 2.0;
+    if theta_o >= f32::consts::PI {
+        return (*a, f32::consts::PI);
+    }
+    // Rotate `a` towards `b` to find the axis of the new cone
+    let perp = *b - *a * linalg::dot(a, b);
+    if perp.length_sqr() < 1e-12 {
+        return (*a, f32::consts::PI);
+    }
+    let theta_r = theta_o - theta_a;
+    let axis = *a * f32::cos(theta_r) + perp.normalized() * f32::sin(theta_r);
+    (axis.normalized(), theta_o)
+}
+
+/// Data for the nodes in the light BVH
+#[derive(Debug)]
+enum LightNodeData {
+    /// An interior node is stored with its first child following it
+    /// and the second child at some later index in the tree
+    Interior { second_child: usize },
+    /// A leaf node holds the index of a single light
+    Leaf { light: usize },
+}
+
+#[derive(Debug)]
+struct LightNode {
+    bounds: LightBounds,
+    node: LightNodeData,
+}
+
+/// A BVH over the lights in the scene used to choose lights by their importance to the
+/// point being shaded. Lights are referred to by their index in the list of bounds the
+/// BVH was built from.
+pub struct LightBVH {
+    /// The flattened tree structure of the BVH
+    tree: Vec<LightNode>,
+    /// The path taken from the root to reach each light's leaf, stored as a bit for each
+    /// level of the tree that is set if the second child is taken. Lights that emit no
+    /// power are not placed in the tree and have no trail
+    trails: Vec<Option<u64>>,
+}
+
+impl LightBVH {
+    /// Build a light BVH over the lights with the bounds passed, lights without bounds
+    /// (e.g. infinite lights) are not placed in the tree
+    pub fn new(lights: &[Option<LightBounds>]) -> LightBVH {
+        let mut build_info: Vec<_> = lights.iter().enumerate().filter_map(|(i, b)| {
+            match *b {
+                Some(b) if b.power > 0.0 => Some((i, b)),
+                _ => None,
+            }
+        }).collect();
+        let mut bvh = LightBVH { tree: Vec::with_capacity(2 * build_info.len()),
+                                 trails: vec![None; lights.len()] };
+        if !build_info.is_empty() {
+            bvh.build(&mut build_info[..], 0, 0);
+        }
+        bvh
+    }
+    /// Check if there are no lights in the tree
+    pub fn is_empty(&self) -> bool {
+        self.tree.is_empty()
+    }
+    /// Build the subtree over the lights passed, returning the bounds of the subtree.
+    /// `trail` and `depth` describe the path taken from the root to reach this subtree
+    fn build(&mut self, build_info: &mut [(usize, LightBounds)], trail: u64, depth: usize) -> LightBounds {
+        if build_info.len() == 1 {
+            let (light, bounds) = build_info[0];
+            self.trails[light] = Some(trail);
+            self.tree.push(LightNode { bounds: bounds, node: LightNodeData::Leaf { light: light } });
+            return bounds;
+        }
+        assert!(depth < 64, "Light BVH is too deep to store light trails");
+        // Split the lights in half along the axis their centers vary most on
+        let centroids = build_info.iter().fold(BBox::new(), |b, &(_, ref l)| b.point_union(&l.center()));
+        let split_axis = centroids.max_extent();
+        build_info.sort_by(|a, b| {
+            match a.1.center()[split_axis].partial_cmp(&b.1.center()[split_axis]) {
+                Some(o) => o,
+                None => panic!("NaNs in light bounds centers?!"),
+            }
+        });
+        let mid = build_info.len() / 2;
+        let offset = self.tree.len();
+        self.tree.push(LightNode { bounds: build_info[0].1, node: LightNodeData::Interior { second_child: 0 } });
+        let left = self.build(&mut build_info[..mid], trail, depth + 1);
+        let second_child = self.tree.len();
+        let right = self.build(&mut build_info[mid..], trail | (1 << depth), depth + 1);
+        self.tree[offset] = LightNode { bounds: left.union(&right),
+                                        node: LightNodeData::Interior { second_child: second_child } };
+        self.tree[offset].bounds
+    }
+    /// Choose a light to sample for the point `p` with normal `n` using the sample `u` in [0, 1).
+    /// Returns the index of the light chosen and the probability of choosing it, or None if
+    /// no light can illuminate the point
+    pub fn sample(&self, p: &Point, n: &Normal, u: f32) -> Option<(usize, f32)> {
+        if self.tree.is_empty() {
+            return None;
+        }
+        let mut u = u;
+        let mut pdf = 1.0;
+        let mut current = 0;
+        loop {
+            match self.tree[current].node {
+                LightNodeData::Leaf { light } => {
+                    if self.tree[current].bounds.importance(p, n) > 0.0 {
+                        return Some((light, pdf));
+                    }
+                    return None;
+                },
+                LightNodeData::Interior { second_child } => {
+                    let imp_left = self.tree[current + 1].bounds.importance(p, n);
+                    let imp_right = self.tree[second_child].bounds.importance(p, n);
+                    if imp_left == 0.0 && imp_right == 0.0 {
+                        return None;
+                    }
+                    // Pick a child and remap the sample so it can be re-used to choose
+                    // at the next level down
+                    let p_left = imp_left / (imp_left + imp_right);
+                    if u < p_left {
+                        u = f32::min(u / p_left, 1.0 - f32::EPSILON);
+                        pdf *= p_left;
+                        current += 1;
+                    } else {
+                        u = f32::min((u - p_left) / (1.0 - p_left), 1.0 - f32::EPSILON);
+                        pdf *= 1.0 - p_left;
+                        current = second_child;
+                    }
+                },
+            }
+        }
+    }
+    /// Get the probability of choosing the light with index `light` when sampling
+    /// for the point `p` with normal `n`
+    pub fn pdf(&self, p: &Point, n: &Normal, light: usize) -> f32 {
+        let mut trail = match self.trails[light] {
+            Some(t) => t,
+            None => return 0.0,
+        };
+        let mut pdf = 1.0;
+        let mut current = 0;
+        loop {
+            match self.tree[current].node {
+                LightNodeData::Leaf { .. } => {
+                    if self.tree[current].bounds.importance(p, n) > 0.0 {
+                        return pdf;
+                    }
+                    return 0.0;
+                },
+                LightNodeData::Interior { second_child } => {
+                    let imp_left = self.tree[current + 1].bounds.importance(p, n);
+                    let imp_right = self.tree[second_child].bounds.importance(p, n);
+                    if imp_left == 0.0 && imp_right == 0.0 {
+                        return 0.0;
+                    }
+                    if trail & 1 == 0 {
+                        pdf *= imp_left / (imp_left + imp_right);
+                        current += 1;
+                    } else {
+                        pdf *= imp_right / (imp_left + imp_right);
+                        current = second_child;
+                    }
+                    trail >>= 1;
+                },
+            }
+        }
+    }
+}
+
+#[test]
+fn test_light_bvh_variance() {
+    use std::sync::Arc;
+    use rand::{Rng, SeedableRng, StdRng};
+    use film::{AnimatedColor, ColorKeyframe, Colorf};
+    use geometry::{Emitter, Rectangle};
+    use light::{Light, LightList, LightSampling};
+    use linalg::{AnimatedTransform, Transform};
+    use material::Matte;
+    use texture::ConstantTexture;
+    // A ceiling of small light panels facing down, shaded from a point in one corner
+    let lights: Vec<_> = (0..256).map(|i| {
+        let emission = AnimatedColor::with_keyframes(vec![ColorKeyframe::new(&Colorf::broadcast(10.0), 0.0)]);
+        let pos = Vector::new((i % 16) as f32 - 7.5, (i / 16) as f32 - 7.5, 2.0);
+        let transform = AnimatedTransform::unanimated(&(Transform::translate(&pos) * Transform::rotate_x(180.0)));
+        let matte = Matte::new(Arc::new(ConstantTexture::new(Colorf::broadcast(0.5))),
+                               Arc::new(ConstantTexture::new(0.0)));
+        Emitter::area(Arc::new(Rectangle::new(0.2, 0.2)), Arc::new(matte), emission, transform, format!("light_{}", i))
+    }).collect();
+    let p = Point::new(-7.2, -7.4, 0.0);
+    let n = Normal::new(0.0, 0.0, 1.0);
+    let irradiance = |light: &Emitter, light_pdf: f32, samples: &(f32, f32)| {
+        let (li, w_i, pdf, _) = light.sample_incident(&p, samples, 0.0);
+        if pdf > 0.0 { li.luminance() * f32::abs(linalg::dot(&w_i, &n)) / (pdf * light_pdf) } else { 0.0 }
+    };
+    // Compute a reference value by sampling each light in turn
+    let reference: f64 = lights.iter().map(|l| {
+        (0..64).map(|i| irradiance(l, 1.0, &(((i % 8) as f32 + 0.5) / 8.0, ((i / 8) as f32 + 0.5) / 8.0)) as f64)
+            .sum::<f64>() / 64.0
+    }).sum();
+    let estimate = |sampling| {
+        let list = LightList::new(lights.iter().collect(), sampling, 0.0, 0.0);
+        let mut rng: StdRng = SeedableRng::from_seed(&[1, 2, 3, 4][..]);
+        let num_samples = 20000;
+        let mut sum = 0.0;
+        let mut sum_sqr = 0.0;
+        for _ in 0..num_samples {
+            let (light, light_pdf) = list.sample_at(&p, &n, rng.next_f32()).unwrap();
+            assert!(f32::abs(list.pdf_at(&p, &n, light) - light_pdf) < 1e-5);
+            let x = irradiance(light, light_pdf, &(rng.next_f32(), rng.next_f32()));
+            sum += x as f64;
+            sum_sqr += (x * x) as f64;
+        }
+        let mean = sum / num_samples as f64;
+        (mean, sum_sqr / num_samples as f64 - mean * mean)
+    };
+    let (power_mean, power_var) = estimate(LightSampling::Power);
+    let (bvh_mean, bvh_var) = estimate(LightSampling::Bvh);
+    assert!(f64::abs(power_mean - reference) / reference < 0.1);
+    assert!(f64::abs(bvh_mean - reference) / reference < 0.02);
+    assert!(bvh_var * 4.0 < power_var);
+}
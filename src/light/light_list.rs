@@ -0,0 +1,187 @@
+//! Provides the `LightList` which holds the lights in the scene along with the
+//! distribution used to choose which light to sample when computing direct
+//! lighting or starting paths at a light.
+//!
+//! # Scene Usage Example
+//! By default lights are chosen with probability proportional to the power they
+//! emit, so a few bright lights in a scene with many dim ones will receive most of
+//! the samples. Lights can instead be chosen uniformly by setting the optional
+//! `light_sampling` parameter of the integrator to `uniform`, for power based
+//! selection it can be set to `power`. Scenes with many lights can set it to `bvh`
+//! to choose lights for direct lighting using the `LightBVH`, see `light::light_bvh`.
+//! Infinite lights like environment maps can't be placed in the `LightBVH`, when
+//! using it they're chosen uniformly alongside the lights in the tree.
+//!
+//! ```json
+//! "integrator": {
+//!     "type": "pathtracer",
+//!     "min_depth": 3,
+//!     "max_depth": 8,
+//!     "light_sampling": "uniform"
+//! }
+//! ```
+
+use std::cmp;
+use std::f32;
+use std::collections::HashMap;
+
+use geometry::Emitter;
+use light::{Light, LightBVH};
+use linalg::{Point, Normal};
+use mc::Distribution1D;
+
+/// The strategies available for choosing which light to sample
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LightSampling {
+    /// Choose lights uniformly
+    Uniform,
+    /// Choose lights with probability proportional to their emitted power
+    Power,
+    /// Choose lights for direct lighting based on their importance to the point
+    /// being shaded using a `LightBVH`, otherwise choose by power
+    Bvh,
+}
+
+/// The lights in the scene along with the distribution to choose them from
+pub struct LightList<'a> {
+    lights: Vec<&'a Emitter>,
+    distribution: Distribution1D,
+    /// Map from the address of each light to its index in `lights`, used to find
+    /// the probability of having chosen a light that a ray hit
+    indices: HashMap<usize, usize>,
+    /// The light BVH to choose lights for direct lighting with, if one was built
+    light_bvh: Option<LightBVH>,
+    /// Indices of the infinite lights, which aren't placed in the light BVH
+    infinite: Vec<usize>,
+}
+
+impl<'a> LightList<'a> {
+    /// Create a light list for the lights passed which will choose lights using the
+    /// `sampling` strategy over the time period [start, end], light powers are computed
+    /// in the middle of the time period. Panics if there are no lights
+    pub fn new(lights: Vec<&'a Emitter>, sampling: LightSampling, start: f32, end: f32) -> LightList<'a> {
+        assert!(!lights.is_empty(), "At least one light is required");
+        let time = (start + end) / 2.0;
+        let weights: Vec<_> = match sampling {
+            LightSampling::Uniform => lights.iter().map(|_| 1.0).collect(),
+            LightSampling::Power | LightSampling::Bvh =>
+                lights.iter().map(|l| f32::max(l.power(time).luminance(), 0.0)).collect(),
+        };
+        let light_bvh = match sampling {
+            LightSampling::Bvh => {
+                let bounds: Vec<_> = lights.iter().map(|l| l.light_bounds(start, end, time)).collect();
+                Some(LightBVH::new(&bounds[..]))
+            },
+            _ => None,
+        };
+        let indices = lights.iter().enumerate().map(|(i, l)| (*l as *const Emitter as usize, i)).collect();
+        let infinite = lights.iter().enumerate().filter(|&(_, l)| l.is_infinite()).map(|(i, _)| i).collect();
+        LightList { lights: lights, distribution: Distribution1D::new(&weights[..]), indices: indices,
+                    light_bvh: light_bvh, infinite: infinite }
+    }
+    /// Get the lights in the list
+    pub fn lights(&self) -> &[&'a Emitter] {
+        &self.lights[..]
+    }
+    /// Get the number of lights in the list
+    pub fn len(&self) -> usize {
+        self.lights.len()
+    }
+    /// Check if there are no lights in the list
+    pub fn is_empty(&self) -> bool {
+        self.lights.is_empty()
+    }
+    /// Choose a light using the sample `u` in [0, 1), returns the light chosen
+    /// and the probability of choosing it
+    pub fn sample(&self, u: f32) -> (&'a Emitter, f32) {
+        let (i, pdf) = self.distribution.sample_discrete(u);
+        (self.lights[i], pdf)
+    }
+    /// Get the probability of choosing `light` when sampling the list
+    pub fn pdf(&self, light: &Emitter) -> f32 {
+        match self.indices.get(&(light as *const Emitter as usize)) {
+            Some(i) => self.distribution.discrete_pdf(*i),
+            None => 0.0,
+        }
+    }
+    /// Choose a light to compute direct lighting from at the point `p` with surface normal `n`
+    /// using the sample `u` in [0, 1). Returns the light chosen and the probability of choosing
+    /// it, or None if no light could be chosen
+    pub fn sample_at(&self, p: &Point, n: &Normal, u: f32) -> Option<(&'a Emitter, f32)> {
+        match self.light_bvh {
+            Some(ref bvh) => {
+                // Infinite lights are chosen uniformly alongside the tree
+                let p_inf = self.infinite_pdf(bvh);
+                if u < p_inf {
+                    let i = ((u / p_inf) * self.infinite.len() as f32) as usize;
+                    let i = cmp::min(i, self.infinite.len() - 1);
+                    Some((self.lights[self.infinite[i]], p_inf / self.infinite.len() as f32))
+                } else {
+                    let u = f32::min((u - p_inf) / (1.0 - p_inf), 1.0 - f32::EPSILON);
+                    bvh.sample(p, n, u).map(|(i, pdf)| (self.lights[i], pdf * (1.0 - p_inf)))
+                }
+            },
+            None => Some(self.sample(u)),
+        }
+    }
+    /// Get the probability of choosing `light` when sampling for the point `p`
+    /// with surface normal `n` with `sample_at`
+    pub fn pdf_at(&self, p: &Point, n: &Normal, light: &Emitter) -> f32 {
+        match self.light_bvh {
+            Some(ref bvh) => {
+                let p_inf = self.infinite_pdf(bvh);
+                match self.indices.get(&(light as *const Emitter as usize)) {
+                    Some(_) if light.is_infinite() => p_inf / self.infinite.len() as f32,
+                    Some(i) => bvh.pdf(p, n, *i) * (1.0 - p_inf),
+                    None => 0.0,
+                }
+            },
+            None => self.pdf(light),
+        }
+    }
+    /// Get the probability of choosing one of the infinite lights instead of
+    /// traversing the light BVH when sampling with `sample_at`
+    fn infinite_pdf(&self, bvh: &LightBVH) -> f32 {
+        if self.infinite.is_empty() {
+            0.0
+        } else if bvh.is_empty() {
+            1.0
+        } else {
+            self.infinite.len() as f32 / (self.infinite.len() as f32 + 1.0)
+        }
+    }
+}
+
+#[test]
+fn test_light_list_sampling() {
+    use std::ptr;
+    use linalg::{AnimatedTransform, Transform};
+    use film::{AnimatedColor, ColorKeyframe, Colorf};
+
+    let point = |strength: f32| {
+        let emission = AnimatedColor::with_keyframes(vec![ColorKeyframe::new(&Colorf::broadcast(strength), 0.0)]);
+        Emitter::point(AnimatedTransform::unanimated(&Transform::identity()), emission, None, "light".to_owned())
+    };
+    let (dim, bright) = (point(1.0), point(9.0));
+    let power = LightList::new(vec![&dim, &bright], LightSampling::Power, 0.0, 0.0);
+    assert!(f32::abs(power.pdf(&dim) - 0.1) < 1e-5);
+    assert!(f32::abs(power.pdf(&bright) - 0.9) < 1e-5);
+    // Lights should be chosen in proportion to their power and report the same pdf as `pdf`
+    let n = 1000;
+    let mut num_bright = 0;
+    for i in 0..n {
+        let (light, pdf) = power.sample((i as f32 + 0.5) / n as f32);
+        assert_eq!(pdf, power.pdf(light));
+        if ptr::eq(light, &bright) {
+            num_bright += 1;
+        }
+    }
+    assert!(f32::abs(num_bright as f32 / n as f32 - 0.9) < 0.01);
+
+    let uniform = LightList::new(vec![&dim, &bright], LightSampling::Uniform, 0.0, 0.0);
+    for i in 0..n {
+        let (light, pdf) = uniform.sample((i as f32 + 0.5) / n as f32);
+        assert_eq!(pdf, 0.5);
+        assert_eq!(uniform.pdf(light), 0.5);
+    }
+}
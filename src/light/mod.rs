@@ -1,13 +1,28 @@
-//! Defines the light interface implemented by all lights in `tray_rust` and
-//! the `OcclusionTester` which provides a convenient interface for doing
-//! shadow tests for lights
+//! Defines the light interface implemented by all lights in `tray_rust`, the
+//! `OcclusionTester` which provides a convenient interface for doing
+//! shadow tests for lights and the `LightList` used to choose lights to sample,
+//! optionally using the `LightBVH`. The `EnvironmentMap` used by environment
+//! lights, the `SunSky` daylight model used by sky lights and the `IesProfile` photometric
+//! profiles used to shape point and spot lights are also provided here
 
 use std::f32;
 
-use linalg::{Point, Vector, Ray};
+use linalg::{Point, Vector, Normal, Ray};
 use film::Colorf;
 use scene::Scene;
 
+pub use self::light_list::{LightList, LightSampling};
+pub use self::light_bvh::{LightBVH, LightBounds};
+pub use self::environment_map::EnvironmentMap;
+pub use self::sun_sky::SunSky;
+pub use self::ies::IesProfile;
+
+pub mod light_list;
+pub mod light_bvh;
+pub mod environment_map;
+pub mod sun_sky;
+pub mod ies;
+
 /// The `OcclusionTester` provides a simple interface for setting up and executing
 /// occlusion queries in the scene
 #[derive(Clone, Copy, Debug)]
@@ -38,8 +53,8 @@ impl OcclusionTester {
 }
 
 /// Trait implemented by all lights in `tray_rust`. Provides methods for sampling
-/// the light and in the future ones for checking if it's a delta light, computing
-/// its power and so on.
+/// the light, sampling rays of light leaving it, checking if it's a delta light
+/// and computing its power.
 pub trait Light {
     /// Sample the illumination from the light arriving at the point `p`
     /// Returns the color, incident light direction, pdf and occlusion tester object
@@ -50,5 +65,20 @@ pub trait Light {
     fn delta_light(&self) -> bool;
     /// Compute the PDF for sampling the point with incident direction `w_i`
     fn pdf(&self, p: &Point, w_i: &Vector, time: f32) -> f32;
+    /// Sample a ray of light leaving the light, used to start paths at the light.
+    /// `pos_samples` are used to pick the point the light is emitted from and
+    /// `dir_samples` to pick the direction it leaves along.
+    /// Returns the radiance emitted along the ray, the ray, the surface normal at the
+    /// ray origin and the positional (area measure) and directional (solid angle) pdfs
+    fn sample_emitted(&self, pos_samples: &(f32, f32), dir_samples: &(f32, f32), time: f32)
+        -> (Colorf, Ray, Normal, f32, f32);
+    /// Compute the positional (area measure) and directional (solid angle) pdfs
+    /// for the light emitting a ray from `p`, with surface normal `n`, along `w`
+    fn pdf_emitted(&self, p: &Point, n: &Normal, w: &Vector, time: f32) -> (f32, f32);
+    /// Compute the total power emitted by the light
+    fn power(&self, time: f32) -> Colorf;
+    /// Compute the radiance arriving from the light along a ray that left the
+    /// scene without hitting anything, only infinite lights return non-black values
+    fn escaped_radiance(&self, ray: &Ray) -> Colorf;
 }
 
@@ -0,0 +1,245 @@
+//! Provides the `SunSky` daylight model, an analytic sky dome along with the
+//! sun disk, parameterized by the atmosphere's turbidity, the albedo of the ground
+//! and the direction towards the sun. The sky radiance is computed with the model from
+//! [Preetham et al., A Practical Analytic Model for Daylight](https://www.cs.utah.edu/~shirley/papers/sunsky/sunsky.pdf)
+//! and the sun's radiance is attenuated by the Rayleigh and aerosol scattering along
+//! its path through the atmosphere, evaluated at a representative wavelength for each
+//! color channel.
+//!
+//! Radiance values are in kcd/m^2, so a clear sky has a radiance on the order of
+//! 1-10 while the sun is around 10^6, the sky can be scaled to the scene's units
+//! with the emission of the sky light. The model is only valid for the sun being above
+//! the horizon. See `geometry::emitter` for how to add a sky light to the scene.
+
+use std::f32;
+
+use linalg::{self, Vector};
+use film::Colorf;
+use light::EnvironmentMap;
+use mc;
+
+/// Angular radius of the sun as seen from the ground in radians
+const SUN_RADIUS: f32 = 0.00465;
+/// Luminance of the sun outside the atmosphere in kcd/m^2
+const SUN_LUMINANCE: f32 = 1.96e6;
+/// Dimensions of the table of sky radiance used to importance sample the sky
+const TABLE_WIDTH: usize = 128;
+const TABLE_HEIGHT: usize = 64;
+
+/// A sky dome and sun which can be importance sampled
+pub struct SunSky {
+    turbidity: f32,
+    ground_albedo: Colorf,
+    /// Coefficients of the Perez sky luminance distribution for Y, x and y
+    perez: [[f32; 5]; 3],
+    /// Coordinate frame of the sky, `up` points towards the zenith
+    x: Vector,
+    y: Vector,
+    up: Vector,
+    /// Table of the sky's radiance used to sample directions in the sky, tabulated
+    /// for the sun direction last set. Directions in the table are in the sky's frame
+    sky_map: EnvironmentMap,
+    /// Radiance of the ground, which is seen in directions below the horizon
+    ground: Colorf,
+    /// Probability of sampling the sun instead of the sky
+    sun_prob: f32,
+    /// Average radiance arriving from the sun and sky over the sphere of directions
+    average: Colorf,
+}
+
+impl SunSky {
+    /// Create a sky for an atmosphere with `turbidity` in [2, 10] over a ground with albedo
+    /// `ground_albedo`. `up` is the direction towards the zenith and `sun` the direction
+    /// towards the sun in world space
+    pub fn new(turbidity: f32, ground_albedo: &Colorf, up: &Vector, sun: &Vector) -> SunSky {
+        let t = turbidity;
+        let perez = [[0.1787 * t - 1.4630, -0.3554 * t + 0.4275, -0.0227 * t + 5.3251,
+                      0.1206 * t - 2.5771, -0.0670 * t + 0.3703],
+                     [-0.0193 * t - 0.2592, -0.0665 * t + 0.0008, -0.0004 * t + 0.2125,
+                      -0.0641 * t - 0.8989, -0.0033 * t + 0.0452],
+                     [-0.0167 * t - 0.2608, -0.0950 * t + 0.0092, -0.0079 * t + 0.2102,
+                      -0.0441 * t - 1.6537, -0.0109 * t + 0.0529]];
+        let up = up.normalized();
+        let (x, y) = linalg::coordinate_system(&up);
+        let mut sky = SunSky { turbidity: turbidity, ground_albedo: *ground_albedo, perez: perez,
+                               x: x, y: y, up: up, sky_map: EnvironmentMap::new(1, 1, vec![Colorf::black()]),
+                               ground: Colorf::black(), sun_prob: 0.0, average: Colorf::black() };
+        sky.set_sun(sun);
+        sky
+    }
+    /// Tabulate the sky for the sun in the direction `sun`, the sampling distribution, ground
+    /// radiance and average radiance of the sky are computed for this sun direction
+    pub fn set_sun(&mut self, sun: &Vector) {
+        let sun = sun.normalized();
+        let d_theta = f32::consts::PI / TABLE_HEIGHT as f32;
+        let d_phi = 2.0 * f32::consts::PI / TABLE_WIDTH as f32;
+        let mut pixels = Vec::with_capacity(TABLE_WIDTH * TABLE_HEIGHT);
+        // Sum up the light arriving at the ground from the sky and sun as we go, which
+        // is reflected by the ground
+        let sun_solid_angle = 2.0 * f32::consts::PI * (1.0 - f32::cos(SUN_RADIUS));
+        let sun_radiance = self.sun_radiance(&sun);
+        let mut irradiance = sun_radiance * sun_solid_angle * f32::max(linalg::dot(&sun, &self.up), 0.0);
+        for j in 0..TABLE_HEIGHT / 2 {
+            let theta = (j as f32 + 0.5) * d_theta;
+            let (sin_theta, cos_theta) = (f32::sin(theta), f32::cos(theta));
+            for i in 0..TABLE_WIDTH {
+                let phi = (i as f32 + 0.5) * d_phi;
+                let w = self.to_world(&linalg::spherical_dir(sin_theta, cos_theta, phi));
+                let l = self.sky_radiance(&w, &sun);
+                irradiance = irradiance + l * cos_theta * sin_theta * d_theta * d_phi;
+                pixels.push(l);
+            }
+        }
+        self.ground = self.ground_albedo * irradiance * f32::consts::FRAC_1_PI;
+        for _ in TABLE_HEIGHT / 2..TABLE_HEIGHT {
+            for _ in 0..TABLE_WIDTH {
+                pixels.push(self.ground);
+            }
+        }
+        self.sky_map = EnvironmentMap::new(TABLE_WIDTH, TABLE_HEIGHT, pixels);
+        let sun_power = sun_radiance.luminance() * sun_solid_angle;
+        let sky_power = self.sky_map.average().luminance() * 4.0 * f32::consts::PI;
+        self.sun_prob = if sun_power > 0.0 { sun_power / (sun_power + sky_power) } else { 0.0 };
+        self.average = self.sky_map.average() + sun_radiance * sun_solid_angle / (4.0 * f32::consts::PI);
+    }
+    /// Get the radiance arriving from the direction `w` when the sun is in the direction `sun`
+    pub fn radiance(&self, w: &Vector, sun: &Vector) -> Colorf {
+        let w = w.normalized();
+        let sun = sun.normalized();
+        let l = if linalg::dot(&w, &self.up) < 0.0 { self.ground } else { self.sky_radiance(&w, &sun) };
+        if linalg::dot(&w, &sun) >= f32::cos(SUN_RADIUS) {
+            l + self.sun_radiance(&sun)
+        } else {
+            l
+        }
+    }
+    /// Sample a direction towards the sun or sky when the sun is in the direction `sun`,
+    /// returns the direction and its solid angle pdf
+    pub fn sample(&self, samples: &(f32, f32), sun: &Vector) -> (Vector, f32) {
+        let sun = sun.normalized();
+        let w = if samples.0 < self.sun_prob {
+            let u = (samples.0 / self.sun_prob, samples.1);
+            let (sun_x, sun_y) = linalg::coordinate_system(&sun);
+            mc::uniform_sample_cone_frame(&u, f32::cos(SUN_RADIUS), &sun_x, &sun_y, &sun)
+        } else {
+            let u = (f32::min((samples.0 - self.sun_prob) / (1.0 - self.sun_prob), 1.0 - f32::EPSILON),
+                     samples.1);
+            let (w, _) = self.sky_map.sample(&u);
+            self.to_world(&w)
+        };
+        (w, self.pdf(&w, &sun))
+    }
+    /// Compute the solid angle pdf of sampling the direction `w` when the sun is
+    /// in the direction `sun`
+    pub fn pdf(&self, w: &Vector, sun: &Vector) -> f32 {
+        let w = w.normalized();
+        let cos_sun = f32::cos(SUN_RADIUS);
+        let pdf_sun = if linalg::dot(&w, &sun.normalized()) >= cos_sun { mc::uniform_cone_pdf(cos_sun) } else { 0.0 };
+        self.sun_prob * pdf_sun + (1.0 - self.sun_prob) * self.sky_map.pdf(&self.to_local(&w))
+    }
+    /// Get the average radiance arriving from the sun and sky over the sphere of directions,
+    /// for the sun direction last set
+    pub fn average(&self) -> Colorf {
+        self.average
+    }
+    /// Compute the radiance of the sky in the direction `w` above the horizon with
+    /// the Preetham model, not including the sun
+    fn sky_radiance(&self, w: &Vector, sun: &Vector) -> Colorf {
+        let cos_theta = linalg::clamp(linalg::dot(w, &self.up), 0.0, 1.0);
+        let theta_s = f32::acos(linalg::clamp(linalg::dot(sun, &self.up), 0.0, 1.0));
+        let gamma = f32::acos(linalg::clamp(linalg::dot(w, sun), -1.0, 1.0));
+        let zenith = self.zenith(theta_s);
+        let mut xyy = [0.0; 3];
+        for i in 0..3 {
+            xyy[i] = zenith[i] * self.perez_fn(i, cos_theta, gamma) / self.perez_fn(i, 1.0, theta_s);
+        }
+        xyy_to_rgb(xyy[1], xyy[2], f32::max(xyy[0], 0.0))
+    }
+    /// Compute the luminance and chromaticity (Y, x, y) at the zenith for the
+    /// sun at the angle `theta_s` from the zenith
+    fn zenith(&self, theta_s: f32) -> [f32; 3] {
+        let t = self.turbidity;
+        let chi = (4.0 / 9.0 - t / 120.0) * (f32::consts::PI - 2.0 * theta_s);
+        let lum = (4.0453 * t - 4.9710) * f32::tan(chi) - 0.2155 * t + 2.4192;
+        let (t1, t2, t3) = (theta_s, theta_s * theta_s, theta_s * theta_s * theta_s);
+        let x = t * t * (0.00166 * t3 - 0.00375 * t2 + 0.00209 * t1)
+            + t * (-0.02903 * t3 + 0.06377 * t2 - 0.03202 * t1 + 0.00394)
+            + (0.11693 * t3 - 0.21196 * t2 + 0.06052 * t1 + 0.25886);
+        let y = t * t * (0.00275 * t3 - 0.00610 * t2 + 0.00317 * t1)
+            + t * (-0.04214 * t3 + 0.08970 * t2 - 0.04153 * t1 + 0.00516)
+            + (0.15346 * t3 - 0.26756 * t2 + 0.06670 * t1 + 0.26688);
+        [lum, x, y]
+    }
+    /// Evaluate the Perez distribution function `i` (Y, x or y) for a direction at
+    /// an angle of acos(`cos_theta`) to the zenith and `gamma` to the sun
+    fn perez_fn(&self, i: usize, cos_theta: f32, gamma: f32) -> f32 {
+        let c = &self.perez[i];
+        let cos_gamma = f32::cos(gamma);
+        (1.0 + c[0] * f32::exp(c[1] / f32::max(cos_theta, 0.01)))
+            * (1.0 + c[2] * f32::exp(c[3] * gamma) + c[4] * cos_gamma * cos_gamma)
+    }
+    /// Compute the radiance of the sun in the direction `sun` after it's been
+    /// attenuated by the atmosphere
+    fn sun_radiance(&self, sun: &Vector) -> Colorf {
+        let cos_theta_s = linalg::dot(sun, &self.up);
+        if cos_theta_s <= 0.0 {
+            return Colorf::black();
+        }
+        let theta_s = f32::acos(f32::min(cos_theta_s, 1.0)) * 180.0 * f32::consts::FRAC_1_PI;
+        // Relative optical mass of the atmosphere the light passes through
+        let m = 1.0 / (cos_theta_s + 0.15 * f32::powf(93.885 - theta_s, -1.253));
+        let beta = 0.04608 * self.turbidity - 0.04586;
+        // Representative wavelengths for red, green and blue in micrometers
+        let lambda = [0.68, 0.55, 0.44];
+        let mut c = [0.0; 3];
+        for i in 0..3 {
+            let tau_rayleigh = f32::exp(-0.008735 * f32::powf(lambda[i], -4.08) * m);
+            let tau_aerosol = f32::exp(-beta * f32::powf(lambda[i], -1.3) * m);
+            c[i] = SUN_LUMINANCE * tau_rayleigh * tau_aerosol;
+        }
+        Colorf::new(c[0], c[1], c[2])
+    }
+    /// Transform the direction `w` from the sky's frame to world space
+    fn to_world(&self, w: &Vector) -> Vector {
+        w.x * self.x + w.y * self.y + w.z * self.up
+    }
+    /// Transform the direction `w` from world space to the sky's frame
+    fn to_local(&self, w: &Vector) -> Vector {
+        Vector::new(linalg::dot(w, &self.x), linalg::dot(w, &self.y), linalg::dot(w, &self.up))
+    }
+}
+
+/// Convert a color with chromaticity `x`, `y` and luminance `lum` to linear sRGB
+fn xyy_to_rgb(x: f32, y: f32, lum: f32) -> Colorf {
+    if y <= 0.0 {
+        return Colorf::black();
+    }
+    let cx = x * lum / y;
+    let cz = (1.0 - x - y) * lum / y;
+    Colorf::new(f32::max(3.2404542 * cx - 1.5371385 * lum - 0.4985314 * cz, 0.0),
+                f32::max(-0.969266 * cx + 1.8760108 * lum + 0.041556 * cz, 0.0),
+                f32::max(0.0556434 * cx - 0.2040259 * lum + 1.0572252 * cz, 0.0))
+}
+
+#[test]
+fn test_sample_pdf() {
+    let up = Vector::new(0.0, 1.0, 0.0);
+    let sun = Vector::new(0.0, 1.0, 1.0).normalized();
+    let sky = SunSky::new(3.0, &Colorf::broadcast(0.3), &up, &sun);
+    // The sky should be brighter around the sun than opposite it, and the sun brighter still
+    let around_sun = sky.radiance(&Vector::new(0.0, 1.0, 0.8), &sun).luminance();
+    let opposite = sky.radiance(&Vector::new(0.0, 1.0, -0.8), &sun).luminance();
+    assert!(around_sun > opposite);
+    assert!(sky.radiance(&sun, &sun).luminance() > 1000.0 * around_sun);
+    let mut sun_samples = 0;
+    for i in 0..64 {
+        let samples = ((i % 8) as f32 / 8.0 + 0.01, (i / 8) as f32 / 8.0 + 0.02);
+        let (w, pdf) = sky.sample(&samples, &sun);
+        assert!(pdf > 0.0);
+        assert!(f32::abs(pdf - sky.pdf(&w, &sun)) / pdf < 1e-3);
+        if linalg::dot(&w, &sun) >= f32::cos(SUN_RADIUS) {
+            sun_samples += 1;
+        }
+    }
+    assert!(sun_samples > 16);
+}
@@ -126,6 +126,32 @@ pub fn refract(w: &Vector, n: &Vector, eta_1: f32, eta_2: f32) -> Option<Vector>
         Some(eta * -*w + (eta * cos_t1 - cos_t2) * *n)
     }
 }
+/// Compute the error function of `x`, using the approximation from Abramowitz and Stegun 7.1.26
+pub fn erf(x: f32) -> f32 {
+    let t = 1.0 / (1.0 + 0.3275911 * f32::abs(x));
+    let y = 1.0 - (((((1.0614054 * t - 1.4531521) * t) + 1.4214138) * t - 0.28449672) * t + 0.2548296)
+        * t * f32::exp(-x * x);
+    if x < 0.0 { -y } else { y }
+}
+/// Compute the inverse of the error function, using the approximation from Giles,
+/// "Approximating the erfinv function", 2010. `x` is clamped to be in (-1, 1)
+pub fn erf_inv(x: f32) -> f32 {
+    let x = clamp(x, -0.99999, 0.99999);
+    let w = -f32::ln((1.0 - x) * (1.0 + x));
+    let p =
+        if w < 5.0 {
+            let w = w - 2.5;
+            let coefs = [2.8102264e-08, 3.4327394e-07, -3.5233877e-06, -4.3915065e-06, 0.00021858087,
+                         -0.001253725, -0.0041776816, 0.24664073, 1.5014094];
+            coefs.iter().fold(0.0, |p, c| c + p * w)
+        } else {
+            let w = f32::sqrt(w) - 3.0;
+            let coefs = [-0.00020021426, 0.00010095056, 0.0013493432, -0.0036734284, 0.0057395077,
+                         -0.0076224613, 0.0094388705, 1.001674, 2.8329768];
+            coefs.iter().fold(0.0, |p, c| c + p * w)
+        };
+    p * x
+}
 
 #[test]
 fn test_cross() {
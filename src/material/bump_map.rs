@@ -0,0 +1,123 @@
+//! Defines a material which perturbs the shading normal of another material using a
+//! height map, giving the appearance of small bumps and grooves on the surface without
+//! needing to model them in the geometry. See Blinn, "Simulation of Wrinkled Surfaces", 1978.
+//!
+//! # Scene Usage Example
+//! Bump mapping can be applied to any material by setting its 'bump' parameter to a number
+//! texture giving the height of the surface, the shading normal is computed from how the height
+//! changes over the surface. The height is in the units of the object's local space, so a texture
+//! with large values will give steep bumps. Only one of 'bump' and 'normal_map' can be set.
+//!
+//! ```json
+//! "materials": [
+//!     {
+//!         "name": "bumpy_matte",
+//!         "type": "matte",
+//!         "diffuse": [0.8, 0.8, 0.8],
+//!         "roughness": 0.0,
+//!         "bump": "bump_texture"
+//!     },
+//!     ...
+//! ]
+//! ```
+
+use std::sync::Arc;
+
+use linalg::{self, Vector};
+use geometry::Intersection;
+use bxdf::BSDF;
+use material::Material;
+use memory::Allocator;
+use texture::Texture;
+
+/// The step taken in the surface's uv coordinates to find how the height changes
+const BUMP_DELTA: f32 = 0.0005;
+
+/// Wraps a material and shades it with the normals of the surface displaced by a height map
+pub struct BumpMap {
+    material: Arc<Material + Send + Sync>,
+    height: Arc<Texture<f32> + Send + Sync>,
+}
+
+impl BumpMap {
+    /// Create a bump mapped version of `material`, with the surface displaced along its
+    /// normal by `height`
+    pub fn new(material: Arc<Material + Send + Sync>, height: Arc<Texture<f32> + Send + Sync>) -> BumpMap {
+        BumpMap { material: material, height: height }
+    }
+}
+
+impl Material for BumpMap {
+    fn bsdf<'a, 'b, 'c>(&'a self, hit: &Intersection<'a, 'b>, alloc: &'c Allocator) -> BSDF<'c> where 'a: 'c {
+        let mut dg = hit.dg;
+        let n = Vector::new(dg.n.x, dg.n.y, dg.n.z);
+        // Find the height at the hit and slightly along the u and v directions of the surface
+        let mut dg_u = dg;
+        dg_u.p = dg.p + dg.dp_du * BUMP_DELTA;
+        dg_u.p_local = dg.p_local + dg.dp_du_local * BUMP_DELTA;
+        dg_u.u = dg.u + BUMP_DELTA;
+        let mut dg_v = dg;
+        dg_v.p = dg.p + dg.dp_dv * BUMP_DELTA;
+        dg_v.p_local = dg.p_local + dg.dp_dv_local * BUMP_DELTA;
+        dg_v.v = dg.v + BUMP_DELTA;
+        let h = self.height.sample(&dg);
+        let h_u = self.height.sample(&dg_u);
+        let h_v = self.height.sample(&dg_v);
+
+        // Compute the derivatives of the displaced surface, the change in the normal over
+        // the surface is ignored since the heights are assumed to be small
+        dg.dp_du = dg.dp_du + n * ((h_u - h) / BUMP_DELTA);
+        dg.dp_dv = dg.dp_dv + n * ((h_v - h) / BUMP_DELTA);
+        let bumped = linalg::cross(&dg.dp_du, &dg.dp_dv).normalized();
+        // Keep the bumped normal on the same side of the surface as the original shading normal
+        let bumped = if linalg::dot(&bumped, &n) < 0.0 { -bumped } else { bumped };
+        if bumped.x.is_finite() && bumped.y.is_finite() && bumped.z.is_finite() {
+            dg.n.x = bumped.x;
+            dg.n.y = bumped.y;
+            dg.n.z = bumped.z;
+        }
+        let bumped_hit = Intersection { dg: dg, instance: hit.instance, material: hit.material };
+        self.material.bsdf(&bumped_hit, alloc)
+    }
+}
+
+
+#[test]
+fn test_bump_map() {
+    use std::f32;
+    use linalg::{AnimatedTransform, Transform, Point, Ray};
+    use film::Colorf;
+    use geometry::{Instance, Rectangle, DifferentialGeometry};
+    use material::Matte;
+    use texture::ConstantTexture;
+    use memory::MemoryArena;
+
+    /// A height map rising linearly along the surface's u coordinate
+    struct Ramp(f32);
+    impl Texture<f32> for Ramp {
+        fn sample(&self, dg: &DifferentialGeometry) -> f32 { self.0 * dg.u }
+    }
+
+    let matte = Arc::new(Matte::new(Arc::new(ConstantTexture::new(Colorf::broadcast(0.5))),
+                                    Arc::new(ConstantTexture::new(0.0))));
+    let instance = Instance::receiver(Arc::new(Rectangle::new(2.0, 2.0)), matte.clone(),
+                                      AnimatedTransform::unanimated(&Transform::identity()), "test".to_owned());
+    let mut ray = Ray::new(&Point::new(0.5, 0.25, 5.0), &Vector::new(0.0, 0.0, -1.0), 0.0);
+    let hit = instance.intersect(&mut ray).expect("The ray should hit the rectangle");
+    let mut arena = MemoryArena::new(4096);
+    let alloc = arena.allocator();
+
+    // A constant height doesn't change the slope of the surface so the normal is unchanged
+    let flat = BumpMap::new(matte.clone(), Arc::new(ConstantTexture::new(0.3)));
+    let bsdf = flat.bsdf(&hit, &alloc);
+    let n = Vector::new(bsdf.n.x - hit.dg.n.x, bsdf.n.y - hit.dg.n.y, bsdf.n.z - hit.dg.n.z);
+    assert!(n.length() < 1e-5);
+
+    // The height rises by 1 over the rectangle's width of 2 so the normal tilts back
+    // towards -x by atan(1 / 2)
+    let ramp = BumpMap::new(matte, Arc::new(Ramp(1.0)));
+    let bsdf = ramp.bsdf(&hit, &alloc);
+    let angle = f32::acos(bsdf.n.z);
+    assert!(f32::abs(angle - f32::atan(0.5)) < 1e-3, "Expected a tilt of {} but got {}", f32::atan(0.5), angle);
+    assert!(bsdf.n.x < 0.0 && f32::abs(bsdf.n.y) < 1e-5);
+}
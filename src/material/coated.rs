@@ -0,0 +1,114 @@
+//! Defines a material which puts a clear dielectric coating, like varnish or lacquer, over
+//! another material. Light reflected by the coating doesn't reach the material below, so
+//! the base material is darkened at grazing angles where the coating reflects more light.
+//!
+//! # Scene Usage Example
+//! The coated material requires the name of the 'base' material to coat, which must be listed
+//! before the coated material. The refractive index of the coating, 'eta', is optional and
+//! defaults to 1.5. The optional 'roughness' (default 0) of the coating gives a smooth mirror
+//! like coating at 0 or a glossy one above 0 using a GGX microfacet distribution.
+//!
+//! ```json
+//! "materials": [
+//!     {
+//!         "name": "varnished_wood",
+//!         "type": "coated",
+//!         "base": "wood",
+//!         "eta": 1.5,
+//!         "roughness": 0.05
+//!     },
+//!     ...
+//! ]
+//! ```
+
+use std::sync::Arc;
+
+use film::Colorf;
+use geometry::Intersection;
+use bxdf::{self, BxDF, BSDF, SpecularReflection, TorranceSparrow};
+use bxdf::microfacet::GGX;
+use bxdf::fresnel::Dielectric;
+use material::Material;
+use memory::Allocator;
+use texture::Texture;
+
+/// A material with a clear dielectric coating over a base material
+pub struct Coated {
+    base: Arc<Material + Send + Sync>,
+    eta: Arc<Texture<f32> + Send + Sync>,
+    roughness: Arc<Texture<f32> + Send + Sync>,
+}
+
+impl Coated {
+    /// Create a material coating `base` with a dielectric with refractive index `eta`
+    /// and surface roughness `roughness`
+    pub fn new(base: Arc<Material + Send + Sync>, eta: Arc<Texture<f32> + Send + Sync>,
+               roughness: Arc<Texture<f32> + Send + Sync>) -> Coated {
+        Coated { base: base, eta: eta, roughness: roughness }
+    }
+}
+
+impl Material for Coated {
+    fn bsdf<'a, 'b, 'c>(&'a self, hit: &Intersection<'a, 'b>, alloc: &'c Allocator) -> BSDF<'c> where 'a: 'c {
+        let eta = self.eta.sample(&hit.dg);
+        let roughness = self.roughness.sample(&hit.dg);
+        let fresnel = &*alloc.alloc(Dielectric::new(1.0, eta));
+        let base = self.base.bsdf(hit, alloc);
+        let mut bsdf = base.map_bxdfs(alloc, move |b| alloc.alloc(bxdf::Coated::new(b, fresnel, 1.0)));
+        let coat = if roughness == 0.0 {
+            alloc.alloc(SpecularReflection::new(&Colorf::broadcast(1.0), fresnel)) as &BxDF
+        } else {
+            let microfacet = alloc.alloc(GGX::new(roughness));
+            alloc.alloc(TorranceSparrow::new(&Colorf::broadcast(1.0), fresnel, microfacet)) as &BxDF
+        };
+        bsdf.add(coat, alloc);
+        bsdf
+    }
+}
+
+#[test]
+fn test_coated_furnace() {
+    use rand::{Rng, SeedableRng, StdRng};
+    use linalg::{AnimatedTransform, Transform, Point, Ray, Vector};
+    use geometry::{Instance, Rectangle};
+    use bxdf::BxDFType;
+    use material::Matte;
+    use sampler::Sample;
+    use texture::ConstantTexture;
+    use memory::MemoryArena;
+
+    let white = Arc::new(Matte::new(Arc::new(ConstantTexture::new(Colorf::broadcast(1.0))),
+                                    Arc::new(ConstantTexture::new(0.0))));
+    let instance = Instance::receiver(Arc::new(Rectangle::new(2.0, 2.0)), white.clone(),
+                                      AnimatedTransform::unanimated(&Transform::identity()), "test".to_owned());
+    let mut ray = Ray::new(&Point::new(0.0, 0.0, 5.0), &Vector::new(0.0, 0.0, -1.0), 0.0);
+    let hit = instance.intersect(&mut ray).expect("The ray should hit the rectangle");
+    let mut rng: StdRng = SeedableRng::from_seed(&[1][..]);
+    let mut arena = MemoryArena::new(4096);
+    // A coating over a white base can't reflect more light than it receives. A smooth coating only
+    // loses the light its underside reflects back into the base so most of the light is reflected,
+    // a rough one also loses light to masking between its microfacets at grazing angles
+    for &roughness in &[0.0, 0.2] {
+        let coated = Coated::new(white.clone(), Arc::new(ConstantTexture::new(1.5)),
+                                 Arc::new(ConstantTexture::new(roughness)));
+        let alloc = arena.allocator();
+        let bsdf = coated.bsdf(&hit, &alloc);
+        for &cos_o in &[1.0, 0.7, 0.3, 0.1] {
+            let w_o = bsdf.from_shading(&Vector::new(f32::sqrt(1.0 - cos_o * cos_o), 0.0, cos_o));
+            let n = 50000;
+            let mut albedo = 0.0;
+            for _ in 0..n {
+                let s = Sample::new(&(rng.next_f32(), rng.next_f32()), rng.next_f32());
+                let (f, w_i, pdf, _) = bsdf.sample(&w_o, BxDFType::all(), &s);
+                if pdf > 0.0 {
+                    albedo += f.luminance() * f32::abs(bsdf.to_shading(&w_i).z) / pdf;
+                }
+            }
+            albedo /= n as f32;
+            assert!(albedo < 1.02, "roughness {} at cos_o {} reflects {}", roughness, cos_o, albedo);
+            if roughness == 0.0 {
+                assert!(albedo > 0.88, "roughness {} at cos_o {} reflects {}", roughness, cos_o, albedo);
+            }
+        }
+    }
+}
@@ -18,18 +18,21 @@
 //! ]
 //! ```
 
-use std::vec::Vec;
+use std::sync::Arc;
 
 use film::Colorf;
 use geometry::Intersection;
-use bxdf::{BxDF, BSDF, SpecularReflection, SpecularTransmission};
-use bxdf::fresnel::{Dielectric, Fresnel};
+use bxdf::{BSDF, SpecularReflection, SpecularTransmission};
+use bxdf::fresnel::Dielectric;
 use material::Material;
+use memory::Allocator;
+use texture::Texture;
 
 /// The Glass material describes specularly transmissive and reflective glass material
 pub struct Glass {
-    bxdfs: Vec<Box<BxDF + Send + Sync>>,
-    eta: f32,
+    reflect: Arc<Texture<Colorf> + Send + Sync>,
+    transmit: Arc<Texture<Colorf> + Send + Sync>,
+    eta: Arc<Texture<f32> + Send + Sync>,
 }
 
 impl Glass {
@@ -37,24 +40,26 @@ impl Glass {
     /// `reflect`: color of reflected light
     /// `transmit`: color of transmitted light
     /// `eta`: refractive index of the material
-    pub fn new(reflect: &Colorf, transmit: &Colorf, eta: f32) -> Glass {
-        let mut bxdfs = Vec::new();
-        if !reflect.is_black() {
-            bxdfs.push(Box::new(SpecularReflection::new(reflect,
-                            Box::new(Dielectric::new(1.0, eta)) as Box<Fresnel + Send + Sync>))
-                      as Box<BxDF + Send + Sync>);
-        }
-        if !transmit.is_black() {
-            bxdfs.push(Box::new(SpecularTransmission::new(transmit, Dielectric::new(1.0, eta)))
-                      as Box<BxDF + Send + Sync>);
-        }
-        Glass { bxdfs: bxdfs, eta: eta }
+    pub fn new(reflect: Arc<Texture<Colorf> + Send + Sync>, transmit: Arc<Texture<Colorf> + Send + Sync>,
+               eta: Arc<Texture<f32> + Send + Sync>) -> Glass {
+        Glass { reflect: reflect, transmit: transmit, eta: eta }
     }
 }
 
 impl Material for Glass {
-    fn bsdf<'a, 'b>(&'a self, hit: &Intersection<'a, 'b>) -> BSDF<'a> {
-        BSDF::new(&self.bxdfs, self.eta, &hit.dg)
+    fn bsdf<'a, 'b, 'c>(&'a self, hit: &Intersection<'a, 'b>, alloc: &'c Allocator) -> BSDF<'c> where 'a: 'c {
+        let reflect = self.reflect.sample(&hit.dg);
+        let transmit = self.transmit.sample(&hit.dg);
+        let eta = self.eta.sample(&hit.dg);
+        let mut bsdf = BSDF::new(&[], eta, &hit.dg, alloc);
+        if !reflect.is_black() {
+            let fresnel = alloc.alloc(Dielectric::new(1.0, eta));
+            bsdf.add(alloc.alloc(SpecularReflection::new(&reflect, fresnel)), alloc);
+        }
+        if !transmit.is_black() {
+            bsdf.add(alloc.alloc(SpecularTransmission::new(&transmit, Dielectric::new(1.0, eta))), alloc);
+        }
+        bsdf
     }
 }
 
@@ -19,36 +19,40 @@
 //! ]
 //! ```
 
-use std::vec::Vec;
+use std::sync::Arc;
 
 use film::Colorf;
 use geometry::Intersection;
 use bxdf::{BxDF, BSDF, Lambertian, OrenNayar};
 use material::Material;
+use memory::Allocator;
+use texture::Texture;
 
 /// The Matte material describes diffuse materials with either a Lambertian or
 /// Oren-Nayar BRDF. The Lambertian BRDF is used for materials with no roughness
 /// while Oren-Nayar is used for those with some roughness.
-/// TODO: Currently we create the BSDF when creating the material but later we'd
-/// like to change material properties over the surface and should use a memory pool
 pub struct Matte {
-    bxdfs: Vec<Box<BxDF + Send + Sync>>,
+    diffuse: Arc<Texture<Colorf> + Send + Sync>,
+    roughness: Arc<Texture<f32> + Send + Sync>,
 }
 
 impl Matte {
     /// Create a new Matte material with the desired diffuse color and roughness
-    pub fn new(diffuse: &Colorf, roughness: f32) -> Matte {
-        if roughness == 0.0 {
-            Matte { bxdfs: vec![Box::new(Lambertian::new(diffuse)) as Box<BxDF + Send + Sync>], }
-        } else {
-            Matte { bxdfs: vec![Box::new(OrenNayar::new(diffuse, roughness)) as Box<BxDF + Send + Sync>], }
-        }
+    pub fn new(diffuse: Arc<Texture<Colorf> + Send + Sync>, roughness: Arc<Texture<f32> + Send + Sync>) -> Matte {
+        Matte { diffuse: diffuse, roughness: roughness }
     }
 }
 
 impl Material for Matte {
-    fn bsdf<'a, 'b>(&'a self, hit: &Intersection<'a, 'b>) -> BSDF<'a> {
-        BSDF::new(&self.bxdfs, 1.0, &hit.dg)
+    fn bsdf<'a, 'b, 'c>(&'a self, hit: &Intersection<'a, 'b>, alloc: &'c Allocator) -> BSDF<'c> where 'a: 'c {
+        let diffuse = self.diffuse.sample(&hit.dg);
+        let roughness = self.roughness.sample(&hit.dg);
+        let bxdf = if roughness == 0.0 {
+            alloc.alloc(Lambertian::new(&diffuse)) as &BxDF
+        } else {
+            alloc.alloc(OrenNayar::new(&diffuse, roughness)) as &BxDF
+        };
+        BSDF::new(&[bxdf], 1.0, &hit.dg, alloc)
     }
 }
 
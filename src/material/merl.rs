@@ -24,8 +24,9 @@ use std::fs::File;
 use std::io::BufReader;
 use byteorder::{LittleEndian, ReadBytesExt};
 
-use bxdf::{self, BSDF, BxDF};
+use bxdf::{self, BSDF};
 use material::Material;
+use memory::Allocator;
 use geometry::Intersection;
 
 /// Material that uses measured data to model the surface reflectance properties.
@@ -33,7 +34,7 @@ use geometry::Intersection;
 /// by Wojciech Matusik, Hanspeter Pfister, Matt Brand and Leonard McMillan,
 /// in ACM Transactions on Graphics 22, 3(2003), 759-769
 pub struct Merl {
-    bxdfs: Vec<Box<BxDF + Send + Sync>>,
+    brdf: bxdf::Merl,
 }
 
 impl Merl {
@@ -71,13 +72,13 @@ impl Merl {
                 brdf[3 * i + c] = f32::max(0.0, x);
             }
         }
-        Merl { bxdfs: vec![Box::new(bxdf::Merl::new(brdf, n_theta_h, n_theta_d, n_phi_d))] }
+        Merl { brdf: bxdf::Merl::new(brdf, n_theta_h, n_theta_d, n_phi_d) }
     }
 }
 
 impl Material for Merl {
-    fn bsdf<'a, 'b>(&'a self, hit: &Intersection<'a, 'b>) -> BSDF<'a> {
-        BSDF::new(&self.bxdfs, 1.0, &hit.dg)
+    fn bsdf<'a, 'b, 'c>(&'a self, hit: &Intersection<'a, 'b>, alloc: &'c Allocator) -> BSDF<'c> where 'a: 'c {
+        BSDF::new(&[&self.brdf], 1.0, &hit.dg, alloc)
     }
 }
 
@@ -1,11 +1,16 @@
 //! Provides a material for modelling metal surfaces of varying roughness
-//! using the Torrance Sparrow BRDF and a Blinn microfacet distribution
-//! TODO: Add Ashikman-Shirley (spelling?) anisotropic microfacet model
+//! using the Torrance Sparrow BRDF and a Beckmann microfacet distribution
 //!
 //! # Scene Usage Example
 //! The metal material requires a refractive index and absorption coefficient
 //! that describe the physical properties of the metal along with a roughness
-//! to specify how rough the surface of the metal is.
+//! to specify how rough the surface of the metal is. For anisotropic metals, such
+//! as brushed metal, the roughness can instead be set separately along the u and v
+//! directions of the surface with `roughness_u` and `roughness_v`, the highlight is
+//! stretched along the direction with the higher roughness. Either of the two can be
+//! left out to use `roughness` for it. Very rough metals look darker than they should since
+//! light reflected more than once by the surface is lost, setting `energy_compensation` to
+//! true adds this light back.
 //!
 //! ```json
 //! "materials": [
@@ -16,37 +21,81 @@
 //!         "absorption_coefficient": [4.82835, 3.12225, 2.14696],
 //!         "roughness": 0.3
 //!     },
+//!     {
+//!         "name": "brushed_aluminium",
+//!         "type": "metal",
+//!         "refractive_index": [1.65746, 0.880369, 0.521229],
+//!         "absorption_coefficient": [9.22387, 6.26952, 4.837],
+//!         "roughness_u": 0.05,
+//!         "roughness_v": 0.4
+//!     },
+//!     {
+//!         "name": "rough_gold",
+//!         "type": "metal",
+//!         "refractive_index": [0.143119, 0.374957, 1.44248],
+//!         "absorption_coefficient": [3.98316, 2.38572, 1.60322],
+//!         "roughness": 0.8,
+//!         "energy_compensation": true
+//!     },
 //!     ...
 //! ]
 //! ```
 
-use std::vec::Vec;
+use std::sync::Arc;
 
 use film::Colorf;
 use geometry::Intersection;
-use bxdf::{BxDF, BSDF, TorranceSparrow};
-use bxdf::microfacet::{MicrofacetDistribution, Beckmann};
-use bxdf::fresnel::{Fresnel, Conductor};
+use bxdf::{BSDF, TorranceSparrow};
+use bxdf::microfacet::{albedo, Beckmann};
+use bxdf::fresnel::Conductor;
 use material::Material;
+use memory::Allocator;
+use texture::Texture;
 
 /// The Metal material describes metals of varying roughness
 pub struct Metal {
-    bxdfs: Vec<Box<BxDF + Send + Sync>>,
+    eta: Arc<Texture<Colorf> + Send + Sync>,
+    k: Arc<Texture<Colorf> + Send + Sync>,
+    /// Roughness along the u direction of the surface
+    roughness_u: Arc<Texture<f32> + Send + Sync>,
+    /// Roughness along the v direction of the surface
+    roughness_v: Arc<Texture<f32> + Send + Sync>,
+    /// Whether to add back the energy lost to light scattering multiple times on the surface
+    energy_compensation: bool,
 }
 
 impl Metal {
     /// Create a new metal material specifying the reflectance properties of the metal
-    pub fn new(eta: &Colorf, k: &Colorf, roughness: f32) -> Metal {
-        let fresnel = Box::new(Conductor::new(eta, k)) as Box<Fresnel + Send + Sync>;
-        let microfacet = Box::new(Beckmann::new(roughness)) as Box<MicrofacetDistribution + Send + Sync>;
-        Metal { bxdfs: vec![Box::new(TorranceSparrow::new(&Colorf::broadcast(1.0), fresnel, microfacet))
-                            as Box<BxDF + Send + Sync>] }
+    pub fn new(eta: Arc<Texture<Colorf> + Send + Sync>, k: Arc<Texture<Colorf> + Send + Sync>,
+               roughness: Arc<Texture<f32> + Send + Sync>) -> Metal {
+        Metal::anisotropic(eta, k, roughness.clone(), roughness)
+    }
+    /// Create a new anisotropic metal material with roughness `roughness_u` along the u
+    /// direction of the surface and `roughness_v` along the v direction
+    pub fn anisotropic(eta: Arc<Texture<Colorf> + Send + Sync>, k: Arc<Texture<Colorf> + Send + Sync>,
+                       roughness_u: Arc<Texture<f32> + Send + Sync>, roughness_v: Arc<Texture<f32> + Send + Sync>)
+                       -> Metal {
+        Metal { eta: eta, k: k, roughness_u: roughness_u, roughness_v: roughness_v, energy_compensation: false }
+    }
+    /// Choose whether to add back the energy lost to light scattering multiple times between the
+    /// microfacets of the surface, which otherwise makes rough metals too dark
+    pub fn energy_compensation(self, compensate: bool) -> Metal {
+        if compensate {
+            // Compute the albedo table now instead of when the first ray hits the material
+            albedo::beckmann();
+        }
+        Metal { energy_compensation: compensate, .. self }
     }
 }
 
 impl Material for Metal {
-    fn bsdf<'a, 'b>(&'a self, hit: &Intersection<'a, 'b>) -> BSDF<'a> {
-        BSDF::new(&self.bxdfs, 1.0, &hit.dg)
+    fn bsdf<'a, 'b, 'c>(&'a self, hit: &Intersection<'a, 'b>, alloc: &'c Allocator) -> BSDF<'c> where 'a: 'c {
+        let fresnel = alloc.alloc(Conductor::new(&self.eta.sample(&hit.dg), &self.k.sample(&hit.dg)));
+        let microfacet = alloc.alloc(Beckmann::anisotropic(self.roughness_u.sample(&hit.dg),
+                                                           self.roughness_v.sample(&hit.dg)));
+        let specular = alloc.alloc(TorranceSparrow::new(&Colorf::broadcast(1.0), fresnel, microfacet)
+                                   .energy_compensation(self.energy_compensation));
+        BSDF::new(&[specular], 1.0, &hit.dg, alloc)
     }
 }
 
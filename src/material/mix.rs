@@ -0,0 +1,60 @@
+//! Defines a material which blends between two other materials, e.g. to add patches
+//! of dust or rust to a metal
+//!
+//! # Scene Usage Example
+//! The mix material requires the names of the two materials to blend, 'mat1' and 'mat2',
+//! and the 'amount' of 'mat2' to use. The amount can be a number or the name of a texture,
+//! at 0 the material is 'mat1' and at 1 it's 'mat2'. The materials must be listed before
+//! the mix material using them. The shading normal of 'mat1' is used for both materials, so
+//! to bump map the mixed material set 'bump' or 'normal_map' on the mix material itself.
+//!
+//! ```json
+//! "materials": [
+//!     {
+//!         "name": "dusty_metal",
+//!         "type": "mix",
+//!         "mat1": "shiny_metal",
+//!         "mat2": "dust",
+//!         "amount": "dust_pattern"
+//!     },
+//!     ...
+//! ]
+//! ```
+
+use std::sync::Arc;
+
+use linalg;
+use geometry::Intersection;
+use bxdf::BSDF;
+use material::Material;
+use memory::Allocator;
+use texture::Texture;
+
+/// A material blending between the BSDFs of two other materials
+pub struct Mix {
+    mat1: Arc<Material + Send + Sync>,
+    mat2: Arc<Material + Send + Sync>,
+    amount: Arc<Texture<f32> + Send + Sync>,
+}
+
+impl Mix {
+    /// Create a material which is `mat1` where `amount` is 0 and `mat2` where it's 1
+    pub fn new(mat1: Arc<Material + Send + Sync>, mat2: Arc<Material + Send + Sync>,
+               amount: Arc<Texture<f32> + Send + Sync>) -> Mix {
+        Mix { mat1: mat1, mat2: mat2, amount: amount }
+    }
+}
+
+impl Material for Mix {
+    fn bsdf<'a, 'b, 'c>(&'a self, hit: &Intersection<'a, 'b>, alloc: &'c Allocator) -> BSDF<'c> where 'a: 'c {
+        let amount = linalg::clamp(self.amount.sample(&hit.dg), 0.0, 1.0);
+        if amount == 0.0 {
+            self.mat1.bsdf(hit, alloc)
+        } else if amount == 1.0 {
+            self.mat2.bsdf(hit, alloc)
+        } else {
+            BSDF::mix(self.mat1.bsdf(hit, alloc), self.mat2.bsdf(hit, alloc), amount, alloc)
+        }
+    }
+}
+
@@ -6,6 +6,11 @@
 //! The material will be specified within the materials list of the scene object. A type
 //! and name for the material along with any additional parameters is required to specify one.
 //! The name is used when specifying which material should be used by an object in the scene.
+//! Any color or number parameter of a material can instead be given the name of a texture
+//! to vary it over the surface, see `texture` for the textures available. The shading normals
+//! of any material can also be perturbed by a height map or normal map, see `bump_map` and
+//! `normal_map` for details. Materials can be built from other materials by blending them
+//! with `mix` or putting a clear coat over them with `coated`.
 //!
 //! ```json
 //! "materials": [
@@ -20,6 +25,7 @@
 
 use geometry::Intersection;
 use bxdf::BSDF;
+use memory::Allocator;
 
 pub use self::matte::Matte;
 pub use self::specular_metal::SpecularMetal;
@@ -28,6 +34,11 @@ pub use self::merl::Merl;
 pub use self::plastic::Plastic;
 pub use self::metal::Metal;
 pub use self::rough_glass::RoughGlass;
+pub use self::principled::Principled;
+pub use self::mix::Mix;
+pub use self::coated::Coated;
+pub use self::bump_map::BumpMap;
+pub use self::normal_map::NormalMap;
 
 pub mod matte;
 pub mod specular_metal;
@@ -36,14 +47,18 @@ pub mod merl;
 pub mod plastic;
 pub mod metal;
 pub mod rough_glass;
+pub mod principled;
+pub mod mix;
+pub mod coated;
+pub mod bump_map;
+pub mod normal_map;
 
 /// Trait implemented by materials. Provides method to get the BSDF describing
 /// the material properties at the intersection
 pub trait Material {
     /// Get the BSDF for the material which defines its properties at the
-    /// hit point. TODO: When we implement a memory pool we need to pass it
-    /// here, currently the BxDFs and BSDF are allocated once at surface
-    /// creation instead of as needed based on material properties.
-    fn bsdf<'a, 'b>(&'a self, hit: &Intersection<'a, 'b>) -> BSDF<'a>;
+    /// hit point. The BxDFs making up the BSDF are allocated with `alloc`
+    /// and live until the integrator finishes the sample.
+    fn bsdf<'a, 'b, 'c>(&'a self, hit: &Intersection<'a, 'b>, alloc: &'c Allocator) -> BSDF<'c> where 'a: 'c;
 }
 
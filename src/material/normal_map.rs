@@ -0,0 +1,108 @@
+//! Defines a material which replaces the shading normal of another material with
+//! normals looked up from a tangent space normal map
+//!
+//! # Scene Usage Example
+//! A normal map can be applied to any material by setting its 'normal_map' parameter to a color
+//! texture storing the normal in the tangent space of the surface. The red, green and blue channels
+//! hold the x, y and z components of the normal mapped from [-1, 1] to [0, 1], where x runs along
+//! increasing u, y along increasing v and z along the surface normal. Normal map images store
+//! vectors, not colors, so they should be loaded with 'srgb' set to false. Only one of 'bump'
+//! and 'normal_map' can be set.
+//!
+//! ```json
+//! "textures": [
+//!     {
+//!         "name": "brick_normals",
+//!         "type": "image",
+//!         "file": "./brick_normals.png",
+//!         "srgb": false
+//!     },
+//!     ...
+//! ],
+//! "materials": [
+//!     {
+//!         "name": "brick",
+//!         "type": "matte",
+//!         "diffuse": [0.6, 0.3, 0.2],
+//!         "roughness": 0.5,
+//!         "normal_map": "brick_normals"
+//!     },
+//!     ...
+//! ]
+//! ```
+
+use std::sync::Arc;
+
+use linalg::{self, Vector};
+use film::Colorf;
+use geometry::Intersection;
+use bxdf::BSDF;
+use material::Material;
+use memory::Allocator;
+use texture::Texture;
+
+/// Wraps a material and shades it with normals read from a tangent space normal map
+pub struct NormalMap {
+    material: Arc<Material + Send + Sync>,
+    normals: Arc<Texture<Colorf> + Send + Sync>,
+}
+
+impl NormalMap {
+    /// Create a version of `material` shaded with the tangent space normals stored in `normals`
+    pub fn new(material: Arc<Material + Send + Sync>, normals: Arc<Texture<Colorf> + Send + Sync>) -> NormalMap {
+        NormalMap { material: material, normals: normals }
+    }
+}
+
+impl Material for NormalMap {
+    fn bsdf<'a, 'b, 'c>(&'a self, hit: &Intersection<'a, 'b>, alloc: &'c Allocator) -> BSDF<'c> where 'a: 'c {
+        let mut dg = hit.dg;
+        let c = self.normals.sample(&dg);
+        let local = Vector::new(2.0 * c.r - 1.0, 2.0 * c.g - 1.0, 2.0 * c.b - 1.0);
+        // Build the tangent frame from the shading normal and the surface's u direction,
+        // flipping the bitangent if the surface's v direction runs the other way
+        let n = Vector::new(dg.n.x, dg.n.y, dg.n.z);
+        let tan = (dg.dp_du - n * linalg::dot(&n, &dg.dp_du)).normalized();
+        let bitan = linalg::cross(&n, &tan);
+        let bitan = if linalg::dot(&bitan, &dg.dp_dv) < 0.0 { -bitan } else { bitan };
+        let mapped = (tan * local.x + bitan * local.y + n * local.z).normalized();
+        if mapped.x.is_finite() && mapped.y.is_finite() && mapped.z.is_finite() {
+            dg.n.x = mapped.x;
+            dg.n.y = mapped.y;
+            dg.n.z = mapped.z;
+            dg.dp_du = tan - mapped * linalg::dot(&mapped, &tan);
+            dg.dp_dv = bitan - mapped * linalg::dot(&mapped, &bitan);
+        }
+        let mapped_hit = Intersection { dg: dg, instance: hit.instance, material: hit.material };
+        self.material.bsdf(&mapped_hit, alloc)
+    }
+}
+
+
+#[test]
+fn test_flat_normal_map() {
+    use linalg::{AnimatedTransform, Transform, Point, Ray};
+    use geometry::{Instance, Sphere};
+    use material::Matte;
+    use texture::ConstantTexture;
+    use memory::MemoryArena;
+
+    let matte = Arc::new(Matte::new(Arc::new(ConstantTexture::new(Colorf::broadcast(0.5))),
+                                    Arc::new(ConstantTexture::new(0.0))));
+    let instance = Instance::receiver(Arc::new(Sphere::new(1.0)), matte.clone(),
+                                      AnimatedTransform::unanimated(&Transform::identity()), "test".to_owned());
+    let mut ray = Ray::new(&Point::new(0.3, -0.4, 5.0), &Vector::new(0.0, 0.0, -1.0), 0.0);
+    let hit = instance.intersect(&mut ray).expect("The ray should hit the sphere");
+    let mut arena = MemoryArena::new(4096);
+    let alloc = arena.allocator();
+
+    // A normal map which stores the unperturbed normal everywhere should give the same
+    // shading frame as the geometry
+    let mapped = NormalMap::new(matte.clone(), Arc::new(ConstantTexture::new(Colorf::new(0.5, 0.5, 1.0))));
+    let expected = matte.bsdf(&hit, &alloc);
+    let bsdf = mapped.bsdf(&hit, &alloc);
+    let n = Vector::new(bsdf.n.x - expected.n.x, bsdf.n.y - expected.n.y, bsdf.n.z - expected.n.z);
+    assert!(n.length() < 1e-5);
+    assert!((bsdf.tan - expected.tan).length() < 1e-5);
+    assert!((bsdf.bitan - expected.bitan).length() < 1e-5);
+}
@@ -1,13 +1,13 @@
 //! A material that models plastic of varying roughness using
-//! the Torrance Sparrow BRDF and a Blinn microfacet distribution
-//! TODO: Add Ashikman-Shirley (spelling?) anisotropic microfacet model
+//! the Torrance Sparrow BRDF and a Beckmann microfacet distribution
 //!
 //! # Scene Usage Example
 //! The plastic material requires a diffuse and glossy color. The diffuse color
 //! is used by a Lambertian model and the gloss color is used by a Torrance-Sparrow
-//! microfacet model with a Blinn microfacet distribution. The roughness will specify
+//! microfacet model with a Beckmann microfacet distribution. The roughness will specify
 //! how reflective the gloss color is while the diffuse color provides a uniform base color
-//! for the object.
+//! for the object. Like the metal material the gloss can be made anisotropic by giving
+//! `roughness_u` and `roughness_v` instead of, or along with, `roughness`.
 //!
 //! ```json
 //! "materials": [
@@ -22,40 +22,58 @@
 //! ]
 //! ```
 
-use std::vec::Vec;
+use std::sync::Arc;
 
 use film::Colorf;
 use geometry::Intersection;
-use bxdf::{BxDF, BSDF, TorranceSparrow, Lambertian};
-use bxdf::microfacet::{MicrofacetDistribution, Beckmann};
-use bxdf::fresnel::{Fresnel, Dielectric};
+use bxdf::{BSDF, TorranceSparrow, Lambertian};
+use bxdf::microfacet::Beckmann;
+use bxdf::fresnel::Dielectric;
 use material::Material;
+use memory::Allocator;
+use texture::Texture;
 
 /// The Plastic material describes plastic materials of varying roughness
 pub struct Plastic {
-    bxdfs: Vec<Box<BxDF + Send + Sync>>,
+    diffuse: Arc<Texture<Colorf> + Send + Sync>,
+    gloss: Arc<Texture<Colorf> + Send + Sync>,
+    /// Roughness along the u direction of the surface
+    roughness_u: Arc<Texture<f32> + Send + Sync>,
+    /// Roughness along the v direction of the surface
+    roughness_v: Arc<Texture<f32> + Send + Sync>,
 }
 
 impl Plastic {
     /// Create a new plastic material specifying the diffuse and glossy colors
     /// along with the roughness of the surface
-    pub fn new(diffuse: &Colorf, gloss: &Colorf, roughness: f32) -> Plastic {
-        let mut bxdfs = Vec::new();
-        if !diffuse.is_black() {
-            bxdfs.push(Box::new(Lambertian::new(diffuse)) as Box<BxDF + Send + Sync>);
-        }
-        if !gloss.is_black() {
-            let fresnel = Box::new(Dielectric::new(1.0, 1.5)) as Box<Fresnel + Send + Sync>;
-            let microfacet = Box::new(Beckmann::new(roughness)) as Box<MicrofacetDistribution + Send + Sync>;
-            bxdfs.push(Box::new(TorranceSparrow::new(gloss, fresnel, microfacet)) as Box<BxDF + Send + Sync>);
-        }
-        Plastic { bxdfs: bxdfs }
+    pub fn new(diffuse: Arc<Texture<Colorf> + Send + Sync>, gloss: Arc<Texture<Colorf> + Send + Sync>,
+               roughness: Arc<Texture<f32> + Send + Sync>) -> Plastic {
+        Plastic::anisotropic(diffuse, gloss, roughness.clone(), roughness)
+    }
+    /// Create a new plastic material whose glossy reflection is anisotropic, with roughness
+    /// `roughness_u` along the u direction of the surface and `roughness_v` along the v direction
+    pub fn anisotropic(diffuse: Arc<Texture<Colorf> + Send + Sync>, gloss: Arc<Texture<Colorf> + Send + Sync>,
+                       roughness_u: Arc<Texture<f32> + Send + Sync>, roughness_v: Arc<Texture<f32> + Send + Sync>)
+                       -> Plastic {
+        Plastic { diffuse: diffuse, gloss: gloss, roughness_u: roughness_u, roughness_v: roughness_v }
     }
 }
 
 impl Material for Plastic {
-    fn bsdf<'a, 'b>(&'a self, hit: &Intersection<'a, 'b>) -> BSDF<'a> {
-        BSDF::new(&self.bxdfs, 1.0, &hit.dg)
+    fn bsdf<'a, 'b, 'c>(&'a self, hit: &Intersection<'a, 'b>, alloc: &'c Allocator) -> BSDF<'c> where 'a: 'c {
+        let diffuse = self.diffuse.sample(&hit.dg);
+        let gloss = self.gloss.sample(&hit.dg);
+        let mut bsdf = BSDF::new(&[], 1.0, &hit.dg, alloc);
+        if !diffuse.is_black() {
+            bsdf.add(alloc.alloc(Lambertian::new(&diffuse)), alloc);
+        }
+        if !gloss.is_black() {
+            let fresnel = alloc.alloc(Dielectric::new(1.0, 1.5));
+            let microfacet = alloc.alloc(Beckmann::anisotropic(self.roughness_u.sample(&hit.dg),
+                                                               self.roughness_v.sample(&hit.dg)));
+            bsdf.add(alloc.alloc(TorranceSparrow::new(&gloss, fresnel, microfacet)), alloc);
+        }
+        bsdf
     }
 }
 
@@ -0,0 +1,192 @@
+//! Defines the principled material, based on the Disney principled BRDF, which describes a
+//! wide range of materials with a few intuitive parameters. See Burley, "Physically Based
+//! Shading at Disney", 2012 and "Extending the Disney BRDF to a BSDF with Integrated
+//! Subsurface Scattering", 2015.
+//!
+//! # Scene Usage Example
+//! The principled material requires a base color, all other parameters are optional and should
+//! be between 0 and 1. 'metallic' (default 0) blends from a dielectric to a metal which reflects
+//! the base color. 'roughness' (default 0.5) sets the roughness of the diffuse and specular lobes
+//! and 'anisotropic' (default 0) stretches the specular highlight along the surface's u direction.
+//! 'specular' (default 0.5) sets the reflectance of the dielectric, 0.5 is a refractive index of
+//! 1.5, and 'specular_tint' (default 0) tints its reflection towards the base color. 'sheen'
+//! (default 0) adds a soft reflection at grazing angles, e.g. for cloth, tinted towards the base
+//! color by 'sheen_tint' (default 0.5). 'clearcoat' (default 0) adds a clear specular coating with
+//! glossiness set by 'clearcoat_gloss' (default 1). 'specular_transmission' (default 0) blends from
+//! a diffuse to a transmissive material like glass tinted by the base color.
+//!
+//! ```json
+//! "materials": [
+//!     {
+//!         "name": "car_paint",
+//!         "type": "principled",
+//!         "base_color": [0.6, 0.05, 0.05],
+//!         "metallic": 0.3,
+//!         "roughness": 0.4,
+//!         "clearcoat": 1.0,
+//!         "clearcoat_gloss": 0.9
+//!     },
+//!     ...
+//! ]
+//! ```
+
+use std::f32;
+use std::sync::Arc;
+
+use linalg;
+use film::Colorf;
+use geometry::{Intersection, DifferentialGeometry};
+use bxdf::{BSDF, Coated, DisneyDiffuse, DisneySheen, DisneyClearcoat, TorranceSparrow,
+           MicrofacetTransmission};
+use bxdf::microfacet::GGX;
+use bxdf::fresnel::{Dielectric, Schlick, DisneyFresnel};
+use material::Material;
+use memory::Allocator;
+use texture::Texture;
+
+/// The principled material, composing the Disney diffuse, sheen, specular and clearcoat
+/// lobes along with a specular transmission lobe. Each layer is attenuated by the light
+/// reflected by the layers above it so the material doesn't reflect more light than it receives
+pub struct Principled {
+    base_color: Arc<Texture<Colorf> + Send + Sync>,
+    metallic: Arc<Texture<f32> + Send + Sync>,
+    roughness: Arc<Texture<f32> + Send + Sync>,
+    specular: Arc<Texture<f32> + Send + Sync>,
+    specular_tint: Arc<Texture<f32> + Send + Sync>,
+    anisotropic: Arc<Texture<f32> + Send + Sync>,
+    sheen: Arc<Texture<f32> + Send + Sync>,
+    sheen_tint: Arc<Texture<f32> + Send + Sync>,
+    clearcoat: Arc<Texture<f32> + Send + Sync>,
+    clearcoat_gloss: Arc<Texture<f32> + Send + Sync>,
+    specular_transmission: Arc<Texture<f32> + Send + Sync>,
+}
+
+impl Principled {
+    /// Create a new principled material, see the module documentation for a description
+    /// of each parameter
+    pub fn new(base_color: Arc<Texture<Colorf> + Send + Sync>, metallic: Arc<Texture<f32> + Send + Sync>,
+               roughness: Arc<Texture<f32> + Send + Sync>, specular: Arc<Texture<f32> + Send + Sync>,
+               specular_tint: Arc<Texture<f32> + Send + Sync>, anisotropic: Arc<Texture<f32> + Send + Sync>,
+               sheen: Arc<Texture<f32> + Send + Sync>, sheen_tint: Arc<Texture<f32> + Send + Sync>,
+               clearcoat: Arc<Texture<f32> + Send + Sync>, clearcoat_gloss: Arc<Texture<f32> + Send + Sync>,
+               specular_transmission: Arc<Texture<f32> + Send + Sync>) -> Principled {
+        Principled { base_color: base_color, metallic: metallic, roughness: roughness, specular: specular,
+                     specular_tint: specular_tint, anisotropic: anisotropic, sheen: sheen, sheen_tint: sheen_tint,
+                     clearcoat: clearcoat, clearcoat_gloss: clearcoat_gloss,
+                     specular_transmission: specular_transmission }
+    }
+    /// Create the BSDF for the material at the differential geometry, allocating its
+    /// BxDFs with `alloc`
+    fn build_bsdf<'a>(&self, dg: &DifferentialGeometry, alloc: &'a Allocator) -> BSDF<'a> {
+        let base_color = self.base_color.sample(dg);
+        let metallic = linalg::clamp(self.metallic.sample(dg), 0.0, 1.0);
+        let roughness = linalg::clamp(self.roughness.sample(dg), 0.0, 1.0);
+        let specular = linalg::clamp(self.specular.sample(dg), 0.0, 1.0);
+        let anisotropic = linalg::clamp(self.anisotropic.sample(dg), 0.0, 1.0);
+        let sheen = self.sheen.sample(dg);
+        let clearcoat = linalg::clamp(self.clearcoat.sample(dg), 0.0, 1.0);
+        let transmission = linalg::clamp(self.specular_transmission.sample(dg), 0.0, 1.0);
+
+        // The hue and saturation of the base color, used to tint the specular and sheen lobes
+        let white = Colorf::broadcast(1.0);
+        let luminance = base_color.luminance();
+        let tint = if luminance > 0.0 { base_color / luminance } else { white };
+        // Find the refractive index giving the specular reflectance at normal incidence
+        let r0 = 0.08 * specular;
+        let eta = f32::max((1.0 + f32::sqrt(r0)) / (1.0 - f32::sqrt(r0)), 1.01);
+        // The roughness is squared to give a more perceptually linear change in the highlight
+        let aspect = f32::sqrt(1.0 - 0.9 * anisotropic);
+        let alpha_x = f32::max(0.001, roughness * roughness / aspect);
+        let alpha_y = f32::max(0.001, roughness * roughness * aspect);
+
+        let transmission_weight = (1.0 - metallic) * transmission;
+        let mut bsdf = BSDF::new(&[], if transmission_weight > 0.0 { eta } else { 1.0 }, dg, alloc);
+        let dielectric = alloc.alloc(Dielectric::new(1.0, eta));
+        // The diffuse and sheen lobes are beneath the dielectric's specular surface
+        let diffuse_weight = (1.0 - metallic) * (1.0 - transmission);
+        if diffuse_weight > 0.0 {
+            let diffuse = alloc.alloc(DisneyDiffuse::new(&(base_color * diffuse_weight), roughness));
+            bsdf.add(alloc.alloc(Coated::new(diffuse, dielectric, 1.0)), alloc);
+            if sheen > 0.0 {
+                let sheen_color = linalg::lerp(self.sheen_tint.sample(dg), &white, &tint) * sheen * diffuse_weight;
+                let sheen = alloc.alloc(DisneySheen::new(&sheen_color));
+                bsdf.add(alloc.alloc(Coated::new(sheen, dielectric, 1.0)), alloc);
+            }
+        }
+        let specular_color = linalg::lerp(metallic, &(linalg::lerp(self.specular_tint.sample(dg), &white, &tint) * r0),
+                                          &base_color);
+        let fresnel = alloc.alloc(DisneyFresnel::new(&specular_color, metallic, eta));
+        let microfacet = alloc.alloc(GGX::anisotropic(alpha_x, alpha_y));
+        bsdf.add(alloc.alloc(TorranceSparrow::new(&white, fresnel, microfacet)), alloc);
+        if transmission_weight > 0.0 {
+            let transmit = Colorf::new(f32::sqrt(base_color.r), f32::sqrt(base_color.g), f32::sqrt(base_color.b))
+                * transmission_weight;
+            bsdf.add(alloc.alloc(MicrofacetTransmission::new(&transmit, *dielectric, microfacet)), alloc);
+        }
+        // The clearcoat is on top of all the other layers
+        if clearcoat > 0.0 {
+            let gloss = linalg::clamp(self.clearcoat_gloss.sample(dg), 0.0, 1.0);
+            let coat_fresnel = &*alloc.alloc(Schlick::new(&Colorf::broadcast(0.04)));
+            bsdf = bsdf.map_bxdfs(alloc, move |b| alloc.alloc(Coated::new(b, coat_fresnel, 0.25 * clearcoat)));
+            bsdf.add(alloc.alloc(DisneyClearcoat::new(clearcoat, gloss)), alloc);
+        }
+        bsdf
+    }
+}
+
+impl Material for Principled {
+    fn bsdf<'a, 'b, 'c>(&'a self, hit: &Intersection<'a, 'b>, alloc: &'c Allocator) -> BSDF<'c> where 'a: 'c {
+        self.build_bsdf(&hit.dg, alloc)
+    }
+}
+
+#[test]
+fn test_white_furnace() {
+    use rand::{Rng, SeedableRng, StdRng};
+    use linalg::{Point, Normal, Vector};
+    use geometry::Sphere;
+    use bxdf::BxDFType;
+    use sampler::Sample;
+    use texture::ConstantTexture;
+    use memory::MemoryArena;
+
+    let sphere = Sphere::new(1.0);
+    let dg = DifferentialGeometry::new(&Point::new(0.0, 0.0, 1.0), &Normal::new(0.0, 0.0, 1.0), 0.0, 0.0,
+                                       &Vector::new(1.0, 0.0, 0.0), &Vector::new(0.0, 1.0, 0.0), &sphere);
+    let tex = |x: f32| Arc::new(ConstantTexture::new(x)) as Arc<Texture<f32> + Send + Sync>;
+    let mut rng: StdRng = SeedableRng::from_seed(&[1][..]);
+    let mut arena = MemoryArena::new(4096);
+    // A white material should never reflect more light than it receives. The smooth metal and
+    // smooth glass, listed first, lose almost no light so they should reflect nearly all of it.
+    // The parameters are metallic, roughness, specular, anisotropic, sheen, clearcoat and
+    // specular_transmission
+    let params = [[1.0, 0.1, 0.5, 0.0, 0.0, 0.0, 0.0], [0.0, 0.0, 0.5, 0.0, 0.0, 0.0, 1.0],
+                  [0.0, 0.0, 0.5, 0.0, 0.0, 0.0, 0.0], [0.0, 1.0, 0.5, 0.0, 0.0, 0.0, 0.0],
+                  [0.0, 0.5, 1.0, 0.0, 1.0, 0.0, 0.0], [1.0, 0.5, 0.5, 1.0, 0.0, 0.0, 0.0],
+                  [0.5, 0.3, 0.5, 0.5, 1.0, 1.0, 0.0], [0.0, 0.5, 0.5, 0.0, 0.0, 1.0, 0.5],
+                  [0.0, 1.0, 1.0, 0.0, 1.0, 1.0, 0.0]];
+    for (i, p) in params.iter().enumerate() {
+        let material = Principled::new(Arc::new(ConstantTexture::new(Colorf::broadcast(1.0))), tex(p[0]), tex(p[1]),
+                                       tex(p[2]), tex(0.0), tex(p[3]), tex(p[4]), tex(0.0), tex(p[5]), tex(1.0),
+                                       tex(p[6]));
+        let alloc = arena.allocator();
+        let bsdf = material.build_bsdf(&dg, &alloc);
+        for &cos_o in &[1.0, 0.7, 0.3, 0.1] {
+            let w_o = Vector::new(f32::sqrt(1.0 - cos_o * cos_o), 0.0, cos_o);
+            let n = 50000;
+            let mut albedo = 0.0;
+            for _ in 0..n {
+                let s = Sample::new(&(rng.next_f32(), rng.next_f32()), rng.next_f32());
+                let (f, w_i, pdf, _) = bsdf.sample(&w_o, BxDFType::all(), &s);
+                if pdf > 0.0 {
+                    albedo += f.luminance() * f32::abs(w_i.z) / pdf;
+                }
+            }
+            albedo /= n as f32;
+            assert!(albedo < 1.02, "{:?} at cos_o {} reflects {}", p, cos_o, albedo);
+            if i < 2 {
+                assert!(albedo > 0.98, "{:?} at cos_o {} reflects {}", p, cos_o, albedo);
+            }
+        }
+    }
+}
@@ -4,6 +4,10 @@
 //! The rough glass material describes a thin glass surface material,
 //! not a solid block of glass (there is no absorption of light). The glass requires
 //! a reflective and emissive color along with a refrective index, eta and roughness.
+//! The roughness can also be given separately along the u and v directions of the
+//! surface with `roughness_u` and `roughness_v` for anisotropic glass. Setting
+//! `energy_compensation` to true adds back the light lost by scattering multiple times
+//! between the microfacets, which otherwise makes very rough glass too dark.
 //!
 //! ```json
 //! "materials": [
@@ -19,19 +23,28 @@
 //! ]
 //! ```
 
-use std::vec::Vec;
+use std::sync::Arc;
 
 use film::Colorf;
 use geometry::Intersection;
-use bxdf::{BxDF, BSDF, MicrofacetTransmission, TorranceSparrow};
-use bxdf::microfacet::{Beckmann, MicrofacetDistribution};
-use bxdf::fresnel::{Dielectric, Fresnel};
+use bxdf::{BSDF, MicrofacetTransmission, TorranceSparrow};
+use bxdf::microfacet::{albedo, Beckmann};
+use bxdf::fresnel::Dielectric;
 use material::Material;
+use memory::Allocator;
+use texture::Texture;
 
 /// The `RoughGlass` material describes specularly transmissive and reflective glass material
 pub struct RoughGlass {
-    bxdfs: Vec<Box<BxDF + Send + Sync>>,
-    eta: f32,
+    reflect: Arc<Texture<Colorf> + Send + Sync>,
+    transmit: Arc<Texture<Colorf> + Send + Sync>,
+    eta: Arc<Texture<f32> + Send + Sync>,
+    /// Roughness along the u direction of the surface
+    roughness_u: Arc<Texture<f32> + Send + Sync>,
+    /// Roughness along the v direction of the surface
+    roughness_v: Arc<Texture<f32> + Send + Sync>,
+    /// Whether to add back the energy lost to light scattering multiple times on the surface
+    energy_compensation: bool,
 }
 
 impl RoughGlass {
@@ -40,26 +53,52 @@ impl RoughGlass {
     /// `transmit`: color of transmitted light
     /// `eta`: refractive index of the material
     /// `roughness`: roughness of the material
-    pub fn new(reflect: &Colorf, transmit: &Colorf, eta: f32, roughness: f32) -> RoughGlass {
-        let mut bxdfs = Vec::new();
-        if !reflect.is_black() {
-            let fresnel = Box::new(Dielectric::new(1.0, eta)) as Box<Fresnel + Send + Sync>;
-            let microfacet = Box::new(Beckmann::new(roughness)) as Box<MicrofacetDistribution + Send + Sync>;
-            bxdfs.push(Box::new(TorranceSparrow::new(reflect, fresnel, microfacet)) as Box<BxDF + Send + Sync>);
-        }
-        if !transmit.is_black() {
-            let fresnel = Dielectric::new(1.0, eta);
-            let microfacet = Box::new(Beckmann::new(roughness)) as Box<MicrofacetDistribution + Send + Sync>;
-            bxdfs.push(Box::new(MicrofacetTransmission::new(transmit, fresnel, microfacet))
-                       as Box<BxDF + Send + Sync>);
+    pub fn new(reflect: Arc<Texture<Colorf> + Send + Sync>, transmit: Arc<Texture<Colorf> + Send + Sync>,
+               eta: Arc<Texture<f32> + Send + Sync>, roughness: Arc<Texture<f32> + Send + Sync>) -> RoughGlass {
+        RoughGlass::anisotropic(reflect, transmit, eta, roughness.clone(), roughness)
+    }
+    /// Create the `RoughGlass` material with an anisotropic surface, having roughness
+    /// `roughness_u` along the u direction of the surface and `roughness_v` along the v direction
+    pub fn anisotropic(reflect: Arc<Texture<Colorf> + Send + Sync>, transmit: Arc<Texture<Colorf> + Send + Sync>,
+                       eta: Arc<Texture<f32> + Send + Sync>, roughness_u: Arc<Texture<f32> + Send + Sync>,
+                       roughness_v: Arc<Texture<f32> + Send + Sync>) -> RoughGlass {
+        RoughGlass { reflect: reflect, transmit: transmit, eta: eta, roughness_u: roughness_u,
+                     roughness_v: roughness_v, energy_compensation: false }
+    }
+    /// Choose whether to add back the energy lost to light scattering multiple times between the
+    /// microfacets of the surface before being reflected or transmitted
+    pub fn energy_compensation(self, compensate: bool) -> RoughGlass {
+        if compensate {
+            // Compute the albedo table now instead of when the first ray hits the material
+            albedo::beckmann_dielectric();
         }
-        RoughGlass { bxdfs: bxdfs, eta: eta }
+        RoughGlass { energy_compensation: compensate, .. self }
     }
 }
 
 impl Material for RoughGlass {
-    fn bsdf<'a, 'b>(&'a self, hit: &Intersection<'a, 'b>) -> BSDF<'a> {
-        BSDF::new(&self.bxdfs, self.eta, &hit.dg)
+    fn bsdf<'a, 'b, 'c>(&'a self, hit: &Intersection<'a, 'b>, alloc: &'c Allocator) -> BSDF<'c> where 'a: 'c {
+        let reflect = self.reflect.sample(&hit.dg);
+        let transmit = self.transmit.sample(&hit.dg);
+        let eta = self.eta.sample(&hit.dg);
+        let microfacet = alloc.alloc(Beckmann::anisotropic(self.roughness_u.sample(&hit.dg),
+                                                           self.roughness_v.sample(&hit.dg)));
+        let mut bsdf = BSDF::new(&[], eta, &hit.dg, alloc);
+        if !reflect.is_black() {
+            let fresnel = alloc.alloc(Dielectric::new(1.0, eta));
+            let mut brdf = TorranceSparrow::new(&reflect, fresnel, microfacet);
+            if self.energy_compensation {
+                brdf = brdf.dielectric_energy_compensation(eta);
+            }
+            bsdf.add(alloc.alloc(brdf), alloc);
+        }
+        if !transmit.is_black() {
+            let fresnel = Dielectric::new(1.0, eta);
+            let btdf = MicrofacetTransmission::new(&transmit, fresnel, microfacet)
+                .energy_compensation(self.energy_compensation);
+            bsdf.add(alloc.alloc(btdf), alloc);
+        }
+        bsdf
     }
 }
 
@@ -17,34 +17,37 @@
 //! ```
 
 
-use std::vec::Vec;
+use std::sync::Arc;
 
 use film::Colorf;
 use geometry::Intersection;
-use bxdf::{BxDF, BSDF, SpecularReflection};
-use bxdf::fresnel::{Fresnel, Conductor};
+use bxdf::{BSDF, SpecularReflection};
+use bxdf::fresnel::Conductor;
 use material::Material;
+use memory::Allocator;
+use texture::Texture;
 
 /// The Specular Metal material describes specularly reflective metals using their
 /// refractive index and absorption coefficient
 pub struct SpecularMetal {
-    bxdfs: Vec<Box<BxDF + Send + Sync>>,
+    eta: Arc<Texture<Colorf> + Send + Sync>,
+    k: Arc<Texture<Colorf> + Send + Sync>,
 }
 
 impl SpecularMetal {
     /// Create a new specular metal with the desired metal properties.
     /// `eta`: refractive index of the metal
     /// `k`: absorption coefficient of the metal
-    pub fn new(eta: &Colorf, k: &Colorf) -> SpecularMetal {
-        SpecularMetal { bxdfs: vec![Box::new(SpecularReflection::new(&Colorf::broadcast(1.0),
-                                         Box::new(Conductor::new(eta, k)) as Box<Fresnel + Send + Sync>))
-                                    as Box<BxDF + Send + Sync>] }
+    pub fn new(eta: Arc<Texture<Colorf> + Send + Sync>, k: Arc<Texture<Colorf> + Send + Sync>) -> SpecularMetal {
+        SpecularMetal { eta: eta, k: k }
     }
 }
 
 impl Material for SpecularMetal {
-    fn bsdf<'a, 'b>(&'a self, hit: &Intersection<'a, 'b>) -> BSDF<'a> {
-        BSDF::new(&self.bxdfs, 1.0, &hit.dg)
+    fn bsdf<'a, 'b, 'c>(&'a self, hit: &Intersection<'a, 'b>, alloc: &'c Allocator) -> BSDF<'c> where 'a: 'c {
+        let fresnel = alloc.alloc(Conductor::new(&self.eta.sample(&hit.dg), &self.k.sample(&hit.dg)));
+        let specular = alloc.alloc(SpecularReflection::new(&Colorf::broadcast(1.0), fresnel));
+        BSDF::new(&[specular], 1.0, &hit.dg, alloc)
     }
 }
 
@@ -87,4 +87,135 @@ pub fn uniform_sample_sphere(samples: &(f32, f32)) -> Vector {
     let phi = f32::consts::PI * 2.0 * samples.1;
     Vector::new(f32::cos(phi) * r, f32::sin(phi) * r, z)
 }
+/// Return the PDF for uniformly sampling a direction on the unit sphere
+pub fn uniform_sphere_pdf() -> f32 {
+    1.0 / (f32::consts::PI * 4.0)
+}
+
+
+/// A piecewise constant 1D distribution which can be sampled with the inversion
+/// method, eg. to pick one of a set of items with probability proportional to its weight
+#[derive(Clone, Debug)]
+pub struct Distribution1D {
+    func: Vec<f32>,
+    /// The CDF of the distribution, has one more entry than `func`
+    cdf: Vec<f32>,
+    /// The integral of `func` over [0, 1]
+    func_int: f32,
+}
+
+impl Distribution1D {
+    /// Create a distribution for the piecewise constant function `func` with `func.len()`
+    /// equally sized pieces over [0, 1]. Values in `func` must not be negative, if they
+    /// are all zero the distribution is uniform
+    pub fn new(func: &[f32]) -> Distribution1D {
+        assert!(!func.is_empty(), "Distribution1D requires a non-empty function");
+        let n = func.len();
+        let mut cdf = Vec::with_capacity(n + 1);
+        cdf.push(0.0);
+        for i in 0..n {
+            let c = cdf[i] + func[i] / n as f32;
+            cdf.push(c);
+        }
+        let func_int = cdf[n];
+        if func_int == 0.0 {
+            for (i, c) in cdf.iter_mut().enumerate() {
+                *c = i as f32 / n as f32;
+            }
+        } else {
+            for c in &mut cdf {
+                *c /= func_int;
+            }
+        }
+        Distribution1D { func: func.to_vec(), cdf: cdf, func_int: func_int }
+    }
+    /// Get the number of pieces in the distribution
+    pub fn count(&self) -> usize { self.func.len() }
+    /// Get the integral of the function the distribution was built from
+    pub fn integral(&self) -> f32 { self.func_int }
+    /// Find the piece of the distribution that the CDF value `u` falls in
+    fn find_piece(&self, u: f32) -> usize {
+        // Binary search for the last CDF entry that is <= u
+        let mut lo = 0;
+        let mut hi = self.func.len();
+        while hi - lo > 1 {
+            let mid = (lo + hi) / 2;
+            if self.cdf[mid] <= u { lo = mid; } else { hi = mid; }
+        }
+        lo
+    }
+    /// Sample one of the pieces of the distribution using the sample `u` in [0, 1),
+    /// returns the index of the piece and the probability of having chosen it
+    pub fn sample_discrete(&self, u: f32) -> (usize, f32) {
+        let i = self.find_piece(u);
+        (i, self.discrete_pdf(i))
+    }
+    /// Compute the probability of choosing piece `i` when sampling discretely
+    pub fn discrete_pdf(&self, i: usize) -> f32 {
+        self.cdf[i + 1] - self.cdf[i]
+    }
+    /// Sample a value in [0, 1) from the distribution using the sample `u` in [0, 1),
+    /// returns the value sampled, the pdf of sampling it and the index of the piece it's in
+    pub fn sample_continuous(&self, u: f32) -> (f32, f32, usize) {
+        let i = self.find_piece(u);
+        // Find how far along the piece we are
+        let width = self.cdf[i + 1] - self.cdf[i];
+        let du = if width > 0.0 { (u - self.cdf[i]) / width } else { 0.0 };
+        let x = f32::min((i as f32 + du) / self.count() as f32, 1.0 - f32::EPSILON);
+        (x, self.pdf(i), i)
+    }
+    /// Compute the pdf of sampling a value in piece `i` when sampling continuously
+    pub fn pdf(&self, i: usize) -> f32 {
+        if self.func_int > 0.0 { self.func[i] / self.func_int } else { 1.0 }
+    }
+}
+
+/// A piecewise constant 2D distribution over [0, 1]^2, eg. for importance
+/// sampling an image. Samples are drawn by choosing a row from the marginal
+/// distribution and then sampling the row's conditional distribution
+#[derive(Clone, Debug)]
+pub struct Distribution2D {
+    /// The distribution of u for each row of the function
+    conditional: Vec<Distribution1D>,
+    /// The distribution of choosing each row
+    marginal: Distribution1D,
+}
 
+impl Distribution2D {
+    /// Create a distribution for the function `func` which is made of `nv` rows
+    /// of `nu` values each
+    pub fn new(func: &[f32], nu: usize, nv: usize) -> Distribution2D {
+        assert_eq!(func.len(), nu * nv);
+        let conditional: Vec<_> = func.chunks(nu).map(|row| Distribution1D::new(row)).collect();
+        let marginal_func: Vec<_> = conditional.iter().map(|c| c.integral()).collect();
+        Distribution2D { conditional: conditional, marginal: Distribution1D::new(&marginal_func[..]) }
+    }
+    /// Sample a point (u, v) in [0, 1)^2 from the distribution using the samples passed,
+    /// returns the point and the pdf of sampling it
+    pub fn sample_continuous(&self, samples: &(f32, f32)) -> ((f32, f32), f32) {
+        let (v, pdf_v, row) = self.marginal.sample_continuous(samples.1);
+        let (u, pdf_u, _) = self.conditional[row].sample_continuous(samples.0);
+        ((u, v), pdf_u * pdf_v)
+    }
+    /// Compute the pdf of sampling the point (u, v) in [0, 1]^2
+    pub fn pdf(&self, uv: &(f32, f32)) -> f32 {
+        let row = linalg::clamp((uv.1 * self.marginal.count() as f32) as usize, 0, self.marginal.count() - 1);
+        let cond = &self.conditional[row];
+        let col = linalg::clamp((uv.0 * cond.count() as f32) as usize, 0, cond.count() - 1);
+        cond.pdf(col) * self.marginal.pdf(row)
+    }
+}
+
+#[test]
+fn test_distribution_1d() {
+    let distrib = Distribution1D::new(&[1.0, 3.0, 0.0, 4.0]);
+    assert_eq!(distrib.count(), 4);
+    assert_eq!(distrib.integral(), 2.0);
+    assert_eq!(distrib.sample_discrete(0.0), (0, 0.125));
+    assert_eq!(distrib.sample_discrete(0.2), (1, 0.375));
+    assert_eq!(distrib.sample_discrete(0.5), (3, 0.5));
+    assert_eq!(distrib.sample_discrete(0.99), (3, 0.5));
+    assert_eq!(distrib.discrete_pdf(2), 0.0);
+    let uniform = Distribution1D::new(&[0.0, 0.0]);
+    assert_eq!(uniform.sample_discrete(0.75), (1, 0.5));
+}
@@ -0,0 +1,150 @@
+//! Provides a memory arena for the short lived objects created while rendering a sample,
+//! such as the BxDFs and BSDFs of the materials hit by a path and the buffers of random
+//! samples used by the integrators. Allocating from the arena just bumps an offset into a
+//! block of memory, avoiding the heap allocations and frees that would otherwise be made
+//! for every hit. Each render thread owns a `MemoryArena` and takes an `Allocator` from it
+//! for each sample it renders, when the allocator is dropped all the memory allocated
+//! through it is released at once to be re-used by the next sample.
+//!
+//! Since the arena never runs destructors only types which are `Copy` can be allocated in it.
+
+use std::{cmp, mem, ptr, slice};
+use std::cell::RefCell;
+
+/// A block of memory in the arena and the number of bytes allocated from it so far
+struct Block {
+    buffer: Vec<u8>,
+    size: usize,
+}
+
+impl Block {
+    fn new(capacity: usize) -> Block {
+        Block { buffer: Vec::with_capacity(capacity), size: 0 }
+    }
+    /// Try to allocate `bytes` aligned to `align` in the remaining space of the block
+    fn alloc(&mut self, bytes: usize, align: usize) -> Option<*mut u8> {
+        let start = self.buffer.as_mut_ptr() as usize + self.size;
+        let pad = (align - start % align) % align;
+        if self.size + pad + bytes > self.buffer.capacity() {
+            None
+        } else {
+            self.size += pad + bytes;
+            Some((start + pad) as *mut u8)
+        }
+    }
+}
+
+/// The blocks of memory in the arena and the block currently being allocated from
+struct Blocks {
+    blocks: Vec<Block>,
+    current: usize,
+}
+
+/// A memory arena which hands out memory in large blocks, see the module documentation
+pub struct MemoryArena {
+    blocks: RefCell<Blocks>,
+    block_size: usize,
+}
+
+impl MemoryArena {
+    /// Create a new arena which allocates memory in blocks of `block_size` bytes, larger
+    /// blocks are allocated for objects that don't fit in a block
+    pub fn new(block_size: usize) -> MemoryArena {
+        let blocks = Blocks { blocks: vec![Block::new(block_size)], current: 0 };
+        MemoryArena { blocks: RefCell::new(blocks), block_size: block_size }
+    }
+    /// Get an allocator to allocate objects in the arena, the memory is released when
+    /// the allocator is dropped
+    pub fn allocator<'a>(&'a mut self) -> Allocator<'a> {
+        Allocator { arena: self }
+    }
+    /// Get the total number of bytes of memory reserved by the arena
+    pub fn capacity(&self) -> usize {
+        self.blocks.borrow().blocks.iter().fold(0, |s, b| s + b.buffer.capacity())
+    }
+}
+
+/// Allocates objects in a memory arena, the objects live as long as the allocator
+pub struct Allocator<'a> {
+    arena: &'a MemoryArena,
+}
+
+impl<'a> Allocator<'a> {
+    /// Move `object` into the arena
+    pub fn alloc<T: Copy>(&self, object: T) -> &T {
+        let p = self.alloc_raw(mem::size_of::<T>(), mem::align_of::<T>()) as *mut T;
+        unsafe {
+            ptr::write(p, object);
+            &*p
+        }
+    }
+    /// Allocate a slice of `len` elements in the arena, each set to `value`
+    #[allow(clippy::mut_from_ref)]
+    pub fn alloc_slice<T: Copy>(&self, len: usize, value: T) -> &mut [T] {
+        self.alloc_slice_with(len, |_| value)
+    }
+    /// Allocate a slice of `len` elements in the arena, setting element `i` to the value
+    /// returned by `f(i)`. `f` can allocate other objects in the arena
+    #[allow(clippy::mut_from_ref)]
+    pub fn alloc_slice_with<T: Copy, F: FnMut(usize) -> T>(&self, len: usize, mut f: F) -> &mut [T] {
+        let p = self.alloc_raw(mem::size_of::<T>() * len, mem::align_of::<T>()) as *mut T;
+        // SAFETY: `alloc_raw` hands out a fresh range of the arena for each call which no other
+        // reference points into, so the slice is the only reference to its memory until the
+        // allocator is dropped and the arena's memory is released
+        unsafe {
+            for i in 0..len {
+                ptr::write(p.add(i), f(i));
+            }
+            slice::from_raw_parts_mut(p, len)
+        }
+    }
+    /// Reserve `bytes` of memory aligned to `align` in the arena
+    fn alloc_raw(&self, bytes: usize, align: usize) -> *mut u8 {
+        let mut blocks = self.arena.blocks.borrow_mut();
+        loop {
+            let current = blocks.current;
+            if let Some(p) = blocks.blocks[current].alloc(bytes, align) {
+                return p;
+            }
+            // Move on to the next block, allocating a new one if we've used all of them
+            if current + 1 == blocks.blocks.len() {
+                blocks.blocks.push(Block::new(cmp::max(self.arena.block_size, bytes + align)));
+            }
+            blocks.current += 1;
+        }
+    }
+}
+
+impl<'a> Drop for Allocator<'a> {
+    fn drop(&mut self) {
+        let mut blocks = self.arena.blocks.borrow_mut();
+        for b in &mut blocks.blocks {
+            b.size = 0;
+        }
+        blocks.current = 0;
+    }
+}
+
+#[test]
+fn test_alloc() {
+    let mut arena = MemoryArena::new(64);
+    let first = {
+        let alloc = arena.allocator();
+        let a = alloc.alloc(1u8) as *const u8;
+        let b = alloc.alloc(2.0f64);
+        assert_eq!(*b, 2.0);
+        assert_eq!(b as *const f64 as usize % mem::align_of::<f64>(), 0);
+        // Slices larger than a block get their own block
+        let s = alloc.alloc_slice_with(100, |i| i as u32);
+        assert!(s.iter().enumerate().all(|(i, x)| *x == i as u32));
+        // Allocating while filling a slice mustn't overlap the slice
+        let refs = alloc.alloc_slice_with(4, |i| alloc.alloc(i));
+        assert!(refs.iter().enumerate().all(|(i, x)| **x == i));
+        assert_eq!(unsafe { *a }, 1);
+        a
+    };
+    assert!(arena.capacity() > 64);
+    // The memory is re-used once the allocator is dropped
+    let alloc = arena.allocator();
+    assert_eq!(alloc.alloc(3u8) as *const u8, first);
+}
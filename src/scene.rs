@@ -4,12 +4,15 @@
 //! # Scene JSON Files
 //! The scene file format has four required sections: a camera, an integrator,
 //! a list of materials and a list of objects and lights. The root object in the
-//! JSON file should contain one of each of these.
+//! JSON file should contain one of each of these. A list of textures used by the
+//! materials can optionally be provided as well. The camera can be left out if a
+//! glTF file imported as an object provides one, in which case its cameras are used.
 //!
 //! ```json
 //! {
 //!     "camera": {...},
 //!     "integrator": {...},
+//!     "textures": [...],
 //!     "materials": [...],
 //!     "objects": [...]
 //! }
@@ -19,8 +22,10 @@
 //!
 //! - Camera: See film/camera
 //! - Integrator: See integrator
+//! - Textures: See texture
 //! - Materials: See materials
 //! - Objects: See geometry
+//! - glTF objects: See gltf
 //!
 
 use std::io::prelude::*;
@@ -33,10 +38,15 @@ use serde_json::{self, Value};
 
 use linalg::{Transform, Point, Vector, Ray, Keyframe, AnimatedTransform};
 use film::{filter, Camera, Colorf, RenderTarget, FrameInfo, AnimatedColor, ColorKeyframe};
-use geometry::{Sphere, Instance, Intersection, BVH, Mesh, Disk, Rectangle,
-               BoundableGeom, SampleableGeom};
-use material::{Material, Matte, Glass, Metal, Merl, Plastic, SpecularMetal, RoughGlass};
+use geometry::{Sphere, Instance, Intersection, BVH, Mesh, MtlMaterial, Disk, Rectangle, Emitter,
+               Boundable, BoundableGeom, SampleableGeom};
+use material::{Material, Matte, Glass, Metal, Merl, Plastic, SpecularMetal, RoughGlass, Principled, Mix, Coated,
+               BumpMap, NormalMap};
 use integrator::{self, Integrator};
+use texture::{Texture, ConstantTexture, ImageTexture, WrapMode, Checkerboard, ScaleTexture, MixTexture,
+              NoiseTexture, NoisePattern};
+use light::{Light, LightSampling, EnvironmentMap, SunSky, IesProfile};
+use gltf::{self, GltfCamera};
 
 /// The scene containing the objects and camera configuration we'd like to render,
 /// shared immutably among the ray tracing threads
@@ -44,7 +54,11 @@ pub struct Scene {
     pub cameras: Vec<Camera>,
     active_camera: Option<usize>,
     pub bvh: BVH<Instance>,
+    /// Lights surrounding the scene which aren't placed in the BVH, e.g. environment lights
+    pub infinite_lights: Vec<Emitter>,
     pub integrator: Box<Integrator + Send + Sync>,
+    /// The strategy used to choose which light to sample
+    pub light_sampling: LightSampling,
 }
 
 impl Scene {
@@ -69,24 +83,52 @@ impl Scene {
         };
 
         let (rt, spp, frame_info) = load_film(data.find("film").expect("The scene must specify a film to write to"));
-        let cameras = load_cameras(&data, rt.dimensions());
-        let integrator = load_integrator(data.find("integrator")
-                                         .expect("The scene must specify the integrator to render with"));
-        let materials = load_materials(path, data.find("materials")
+        let integrator_elem = data.find("integrator").expect("The scene must specify the integrator to render with");
+        let integrator = load_integrator(integrator_elem);
+        let light_sampling = load_light_sampling(integrator_elem);
+        let textures = match data.find("textures") {
+            Some(t) => load_textures(path, t),
+            None => Textures { color: HashMap::new(), scalar: HashMap::new() },
+        };
+        let materials = load_materials(path, &textures, data.find("materials")
                                        .expect("The scene must specify an array of materials"));
-        // mesh cache is a map of file_name -> (map of mesh name -> mesh)
-        let mut mesh_cache = HashMap::new();
-        let instances = load_objects(path, &materials, &mut mesh_cache,
-                                     data.find("objects").expect("The scene must specify a list of objects"));
+        let mut mesh_cache = MeshCache { meshes: HashMap::new(), mtl: HashMap::new(),
+                                         mtl_materials: HashMap::new(), reshaded: HashMap::new() };
+        let mut gltf_cameras = Vec::new();
+        let objects = load_objects(path, &materials, &mut mesh_cache, &mut gltf_cameras,
+                                   data.find("objects").expect("The scene must specify a list of objects"));
+        let cameras = if data.find("camera").is_none() && data.find("cameras").is_none() && !gltf_cameras.is_empty() {
+            gltf_cameras.iter().map(|c| c.to_camera(rt.dimensions())).collect()
+        } else {
+            load_cameras(&data, rt.dimensions())
+        };
+        // Infinite lights surround the scene so they're kept out of the BVH
+        let mut instances = Vec::with_capacity(objects.len());
+        let mut infinite_lights = Vec::new();
+        for o in objects {
+            match o {
+                Instance::Emitter(e) => {
+                    if e.is_infinite() {
+                        infinite_lights.push(e);
+                    } else {
+                        instances.push(Instance::Emitter(e));
+                    }
+                },
+                _ => instances.push(o),
+            }
+        }
 
         assert!(!instances.is_empty(), "Aborting: the scene does not have any objects!");
-        let scene = Scene {
+        let mut scene = Scene {
             cameras: cameras,
             active_camera: None,
             // TODO: Read time parameters from the scene file, update BVH every few frames
             bvh: BVH::new(4, instances, 0.0, frame_info.time),
+            infinite_lights: infinite_lights,
             integrator: integrator,
+            light_sampling: light_sampling,
         };
+        scene.update_world_bounds();
         (scene, rt, spp, frame_info)
     }
     /// Test the ray for intersections against the objects in the scene.
@@ -94,6 +136,11 @@ impl Scene {
     pub fn intersect(&self, ray: &mut Ray) -> Option<Intersection> {
         self.bvh.intersect(ray, |r, i| i.intersect(r))
     }
+    /// Compute the radiance arriving along a ray that left the scene
+    /// without hitting anything, which comes from the infinite lights
+    pub fn escaped_radiance(&self, ray: &Ray) -> Colorf {
+        self.infinite_lights.iter().fold(Colorf::black(), |c, l| c + l.escaped_radiance(ray))
+    }
     /// Advance the time the scene is currently displaying to the time range passed
     pub fn update_frame(&mut self, frame: usize, start: f32, end: f32) {
         let cam = match self.active_camera {
@@ -119,6 +166,17 @@ impl Scene {
         let shutter_time = self.cameras[cam].shutter_time();
         println!("Frame {}: re-building bvh for {} to {}", frame, shutter_time.0, shutter_time.1);
         self.bvh.rebuild(shutter_time.0, shutter_time.1);
+        self.update_world_bounds();
+        for l in &mut self.infinite_lights {
+            l.update_frame(shutter_time.0, shutter_time.1);
+        }
+    }
+    /// Let the infinite lights know the bounds of the scene they surround
+    fn update_world_bounds(&mut self) {
+        let bounds = self.bvh.bounds(0.0, 0.0);
+        for l in &mut self.infinite_lights {
+            l.set_world_bounds(&bounds);
+        }
     }
     /// Get the active camera for the current frame
     pub fn active_camera(&self) -> &Camera {
@@ -249,6 +307,26 @@ fn load_integrator(elem: &Value) -> Box<Integrator + Send + Sync> {
         let max_depth = elem.find("max_depth").expect("The integrator must specify the maximum ray depth")
             .as_u64().expect("max_depth must be a number") as u32;
         Box::new(integrator::Path::new(min_depth, max_depth))
+    } else if ty == "bidir" {
+        let min_depth = elem.find("min_depth").expect("The integrator must specify the minimum ray depth")
+            .as_u64().expect("min_depth must be a number") as u32;
+        let max_depth = elem.find("max_depth").expect("The integrator must specify the maximum ray depth")
+            .as_u64().expect("max_depth must be a number") as u32;
+        Box::new(integrator::Bidir::new(min_depth, max_depth))
+    } else if ty == "sppm" {
+        let num_passes = elem.find("num_passes").expect("The SPPM integrator must specify the number of passes")
+            .as_u64().expect("num_passes must be a number") as u32;
+        let photons_per_pass = elem.find("photons_per_pass")
+            .expect("The SPPM integrator must specify the number of photons per pass")
+            .as_u64().expect("photons_per_pass must be a number") as u32;
+        let initial_radius = elem.find("initial_radius")
+            .expect("The SPPM integrator must specify the initial gather radius")
+            .as_f64().expect("initial_radius must be a number") as f32;
+        let alpha = elem.find("alpha").expect("The SPPM integrator must specify alpha")
+            .as_f64().expect("alpha must be a number") as f32;
+        let max_depth = elem.find("max_depth").expect("The integrator must specify the maximum ray depth")
+            .as_u64().expect("max_depth must be a number") as u32;
+        Box::new(integrator::SPPM::new(num_passes, photons_per_pass, initial_radius, alpha, max_depth))
     } else if ty == "whitted" {
         let min_depth = elem.find("min_depth").expect("The integrator must specify the minimum ray depth")
             .as_u64().expect("min_depth must be a number") as u32;
@@ -260,15 +338,252 @@ fn load_integrator(elem: &Value) -> Box<Integrator + Send + Sync> {
     }
 }
 
+/// Load the strategy used to choose lights to sample from the integrator's optional
+/// `light_sampling` parameter, if it's not set lights are chosen based on their power
+fn load_light_sampling(elem: &Value) -> LightSampling {
+    match elem.find("light_sampling") {
+        Some(s) => {
+            let ty = s.as_str().expect("light_sampling must be a string");
+            if ty == "uniform" {
+                LightSampling::Uniform
+            } else if ty == "power" {
+                LightSampling::Power
+            } else if ty == "bvh" {
+                LightSampling::Bvh
+            } else {
+                panic!("Unrecognized light sampling type '{}'", ty);
+            }
+        },
+        None => LightSampling::Power,
+    }
+}
+
+/// The textures loaded from the scene file, each texture is loaded for use by both
+/// color and number parameters
+struct Textures {
+    color: HashMap<String, Arc<Texture<Colorf> + Send + Sync>>,
+    scalar: HashMap<String, Arc<Texture<f32> + Send + Sync>>,
+}
+
+/// Generate a texture loading error string
+fn tex_error(tex_name: &str, msg: &str) -> String {
+    format!("Error loading texture '{}': {}", tex_name, msg)
+}
+
+/// Load the array of textures used in the scene, panics if a texture is specified
+/// incorrectly. Image files are found relative to the directory containing the scene
+/// file, `path`, if they're not absolute paths.
+fn load_textures(path: &Path, elem: &Value) -> Textures {
+    let mut textures = Textures { color: HashMap::new(), scalar: HashMap::new() };
+    let tex_vec = elem.as_array().expect("The textures must be an array of textures used");
+    for (i, t) in tex_vec.iter().enumerate() {
+        let name = t.find("name").expect(&format!("Error loading texture #{}: A name is required", i)[..])
+            .as_str().expect(&format!("Error loading texture #{}: name must be a string", i)[..])
+            .to_owned();
+        let ty = t.find("type").expect(&tex_error(&name, "a type is required")[..])
+            .as_str().expect(&tex_error(&name, "type must be a string")[..]);
+        if textures.color.contains_key(&name) {
+            panic!("Error loading texture '{}': name conflicts with an existing entry", name);
+        }
+        let (color, scalar) = if ty == "constant" {
+            let value = t.find("value").expect(&tex_error(&name, "A value is required for constant textures")[..]);
+            (load_color_texture(value, &textures).expect(&tex_error(&name, "Invalid value for constant texture")[..]),
+             load_scalar_texture(value, &textures).expect(&tex_error(&name, "Invalid value for constant texture")[..]))
+        } else if ty == "image" {
+            let file_path = Path::new(t.find("file")
+                      .expect(&tex_error(&name, "A file is required for image textures")[..])
+                      .as_str().expect(&tex_error(&name, "The image file must be a string")[..]));
+            let wrap = match t.find("wrap") {
+                Some(w) => {
+                    let w = w.as_str().expect(&tex_error(&name, "wrap must be a string")[..]);
+                    if w == "repeat" {
+                        WrapMode::Repeat
+                    } else if w == "clamp" {
+                        WrapMode::Clamp
+                    } else if w == "mirror" {
+                        WrapMode::Mirror
+                    } else {
+                        panic!("Error loading texture '{}': unrecognized wrap mode '{}'", name, w);
+                    }
+                },
+                None => WrapMode::Repeat,
+            };
+            let srgb = match t.find("srgb") {
+                Some(s) => s.as_bool().expect(&tex_error(&name, "srgb must be a bool")[..]),
+                None => true,
+            };
+            let image = if file_path.is_relative() {
+                Arc::new(ImageTexture::load_file(path.join(file_path).as_path(), srgb, wrap))
+            } else {
+                Arc::new(ImageTexture::load_file(file_path, srgb, wrap))
+            };
+            (image.clone() as Arc<Texture<Colorf> + Send + Sync>, image as Arc<Texture<f32> + Send + Sync>)
+        } else if ty == "checkerboard" {
+            let tex1 = t.find("tex1").expect(&tex_error(&name, "tex1 is required for checkerboard")[..]);
+            let tex2 = t.find("tex2").expect(&tex_error(&name, "tex2 is required for checkerboard")[..]);
+            let scale_u = t.find("scale_u").expect(&tex_error(&name, "scale_u is required for checkerboard")[..])
+                .as_f64().expect(&tex_error(&name, "scale_u must be a number")[..]) as f32;
+            let scale_v = t.find("scale_v").expect(&tex_error(&name, "scale_v is required for checkerboard")[..])
+                .as_f64().expect(&tex_error(&name, "scale_v must be a number")[..]) as f32;
+            let err = tex_error(&name, "Invalid color or texture specified for checkerboard");
+            (Arc::new(Checkerboard::new(load_color_texture(tex1, &textures).expect(&err[..]),
+                                        load_color_texture(tex2, &textures).expect(&err[..]), scale_u, scale_v))
+                as Arc<Texture<Colorf> + Send + Sync>,
+             Arc::new(Checkerboard::new(load_scalar_texture(tex1, &textures).expect(&err[..]),
+                                        load_scalar_texture(tex2, &textures).expect(&err[..]), scale_u, scale_v))
+                as Arc<Texture<f32> + Send + Sync>)
+        } else if ty == "scale" {
+            let tex = t.find("tex").expect(&tex_error(&name, "tex is required for scale")[..]);
+            let scale = t.find("scale").expect(&tex_error(&name, "scale is required for scale")[..]);
+            let err = tex_error(&name, "Invalid color or texture specified for scale");
+            let scale = load_scalar_texture(scale, &textures).expect(&err[..]);
+            (Arc::new(ScaleTexture::new(load_color_texture(tex, &textures).expect(&err[..]), scale.clone()))
+                as Arc<Texture<Colorf> + Send + Sync>,
+             Arc::new(ScaleTexture::new(load_scalar_texture(tex, &textures).expect(&err[..]), scale))
+                as Arc<Texture<f32> + Send + Sync>)
+        } else if ty == "mix" {
+            let tex1 = t.find("tex1").expect(&tex_error(&name, "tex1 is required for mix")[..]);
+            let tex2 = t.find("tex2").expect(&tex_error(&name, "tex2 is required for mix")[..]);
+            let amount = t.find("amount").expect(&tex_error(&name, "amount is required for mix")[..]);
+            let err = tex_error(&name, "Invalid color or texture specified for mix");
+            let amount = load_scalar_texture(amount, &textures).expect(&err[..]);
+            (Arc::new(MixTexture::new(load_color_texture(tex1, &textures).expect(&err[..]),
+                                      load_color_texture(tex2, &textures).expect(&err[..]), amount.clone()))
+                as Arc<Texture<Colorf> + Send + Sync>,
+             Arc::new(MixTexture::new(load_scalar_texture(tex1, &textures).expect(&err[..]),
+                                      load_scalar_texture(tex2, &textures).expect(&err[..]), amount))
+                as Arc<Texture<f32> + Send + Sync>)
+        } else if let Some(pattern) = noise_pattern(ty) {
+            let number = |param: &str, default: f64| match t.find(param) {
+                Some(x) => x.as_f64().expect(&tex_error(&name, &format!("{} must be a number", param))[..]),
+                None => default,
+            };
+            let frequency = number("frequency", 1.0) as f32;
+            let octaves = number("octaves", 6.0) as usize;
+            let lacunarity = number("lacunarity", 2.0) as f32;
+            let gain = number("gain", 0.5) as f32;
+            let variation = number("variation", 1.0) as f32;
+            let seed = number("seed", 0.0) as usize;
+            let err = tex_error(&name, "Invalid color or texture specified for noise texture");
+            let color = |param: &str, default: f32| match t.find(param) {
+                Some(x) => load_color_texture(x, &textures).expect(&err[..]),
+                None => Arc::new(ConstantTexture::new(Colorf::broadcast(default))),
+            };
+            let scalar = |param: &str, default: f32| match t.find(param) {
+                Some(x) => load_scalar_texture(x, &textures).expect(&err[..]),
+                None => Arc::new(ConstantTexture::new(default)),
+            };
+            (Arc::new(NoiseTexture::new(pattern, color("tex1", 0.0), color("tex2", 1.0), frequency, octaves,
+                                        lacunarity, gain, variation, seed))
+                as Arc<Texture<Colorf> + Send + Sync>,
+             Arc::new(NoiseTexture::new(pattern, scalar("tex1", 0.0), scalar("tex2", 1.0), frequency, octaves,
+                                        lacunarity, gain, variation, seed))
+                as Arc<Texture<f32> + Send + Sync>)
+        } else {
+            panic!("Error parsing texture '{}': unrecognized type '{}'", name, ty);
+        };
+        textures.color.insert(name.clone(), color);
+        textures.scalar.insert(name, scalar);
+    }
+    textures
+}
+
+/// Get the pattern computed by the noise texture type `ty`, or None if it's not a noise texture
+fn noise_pattern(ty: &str) -> Option<NoisePattern> {
+    if ty == "noise" {
+        Some(NoisePattern::Noise)
+    } else if ty == "fbm" {
+        Some(NoisePattern::Fbm)
+    } else if ty == "turbulence" {
+        Some(NoisePattern::Turbulence)
+    } else if ty == "marble" {
+        Some(NoisePattern::Marble)
+    } else if ty == "wood" {
+        Some(NoisePattern::Wood)
+    } else if ty == "windy" {
+        Some(NoisePattern::Windy)
+    } else {
+        None
+    }
+}
+
+/// Load a color texture parameter from the JSON element passed, which is either the name of
+/// a texture or a color or number to use everywhere. Returns None if the element wasn't a valid
+/// color or texture name, panics if the named texture hasn't been loaded
+fn load_color_texture(elem: &Value, textures: &Textures) -> Option<Arc<Texture<Colorf> + Send + Sync>> {
+    if let Some(name) = elem.as_str() {
+        match textures.color.get(name) {
+            Some(t) => Some(t.clone()),
+            None => panic!("Texture {} was not found in the texture list", name),
+        }
+    } else if let Some(x) = elem.as_f64() {
+        Some(Arc::new(ConstantTexture::new(Colorf::broadcast(x as f32))))
+    } else {
+        load_color(elem).map(|c| Arc::new(ConstantTexture::new(c)) as Arc<Texture<Colorf> + Send + Sync>)
+    }
+}
+
+/// Load a number texture parameter from the JSON element passed, which is either the name of
+/// a texture or a number or color to use everywhere, colors are converted to numbers by taking
+/// their luminance. Returns None if the element wasn't a valid number or texture name, panics
+/// if the named texture hasn't been loaded
+fn load_scalar_texture(elem: &Value, textures: &Textures) -> Option<Arc<Texture<f32> + Send + Sync>> {
+    if let Some(name) = elem.as_str() {
+        match textures.scalar.get(name) {
+            Some(t) => Some(t.clone()),
+            None => panic!("Texture {} was not found in the texture list", name),
+        }
+    } else if let Some(x) = elem.as_f64() {
+        Some(Arc::new(ConstantTexture::new(x as f32)))
+    } else {
+        load_color(elem).map(|c| Arc::new(ConstantTexture::new(c.luminance())) as Arc<Texture<f32> + Send + Sync>)
+    }
+}
+
 /// Generate a material loading error string
 fn mat_error(mat_name: &str, msg: &str) -> String {
     format!("Error loading material '{}': {}", mat_name, msg)
 }
 
+/// Load the roughness of a microfacet material along the u and v directions of the surface,
+/// given by 'roughness_u' and 'roughness_v'. Either one that isn't set uses 'roughness'
+fn load_roughness(mat_name: &str, elem: &Value, textures: &Textures)
+                  -> (Arc<Texture<f32> + Send + Sync>, Arc<Texture<f32> + Send + Sync>) {
+    let load = |param: &str| {
+        elem.find(param).or_else(|| elem.find("roughness"))
+            .map(|r| load_scalar_texture(r, textures)
+                 .expect(&mat_error(mat_name, &format!("{} must be a float or scalar texture", param))[..]))
+            .expect(&mat_error(mat_name, &format!("A roughness or {} is required", param))[..])
+    };
+    (load("roughness_u"), load("roughness_v"))
+}
+
+/// Load whether a microfacet material should add back the energy lost to multiple scattering,
+/// set by 'energy_compensation' which is false if not set
+fn load_energy_compensation(mat_name: &str, elem: &Value) -> bool {
+    match elem.find("energy_compensation") {
+        Some(e) => e.as_bool().expect(&mat_error(mat_name, "energy_compensation must be a bool")[..]),
+        None => false,
+    }
+}
+
+/// Look up the previously loaded material named by the parameter `param` of the material `mat_name`,
+/// used by materials built from other materials
+fn load_material_ref(mat_name: &str, param: &str, elem: &Value,
+                     materials: &HashMap<String, Arc<Material + Send + Sync>>) -> Arc<Material + Send + Sync> {
+    let name = elem.find(param).expect(&mat_error(mat_name, &format!("{} is required", param))[..])
+        .as_str().expect(&mat_error(mat_name, &format!("{} must be the name of a material", param))[..]);
+    match materials.get(name) {
+        Some(m) => m.clone(),
+        None => panic!("Error loading material '{}': material {} was not found, materials must be listed \
+                        before materials using them", mat_name, name),
+    }
+}
+
 /// Load the array of materials used in the scene, panics if a material is specified
 /// incorrectly. The path to the directory containing the scene file is required to find
 /// referenced material data relative to the scene file.
-fn load_materials(path: &Path, elem: &Value) -> HashMap<String, Arc<Material + Send + Sync>> {
+fn load_materials(path: &Path, textures: &Textures, elem: &Value) -> HashMap<String, Arc<Material + Send + Sync>> {
     let mut materials = HashMap::new();
     let mat_vec = elem.as_array().expect("The materials must be an array of materials used");
     for (i, m) in mat_vec.iter().enumerate() {
@@ -281,96 +596,160 @@ fn load_materials(path: &Path, elem: &Value) -> HashMap<String, Arc<Material + S
         if materials.contains_key(&name) {
             panic!("Error loading material '{}': name conflicts with an existing entry", name);
         }
-        if ty == "glass" {
-            let reflect = load_color(m.find("reflect")
-                                     .expect(&mat_error(&name, "A reflect color is required for glass")[..]))
+        let material = if ty == "glass" {
+            let reflect = load_color_texture(m.find("reflect")
+                                     .expect(&mat_error(&name, "A reflect color is required for glass")[..]), textures)
                 .expect(&mat_error(&name, "Invalid color specified for reflect of glass")[..]);
-            let transmit = load_color(m.find("transmit")
-                                      .expect(&mat_error(&name, "A transmit color is required for glass")[..]))
+            let transmit = load_color_texture(m.find("transmit")
+                                      .expect(&mat_error(&name, "A transmit color is required for glass")[..]),
+                                      textures)
                 .expect(&mat_error(&name, "Invalid color specified for transmit of glass")[..]);
-            let eta = m.find("eta")
-                .expect(&mat_error(&name, "A refractive index 'eta' is required for glass")[..]).as_f64()
-                .expect(&mat_error(&name, "glass eta must be a float")[..]) as f32;
-            materials.insert(name, Arc::new(Glass::new(&reflect, &transmit, eta)) as Arc<Material + Send + Sync>);
+            let eta = load_scalar_texture(m.find("eta")
+                .expect(&mat_error(&name, "A refractive index 'eta' is required for glass")[..]), textures)
+                .expect(&mat_error(&name, "glass eta must be a float")[..]);
+            Arc::new(Glass::new(reflect, transmit, eta)) as Arc<Material + Send + Sync>
         } else if ty == "rough_glass" {
-            let reflect = load_color(m.find("reflect")
-                                     .expect(&mat_error(&name, "A reflect color is required for roughglass")[..]))
+            let reflect = load_color_texture(m.find("reflect")
+                                     .expect(&mat_error(&name, "A reflect color is required for roughglass")[..]),
+                                     textures)
                 .expect(&mat_error(&name, "Invalid color specified for reflect of glass")[..]);
-            let transmit = load_color(m.find("transmit")
-                                      .expect(&mat_error(&name, "A transmit color is required for roughglass")[..]))
+            let transmit = load_color_texture(m.find("transmit")
+                                      .expect(&mat_error(&name, "A transmit color is required for roughglass")[..]),
+                                      textures)
                 .expect(&mat_error(&name, "Invalid color specified for transmit of roughglass")[..]);
-            let eta = m.find("eta")
-                .expect(&mat_error(&name, "A refractive index 'eta' is required for roughglass")[..]).as_f64()
-                .expect(&mat_error(&name, "roughglass eta must be a float")[..]) as f32;
-            let roughness = m.find("roughness")
-                .expect(&mat_error(&name, "A roughness is required for roughglass")[..]).as_f64()
-                .expect(&mat_error(&name, "roughness of roughglass must be a float")[..]) as f32;
-            materials.insert(name, Arc::new(RoughGlass::new(&reflect, &transmit, eta, roughness))
-                             as Arc<Material + Send + Sync>);
+            let eta = load_scalar_texture(m.find("eta")
+                .expect(&mat_error(&name, "A refractive index 'eta' is required for roughglass")[..]), textures)
+                .expect(&mat_error(&name, "roughglass eta must be a float")[..]);
+            let (roughness_u, roughness_v) = load_roughness(&name, m, textures);
+            Arc::new(RoughGlass::anisotropic(reflect, transmit, eta, roughness_u, roughness_v)
+                     .energy_compensation(load_energy_compensation(&name, m)))
+                as Arc<Material + Send + Sync>
         } else if ty == "matte" {
-            let diffuse = load_color(m.find("diffuse")
-                                     .expect(&mat_error(&name, "A diffuse color is required for matte")[..]))
+            let diffuse = load_color_texture(m.find("diffuse")
+                                     .expect(&mat_error(&name, "A diffuse color is required for matte")[..]), textures)
                 .expect(&mat_error(&name, "Invalid color specified for diffuse of matte")[..]);
-            let roughness = m.find("roughness")
-                .expect(&mat_error(&name, "A roughness is required for matte")[..]).as_f64()
-                .expect(&mat_error(&name, "roughness must be a float")[..]) as f32;
-            materials.insert(name, Arc::new(Matte::new(&diffuse, roughness)) as Arc<Material + Send + Sync>);
+            let roughness = load_scalar_texture(m.find("roughness")
+                .expect(&mat_error(&name, "A roughness is required for matte")[..]), textures)
+                .expect(&mat_error(&name, "roughness must be a float")[..]);
+            Arc::new(Matte::new(diffuse, roughness)) as Arc<Material + Send + Sync>
         } else if ty == "merl" {
             let file_path = Path::new(m.find("file")
                       .expect(&mat_error(&name, "A filename containing the MERL material data is required")[..])
                       .as_str().expect(&mat_error(&name, "The MERL file must be a string")[..]));
             if file_path.is_relative() {
-                materials.insert(name, Arc::new(Merl::load_file(path.join(file_path).as_path()))
-                                 as Arc<Material + Send + Sync>);
+                Arc::new(Merl::load_file(path.join(file_path).as_path())) as Arc<Material + Send + Sync>
             } else {
-                materials.insert(name, Arc::new(Merl::load_file(file_path)) as Arc<Material + Send + Sync>);
+                Arc::new(Merl::load_file(file_path)) as Arc<Material + Send + Sync>
             }
         } else if ty == "metal" {
-            let refr_index = load_color(m.find("refractive_index")
-                            .expect(&mat_error(&name, "A refractive_index color is required for metal")[..]))
+            let refr_index = load_color_texture(m.find("refractive_index")
+                            .expect(&mat_error(&name, "A refractive_index color is required for metal")[..]), textures)
                 .expect(&mat_error(&name, "Invalid color specified for refractive_index of metal")[..]);
-            let absorption_coef = load_color(m.find("absorption_coefficient")
-                         .expect(&mat_error(&name, "An absorption_coefficient color is required for metal")[..]))
+            let absorption_coef = load_color_texture(m.find("absorption_coefficient")
+                         .expect(&mat_error(&name, "An absorption_coefficient color is required for metal")[..]),
+                         textures)
                 .expect(&mat_error(&name, "Invalid color specified for absorption_coefficient of metal")[..]);
-            let roughness = m.find("roughness")
-                .expect(&mat_error(&name, "A roughness is required for metal")[..]).as_f64()
-                .expect(&mat_error(&name, "roughness must be a float")[..]) as f32;
-            materials.insert(name, Arc::new(Metal::new(&refr_index, &absorption_coef, roughness))
-                             as Arc<Material + Send + Sync>);
+            let (roughness_u, roughness_v) = load_roughness(&name, m, textures);
+            Arc::new(Metal::anisotropic(refr_index, absorption_coef, roughness_u, roughness_v)
+                     .energy_compensation(load_energy_compensation(&name, m)))
+                as Arc<Material + Send + Sync>
         } else if ty == "plastic" {
-            let diffuse = load_color(m.find("diffuse")
-                             .expect(&mat_error(&name, "A diffuse color is required for plastic")[..]))
+            let diffuse = load_color_texture(m.find("diffuse")
+                             .expect(&mat_error(&name, "A diffuse color is required for plastic")[..]), textures)
                 .expect(&mat_error(&name, "Invalid color specified for diffuse of plastic")[..]);
-            let gloss = load_color(m.find("gloss")
-                             .expect(&mat_error(&name, "A gloss color is required for plastic")[..]))
+            let gloss = load_color_texture(m.find("gloss")
+                             .expect(&mat_error(&name, "A gloss color is required for plastic")[..]), textures)
                 .expect(&mat_error(&name, "Invalid color specified for gloss of plastic")[..]);
-            let roughness = m.find("roughness")
-                .expect(&mat_error(&name, "A roughness is required for plastic")[..]).as_f64()
-                .expect(&mat_error(&name, "roughness must be a float")[..]) as f32;
-            materials.insert(name, Arc::new(Plastic::new(&diffuse, &gloss, roughness))
-                             as Arc<Material + Send + Sync>);
+            let (roughness_u, roughness_v) = load_roughness(&name, m, textures);
+            Arc::new(Plastic::anisotropic(diffuse, gloss, roughness_u, roughness_v)) as Arc<Material + Send + Sync>
         } else if ty == "specular_metal" {
-            let refr_index = load_color(m.find("refractive_index")
-                    .expect(&mat_error(&name, "A refractive_index color is required for specular metal")[..]))
+            let refr_index = load_color_texture(m.find("refractive_index")
+                    .expect(&mat_error(&name, "A refractive_index color is required for specular metal")[..]),
+                    textures)
                 .expect(&mat_error(&name, "Invalid color specified for refractive_index of specular metal")[..]);
-            let absorption_coef = load_color(m.find("absorption_coefficient")
+            let absorption_coef = load_color_texture(m.find("absorption_coefficient")
                      .expect(&mat_error(&name,
-                                        "An absorption_coefficient color is required for specular metal")[..]))
+                                        "An absorption_coefficient color is required for specular metal")[..]),
+                     textures)
                 .expect(&mat_error(&name,
                                    "Invalid color specified for absorption_coefficient of specular metal")[..]);
-            materials.insert(name, Arc::new(SpecularMetal::new(&refr_index, &absorption_coef))
-                             as Arc<Material + Send + Sync>);
+            Arc::new(SpecularMetal::new(refr_index, absorption_coef)) as Arc<Material + Send + Sync>
+        } else if ty == "principled" {
+            let base_color = load_color_texture(m.find("base_color")
+                .expect(&mat_error(&name, "A base_color is required for principled")[..]), textures)
+                .expect(&mat_error(&name, "Invalid color specified for base_color of principled")[..]);
+            // All other parameters are optional
+            let param = |p: &str, default: f32| match m.find(p) {
+                Some(v) => load_scalar_texture(v, textures)
+                    .expect(&mat_error(&name, &format!("Invalid number specified for {} of principled", p))[..]),
+                None => Arc::new(ConstantTexture::new(default)) as Arc<Texture<f32> + Send + Sync>,
+            };
+            Arc::new(Principled::new(base_color, param("metallic", 0.0), param("roughness", 0.5),
+                                     param("specular", 0.5), param("specular_tint", 0.0), param("anisotropic", 0.0),
+                                     param("sheen", 0.0), param("sheen_tint", 0.5), param("clearcoat", 0.0),
+                                     param("clearcoat_gloss", 1.0), param("specular_transmission", 0.0)))
+                as Arc<Material + Send + Sync>
+        } else if ty == "mix" {
+            let mat1 = load_material_ref(&name, "mat1", m, &materials);
+            let mat2 = load_material_ref(&name, "mat2", m, &materials);
+            let amount = load_scalar_texture(m.find("amount")
+                .expect(&mat_error(&name, "An amount is required for mix")[..]), textures)
+                .expect(&mat_error(&name, "Invalid number specified for amount of mix")[..]);
+            Arc::new(Mix::new(mat1, mat2, amount)) as Arc<Material + Send + Sync>
+        } else if ty == "coated" {
+            let base = load_material_ref(&name, "base", m, &materials);
+            let eta = match m.find("eta") {
+                Some(e) => load_scalar_texture(e, textures)
+                    .expect(&mat_error(&name, "Invalid number specified for eta of coated")[..]),
+                None => Arc::new(ConstantTexture::new(1.5f32)) as Arc<Texture<f32> + Send + Sync>,
+            };
+            let roughness = match m.find("roughness") {
+                Some(r) => load_scalar_texture(r, textures)
+                    .expect(&mat_error(&name, "Invalid number specified for roughness of coated")[..]),
+                None => Arc::new(ConstantTexture::new(0.0f32)) as Arc<Texture<f32> + Send + Sync>,
+            };
+            Arc::new(Coated::new(base, eta, roughness)) as Arc<Material + Send + Sync>
         } else {
             panic!("Error parsing material '{}': unrecognized type '{}'", name, ty);
-        }
+        };
+        // Any material can have its shading normals perturbed by a bump or normal map
+        let material = match (m.find("bump"), m.find("normal_map")) {
+            (Some(_), Some(_)) => panic!("Error loading material '{}': only one of bump and normal_map can be set",
+                                         name),
+            (Some(b), None) => {
+                let height = load_scalar_texture(b, textures)
+                    .expect(&mat_error(&name, "Invalid number or texture specified for bump")[..]);
+                Arc::new(BumpMap::new(material, height)) as Arc<Material + Send + Sync>
+            },
+            (None, Some(nm)) => {
+                let normals = load_color_texture(nm, textures)
+                    .expect(&mat_error(&name, "Invalid color or texture specified for normal_map")[..]);
+                Arc::new(NormalMap::new(material, normals)) as Arc<Material + Send + Sync>
+            },
+            (None, None) => material,
+        };
+        materials.insert(name, material);
     }
     materials
 }
 
-/// Loads the array of objects in the scene, assigning them materials from the materials map. Will
-/// panic if an incorrectly specified object is found.
+/// The meshes loaded from model files, kept so the same models can be shared by objects
+struct MeshCache {
+    /// Map of file name -> (map of model name -> mesh)
+    meshes: HashMap<String, HashMap<String, Arc<Mesh>>>,
+    /// Map of file name -> (map of model name -> material assigned by the OBJ's MTL file)
+    mtl: HashMap<String, HashMap<String, MtlMaterial>>,
+    /// The materials created from MTL materials, keyed by the file and material name
+    mtl_materials: HashMap<(String, String), Arc<Material + Send + Sync>>,
+    /// The meshes with re-computed normals, keyed by the file, model name and crease angle
+    reshaded: HashMap<(String, String, String), Arc<Mesh>>,
+}
+
+/// Loads the array of objects in the scene, assigning them materials from the materials map. The
+/// cameras of any glTF files loaded are added to `gltf_cameras`. Will panic if an incorrectly
+/// specified object is found.
 fn load_objects(path: &Path, materials: &HashMap<String, Arc<Material + Send + Sync>>,
-                mesh_cache: &mut HashMap<String, HashMap<String, Arc<Mesh>>>, elem: &Value)
+                mesh_cache: &mut MeshCache, gltf_cameras: &mut Vec<GltfCamera>, elem: &Value)
                 -> Vec<Instance> {
     let mut instances = Vec::new();
     let objects = elem.as_array().expect("The objects must be an array of objects used");
@@ -397,31 +776,71 @@ fn load_objects(path: &Path, materials: &HashMap<String, Arc<Material + Send + S
                     .expect("An emission color is required for emitters"))
                     .expect("Emitter emission must be a color");
             if emit_ty == "point" {
-                instances.push(Instance::point_light(transform, emission, name));
+                instances.push(Instance::point_light(transform, emission, load_ies(path, o), name));
+            } else if emit_ty == "spot" {
+                let outer = o.find("outer_angle").expect("An outer_angle is required for spot lights")
+                    .as_f64().expect("Spot light outer_angle must be a number") as f32;
+                let inner = o.find("inner_angle").expect("An inner_angle is required for spot lights")
+                    .as_f64().expect("Spot light inner_angle must be a number") as f32;
+                instances.push(Instance::spot_light(transform, emission, inner, outer, load_ies(path, o), name));
+            } else if emit_ty == "directional" {
+                instances.push(Instance::directional_light(transform, emission, name));
             } else if emit_ty == "area" {
                 let mat_name = o.find("material").expect("A material is required for an object")
                     .as_str().expect("Object material name must be a string");
                 let mat = materials.get(mat_name)
                     .expect(&format!("Material {} was not found in the material list", mat_name)).clone();
-                let geom = load_sampleable_geometry(o.find("geometry")
+                let geom = load_sampleable_geometry(path, mesh_cache, o.find("geometry")
                                                     .expect("Geometry is required for area lights"));
 
                 instances.push(Instance::area_light(geom, mat, emission, transform, name));
+            } else if emit_ty == "environment" {
+                let file_path = Path::new(o.find("file").expect("A file is required for environment lights")
+                                          .as_str().expect("Environment light file must be a string"));
+                let map = if file_path.is_relative() {
+                    EnvironmentMap::load_file(path.join(file_path).as_path())
+                } else {
+                    EnvironmentMap::load_file(file_path)
+                };
+                instances.push(Instance::environment_light(Arc::new(map), transform, emission, name));
+            } else if emit_ty == "sky" {
+                let sun = load_vector(o.find("sun_direction").expect("A sun_direction is required for sky lights"))
+                    .expect("Invalid vector specified for sun_direction");
+                let up = match o.find("up") {
+                    Some(u) => load_vector(u).expect("Invalid vector specified for sky up direction"),
+                    None => Vector::new(0.0, 1.0, 0.0),
+                };
+                let turbidity = match o.find("turbidity") {
+                    Some(t) => t.as_f64().expect("Sky turbidity must be a number") as f32,
+                    None => 3.0,
+                };
+                let ground_albedo = match o.find("ground_albedo") {
+                    Some(c) => load_color(c).expect("Invalid color specified for sky ground_albedo"),
+                    None => Colorf::broadcast(0.3),
+                };
+                let sky = SunSky::new(turbidity, &ground_albedo, &up, &(transform.transform(0.0) * sun));
+                instances.push(Instance::sky_light(sky, &sun, transform, emission, name));
             } else {
                 panic!("Invalid emitter type specified: {}", emit_ty);
             }
         } else if ty == "receiver" {
-            let mat_name = o.find("material").expect("A material is required for an object")
-                    .as_str().expect("Object material name must be a string");
-            let mat = materials.get(mat_name)
-                .expect(&format!("Material {} was not found in the material list", mat_name)).clone();
-            let geom = load_geometry(path, mesh_cache, o.find("geometry")
-                                     .expect("Geometry is required for receivers"));
+            let geom_elem = o.find("geometry").expect("Geometry is required for receivers");
+            let geom = load_geometry(path, mesh_cache, geom_elem);
+            let mat = match load_mtl_material(path, materials, mesh_cache, geom_elem) {
+                Some(m) => m,
+                None => {
+                    let mat_name = o.find("material").expect("A material is required for an object")
+                        .as_str().expect("Object material name must be a string");
+                    materials.get(mat_name)
+                        .expect(&format!("Material {} was not found in the material list", mat_name)).clone()
+                },
+            };
 
             instances.push(Instance::receiver(geom, mat, transform, name));
         } else if ty == "group" {
             let group_objects = o.find("objects").expect("A group must specify an array of objects in the group");
-            let group_instances = load_objects(path, materials, mesh_cache, group_objects);
+            let first_camera = gltf_cameras.len();
+            let group_instances = load_objects(path, materials, mesh_cache, gltf_cameras, group_objects);
             for mut gi in group_instances {
                 {
                     let t = gi.get_transform().clone();
@@ -429,6 +848,28 @@ fn load_objects(path: &Path, materials: &HashMap<String, Arc<Material + Send + S
                 }
                 instances.push(gi);
             }
+            for c in &mut gltf_cameras[first_camera..] {
+                c.transform = transform.clone() * c.transform.clone();
+            }
+        } else if ty == "gltf" {
+            let file_path = Path::new(o.find("file").expect("A file is required for glTF objects")
+                                      .as_str().expect("glTF file must be a string"));
+            let scene = if file_path.is_relative() {
+                gltf::load_file(path.join(file_path).as_path(), materials)
+            } else {
+                gltf::load_file(file_path, materials)
+            };
+            for mut gi in scene.instances {
+                {
+                    let t = gi.get_transform().clone();
+                    gi.set_transform(transform.clone() * t);
+                }
+                instances.push(gi);
+            }
+            for mut c in scene.cameras {
+                c.transform = transform.clone() * c.transform;
+                gltf_cameras.push(c);
+            }
         } else {
             panic!("Error parsing object '{}': unrecognized type '{}'", name, ty);
         }
@@ -438,8 +879,7 @@ fn load_objects(path: &Path, materials: &HashMap<String, Arc<Material + Send + S
 
 /// Load the geometry specified by the JSON value. Will re-use any already loaded meshes
 /// and will place newly loaded meshees in the mesh cache.
-fn load_geometry(path: &Path, meshes: &mut HashMap<String, HashMap<String, Arc<Mesh>>>, elem: &Value)
-             -> Arc<BoundableGeom + Send + Sync> {
+fn load_geometry(path: &Path, meshes: &mut MeshCache, elem: &Value) -> Arc<BoundableGeom + Send + Sync> {
     let ty = elem.find("type").expect("A type is required for geometry")
         .as_str().expect("Geometry type must be a string");
     if ty == "sphere" {
@@ -462,31 +902,125 @@ fn load_geometry(path: &Path, meshes: &mut HashMap<String, HashMap<String, Arc<M
             .expect("height must be a number") as f32;
         Arc::new(Rectangle::new(width, height))
     } else if ty == "mesh" {
-        let mut file = Path::new(elem.find("file").expect("An OBJ file is required for meshes")
-            .as_str().expect("OBJ filename must be a string")).to_path_buf();
-        let model = elem.find("model").expect("A model name is required for geometry")
-            .as_str().expect("Model name type must be a string");
-
-        if file.is_relative() {
-            file = path.join(file);
-        }
-        let file_string = file.to_str().expect("Invalid file name");
-        if meshes.get(file_string).is_none() {
-            meshes.insert(file_string.to_owned(), Mesh::load_obj(Path::new(&file)));
-        }
-        let file_meshes = &meshes[file_string];
-        match file_meshes.get(model) {
-            Some(m) => m.clone(),
-            None => panic!("Requested model '{}' was not found in '{:?}'", model, file),
-        }
+        load_mesh(path, meshes, elem)
     } else {
         panic!("Unrecognized geometry type '{}'", ty);
     }
 }
 
-/// Load the sampleable geometry specified by the JSON value. Will panic if the geometry specified
-/// is not sampleable.
-fn load_sampleable_geometry(elem: &Value) -> Arc<SampleableGeom + Send + Sync> {
+/// Load the mesh geometry specified by the JSON value, re-using it if the file was already
+/// loaded into the mesh cache. If the geometry sets 'smooth' to false or a 'crease_angle'
+/// the mesh's normals are re-computed
+fn load_mesh(path: &Path, meshes: &mut MeshCache, elem: &Value) -> Arc<Mesh> {
+    let (file, model) = mesh_file_model(path, elem);
+    if meshes.meshes.get(&file).is_none() {
+        let (file_meshes, file_materials) = if is_ply(&file) {
+            (Mesh::load_ply(Path::new(&file)), HashMap::new())
+        } else {
+            Mesh::load_obj_with_materials(Path::new(&file))
+        };
+        meshes.meshes.insert(file.clone(), file_meshes);
+        meshes.mtl.insert(file.clone(), file_materials);
+    }
+    let mesh = match meshes.meshes[&file].get(&model) {
+        Some(m) => m.clone(),
+        None => panic!("Requested model '{}' was not found in '{}'", model, file),
+    };
+    let smooth = match elem.find("smooth") {
+        Some(s) => s.as_bool().expect("smooth must be a bool"),
+        None => true,
+    };
+    let crease_angle = if !smooth {
+        Some(0.0)
+    } else {
+        elem.find("crease_angle").map(|c| c.as_f64().expect("crease_angle must be a number") as f32)
+    };
+    match crease_angle {
+        Some(c) => {
+            let key = (file, model, c.to_string());
+            meshes.reshaded.entry(key).or_insert_with(|| Arc::new(mesh.with_normals(c))).clone()
+        },
+        None => mesh,
+    }
+}
+
+/// Get the file name, resolved relative to the scene file, and model name of the mesh geometry.
+/// PLY files hold a single model named after the file so their model name is optional
+fn mesh_file_model(path: &Path, elem: &Value) -> (String, String) {
+    let mut file = Path::new(elem.find("file").expect("An OBJ or PLY file is required for meshes")
+        .as_str().expect("Mesh filename must be a string")).to_path_buf();
+    let model = match elem.find("model") {
+        Some(m) => m.as_str().expect("Model name type must be a string").to_owned(),
+        None if is_ply(file.to_str().expect("Invalid file name")) => {
+            file.file_stem().expect("Invalid file name").to_string_lossy().into_owned()
+        },
+        None => panic!("A model name is required for geometry"),
+    };
+    if file.is_relative() {
+        file = path.join(file);
+    }
+    (file.to_str().expect("Invalid file name").to_owned(), model)
+}
+
+/// Check if the mesh file is a PLY file, otherwise it's loaded as an OBJ file
+fn is_ply(file: &str) -> bool {
+    match Path::new(file).extension() {
+        Some(e) => e.to_string_lossy().to_lowercase() == "ply",
+        None => false,
+    }
+}
+
+/// Get the material assigned to the mesh model by the OBJ's MTL file if the geometry sets
+/// 'use_mtl', the geometry must have already been loaded into the mesh cache. Materials in
+/// the scene's materials list with the same name as the MTL material override it. Returns
+/// None if the geometry doesn't use MTL materials or the model has no material
+fn load_mtl_material(path: &Path, materials: &HashMap<String, Arc<Material + Send + Sync>>,
+                     meshes: &mut MeshCache, elem: &Value) -> Option<Arc<Material + Send + Sync>> {
+    let use_mtl = match elem.find("use_mtl") {
+        Some(u) => u.as_bool().expect("use_mtl must be a bool"),
+        None => false,
+    };
+    if !use_mtl {
+        return None;
+    }
+    let (file, model) = mesh_file_model(path, elem);
+    let mtl = match meshes.mtl[&file].get(&model) {
+        Some(m) => m.clone(),
+        None => return None,
+    };
+    if let Some(m) = materials.get(&mtl.name) {
+        return Some(m.clone());
+    }
+    let key = (file, mtl.name.clone());
+    let material = meshes.mtl_materials.entry(key).or_insert_with(|| mtl_to_material(&mtl));
+    Some(material.clone())
+}
+
+/// Create a material with the look of the MTL material. Transparent materials become glass,
+/// those with a specular color become plastic and all others become matte
+fn mtl_to_material(mtl: &MtlMaterial) -> Arc<Material + Send + Sync> {
+    let diffuse: Arc<Texture<Colorf> + Send + Sync> = match mtl.diffuse_texture {
+        Some(ref f) => Arc::new(ImageTexture::load_file(f, true, WrapMode::Repeat)),
+        None => Arc::new(ConstantTexture::new(mtl.diffuse)),
+    };
+    if mtl.dissolve < 1.0 {
+        Arc::new(Glass::new(Arc::new(ConstantTexture::new(Colorf::broadcast(1.0))), diffuse,
+                            Arc::new(ConstantTexture::new(mtl.ior))))
+    } else if !mtl.specular.is_black() {
+        // Convert the Phong exponent to the width of the Beckmann distribution, see
+        // Walter et al., "Microfacet Models for Refraction through Rough Surfaces", 2007
+        let roughness = f32::sqrt(2.0 / (f32::max(mtl.shininess, 0.0) + 2.0));
+        Arc::new(Plastic::new(diffuse, Arc::new(ConstantTexture::new(mtl.specular)),
+                              Arc::new(ConstantTexture::new(roughness))))
+    } else {
+        Arc::new(Matte::new(diffuse, Arc::new(ConstantTexture::new(0.0))))
+    }
+}
+
+/// Load the sampleable geometry specified by the JSON value, meshes are shared with the receivers
+/// through the mesh cache. Will panic if the geometry specified is not sampleable.
+fn load_sampleable_geometry(path: &Path, meshes: &mut MeshCache, elem: &Value)
+                            -> Arc<SampleableGeom + Send + Sync> {
     let ty = elem.find("type").expect("A type is required for geometry")
         .as_str().expect("Geometry type must be a string");
     if ty == "sphere" {
@@ -505,11 +1039,27 @@ fn load_sampleable_geometry(elem: &Value) -> Arc<SampleableGeom + Send + Sync> {
         let height = elem.find("height").expect("A height is required for a rectangle").as_f64()
             .expect("height must be a number") as f32;
         Arc::new(Rectangle::new(width, height))
+    } else if ty == "mesh" {
+        load_mesh(path, meshes, elem)
     } else {
         panic!("Geometry of type '{}' is not sampleable and can't be used for area light geometry", ty);
     }
 }
 
+/// Loads the optional IES profile for a point or spot light from the file named by its `ies`
+/// parameter, relative paths are resolved relative to the scene file
+fn load_ies(path: &Path, elem: &Value) -> Option<Arc<IesProfile>> {
+    elem.find("ies").map(|f| {
+        let file_path = Path::new(f.as_str().expect("The IES profile file must be a string"));
+        let profile = if file_path.is_relative() {
+            IesProfile::load_file(path.join(file_path).as_path())
+        } else {
+            IesProfile::load_file(file_path)
+        };
+        Arc::new(profile)
+    })
+}
+
 /// Load a vector from the JSON element passed. Returns None if the element
 /// did not contain a valid vector (eg. [1.0, 2.0, 0.5])
 fn load_vector(elem: &Value) -> Option<Vector> {
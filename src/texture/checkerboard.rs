@@ -0,0 +1,55 @@
+//! Defines a checkerboard texture which alternates between two textures in a
+//! grid of checks over the surface's uv coordinates
+//!
+//! # Scene Usage Example
+//! The checkerboard requires the two textures to alternate between, which can be
+//! colors, numbers or the names of other textures, and the number of checks to
+//! place along u and v.
+//!
+//! ```json
+//! "textures": [
+//!     {
+//!         "name": "checks",
+//!         "type": "checkerboard",
+//!         "tex1": [1, 1, 1],
+//!         "tex2": [0.1, 0.1, 0.1],
+//!         "scale_u": 8,
+//!         "scale_v": 8
+//!     },
+//!     ...
+//! ]
+//! ```
+
+use std::f32;
+use std::sync::Arc;
+
+use geometry::DifferentialGeometry;
+use texture::Texture;
+
+/// A texture alternating between two textures in a checkerboard pattern
+pub struct Checkerboard<T> {
+    tex1: Arc<Texture<T> + Send + Sync>,
+    tex2: Arc<Texture<T> + Send + Sync>,
+    scale_u: f32,
+    scale_v: f32,
+}
+
+impl<T> Checkerboard<T> {
+    /// Create a checkerboard with `scale_u` by `scale_v` checks over the unit uv square,
+    /// the check containing uv (0, 0) uses `tex1`
+    pub fn new(tex1: Arc<Texture<T> + Send + Sync>, tex2: Arc<Texture<T> + Send + Sync>,
+               scale_u: f32, scale_v: f32) -> Checkerboard<T> {
+        Checkerboard { tex1: tex1, tex2: tex2, scale_u: scale_u, scale_v: scale_v }
+    }
+}
+
+impl<T> Texture<T> for Checkerboard<T> {
+    fn sample(&self, dg: &DifferentialGeometry) -> T {
+        let check = f32::floor(dg.u * self.scale_u) as i32 + f32::floor(dg.v * self.scale_v) as i32;
+        if check % 2 == 0 {
+            self.tex1.sample(dg)
+        } else {
+            self.tex2.sample(dg)
+        }
+    }
+}
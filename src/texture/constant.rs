@@ -0,0 +1,39 @@
+//! Defines a texture which returns the same value everywhere, used for material
+//! parameters which don't vary over the surface
+//!
+//! # Scene Usage Example
+//! A constant texture is created when a color or number is given for a material or
+//! texture parameter instead of the name of a texture, it can also be named in the
+//! textures list to share it between materials.
+//!
+//! ```json
+//! "textures": [
+//!     {
+//!         "name": "grey",
+//!         "type": "constant",
+//!         "value": [0.5, 0.5, 0.5]
+//!     },
+//!     ...
+//! ]
+//! ```
+
+use geometry::DifferentialGeometry;
+use texture::Texture;
+
+/// A texture returning a single value over the entire surface
+pub struct ConstantTexture<T> {
+    value: T,
+}
+
+impl<T: Copy> ConstantTexture<T> {
+    /// Create a constant texture which returns `value` everywhere
+    pub fn new(value: T) -> ConstantTexture<T> {
+        ConstantTexture { value: value }
+    }
+}
+
+impl<T: Copy> Texture<T> for ConstantTexture<T> {
+    fn sample(&self, _: &DifferentialGeometry) -> T {
+        self.value
+    }
+}
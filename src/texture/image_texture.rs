@@ -0,0 +1,138 @@
+//! Defines a texture which looks up its values from an image mapped over the surface
+//! using its uv coordinates, with bilinear filtering between the image's pixels
+//!
+//! # Scene Usage Example
+//! The image texture requires the image file to load, relative paths are resolved relative
+//! to the scene file. Radiance HDR files are loaded as linear values while other formats, e.g.
+//! PNG or JPG, are assumed to be sRGB and converted to linear unless 'srgb' is set to false,
+//! which should be done for images storing non-color data such as roughness.
+//! The optional 'wrap' parameter sets how uv coordinates outside [0, 1] are treated, it can be
+//! "repeat" (the default), "clamp" to the edge of the image, or "mirror" to repeat the image
+//! flipping it each time. The bottom left of the image is at uv (0, 0).
+//!
+//! ```json
+//! "textures": [
+//!     {
+//!         "name": "wood_floor",
+//!         "type": "image",
+//!         "file": "./wood.png",
+//!         "wrap": "repeat"
+//!     },
+//!     ...
+//! ]
+//! ```
+
+use std::f32;
+use std::path::Path;
+
+use linalg;
+use film::Colorf;
+use geometry::DifferentialGeometry;
+use texture::{self, Texture};
+
+/// How texture coordinates outside the image are mapped back onto it
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum WrapMode {
+    /// Tile the image over the surface
+    Repeat,
+    /// Clamp coordinates to the edge of the image
+    Clamp,
+    /// Tile the image over the surface, flipping every other tile
+    Mirror,
+}
+
+/// A texture which maps an image onto the surface using its uv coordinates
+pub struct ImageTexture {
+    width: usize,
+    height: usize,
+    /// The pixels in rows starting at the top of the image
+    pixels: Vec<Colorf>,
+    wrap: WrapMode,
+}
+
+impl ImageTexture {
+    /// Create an image texture from the `width` by `height` image passed, the pixels
+    /// are stored in rows starting at the top of the image
+    pub fn new(width: usize, height: usize, pixels: Vec<Colorf>, wrap: WrapMode) -> ImageTexture {
+        assert_eq!(pixels.len(), width * height);
+        ImageTexture { width: width, height: height, pixels: pixels, wrap: wrap }
+    }
+    /// Load an image texture from a file, if `srgb` is set non-HDR images are converted from
+    /// sRGB to linear. Panics if the file can't be loaded
+    pub fn load_file(path: &Path, srgb: bool, wrap: WrapMode) -> ImageTexture {
+        match texture::load_image(path, srgb) {
+            Ok((width, height, pixels)) => ImageTexture::new(width, height, pixels, wrap),
+            Err(e) => panic!("Failed to load image texture: {}", e),
+        }
+    }
+    /// Get the pixel at `(x, y)` after wrapping the coordinates onto the image, `y`
+    /// counts up from the bottom of the image
+    fn texel(&self, x: i32, y: i32) -> Colorf {
+        let x = wrap_coord(x, self.width, self.wrap);
+        let y = wrap_coord(y, self.height, self.wrap);
+        self.pixels[(self.height - 1 - y) * self.width + x]
+    }
+    /// Look up the bilinearly filtered color of the image at `(u, v)`
+    fn lookup(&self, u: f32, v: f32) -> Colorf {
+        // Find the pixels surrounding the point, treating pixel centers as being at
+        // half integer coordinates
+        let x = u * self.width as f32 - 0.5;
+        let y = v * self.height as f32 - 0.5;
+        let (x0, y0) = (f32::floor(x), f32::floor(y));
+        let (tx, ty) = (x - x0, y - y0);
+        let (x0, y0) = (x0 as i32, y0 as i32);
+        let a = linalg::lerp(tx, &self.texel(x0, y0), &self.texel(x0 + 1, y0));
+        let b = linalg::lerp(tx, &self.texel(x0, y0 + 1), &self.texel(x0 + 1, y0 + 1));
+        linalg::lerp(ty, &a, &b)
+    }
+}
+
+/// Wrap the pixel coordinate `x` onto an image axis with `n` pixels
+fn wrap_coord(x: i32, n: usize, wrap: WrapMode) -> usize {
+    let n = n as i32;
+    let x = match wrap {
+        WrapMode::Repeat => ((x % n) + n) % n,
+        WrapMode::Clamp => linalg::clamp(x, 0, n - 1),
+        WrapMode::Mirror => {
+            let x = ((x % (2 * n)) + 2 * n) % (2 * n);
+            if x < n { x } else { 2 * n - 1 - x }
+        },
+    };
+    x as usize
+}
+
+impl Texture<Colorf> for ImageTexture {
+    fn sample(&self, dg: &DifferentialGeometry) -> Colorf {
+        self.lookup(dg.u, dg.v)
+    }
+}
+
+impl Texture<f32> for ImageTexture {
+    fn sample(&self, dg: &DifferentialGeometry) -> f32 {
+        self.lookup(dg.u, dg.v).luminance()
+    }
+}
+
+#[test]
+fn test_wrap_filter() {
+    // A 2x2 image with a distinct value in each pixel
+    let pixels = vec![Colorf::broadcast(0.0), Colorf::broadcast(1.0),
+                      Colorf::broadcast(2.0), Colorf::broadcast(3.0)];
+    let repeat = ImageTexture::new(2, 2, pixels.clone(), WrapMode::Repeat);
+    // Pixel centers return the pixel's value, the top row of the image is at v = 1
+    assert_eq!(repeat.lookup(0.25, 0.25), Colorf::broadcast(2.0));
+    assert_eq!(repeat.lookup(0.75, 0.75), Colorf::broadcast(1.0));
+    assert_eq!(repeat.lookup(1.25, -0.75), Colorf::broadcast(2.0));
+    // Halfway between the bottom pixels
+    assert_eq!(repeat.lookup(0.5, 0.25), Colorf::broadcast(2.5));
+    // At the left edge we filter with the pixel wrapped around from the right
+    assert_eq!(repeat.lookup(0.0, 0.25), Colorf::broadcast(2.5));
+
+    let clamp = ImageTexture::new(2, 2, pixels.clone(), WrapMode::Clamp);
+    assert_eq!(clamp.lookup(0.0, 0.25), Colorf::broadcast(2.0));
+    assert_eq!(clamp.lookup(5.0, 5.0), Colorf::broadcast(1.0));
+
+    let mirror = ImageTexture::new(2, 2, pixels, WrapMode::Mirror);
+    assert_eq!(mirror.lookup(1.25, 0.25), Colorf::broadcast(3.0));
+    assert_eq!(mirror.lookup(2.25, 0.25), Colorf::broadcast(2.0));
+}
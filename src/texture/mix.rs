@@ -0,0 +1,49 @@
+//! Defines a texture which linearly interpolates between two textures
+//!
+//! # Scene Usage Example
+//! The mix texture requires the two textures to blend between and the amount of
+//! the second texture to use, all can be colors, numbers or the names of other
+//! textures. The amount is always treated as a number.
+//!
+//! ```json
+//! "textures": [
+//!     {
+//!         "name": "worn_paint",
+//!         "type": "mix",
+//!         "tex1": [0.8, 0.1, 0.1],
+//!         "tex2": "rust",
+//!         "amount": "wear_mask"
+//!     },
+//!     ...
+//! ]
+//! ```
+
+use std::ops::{Add, Mul};
+use std::sync::Arc;
+
+use linalg;
+use geometry::DifferentialGeometry;
+use texture::Texture;
+
+/// A texture blending between `tex1` and `tex2` based on `amount`
+pub struct MixTexture<T> {
+    tex1: Arc<Texture<T> + Send + Sync>,
+    tex2: Arc<Texture<T> + Send + Sync>,
+    amount: Arc<Texture<f32> + Send + Sync>,
+}
+
+impl<T> MixTexture<T> {
+    /// Create a texture which returns `tex1` where `amount` is 0 and `tex2` where
+    /// it's 1, linearly interpolating between them for values in between
+    pub fn new(tex1: Arc<Texture<T> + Send + Sync>, tex2: Arc<Texture<T> + Send + Sync>,
+               amount: Arc<Texture<f32> + Send + Sync>) -> MixTexture<T> {
+        MixTexture { tex1: tex1, tex2: tex2, amount: amount }
+    }
+}
+
+impl<T: Mul<f32, Output = T> + Add<Output = T> + Copy> Texture<T> for MixTexture<T> {
+    fn sample(&self, dg: &DifferentialGeometry) -> T {
+        let t = self.amount.sample(dg);
+        linalg::lerp(t, &self.tex1.sample(dg), &self.tex2.sample(dg))
+    }
+}
@@ -0,0 +1,94 @@
+//! Defines the trait implemented by all textures and exports the supported texture
+//! types. Textures are used to vary material properties over a surface, they're
+//! looked up using the differential geometry at the hit point. Image and checkerboard
+//! textures are mapped using the surface's uv coordinates while the procedural noise
+//! textures are computed from the hit point in the object's local space.
+//!
+//! # Scene Usage Example
+//! Textures are specified in the textures list of the scene object. A type and name for
+//! the texture along with any additional parameters is required to specify one. Material
+//! parameters which take a color or number can instead be given the name of a texture
+//! to vary the parameter over the surface.
+//!
+//! Some textures are built from other textures, these parameters can also be a color, a number
+//! or the name of another texture. Textures must be listed after any textures they refer to.
+//! When a texture is used for a number parameter, e.g. a roughness, colors are converted to
+//! numbers by taking their luminance.
+//!
+//! ```json
+//! "textures": [
+//!     {
+//!         "name": "my_texture",
+//!         "type": "The_Texture_Type",
+//!          ...
+//!     }
+//!     ...
+//! ],
+//! "materials": [
+//!     {
+//!         "name": "textured_matte",
+//!         "type": "matte",
+//!         "diffuse": "my_texture",
+//!         "roughness": 0.0
+//!     },
+//!     ...
+//! ]
+//! ```
+
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+
+use image::{self, hdr};
+
+use film::Colorf;
+use geometry::DifferentialGeometry;
+
+pub use self::constant::ConstantTexture;
+pub use self::image_texture::{ImageTexture, WrapMode};
+pub use self::checkerboard::Checkerboard;
+pub use self::scale::ScaleTexture;
+pub use self::mix::MixTexture;
+pub use self::perlin::Perlin;
+pub use self::noise::{NoiseTexture, NoisePattern};
+
+pub mod constant;
+pub mod image_texture;
+pub mod checkerboard;
+pub mod scale;
+pub mod mix;
+pub mod perlin;
+pub mod noise;
+
+/// Trait implemented by textures returning values of type `T`, e.g. a color
+/// or a scalar, over the surface of some geometry
+pub trait Texture<T> {
+    /// Look up the value of the texture at the hit point described by `dg`
+    fn sample(&self, dg: &DifferentialGeometry) -> T;
+}
+
+/// Load the image file at `path` as linear RGB, returning its width, height and pixels
+/// stored in rows starting at the top of the image. Radiance HDR files are loaded as
+/// linear values while other formats are converted from sRGB to linear if `srgb` is set
+pub fn load_image(path: &Path, srgb: bool) -> Result<(usize, usize, Vec<Colorf>), String> {
+    let is_hdr = match path.extension() {
+        Some(ext) => ext == "hdr",
+        None => false,
+    };
+    if is_hdr {
+        let file = try!(File::open(path).map_err(|e| format!("failed to open {}: {}", path.display(), e)));
+        let decoder = try!(hdr::HDRDecoder::new(BufReader::new(file))
+                           .map_err(|e| format!("failed to read {}: {}", path.display(), e)));
+        let meta = decoder.metadata();
+        let pixels = try!(decoder.read_image_hdr().map_err(|e| format!("failed to read {}: {}", path.display(), e)));
+        let pixels = pixels.iter().map(|c| Colorf::new(c.data[0], c.data[1], c.data[2])).collect();
+        Ok((meta.width as usize, meta.height as usize, pixels))
+    } else {
+        let img = try!(image::open(path).map_err(|e| format!("failed to open {}: {}", path.display(), e))).to_rgb();
+        let pixels = img.pixels().map(|c| {
+            let c = Colorf::new(c.data[0] as f32 / 255.0, c.data[1] as f32 / 255.0, c.data[2] as f32 / 255.0);
+            if srgb { c.to_linear() } else { c }
+        }).collect();
+        Ok((img.width() as usize, img.height() as usize, pixels))
+    }
+}
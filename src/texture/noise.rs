@@ -0,0 +1,117 @@
+//! Defines procedural solid textures built from Perlin noise. The noise is computed
+//! from the hit point in the local space of the object, so the pattern moves with the
+//! object and doesn't depend on how its surface is parameterized. Each texture computes
+//! a pattern value between 0 and 1 which is used to blend between two other textures.
+//!
+//! # Scene Usage Example
+//! The noise texture types are "noise" for plain gradient noise, "fbm" for fractional
+//! Brownian motion, "turbulence", "marble" for stripes along the object's y axis distorted
+//! by noise, "wood" for rings about the object's y axis and "windy" for waves like those
+//! on the surface of water blown by the wind.
+//!
+//! The textures to blend between, 'tex1' where the pattern is 0 and 'tex2' where it's 1,
+//! can be colors, numbers or the names of other textures and default to 0 and 1. All other
+//! parameters are optional: 'frequency' (default 1) scales the point before computing
+//! the noise, 'octaves' (default 6) sets the number of octaves of noise summed, each with
+//! 'lacunarity' (default 2) times the frequency and 'gain' (default 0.5) times the amplitude
+//! of the previous. 'variation' (default 1) sets how strongly the noise distorts the marble
+//! and wood patterns and 'seed' (default 0) picks a different but repeatable noise pattern.
+//!
+//! ```json
+//! "textures": [
+//!     {
+//!         "name": "white_marble",
+//!         "type": "marble",
+//!         "tex1": [0.9, 0.9, 0.85],
+//!         "tex2": [0.3, 0.3, 0.35],
+//!         "frequency": 4,
+//!         "octaves": 8,
+//!         "variation": 3
+//!     },
+//!     ...
+//! ]
+//! ```
+
+use std::f32;
+use std::cmp;
+use std::ops::{Add, Mul};
+use std::sync::Arc;
+
+use linalg;
+use geometry::DifferentialGeometry;
+use texture::{Texture, Perlin};
+
+/// The pattern computed from the noise by a `NoiseTexture`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum NoisePattern {
+    /// A single octave of gradient noise
+    Noise,
+    /// Fractional Brownian motion
+    Fbm,
+    /// Turbulence, the sum of the absolute value of each octave of noise
+    Turbulence,
+    /// Stripes along the y axis distorted by fractional Brownian motion
+    Marble,
+    /// Rings about the y axis distorted by fractional Brownian motion
+    Wood,
+    /// Waves scaled by a low frequency wind strength
+    Windy,
+}
+
+/// A procedural texture blending between two textures based on a noise pattern
+pub struct NoiseTexture<T> {
+    perlin: Perlin,
+    pattern: NoisePattern,
+    tex1: Arc<Texture<T> + Send + Sync>,
+    tex2: Arc<Texture<T> + Send + Sync>,
+    frequency: f32,
+    octaves: usize,
+    lacunarity: f32,
+    gain: f32,
+    variation: f32,
+}
+
+impl<T> NoiseTexture<T> {
+    /// Create a noise texture computing `pattern` using the noise chosen by `seed`, the texture
+    /// is `tex1` where the pattern is 0 and `tex2` where it's 1. The local hit point is scaled
+    /// by `frequency` and `octaves` octaves of noise are summed, each with `lacunarity` times the
+    /// frequency and `gain` times the amplitude of the previous. `variation` sets the strength of
+    /// the noise distorting the marble and wood patterns
+    pub fn new(pattern: NoisePattern, tex1: Arc<Texture<T> + Send + Sync>, tex2: Arc<Texture<T> + Send + Sync>,
+               frequency: f32, octaves: usize, lacunarity: f32, gain: f32, variation: f32, seed: usize)
+               -> NoiseTexture<T> {
+        NoiseTexture { perlin: Perlin::new(seed), pattern: pattern, tex1: tex1, tex2: tex2, frequency: frequency,
+                       octaves: octaves, lacunarity: lacunarity, gain: gain, variation: variation }
+    }
+    /// Compute the value of the pattern at the hit, between 0 and 1
+    fn pattern(&self, dg: &DifferentialGeometry) -> f32 {
+        let p = dg.p_local * self.frequency;
+        let t = match self.pattern {
+            NoisePattern::Noise => 0.5 + 0.5 * self.perlin.noise(&p),
+            NoisePattern::Fbm => 0.5 + 0.5 * self.perlin.fbm(&p, self.octaves, self.lacunarity, self.gain),
+            NoisePattern::Turbulence => self.perlin.turbulence(&p, self.octaves, self.lacunarity, self.gain),
+            NoisePattern::Marble => {
+                let fbm = self.perlin.fbm(&p, self.octaves, self.lacunarity, self.gain);
+                0.5 + 0.5 * f32::sin(p.y + self.variation * fbm)
+            },
+            NoisePattern::Wood => {
+                let fbm = self.perlin.fbm(&p, self.octaves, self.lacunarity, self.gain);
+                let r = f32::sqrt(p.x * p.x + p.z * p.z) + self.variation * fbm;
+                r - f32::floor(r)
+            },
+            NoisePattern::Windy => {
+                let wind = self.perlin.fbm(&(p * 0.1), cmp::min(self.octaves, 3), self.lacunarity, self.gain);
+                let waves = self.perlin.fbm(&p, self.octaves, self.lacunarity, self.gain);
+                0.5 + 0.5 * f32::abs(wind) * waves
+            },
+        };
+        linalg::clamp(t, 0.0, 1.0)
+    }
+}
+
+impl<T: Mul<f32, Output = T> + Add<Output = T> + Copy> Texture<T> for NoiseTexture<T> {
+    fn sample(&self, dg: &DifferentialGeometry) -> T {
+        let t = self.pattern(dg);
+        linalg::lerp(t, &self.tex1.sample(dg), &self.tex2.sample(dg))
+    }
+}
@@ -0,0 +1,110 @@
+//! Provides Ken Perlin's improved gradient noise along with fractional Brownian
+//! motion and turbulence built by summing octaves of the noise, used to create
+//! procedural solid textures. See Perlin, "Improving Noise", SIGGRAPH 2002.
+
+use std::f32;
+
+use rand::{Rng, SeedableRng, StdRng};
+
+use linalg::{self, Point};
+
+/// Gradient noise with a permutation table chosen by a seed, so different seeds give
+/// different but repeatable noise
+pub struct Perlin {
+    /// The permutation of 0..256, repeated twice to avoid wrapping indices
+    perm: Vec<u8>,
+}
+
+impl Perlin {
+    /// Create the noise function using the permutation chosen by `seed`
+    pub fn new(seed: usize) -> Perlin {
+        let mut perm: Vec<u8> = (0..256).map(|x| x as u8).collect();
+        let mut rng: StdRng = SeedableRng::from_seed(&[seed][..]);
+        rng.shuffle(&mut perm[..]);
+        let second = perm.clone();
+        perm.extend(second);
+        Perlin { perm: perm }
+    }
+    /// Evaluate the noise at `p`, returns a value in about [-1, 1] which is 0 at
+    /// integer coordinates
+    pub fn noise(&self, p: &Point) -> f32 {
+        let (fx, fy, fz) = (f32::floor(p.x), f32::floor(p.y), f32::floor(p.z));
+        let (xi, yi, zi) = ((fx as i32 & 255) as usize, (fy as i32 & 255) as usize, (fz as i32 & 255) as usize);
+        let (x, y, z) = (p.x - fx, p.y - fy, p.z - fz);
+        let (u, v, w) = (fade(x), fade(y), fade(z));
+        let perm = &self.perm;
+        let a = perm[xi] as usize + yi;
+        let aa = perm[a] as usize + zi;
+        let ab = perm[a + 1] as usize + zi;
+        let b = perm[xi + 1] as usize + yi;
+        let ba = perm[b] as usize + zi;
+        let bb = perm[b + 1] as usize + zi;
+        let near = linalg::lerp(v, &linalg::lerp(u, &grad(perm[aa], x, y, z), &grad(perm[ba], x - 1.0, y, z)),
+                                &linalg::lerp(u, &grad(perm[ab], x, y - 1.0, z),
+                                              &grad(perm[bb], x - 1.0, y - 1.0, z)));
+        let far = linalg::lerp(v, &linalg::lerp(u, &grad(perm[aa + 1], x, y, z - 1.0),
+                                                &grad(perm[ba + 1], x - 1.0, y, z - 1.0)),
+                               &linalg::lerp(u, &grad(perm[ab + 1], x, y - 1.0, z - 1.0),
+                                             &grad(perm[bb + 1], x - 1.0, y - 1.0, z - 1.0)));
+        linalg::lerp(w, &near, &far)
+    }
+    /// Compute fractional Brownian motion at `p` by summing `octaves` octaves of noise,
+    /// each `lacunarity` times the frequency and `gain` times the amplitude of the previous.
+    /// The sum is normalized to be in about [-1, 1]
+    pub fn fbm(&self, p: &Point, octaves: usize, lacunarity: f32, gain: f32) -> f32 {
+        self.sum_octaves(p, octaves, lacunarity, gain, |x| x)
+    }
+    /// Compute turbulence at `p`, which is like `fbm` but sums the absolute value of
+    /// each octave of noise. The sum is normalized to be in about [0, 1]
+    pub fn turbulence(&self, p: &Point, octaves: usize, lacunarity: f32, gain: f32) -> f32 {
+        self.sum_octaves(p, octaves, lacunarity, gain, f32::abs)
+    }
+    fn sum_octaves<F: Fn(f32) -> f32>(&self, p: &Point, octaves: usize, lacunarity: f32, gain: f32, f: F)
+        -> f32 {
+        let mut sum = 0.0;
+        let mut total_amplitude = 0.0;
+        let mut amplitude = 1.0;
+        let mut frequency = 1.0;
+        for _ in 0..octaves {
+            sum += amplitude * f(self.noise(&(*p * frequency)));
+            total_amplitude += amplitude;
+            amplitude *= gain;
+            frequency *= lacunarity;
+        }
+        if total_amplitude > 0.0 { sum / total_amplitude } else { 0.0 }
+    }
+}
+
+/// The quintic curve used to smoothly interpolate between lattice points
+fn fade(t: f32) -> f32 {
+    t * t * t * (t * (t * 6.0 - 15.0) + 10.0)
+}
+
+/// Compute the dot product of the offset `(x, y, z)` with one of the 12 gradient
+/// directions picked by the hash
+fn grad(hash: u8, x: f32, y: f32, z: f32) -> f32 {
+    let h = hash & 15;
+    let u = if h < 8 { x } else { y };
+    let v = if h < 4 { y } else if h == 12 || h == 14 { x } else { z };
+    (if h & 1 == 0 { u } else { -u }) + (if h & 2 == 0 { v } else { -v })
+}
+
+#[test]
+fn test_noise() {
+    let a = Perlin::new(0);
+    let b = Perlin::new(7);
+    let mut differ = false;
+    for i in 0..64 {
+        let p = Point::new(i as f32 * 0.37 - 5.0, i as f32 * 0.11, 3.0 - i as f32 * 0.23);
+        let n = a.noise(&p);
+        assert!(n >= -1.1 && n <= 1.1);
+        assert!(a.fbm(&p, 6, 2.0, 0.5) >= -1.1 && a.fbm(&p, 6, 2.0, 0.5) <= 1.1);
+        assert!(a.turbulence(&p, 6, 2.0, 0.5) >= 0.0);
+        // The same seed gives the same noise
+        assert_eq!(n, Perlin::new(0).noise(&p));
+        differ = differ || n != b.noise(&p);
+    }
+    assert!(differ);
+    // Noise is zero at the lattice points
+    assert_eq!(a.noise(&Point::new(3.0, -2.0, 5.0)), 0.0);
+}
@@ -0,0 +1,43 @@
+//! Defines a texture which scales the values of one texture by another
+//!
+//! # Scene Usage Example
+//! The scale texture requires the texture to scale and the scale to apply to it,
+//! both can be colors, numbers or the names of other textures. The scale is
+//! always treated as a number.
+//!
+//! ```json
+//! "textures": [
+//!     {
+//!         "name": "dim_checks",
+//!         "type": "scale",
+//!         "tex": "checks",
+//!         "scale": 0.5
+//!     },
+//!     ...
+//! ]
+//! ```
+
+use std::ops::Mul;
+use std::sync::Arc;
+
+use geometry::DifferentialGeometry;
+use texture::Texture;
+
+/// A texture whose values are those of `tex` scaled by `scale`
+pub struct ScaleTexture<T> {
+    tex: Arc<Texture<T> + Send + Sync>,
+    scale: Arc<Texture<f32> + Send + Sync>,
+}
+
+impl<T> ScaleTexture<T> {
+    /// Create a texture scaling the values of `tex` by `scale`
+    pub fn new(tex: Arc<Texture<T> + Send + Sync>, scale: Arc<Texture<f32> + Send + Sync>) -> ScaleTexture<T> {
+        ScaleTexture { tex: tex, scale: scale }
+    }
+}
+
+impl<T: Mul<f32, Output = T>> Texture<T> for ScaleTexture<T> {
+    fn sample(&self, dg: &DifferentialGeometry) -> T {
+        self.tex.sample(dg) * self.scale.sample(dg)
+    }
+}